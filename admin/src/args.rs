@@ -46,6 +46,18 @@ pub enum AcmeCommand {
         acme_server: Option<String>,
     },
 
+    /// Replace the ACME account of the gateway with a new one, for
+    /// when the current account is compromised or rate-limited
+    RotateAccount {
+        /// Email for managing all certificates
+        #[arg(long)]
+        email: String,
+
+        /// Acme server to create account on. Gateway will default to LetsEncrypt
+        #[arg(long)]
+        acme_server: Option<String>,
+    },
+
     /// Request a certificate for a FQDN
     Request {
         /// Fqdn to request certificate for
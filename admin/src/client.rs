@@ -33,6 +33,15 @@ impl Client {
         self.post(&path, Some(acme_server)).await
     }
 
+    pub async fn acme_account_rotate(
+        &self,
+        email: &str,
+        acme_server: Option<String>,
+    ) -> Result<serde_json::Value> {
+        let path = format!("/admin/acme/rotate/{email}");
+        self.post(&path, Some(acme_server)).await
+    }
+
     pub async fn acme_request_certificate(
         &self,
         fqdn: &str,
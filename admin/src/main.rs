@@ -36,6 +36,18 @@ async fn main() {
 
             res
         }
+        Command::Acme(AcmeCommand::RotateAccount { email, acme_server }) => {
+            let account = client
+                .acme_account_rotate(&email, acme_server)
+                .await
+                .expect("to rotate ACME account");
+
+            let mut res = String::new();
+            writeln!(res, "The gateway now uses the following ACME account. Keep this safe as it will be needed to create certificates in the future").unwrap();
+            writeln!(res, "{}", serde_json::to_string_pretty(&account).unwrap()).unwrap();
+
+            res
+        }
         Command::Acme(AcmeCommand::Request {
             fqdn,
             project,
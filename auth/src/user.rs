@@ -355,6 +355,7 @@ mod tests {
                     Scope::AcmeCreate,
                     Scope::CustomDomainCreate,
                     Scope::CustomDomainCertificateRenew,
+                    Scope::CustomDomainDelete,
                     Scope::GatewayCertificateRenew,
                     Scope::Admin,
                     Scope::Deployment,
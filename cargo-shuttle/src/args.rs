@@ -12,7 +12,7 @@ use clap::{
     Parser, ValueEnum,
 };
 use clap_complete::Shell;
-use shuttle_common::{models::project::IDLE_MINUTES, project::ProjectName};
+use shuttle_common::project::ProjectName;
 use uuid::Uuid;
 
 #[derive(Parser)]
@@ -150,6 +150,10 @@ pub enum DeploymentCommand {
         #[arg(long, default_value = "10")]
         /// How many projects per page to display
         limit: u32,
+
+        #[arg(long, value_enum, default_value_t = OutputMode::Table)]
+        /// How to display the deployments
+        output: OutputMode,
     },
     /// View status of a deployment
     Status {
@@ -192,10 +196,17 @@ pub enum ProjectCommand {
 
 #[derive(Parser, Debug)]
 pub struct ProjectStartArgs {
-    #[arg(long, default_value_t = IDLE_MINUTES)]
+    #[arg(long)]
     /// How long to wait before putting the project in an idle state due to inactivity.
-    /// 0 means the project will never idle
-    pub idle_minutes: u64,
+    /// 0 means the project will never idle. The default of the platform is used when missing
+    pub idle_minutes: Option<u64>,
+    #[arg(long)]
+    /// Give the project a chance to shut down gracefully when it is stopped, instead of killing it
+    pub graceful_stop: bool,
+    #[arg(long)]
+    /// Put the project on a network of its own, away from other projects. It also cannot
+    /// reach the shared services, such as the provisioner of databases
+    pub isolated_network: bool,
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -219,6 +230,9 @@ pub struct DeployArgs {
     /// Don't run pre-deploy tests
     #[arg(long)]
     pub no_test: bool,
+    /// Only build and test the service on Shuttle, without running it
+    #[arg(long)]
+    pub dry_run: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -257,6 +271,16 @@ pub struct InitArgs {
     pub login_args: LoginArgs,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    /// A table for people to read
+    Table,
+    /// JSON for scripts to consume
+    Json,
+    /// CSV for spreadsheets
+    Csv,
+}
+
 #[derive(ValueEnum, Clone, Debug, strum::Display, strum::EnumIter)]
 #[strum(serialize_all = "kebab-case")]
 pub enum InitTemplateArg {
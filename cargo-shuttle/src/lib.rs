@@ -17,8 +17,11 @@ use shuttle_common::models::deployment::CREATE_SERVICE_BODY_LIMIT;
 use shuttle_common::{
     claims::{ClaimService, InjectPropagation},
     models::{
-        deployment::{get_deployments_table, DeploymentRequest, GIT_STRINGS_MAX_LENGTH},
-        project::{self, IDLE_MINUTES},
+        deployment::{
+            get_deployments_csv, get_deployments_json, get_deployments_table, DeploymentRequest,
+            GIT_STRINGS_MAX_LENGTH,
+        },
+        project,
         resource::get_resources_table,
         secret,
     },
@@ -57,7 +60,7 @@ use uuid::Uuid;
 
 pub use crate::args::{Command, ProjectArgs, RunArgs, ShuttleArgs};
 use crate::args::{
-    DeployArgs, DeploymentCommand, InitArgs, LoginArgs, LogoutArgs, ProjectCommand,
+    DeployArgs, DeploymentCommand, InitArgs, LoginArgs, LogoutArgs, OutputMode, ProjectCommand,
     ProjectStartArgs, ResourceCommand, EXAMPLES_REPO,
 };
 use crate::client::Client;
@@ -121,8 +124,13 @@ impl Shuttle {
             Command::Logs { id, latest, follow } => {
                 self.logs(&self.client()?, id, latest, follow).await
             }
-            Command::Deployment(DeploymentCommand::List { page, limit }) => {
-                self.deployments_list(&self.client()?, page, limit).await
+            Command::Deployment(DeploymentCommand::List {
+                page,
+                limit,
+                output,
+            }) => {
+                self.deployments_list(&self.client()?, page, limit, output)
+                    .await
             }
             Command::Deployment(DeploymentCommand::Status { id }) => {
                 self.deployment_get(&self.client()?, id).await
@@ -131,11 +139,31 @@ impl Shuttle {
             Command::Stop => self.stop(&self.client()?).await,
             Command::Clean => self.clean(&self.client()?).await,
             Command::Secrets => self.secrets(&self.client()?).await,
-            Command::Project(ProjectCommand::Start(ProjectStartArgs { idle_minutes })) => {
-                self.project_create(&self.client()?, idle_minutes).await
+            Command::Project(ProjectCommand::Start(ProjectStartArgs {
+                idle_minutes,
+                graceful_stop,
+                isolated_network,
+            })) => {
+                self.project_create(
+                    &self.client()?,
+                    idle_minutes,
+                    graceful_stop,
+                    isolated_network,
+                )
+                .await
             }
-            Command::Project(ProjectCommand::Restart(ProjectStartArgs { idle_minutes })) => {
-                self.project_recreate(&self.client()?, idle_minutes).await
+            Command::Project(ProjectCommand::Restart(ProjectStartArgs {
+                idle_minutes,
+                graceful_stop,
+                isolated_network,
+            })) => {
+                self.project_recreate(
+                    &self.client()?,
+                    idle_minutes,
+                    graceful_stop,
+                    isolated_network,
+                )
+                .await
             }
             Command::Project(ProjectCommand::Status { follow }) => {
                 self.project_status(&self.client()?, follow).await
@@ -283,7 +311,8 @@ impl Shuttle {
             project_args.working_directory = path.clone();
 
             self.load_project(&mut project_args)?;
-            self.project_create(&self.client()?, IDLE_MINUTES).await?;
+            self.project_create(&self.client()?, None, false, false)
+                .await?;
         }
 
         if std::env::current_dir().is_ok_and(|d| d != path) {
@@ -481,7 +510,13 @@ impl Shuttle {
         Ok(())
     }
 
-    async fn deployments_list(&self, client: &Client, page: u32, limit: u32) -> Result<()> {
+    async fn deployments_list(
+        &self,
+        client: &Client,
+        page: u32,
+        limit: u32,
+        output: OutputMode,
+    ) -> Result<()> {
         if limit == 0 {
             println!();
             return Ok(());
@@ -489,6 +524,19 @@ impl Shuttle {
 
         let proj_name = self.ctx.project_name();
         let deployments = client.get_deployments(proj_name, page, limit).await?;
+
+        match output {
+            OutputMode::Table => {}
+            OutputMode::Json => {
+                println!("{}", get_deployments_json(&deployments));
+                return Ok(());
+            }
+            OutputMode::Csv => {
+                print!("{}", get_deployments_csv(&deployments));
+                return Ok(());
+            }
+        }
+
         let table = get_deployments_table(&deployments, proj_name.as_str(), page);
 
         println!("{table}");
@@ -1054,6 +1102,7 @@ impl Shuttle {
 
         let mut deployment_req: DeploymentRequest = DeploymentRequest {
             no_test: args.no_test,
+            dry_run: args.dry_run,
             ..Default::default()
         };
 
@@ -1113,7 +1162,8 @@ impl Shuttle {
                         shuttle_common::deployment::State::Queued
                         | shuttle_common::deployment::State::Building
                         | shuttle_common::deployment::State::Built
-                        | shuttle_common::deployment::State::Loading => {
+                        | shuttle_common::deployment::State::Loading
+                        | shuttle_common::deployment::State::Provisioning => {
                             println!("{log_item}");
                         }
                         shuttle_common::deployment::State::Crashed => {
@@ -1153,6 +1203,15 @@ impl Shuttle {
             .get_deployment_details(self.ctx.project_name(), &deployment.id)
             .await?;
 
+        if deployment.dry_run && deployment.state == shuttle_common::deployment::State::Completed {
+            match deployment.tests_passed {
+                Some(true) => println!("Dry run succeeded: the service built and its tests passed"),
+                _ => println!("Dry run succeeded: the service built"),
+            }
+
+            return Ok(CommandOutcome::Ok);
+        }
+
         // A deployment will only exist if there is currently one in the running state
         if deployment.state == shuttle_common::deployment::State::Running {
             let service = client.get_service(self.ctx.project_name()).await?;
@@ -1203,8 +1262,18 @@ impl Shuttle {
         }
     }
 
-    async fn project_create(&self, client: &Client, idle_minutes: u64) -> Result<()> {
-        let config = project::Config { idle_minutes };
+    async fn project_create(
+        &self,
+        client: &Client,
+        idle_minutes: Option<u64>,
+        graceful_stop: bool,
+        isolated_network: bool,
+    ) -> Result<()> {
+        let config = project::Config {
+            idle_minutes,
+            graceful_stop,
+            isolated_network,
+        };
 
         self.wait_with_spinner(
             &[
@@ -1223,9 +1292,16 @@ impl Shuttle {
         Ok(())
     }
 
-    async fn project_recreate(&self, client: &Client, idle_minutes: u64) -> Result<()> {
+    async fn project_recreate(
+        &self,
+        client: &Client,
+        idle_minutes: Option<u64>,
+        graceful_stop: bool,
+        isolated_network: bool,
+    ) -> Result<()> {
         self.project_delete(client).await?;
-        self.project_create(client, idle_minutes).await?;
+        self.project_create(client, idle_minutes, graceful_stop, isolated_network)
+            .await?;
 
         Ok(())
     }
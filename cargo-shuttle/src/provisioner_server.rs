@@ -67,7 +67,7 @@ impl LocalProvisioner {
             port,
             env,
             is_ready_cmd,
-        } = db_type_to_config(db_type);
+        } = db_type_to_config(db_type)?;
         let container_name = format!("shuttle_{service_name}_{type}");
 
         let container = match self.docker.inspect_container(&container_name, None).await {
@@ -329,9 +329,13 @@ struct EngineConfig {
     is_ready_cmd: Vec<String>,
 }
 
-fn db_type_to_config(db_type: Type) -> EngineConfig {
+/// The container to run for a database type. Embedded databases, like sqlite, have none.
+fn db_type_to_config(db_type: Type) -> Result<EngineConfig, Status> {
     match db_type {
-        Type::Shared(SharedEngine::Postgres) => EngineConfig {
+        Type::Shared(SharedEngine::Sqlite) => Err(Status::invalid_argument(
+            "sqlite databases are embedded in the service and cannot be provisioned",
+        )),
+        Type::Shared(SharedEngine::Postgres) => Ok(EngineConfig {
             r#type: "shared_postgres".to_string(),
             image: "docker.io/library/postgres:11".to_string(),
             engine: "postgres".to_string(),
@@ -345,8 +349,8 @@ fn db_type_to_config(db_type: Type) -> EngineConfig {
                 "-c".to_string(),
                 "pg_isready | grep 'accepting connections'".to_string(),
             ],
-        },
-        Type::Shared(SharedEngine::MongoDb) => EngineConfig {
+        }),
+        Type::Shared(SharedEngine::MongoDb) => Ok(EngineConfig {
             r#type: "shared_mongodb".to_string(),
             image: "docker.io/library/mongo:5.0.10".to_string(),
             engine: "mongodb".to_string(),
@@ -364,8 +368,25 @@ fn db_type_to_config(db_type: Type) -> EngineConfig {
                 "--eval".to_string(),
                 "db".to_string(),
             ],
-        },
-        Type::AwsRds(AwsRdsEngine::Postgres) => EngineConfig {
+        }),
+        Type::Shared(SharedEngine::MySql) => Ok(EngineConfig {
+            r#type: "shared_mysql".to_string(),
+            image: "docker.io/library/mysql:8.0.28".to_string(),
+            engine: "mysql".to_string(),
+            username: "root".to_string(),
+            password: "mysql".to_string(),
+            database_name: "mysql".to_string(),
+            port: "3306/tcp".to_string(),
+            env: Some(vec!["MYSQL_ROOT_PASSWORD=mysql".to_string()]),
+            is_ready_cmd: vec![
+                "mysql".to_string(),
+                "-pmysql".to_string(),
+                "--silent".to_string(),
+                "-e".to_string(),
+                "show databases;".to_string(),
+            ],
+        }),
+        Type::AwsRds(AwsRdsEngine::Postgres) => Ok(EngineConfig {
             r#type: "aws_rds_postgres".to_string(),
             image: "docker.io/library/postgres:13.4".to_string(),
             engine: "postgres".to_string(),
@@ -379,8 +400,8 @@ fn db_type_to_config(db_type: Type) -> EngineConfig {
                 "-c".to_string(),
                 "pg_isready | grep 'accepting connections'".to_string(),
             ],
-        },
-        Type::AwsRds(AwsRdsEngine::MariaDB) => EngineConfig {
+        }),
+        Type::AwsRds(AwsRdsEngine::MariaDB) => Ok(EngineConfig {
             r#type: "aws_rds_mariadb".to_string(),
             image: "docker.io/library/mariadb:10.6.7".to_string(),
             engine: "mariadb".to_string(),
@@ -396,8 +417,31 @@ fn db_type_to_config(db_type: Type) -> EngineConfig {
                 "-e".to_string(),
                 "show databases;".to_string(),
             ],
-        },
-        Type::AwsRds(AwsRdsEngine::MySql) => EngineConfig {
+        }),
+        Type::AwsRds(AwsRdsEngine::SqlServer) => Ok(EngineConfig {
+            r#type: "aws_rds_sqlserver".to_string(),
+            image: "mcr.microsoft.com/mssql/server:2022-latest".to_string(),
+            engine: "sqlserver".to_string(),
+            username: "sa".to_string(),
+            password: "Shuttle-sqlserver1".to_string(),
+            database_name: "master".to_string(),
+            port: "1433/tcp".to_string(),
+            env: Some(vec![
+                "ACCEPT_EULA=Y".to_string(),
+                "MSSQL_SA_PASSWORD=Shuttle-sqlserver1".to_string(),
+            ]),
+            is_ready_cmd: vec![
+                "/opt/mssql-tools18/bin/sqlcmd".to_string(),
+                "-C".to_string(),
+                "-U".to_string(),
+                "sa".to_string(),
+                "-P".to_string(),
+                "Shuttle-sqlserver1".to_string(),
+                "-Q".to_string(),
+                "SELECT 1".to_string(),
+            ],
+        }),
+        Type::AwsRds(AwsRdsEngine::MySql) => Ok(EngineConfig {
             r#type: "aws_rds_mysql".to_string(),
             image: "docker.io/library/mysql:8.0.28".to_string(),
             engine: "mysql".to_string(),
@@ -413,6 +457,6 @@ fn db_type_to_config(db_type: Type) -> EngineConfig {
                 "-e".to_string(),
                 "show databases;".to_string(),
             ],
-        },
+        }),
     }
 }
@@ -96,3 +96,67 @@ impl Header for XShuttleProject {
         }
     }
 }
+
+pub static X_SHUTTLE_DEPLOYMENT_ID: HeaderName = HeaderName::from_static("x-shuttle-deployment-id");
+
+/// Typed header for telling which deployment served a request
+pub struct XShuttleDeploymentId(pub String);
+
+impl Header for XShuttleDeploymentId {
+    fn name() -> &'static HeaderName {
+        &X_SHUTTLE_DEPLOYMENT_ID
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        Self: Sized,
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values
+            .next()
+            .ok_or_else(headers::Error::invalid)?
+            .to_str()
+            .map_err(|_| headers::Error::invalid())?
+            .to_string();
+
+        Ok(Self(value))
+    }
+
+    fn encode<E: Extend<http::HeaderValue>>(&self, values: &mut E) {
+        if let Ok(value) = HeaderValue::from_str(self.0.as_str()) {
+            values.extend(std::iter::once(value));
+        }
+    }
+}
+
+pub static X_SHUTTLE_REQUEST_TIMEOUT: HeaderName =
+    HeaderName::from_static("x-shuttle-request-timeout");
+
+/// Typed header for telling a runtime how many seconds a request can take before the proxy cuts it off
+pub struct XShuttleRequestTimeout(pub u64);
+
+impl Header for XShuttleRequestTimeout {
+    fn name() -> &'static HeaderName {
+        &X_SHUTTLE_REQUEST_TIMEOUT
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        Self: Sized,
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values
+            .next()
+            .ok_or_else(headers::Error::invalid)?
+            .to_str()
+            .map_err(|_| headers::Error::invalid())?
+            .parse()
+            .map_err(|_| headers::Error::invalid())?;
+
+        Ok(Self(value))
+    }
+
+    fn encode<E: Extend<http::HeaderValue>>(&self, values: &mut E) {
+        values.extend(std::iter::once(HeaderValue::from(self.0)));
+    }
+}
@@ -80,6 +80,9 @@ pub enum Scope {
     /// Renew the certificate of a custom domain.
     CustomDomainCertificateRenew,
 
+    /// Remove a custom domain
+    CustomDomainDelete,
+
     /// Request renewal of the gateway certificate.
     /// Note: this step should be completed manually in terms
     /// of DNS-01 challenge completion.
@@ -105,6 +108,7 @@ impl ScopeBuilder {
             Scope::AcmeCreate,
             Scope::CustomDomainCreate,
             Scope::CustomDomainCertificateRenew,
+            Scope::CustomDomainDelete,
             Scope::GatewayCertificateRenew,
             Scope::Admin,
         ]);
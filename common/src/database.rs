@@ -22,6 +22,7 @@ pub enum AwsRdsEngine {
     Postgres,
     MySql,
     MariaDB,
+    SqlServer,
 }
 
 #[derive(Clone, Debug, Deserialize, Display, Serialize, Eq, PartialEq)]
@@ -31,6 +32,9 @@ pub enum AwsRdsEngine {
 pub enum SharedEngine {
     Postgres,
     MongoDb,
+    MySql,
+    /// An embedded SQLite (or libSQL) database
+    Sqlite,
 }
 
 impl Display for Type {
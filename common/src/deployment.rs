@@ -3,6 +3,8 @@ use strum::{Display, EnumString};
 #[cfg(feature = "openapi")]
 use utoipa::ToSchema;
 
+/// States a deployment can be in. Parsing a state ignores case and accepts the verb it is named after,
+/// like "run" for [State::Running]
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Display, Serialize, EnumString)]
 #[serde(rename_all = "lowercase")]
 #[strum(serialize_all = "lowercase")]
@@ -10,13 +12,22 @@ use utoipa::ToSchema;
 #[cfg_attr(feature = "openapi", derive(ToSchema))]
 #[cfg_attr(feature = "openapi", schema(as = shuttle_common::deployment::State))]
 pub enum State {
+    #[strum(to_string = "queued", serialize = "queue")]
     Queued,
+    #[strum(to_string = "building", serialize = "build")]
     Building,
     Built,
+    #[strum(to_string = "loading", serialize = "load")]
     Loading,
+    #[strum(to_string = "provisioning", serialize = "provision")]
+    Provisioning,
+    #[strum(to_string = "running", serialize = "run")]
     Running,
+    #[strum(to_string = "completed", serialize = "complete")]
     Completed,
+    #[strum(to_string = "stopped", serialize = "stop")]
     Stopped,
+    #[strum(to_string = "crashed", serialize = "crash")]
     Crashed,
     Unknown,
 }
@@ -39,5 +50,8 @@ mod tests {
         assert_eq!(State::Queued, State::from_str("Queued").unwrap());
         assert_eq!(State::Unknown, State::from_str("unKnown").unwrap());
         assert_eq!(State::Built, State::from_str("built").unwrap());
+        assert_eq!(State::Crashed, State::from_str("Crash").unwrap());
+        assert_eq!(State::Crashed.to_string(), "crashed");
+        assert!(State::from_str("crashing").is_err());
     }
 }
@@ -11,6 +11,7 @@ pub mod log;
 pub mod models;
 #[cfg(feature = "service")]
 pub mod project;
+pub mod queue;
 pub mod resource;
 #[cfg(feature = "service")]
 pub mod storage_manager;
@@ -29,6 +29,9 @@ pub struct Item {
     pub line: Option<u32>,
     pub target: String,
     pub fields: Vec<u8>,
+    /// Id of the OpenTelemetry trace the log happened in, to correlate it with the traces of other services
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
 }
 
 #[cfg(feature = "display")]
@@ -149,6 +152,7 @@ mod tests {
                 "message": "Building",
             }))
             .unwrap(),
+            trace_id: None,
         };
 
         with_tz("CEST", || {
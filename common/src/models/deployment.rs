@@ -1,4 +1,5 @@
 use crate::deployment::State;
+use crate::models::{styled, use_colors};
 use chrono::{DateTime, Utc};
 use comfy_table::{
     modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Attribute, Cell, CellAlignment, Color,
@@ -27,6 +28,80 @@ pub struct Response {
     pub git_commit_msg: Option<String>,
     pub git_branch: Option<String>,
     pub git_dirty: Option<bool>,
+    /// Versions of `rustc` and `cargo` the deployment was built with
+    #[serde(default)]
+    pub toolchain_info: Option<String>,
+    /// Whether the deployment was only built and tested, without being run
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Whether the tests of the deployment passed, once they ran
+    #[serde(default)]
+    pub tests_passed: Option<bool>,
+}
+
+/// A deployment which is loading or running
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::deployment::ActiveResponse))]
+pub struct ActiveResponse {
+    #[cfg_attr(feature = "openapi", schema(value_type = KnownFormat::Uuid))]
+    pub id: Uuid,
+    #[cfg_attr(feature = "openapi", schema(value_type = KnownFormat::Uuid))]
+    pub service_id: Uuid,
+    pub service_name: String,
+    #[cfg_attr(feature = "openapi", schema(value_type = shuttle_common::deployment::State))]
+    pub state: State,
+    /// Whether the deployer actually has a runtime up for the deployment
+    pub has_runtime: bool,
+}
+
+/// Request metrics of a running deployment, as seen by the proxy in front of it
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::deployment::Metrics))]
+pub struct Metrics {
+    /// Number of requests served
+    pub request_count: u64,
+    /// Number of requests answered with a server error
+    pub error_count: u64,
+    /// Average time taken to answer a request, in milliseconds
+    pub average_latency_ms: f64,
+    /// Longest time taken to answer a request, in milliseconds
+    pub max_latency_ms: f64,
+}
+
+/// An attempt of the gateway at bringing a project back up, during which its deployment was interrupted
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::deployment::RestartAttempt))]
+pub struct RestartAttempt {
+    pub kind: RestartKind,
+    /// How many times the project was brought back up this way in a row
+    pub attempt: usize,
+}
+
+/// How the gateway tried to bring a project back up
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::deployment::RestartKind))]
+pub enum RestartKind {
+    Restart,
+    Recreate,
+    Reboot,
+}
+
+impl Display for RestartAttempt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match self.kind {
+            RestartKind::Restart => "restarting",
+            RestartKind::Recreate => "recreating",
+            RestartKind::Reboot => "rebooting",
+        };
+
+        write!(f, "{action} (attempt {})", self.attempt)
+    }
 }
 
 impl Display for Response {
@@ -34,12 +109,14 @@ impl Display for Response {
         write!(
             f,
             "{} deployment '{}' is {}",
-            self.last_update
-                .format("%Y-%m-%dT%H:%M:%SZ")
-                .to_string()
-                .dim(),
+            styled(
+                self.last_update
+                    .format("%Y-%m-%dT%H:%M:%SZ")
+                    .to_string()
+                    .dim()
+            ),
             self.id,
-            self.state.to_string().cyan()
+            styled(self.state.to_string().cyan())
         )
     }
 }
@@ -50,7 +127,11 @@ impl State {
     /// we may end up with two different versions of Color.
     pub fn get_color(&self) -> &str {
         match self {
-            State::Queued | State::Building | State::Built | State::Loading => "cyan",
+            State::Queued
+            | State::Building
+            | State::Built
+            | State::Loading
+            | State::Provisioning => "cyan",
             State::Running => "green",
             State::Completed | State::Stopped => "blue",
             State::Crashed => "red",
@@ -59,21 +140,70 @@ impl State {
     }
 }
 
+/// Serialize the deployments to pretty JSON, for scripts rather than people to read
+pub fn get_deployments_json(deployments: &[Response]) -> String {
+    serde_json::to_string_pretty(deployments).expect("deployments to serialize")
+}
+
+/// Serialize the deployments to CSV with the same columns as [get_deployments_table], for spreadsheets
+pub fn get_deployments_csv(deployments: &[Response]) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    writer
+        .write_record([
+            "Deployment ID",
+            "Status",
+            "Last updated",
+            "Commit ID",
+            "Commit Message",
+            "Branch",
+            "Dirty",
+        ])
+        .expect("header to be written");
+
+    for deploy in deployments {
+        writer
+            .write_record([
+                deploy.id.to_string(),
+                deploy.state.to_string(),
+                deploy.last_update.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                deploy.git_commit_id.clone().unwrap_or_default(),
+                deploy.git_commit_msg.clone().unwrap_or_default(),
+                deploy.git_branch.clone().unwrap_or_default(),
+                deploy
+                    .git_dirty
+                    .map(|dirty| dirty.to_string())
+                    .unwrap_or_default(),
+            ])
+            .expect("deployment to be written");
+    }
+
+    let bytes = writer.into_inner().expect("writer to be flushed");
+
+    String::from_utf8(bytes).expect("CSV to be valid UTF-8")
+}
+
 pub fn get_deployments_table(deployments: &Vec<Response>, service_name: &str, page: u32) -> String {
     if deployments.is_empty() {
         if page <= 1 {
             format!(
                 "{}\n",
-                "No deployments are linked to this service".yellow().bold()
+                styled("No deployments are linked to this service".yellow().bold())
             )
         } else {
             format!(
                 "{}\n",
-                "No more deployments linked to this service".yellow().bold()
+                styled("No more deployments linked to this service".yellow().bold())
             )
         }
     } else {
         let mut table = Table::new();
+
+        // Comfy-table only checks for a terminal itself
+        if !use_colors() {
+            table.force_no_tty();
+        }
+
         table
             .load_preset(UTF8_FULL)
             .apply_modifier(UTF8_ROUND_CORNERS)
@@ -149,10 +279,10 @@ Most recent {} for {}
 
 {}
 "#,
-            "deployments".bold(),
+            styled("deployments".bold()),
             service_name,
             table,
-            "More projects might be available on the next page using --page.".bold()
+            styled("More projects might be available on the next page using --page.".bold())
         )
     }
 }
@@ -167,8 +297,131 @@ pub struct DeploymentRequest {
     pub git_commit_msg: Option<String>,
     pub git_branch: Option<String>,
     pub git_dirty: Option<bool>,
+    /// Deploy this repository instead of the archive in `data`
+    #[serde(default)]
+    pub git_source: Option<GitSource>,
+    /// Only build and test the deployment, without running it
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl DeploymentRequest {
+    /// Start building a request to deploy the archive in `data`
+    pub fn builder(data: Vec<u8>) -> DeploymentRequestBuilder {
+        DeploymentRequestBuilder::new(data)
+    }
+
+    /// Start building a request to deploy a git repository, which the deployer clones itself
+    pub fn builder_from_git(source: GitSource) -> DeploymentRequestBuilder {
+        DeploymentRequestBuilder::from_git(source)
+    }
+}
+
+/// A git repository to deploy at a given reference
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::deployment::GitSource))]
+pub struct GitSource {
+    /// Url of the repository, which has to be reachable over https on a public host. Credentials
+    /// in the url are kept out of the build logs
+    pub url: String,
+    /// Branch, tag or commit to deploy
+    pub reference: String,
+}
+
+/// Builds a [DeploymentRequest]. Tests are run and no git metadata is sent unless set otherwise.
+pub struct DeploymentRequestBuilder(DeploymentRequest);
+
+impl DeploymentRequestBuilder {
+    /// Create a builder for a request to deploy the archive in `data`
+    pub fn new(data: Vec<u8>) -> Self {
+        Self(DeploymentRequest {
+            data,
+            ..Default::default()
+        })
+    }
+
+    /// Create a builder for a request to deploy the repository in `source`
+    pub fn from_git(source: GitSource) -> Self {
+        Self(DeploymentRequest {
+            git_source: Some(source),
+            ..Default::default()
+        })
+    }
+
+    /// Skip running the tests of the service before deploying it
+    pub fn with_no_test(mut self, no_test: bool) -> Self {
+        self.0.no_test = no_test;
+        self
+    }
+
+    /// Stop once the service is built and tested, instead of running it
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.0.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_git_commit_id(mut self, git_commit_id: impl Into<String>) -> Self {
+        self.0.git_commit_id = Some(git_commit_id.into());
+        self
+    }
+
+    pub fn with_git_commit_msg(mut self, git_commit_msg: impl Into<String>) -> Self {
+        self.0.git_commit_msg = Some(git_commit_msg.into());
+        self
+    }
+
+    pub fn with_git_branch(mut self, git_branch: impl Into<String>) -> Self {
+        self.0.git_branch = Some(git_branch.into());
+        self
+    }
+
+    /// Whether the working tree had uncommitted changes when it was archived
+    pub fn with_git_dirty(mut self, git_dirty: bool) -> Self {
+        self.0.git_dirty = Some(git_dirty);
+        self
+    }
+
+    pub fn build(self) -> DeploymentRequest {
+        self.0
+    }
 }
 
 pub const GIT_STRINGS_MAX_LENGTH: usize = 80;
 const GIT_OPTION_NONE_TEXT: &str = "N/A";
 pub const CREATE_SERVICE_BODY_LIMIT: usize = 50_000_000;
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    use super::{get_deployments_csv, Response};
+    use crate::deployment::State;
+
+    #[test]
+    fn deployments_csv() {
+        let id = Uuid::new_v4();
+        let deployments = vec![Response {
+            id,
+            service_id: Uuid::new_v4(),
+            state: State::Running,
+            last_update: Utc.with_ymd_and_hms(2023, 7, 1, 12, 30, 0).unwrap(),
+            git_commit_id: Some("6a1e2cf".to_string()),
+            git_commit_msg: Some("fix: quotes, \"commas\" and all".to_string()),
+            git_branch: None,
+            git_dirty: Some(false),
+            toolchain_info: None,
+            dry_run: false,
+            tests_passed: None,
+        }];
+
+        assert_eq!(
+            get_deployments_csv(&deployments),
+            format!(
+                "Deployment ID,Status,Last updated,Commit ID,Commit Message,Branch,Dirty\n\
+                 {id},running,2023-07-01T12:30:00Z,6a1e2cf,\"fix: quotes, \"\"commas\"\" and all\",,false\n"
+            )
+        );
+    }
+}
@@ -44,10 +44,16 @@ pub enum ErrorKind {
     ProjectAlreadyExists,
     ProjectNotReady,
     ProjectUnavailable,
+    ProjectTimedOut,
+    ProjectFailing,
+    ProjectRateLimited,
+    ProjectConflict,
     CustomDomainNotFound,
     InvalidCustomDomain,
     CustomDomainAlreadyExists,
+    InvalidCertificate,
     InvalidOperation,
+    QuotaExceeded,
     Internal,
     NotReady,
     ServiceUnavailable,
@@ -74,6 +80,22 @@ impl From<ErrorKind> for ApiError {
             ErrorKind::ProjectUnavailable => {
                 (StatusCode::BAD_GATEWAY, "project returned invalid response")
             }
+            ErrorKind::ProjectTimedOut => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "project took too long to respond",
+            ),
+            ErrorKind::ProjectFailing => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "project keeps failing, try again later",
+            ),
+            ErrorKind::ProjectRateLimited => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "project received too many requests, try again later",
+            ),
+            ErrorKind::ProjectConflict => (
+                StatusCode::CONFLICT,
+                "project was changed at the same time, try again",
+            ),
             ErrorKind::InvalidProjectName => (
                 StatusCode::BAD_REQUEST,
                 r#"
@@ -90,6 +112,10 @@ impl From<ErrorKind> for ApiError {
                 StatusCode::BAD_REQUEST,
                 "the requested operation is invalid",
             ),
+            ErrorKind::QuotaExceeded => (
+                StatusCode::FORBIDDEN,
+                "the project quota of this account has been reached",
+            ),
             ErrorKind::ProjectAlreadyExists => (
                 StatusCode::BAD_REQUEST,
                 "a project with the same name already exists",
@@ -99,6 +125,10 @@ impl From<ErrorKind> for ApiError {
             ErrorKind::CustomDomainAlreadyExists => {
                 (StatusCode::BAD_REQUEST, "custom domain already in use")
             }
+            ErrorKind::InvalidCertificate => (
+                StatusCode::BAD_REQUEST,
+                "the certificate or its private key is invalid",
+            ),
             ErrorKind::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized"),
             ErrorKind::Forbidden => (StatusCode::FORBIDDEN, "forbidden"),
             ErrorKind::NotReady => (StatusCode::INTERNAL_SERVER_ERROR, "service not ready"),
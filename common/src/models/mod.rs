@@ -7,12 +7,30 @@ pub mod service;
 pub mod stats;
 pub mod user;
 
+use std::fmt::Display;
+
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use crossterm::{style::StyledContent, tty::IsTty};
 use http::StatusCode;
 use serde::de::DeserializeOwned;
 use tracing::trace;
 
+/// Whether output can be styled, which it cannot when `NO_COLOR` is set or stdout is not a terminal
+pub fn use_colors() -> bool {
+    std::env::var_os("NO_COLOR").map_or(true, |no_color| no_color.is_empty())
+        && std::io::stdout().is_tty()
+}
+
+/// Render styled content, or only its text when output cannot be styled
+pub fn styled<D: Display>(content: StyledContent<D>) -> String {
+    if use_colors() {
+        content.to_string()
+    } else {
+        content.content().to_string()
+    }
+}
+
 /// A to_json wrapper for handling our error states
 #[async_trait]
 pub trait ToJson {
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use comfy_table::{
     modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Cell, CellAlignment, Color,
     ContentArrangement, Table,
@@ -25,6 +26,58 @@ pub struct Response {
     pub name: String,
     #[cfg_attr(feature = "openapi", schema(value_type = shuttle_common::models::project::State))]
     pub state: State,
+    /// Most recent state transitions of the project, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Vec<shuttle_common::models::project::Transition>))]
+    pub transition_history: Vec<Transition>,
+    /// When the project was created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<KnownFormat::DateTime>))]
+    pub created_at: Option<DateTime<Utc>>,
+    /// When the project was last changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<KnownFormat::DateTime>))]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A change of state of a project
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::Transition))]
+pub struct Transition {
+    pub from: String,
+    pub to: String,
+    pub at: DateTime<Utc>,
+}
+
+/// An operation on the lifecycle of a project, as recorded in its audit log
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, EnumString, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::AuditAction))]
+pub enum AuditAction {
+    Create,
+    Destroy,
+    Transfer,
+    Rename,
+}
+
+/// An entry of the audit log of a project
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::AuditEntry))]
+pub struct AuditEntry {
+    /// The name the project had when the operation happened
+    pub project_name: String,
+    /// The account the project belonged to after the operation
+    pub account_name: String,
+    #[cfg_attr(feature = "openapi", schema(value_type = shuttle_common::models::project::AuditAction))]
+    pub action: AuditAction,
+    /// Extra information about the operation, like the previous owner of a transferred project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    pub at: DateTime<Utc>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, EnumString)]
@@ -174,7 +227,92 @@ impl State {
 /// Config when creating a new project
 #[derive(Deserialize, Serialize)]
 pub struct Config {
-    pub idle_minutes: u64,
+    /// Minutes of inactivity before the project is stopped. The gateway's default is used when missing
+    #[serde(default)]
+    pub idle_minutes: Option<u64>,
+    /// Send a `SIGTERM` and give the project time to exit when stopping it, instead of killing it outright
+    #[serde(default)]
+    pub graceful_stop: bool,
+    /// Put the project on a docker network of its own, cut off from other projects and from the shared internal services
+    #[serde(default)]
+    pub isolated_network: bool,
+}
+
+/// The timeout of the requests proxied to a project
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::RequestTimeout))]
+pub struct RequestTimeout {
+    /// Seconds a request can take before it is cut off. Only the proxy's defaults apply when missing
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// How many times the failed builds of a project are retried
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::BuildRetries))]
+pub struct BuildRetries {
+    /// Retries of a build which failed on a transient error. The deployer's default is used when missing
+    #[serde(default)]
+    pub max_build_retries: Option<u32>,
+}
+
+/// The tags a project is grouped by
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::Tags))]
+pub struct Tags {
+    /// Tags of the project, like its team or environment
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// A sample of the resources used by a project
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::StatsSample))]
+pub struct StatsSample {
+    /// When the sample was taken
+    #[cfg_attr(feature = "openapi", schema(value_type = KnownFormat::DateTime))]
+    pub timestamp: DateTime<Utc>,
+    /// CPU time used by the project since it started, in nanoseconds
+    pub cpu_usage: u64,
+    /// Memory used by the project, in bytes
+    #[serde(default)]
+    pub memory_usage: Option<u64>,
+    /// Memory the project can use at most, in bytes
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+}
+
+/// The limit on the rate of requests routed to a project
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::RateLimit))]
+pub struct RateLimit {
+    /// Requests a second the project takes on average. Not limited when missing
+    #[serde(default)]
+    pub requests_per_second: Option<u32>,
+    /// Requests the project takes at once on top of its average. The rate is used when missing
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+/// Outbound network access allowed to a project
+#[derive(
+    Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, EnumString, strum::Display,
+)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::EgressPolicy))]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum EgressPolicy {
+    /// The project can reach anything, the same as any other container
+    #[default]
+    Open,
+    /// The project joins the restricted network, which has no route outside of the host
+    Restricted,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -183,6 +321,34 @@ pub struct Config {
 pub struct AdminResponse {
     pub project_name: String,
     pub account_name: String,
+    /// Number of times the project was restarted, across reboots
+    #[serde(default)]
+    pub total_restarts: u32,
+    /// When the project was last changed
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A custom domain pointing at a project
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::CustomDomainResponse))]
+pub struct CustomDomainResponse {
+    pub fqdn: String,
+    /// When the certificate of the domain expires, if it could be read
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<KnownFormat::DateTime>))]
+    pub certificate_expiry: Option<DateTime<Utc>>,
+}
+
+/// A certificate issued outside of the gateway, to serve a custom domain with
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::project::CustomDomainCertificate))]
+pub struct CustomDomainCertificate {
+    /// The PEM encoded certificate chain, leaf certificate first
+    pub certificate: String,
+    /// The PEM encoded private key of the certificate
+    pub private_key: String,
 }
 
 pub fn get_table(projects: &Vec<Response>, page: u32) -> String {
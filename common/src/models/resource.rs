@@ -1,16 +1,34 @@
 use std::collections::HashMap;
 
+use chrono::{DateTime, Utc};
 use comfy_table::{
     modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Attribute, Cell, CellAlignment,
     ContentArrangement, Table,
 };
 use crossterm::style::Stylize;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
 
 use crate::{
-    resource::{Response, Type},
+    models::secret::SecretsData,
+    resource::{ProvisioningStatus, Response, Type},
     DbOutput, SecretStore,
 };
 
+/// A change of the provisioning status of a resource of a deployment
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::resource::StatusChange))]
+pub struct StatusChange {
+    #[cfg_attr(feature = "openapi", schema(value_type = shuttle_common::resource::Type))]
+    pub r#type: Type,
+    #[cfg_attr(feature = "openapi", schema(value_type = shuttle_common::resource::ProvisioningStatus))]
+    pub status: ProvisioningStatus,
+    #[cfg_attr(feature = "openapi", schema(value_type = KnownFormat::DateTime))]
+    pub timestamp: DateTime<Utc>,
+}
+
 pub fn get_resources_table(resources: &Vec<Response>, service_name: &str) -> String {
     if resources.is_empty() {
         format!("{}\n", "No resources are linked to this service".bold())
@@ -22,6 +40,8 @@ pub fn get_resources_table(resources: &Vec<Response>, service_name: &str) -> Str
                 Type::StaticFolder => "Static Folder",
                 Type::Persist => "Persist",
                 Type::Turso => "Turso",
+                Type::Redis => "Redis",
+                Type::Queue(_) => "Queues",
                 Type::Custom => "Custom",
             };
 
@@ -98,14 +118,29 @@ fn get_secrets_table(secrets: &[&Response], service_name: &str) -> String {
     table
         .load_preset(UTF8_FULL)
         .apply_modifier(UTF8_ROUND_CORNERS)
-        .set_header(vec![Cell::new("Keys")
-            .add_attribute(Attribute::Bold)
-            .set_alignment(CellAlignment::Center)]);
+        .set_header(vec![
+            Cell::new("Keys")
+                .add_attribute(Attribute::Bold)
+                .set_alignment(CellAlignment::Center),
+            Cell::new("Last updated")
+                .add_attribute(Attribute::Bold)
+                .set_alignment(CellAlignment::Center),
+        ]);
 
-    let secrets = serde_json::from_value::<SecretStore>(secrets[0].data.clone()).unwrap();
+    // Older deployers send the store itself, which has no metadata
+    if let Ok(data) = serde_json::from_value::<SecretsData>(secrets[0].data.clone()) {
+        for secret in data.secrets {
+            table.add_row(vec![
+                secret.key,
+                secret.last_update.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            ]);
+        }
+    } else {
+        let secrets = serde_json::from_value::<SecretStore>(secrets[0].data.clone()).unwrap();
 
-    for key in secrets.secrets.keys() {
-        table.add_row(vec![key]);
+        for key in secrets.secrets.keys() {
+            table.add_row(vec![key.to_string(), String::new()]);
+        }
     }
 
     format!(
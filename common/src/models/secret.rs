@@ -17,6 +17,21 @@ pub struct Response {
     pub last_update: DateTime<Utc>,
 }
 
+/// When a secret was set, without exposing its value
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Metadata {
+    pub key: String,
+    pub created_at: DateTime<Utc>,
+    /// Only moves when the value of the secret changes
+    pub last_update: DateTime<Utc>,
+}
+
+/// The data of a [crate::resource::Type::Secrets] resource
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SecretsData {
+    pub secrets: Vec<Metadata>,
+}
+
 pub fn get_table(secrets: &Vec<Response>) -> String {
     if secrets.is_empty() {
         format!("{}\n", "No secrets are linked to this service".bold())
@@ -6,7 +6,7 @@ use std::str::FromStr;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::models::deployment;
+use crate::models::{deployment, styled};
 
 #[derive(Deserialize, Serialize)]
 #[cfg_attr(feature = "openapi", derive(ToSchema))]
@@ -38,21 +38,23 @@ Status:        {}
 Last Updated:  {}
 URI:           {}
 "#,
-                self.name.clone().bold(),
+                styled(self.name.clone().bold()),
                 deployment.id,
-                deployment.state.to_string().with(
+                styled(deployment.state.to_string().with(
                     // Unwrap is safe because Color::from_str returns the color white if str is not a Color.
                     Color::from_str(deployment.state.get_color()).unwrap()
-                ),
+                )),
                 deployment.last_update.format("%Y-%m-%dT%H:%M:%SZ"),
                 self.uri,
             )
         } else {
             format!(
                 "{}\n\n",
-                "No deployment is currently running for this service"
-                    .yellow()
-                    .bold()
+                styled(
+                    "No deployment is currently running for this service"
+                        .yellow()
+                        .bold()
+                )
             )
         };
 
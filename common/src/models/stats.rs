@@ -8,6 +8,9 @@ use uuid::Uuid;
 #[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::stats::LoadRequest))]
 pub struct LoadRequest {
     pub id: Uuid,
+    /// Project the build belongs to, to look up its build settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -16,4 +19,19 @@ pub struct LoadRequest {
 pub struct LoadResponse {
     pub builds_count: usize,
     pub has_capacity: bool,
+    /// Retries the project set for its failed builds, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_build_retries: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::models::stats::TaskRouterResponse))]
+pub struct TaskRouterResponse {
+    /// Number of projects a task worker is kept for
+    pub tracked_count: usize,
+    /// Number of projects with queued or running tasks
+    pub active_count: usize,
+    /// Number of projects past which idle workers are evicted
+    pub limit: usize,
 }
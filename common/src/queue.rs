@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+/// The engine of a managed message queue
+#[derive(Clone, Debug, Deserialize, Display, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::queue::Type))]
+pub enum Type {
+    RabbitMq,
+    Sqs,
+}
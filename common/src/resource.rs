@@ -2,10 +2,11 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use strum::EnumString;
 #[cfg(feature = "openapi")]
 use utoipa::ToSchema;
 
-use crate::database;
+use crate::{database, queue, DbInput, DbOutput, SecretStore};
 
 /// Common type to hold all the information we need for a generic resource
 #[derive(Clone, Deserialize, Serialize)]
@@ -36,9 +37,42 @@ pub enum Type {
     StaticFolder,
     Persist,
     Turso,
+    Redis,
+    #[cfg_attr(feature = "openapi", schema(value_type = shuttle_common::queue::Type))]
+    Queue(queue::Type),
     Custom,
 }
 
+/// Log field holding the type of the resource a provisioning status is about
+pub const RESOURCE_TYPE_FIELD: &str = "resource_type";
+/// Log field holding the [ProvisioningStatus] of a resource
+pub const RESOURCE_STATUS_FIELD: &str = "resource_status";
+
+/// Where a resource of a deployment is at in its provisioning
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq, strum::Display, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::resource::ProvisioningStatus))]
+pub enum ProvisioningStatus {
+    /// Used by a previous deployment, but not requested yet by this one
+    Pending,
+    Provisioning,
+    Ready,
+    Failed,
+}
+
+/// The provisioning status of a resource of a deployment
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+#[cfg_attr(feature = "openapi", schema(as = shuttle_common::resource::StatusResponse))]
+pub struct StatusResponse {
+    #[cfg_attr(feature = "openapi", schema(value_type = shuttle_common::resource::Type))]
+    pub r#type: Type,
+    #[cfg_attr(feature = "openapi", schema(value_type = shuttle_common::resource::ProvisioningStatus))]
+    pub status: ProvisioningStatus,
+}
+
 impl Response {
     pub fn into_bytes(self) -> Vec<u8> {
         self.to_bytes()
@@ -51,6 +85,56 @@ impl Response {
     pub fn from_bytes(bytes: Vec<u8>) -> Self {
         serde_json::from_slice(&bytes).expect("to turn bytes into a resource")
     }
+
+    /// Check the config and data have the shape expected for the type of this resource
+    pub fn validate(&self) -> Result<(), String> {
+        match self.r#type {
+            Type::Database(_) => {
+                expect_shape::<DbInput>("config", &self.config)?;
+                expect_shape::<DbOutput>("data", &self.data)
+            }
+            Type::Secrets => {
+                expect_null("config", &self.config)?;
+                expect_shape::<SecretStore>("data", &self.data)
+            }
+            Type::StaticFolder => {
+                expect_shape::<String>("config", &self.config)?;
+                expect_shape::<String>("data", &self.data)
+            }
+            Type::Persist => {
+                expect_null("config", &self.config)?;
+                expect_object("data", &self.data)
+            }
+            Type::Turso => {
+                expect_object("config", &self.config)?;
+                expect_object("data", &self.data)
+            }
+            // The shape of these is up to whoever provides them
+            Type::Redis | Type::Queue(_) | Type::Custom => Ok(()),
+        }
+    }
+}
+
+fn expect_shape<T: serde::de::DeserializeOwned>(name: &str, value: &Value) -> Result<(), String> {
+    serde_json::from_value::<T>(value.clone())
+        .map(|_| ())
+        .map_err(|error| format!("{name} is malformed: {error}"))
+}
+
+fn expect_null(name: &str, value: &Value) -> Result<(), String> {
+    if value.is_null() {
+        Ok(())
+    } else {
+        Err(format!("{name} should be empty, but is {value}"))
+    }
+}
+
+fn expect_object(name: &str, value: &Value) -> Result<(), String> {
+    if value.is_object() {
+        Ok(())
+    } else {
+        Err(format!("{name} should be an object, but is {value}"))
+    }
 }
 
 impl Display for Type {
@@ -61,7 +145,63 @@ impl Display for Type {
             Type::StaticFolder => write!(f, "static_folder"),
             Type::Persist => write!(f, "persist"),
             Type::Turso => write!(f, "turso"),
+            Type::Redis => write!(f, "redis"),
+            Type::Queue(queue_type) => write!(f, "queue::{queue_type}"),
             Type::Custom => write!(f, "custom"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Response, Type};
+    use crate::database;
+
+    #[test]
+    fn validate() {
+        let resource = |r#type, config, data| Response {
+            r#type,
+            config,
+            data,
+        };
+        let postgres = Type::Database(database::Type::Shared(database::SharedEngine::Postgres));
+
+        assert!(resource(
+            postgres.clone(),
+            json!({"local_uri": null}),
+            json!({"Local": "postgres://localhost"})
+        )
+        .validate()
+        .is_ok());
+        assert!(resource(
+            Type::Secrets,
+            json!(null),
+            json!({"secrets": {"KEY": "value"}})
+        )
+        .validate()
+        .is_ok());
+        assert!(
+            resource(Type::StaticFolder, json!("static"), json!("/app/static"))
+                .validate()
+                .is_ok()
+        );
+        assert!(resource(Type::Custom, json!([1, 2]), json!("anything"))
+            .validate()
+            .is_ok());
+
+        assert_eq!(
+            resource(Type::Persist, json!({"folder": "data"}), json!({}))
+                .validate()
+                .unwrap_err(),
+            r#"config should be empty, but is {"folder":"data"}"#
+        );
+        assert!(
+            resource(postgres, json!({"local_uri": 5}), json!({"Local": ""}))
+                .validate()
+                .unwrap_err()
+                .starts_with("config is malformed")
+        );
+    }
+}
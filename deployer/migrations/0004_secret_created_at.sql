@@ -0,0 +1,4 @@
+ALTER TABLE secrets
+ADD COLUMN created_at INTEGER;
+
+UPDATE secrets SET created_at = last_update;
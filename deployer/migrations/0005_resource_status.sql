@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS resource_statuses (
+  deployment_id TEXT,    -- The deployment the resource is provisioned for
+  type TEXT,             -- Type of resource
+  status TEXT,           -- Provisioning status of the resource
+  last_update INTEGER,   -- Unix epoch of the last status update
+  PRIMARY KEY (deployment_id, type),
+  FOREIGN KEY(deployment_id) REFERENCES deployments(id)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE deployments
+ADD COLUMN logs_object_key TEXT; -- Key of the archived logs in object storage, if archived
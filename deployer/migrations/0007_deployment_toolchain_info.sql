@@ -0,0 +1,2 @@
+ALTER TABLE deployments
+ADD COLUMN toolchain_info TEXT; -- Versions of rustc and cargo the deployment was built with
@@ -0,0 +1,2 @@
+ALTER TABLE logs
+ADD COLUMN trace_id TEXT; -- Id of the OpenTelemetry trace the log happened in
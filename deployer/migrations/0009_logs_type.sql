@@ -0,0 +1,5 @@
+ALTER TABLE logs
+ADD COLUMN type TEXT NOT NULL DEFAULT 'Event'; -- Whether the log is an event, a state change or build output
+
+UPDATE logs SET type = 'State' WHERE fields = '"NEW STATE"';
+UPDATE logs SET type = 'BuildOutput' WHERE state = 'Building' AND type = 'Event' AND target = '';
@@ -0,0 +1,26 @@
+-- Logs can share a timestamp, so they are keyed on the order in which they were recorded instead.
+-- The columns of indexed log fields are added again on start.
+CREATE TABLE logs_sequenced (
+    id TEXT,                    -- The deployment that this log line pertains to.
+    timestamp INTEGER,          -- Unix epoch timestamp.
+    state TEXT,                 -- The state of the deployment at the time at which the log text was produced.
+    level TEXT,                 -- The log level
+    file TEXT,                  -- The file log took place in
+    line INTEGER,               -- The line log took place on
+    target TEXT,                -- The module log took place in
+    fields TEXT,                -- Log fields object.
+    trace_id TEXT,              -- Id of the OpenTelemetry trace the log happened in
+    type TEXT NOT NULL DEFAULT 'Event', -- Whether the log is an event, a state change or build output
+    sequence INTEGER NOT NULL,  -- Order in which the log was recorded, unlike the timestamp this is unique
+    PRIMARY KEY (id, sequence),
+    FOREIGN KEY(id) REFERENCES deployments(id)
+);
+
+INSERT INTO logs_sequenced (id, timestamp, state, level, file, line, target, fields, trace_id, type, sequence)
+SELECT id, timestamp, state, level, file, line, target, fields, trace_id, type, rowid FROM logs;
+
+DROP TABLE logs;
+
+ALTER TABLE logs_sequenced RENAME TO logs;
+
+CREATE INDEX IF NOT EXISTS logs_timestamp ON logs (id, timestamp);
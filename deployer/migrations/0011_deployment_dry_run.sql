@@ -0,0 +1,5 @@
+ALTER TABLE deployments
+ADD COLUMN dry_run BOOLEAN NOT NULL DEFAULT 0; -- Only built and tested, never run
+
+ALTER TABLE deployments
+ADD COLUMN tests_passed BOOLEAN; -- Outcome of the tests, once they ran
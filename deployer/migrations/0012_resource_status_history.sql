@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS resource_status_history (
+  deployment_id TEXT,    -- The deployment the resource is provisioned for
+  type TEXT,             -- Type of resource
+  status TEXT,           -- Provisioning status the resource moved to
+  timestamp DATETIME,    -- When the resource moved to this status
+  FOREIGN KEY(deployment_id) REFERENCES deployments(id)
+);
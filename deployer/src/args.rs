@@ -51,6 +51,59 @@ pub struct Args {
     #[clap(long, default_value = "/tmp")]
     pub artifacts_path: PathBuf,
 
+    /// Number of times to automatically retry a build that failed on a transient error,
+    /// like a network failure while downloading dependencies. Compile errors are never retried.
+    /// Projects which set their own build retries in the gateway use those instead
+    #[clap(long, default_value = "0")]
+    pub max_build_retries: u32,
+
+    /// Number of builds of this project allowed to run at once. Further deployments stay queued until
+    /// one finishes, so a project pushing many deployments cannot hold all the slots of the builders
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_concurrent_builds: u32,
+
+    /// S3 bucket to archive the logs of deployments to once they are done. Logs are not archived
+    /// when this is not set
+    #[clap(long)]
+    pub logs_bucket: Option<String>,
+
+    /// Only persist the deployment logs of these targets and their submodules. All targets are
+    /// persisted when this is not set
+    #[clap(long, value_delimiter = ',')]
+    pub log_targets_allow: Vec<String>,
+
+    /// Never persist the deployment logs of these targets and their submodules, like chatty
+    /// dependencies such as `h2` and `hyper`
+    #[clap(long, value_delimiter = ',')]
+    pub log_targets_deny: Vec<String>,
+
+    /// Fields of the deployment logs to store in their own indexed column, like `request.path`, so logs
+    /// can be filtered on them
+    #[clap(long, value_delimiter = ',')]
+    pub indexed_log_fields: Vec<String>,
+
+    /// Compress the fields of deployment logs in the database to save space
+    #[clap(long)]
+    pub compress_log_fields: bool,
+
+    /// Delete the logs of deployments once they are older than this many days. Logs are kept forever
+    /// when this is not set
+    #[clap(long)]
+    pub log_retention_days: Option<u32>,
+
+    /// Only keep this many of the latest logs of each deployment
+    #[clap(long)]
+    pub log_max_lines: Option<u32>,
+
+    /// Number of the latest logs of a stopped, completed or crashed deployment to keep when its logs
+    /// are older than the retention period
+    #[clap(long, default_value = "1000")]
+    pub log_terminal_tail: u32,
+
+    /// Values to always redact from the recorded deployment logs, on top of the secrets of the project
+    #[clap(long, value_delimiter = ',')]
+    pub redact: Vec<String>,
+
     /// Add an auth layer to deployer for local development
     #[arg(long)]
     pub local: bool,
@@ -20,15 +20,26 @@
 //! **Warning** Don't log out sensitive info in functions with these annotations
 
 use chrono::{DateTime, Utc};
+use opentelemetry::trace::TraceContextExt;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use shuttle_common::{tracing::JsonVisitor, ParseError, STATE_MESSAGE};
+use shuttle_common::{
+    models::deployment::RestartAttempt, tracing::JsonVisitor, ParseError, STATE_MESSAGE,
+};
 use shuttle_proto::runtime;
-use std::{convert::TryFrom, str::FromStr, time::SystemTime};
+use std::{
+    collections::BTreeSet,
+    convert::TryFrom,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
 use tracing::{field::Visit, span, warn, Metadata, Subscriber};
+use tracing_opentelemetry::OtelData;
 use tracing_subscriber::Layer;
 use uuid::Uuid;
 
-use crate::persistence::{self, DeploymentState, LogLevel, State};
+use crate::persistence::{self, DeploymentState, LogLevel, Secret, SecretGetter, State};
 
 /// Records logs for the deployment progress
 pub trait LogRecorder: Clone + Send + 'static {
@@ -62,15 +73,41 @@ pub struct Log {
     /// Extra structured log fields
     pub fields: serde_json::Value,
 
+    /// Id of the OpenTelemetry trace the log happened in
+    pub trace_id: Option<String>,
+
     pub r#type: LogType,
 }
 
+impl Log {
+    /// An event for the gateway having to bring the project of a deployment back up. These happen outside of
+    /// any state scope, so they are recorded directly to make a flapping deployment visible in its logs.
+    pub fn restart_attempt(id: Uuid, state: State, attempt: RestartAttempt) -> Self {
+        Self {
+            id,
+            state,
+            level: LogLevel::Warn,
+            timestamp: Utc::now(),
+            file: None,
+            line: None,
+            target: module_path!().to_string(),
+            fields: json!({
+                "message": attempt.to_string(),
+                "restart_kind": attempt.kind,
+                "attempt": attempt.attempt,
+            }),
+            trace_id: None,
+            r#type: LogType::Event,
+        }
+    }
+}
+
 impl From<Log> for persistence::Log {
     fn from(log: Log) -> Self {
         // Make sure state message is set for state logs
         // This is used to know when the end of the build logs has been reached
         let fields = match log.r#type {
-            LogType::Event => log.fields,
+            LogType::Event | LogType::BuildOutput => log.fields,
             LogType::State => json!(STATE_MESSAGE),
         };
 
@@ -83,6 +120,10 @@ impl From<Log> for persistence::Log {
             line: log.line,
             target: log.target,
             fields,
+            trace_id: log.trace_id,
+            r#type: log.r#type,
+            // Only known once the log is persisted
+            sequence: 0,
         }
     }
 }
@@ -98,6 +139,7 @@ impl From<Log> for shuttle_common::LogItem {
             line: log.line,
             target: log.target,
             fields: serde_json::to_vec(&log.fields).unwrap(),
+            trace_id: log.trace_id,
         }
     }
 }
@@ -118,7 +160,7 @@ impl TryFrom<runtime::LogItem> for Log {
     fn try_from(log: runtime::LogItem) -> Result<Self, Self::Error> {
         Ok(Self {
             id: Default::default(),
-            state: State::from_str(&log.state).unwrap_or_default(),
+            state: State::from_str(&log.state).map_err(|_| ParseError::State(log.state.clone()))?,
             level: runtime::LogLevel::from_i32(log.level)
                 .unwrap_or_default()
                 .into(),
@@ -127,6 +169,7 @@ impl TryFrom<runtime::LogItem> for Log {
             line: log.line,
             target: log.target,
             fields: serde_json::from_slice(&log.fields)?,
+            trace_id: None,
             r#type: LogType::Event,
         })
     }
@@ -144,10 +187,125 @@ impl From<runtime::LogLevel> for LogLevel {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
 pub enum LogType {
     Event,
     State,
+    /// Output of the compiler while the deployment is building
+    BuildOutput,
+}
+
+/// Decides which event targets are recorded by the [DeployLayer]. A prefix matches a target when
+/// it is the same module or one of its submodules, so `h2` matches `h2::codec` but not `h2o`.
+#[derive(Clone, Debug, Default)]
+pub struct TargetFilter {
+    /// Only record targets matching one of these prefixes. Everything is allowed when empty
+    pub allow: Vec<String>,
+
+    /// Never record targets matching one of these prefixes, even if they are allowed
+    pub deny: Vec<String>,
+}
+
+impl TargetFilter {
+    /// Whether events from `target` should be recorded
+    pub fn is_recorded(&self, target: &str) -> bool {
+        let matches = |prefix: &String| {
+            target
+                .strip_prefix(prefix.as_str())
+                .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+        };
+
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+}
+
+/// What redacted values are replaced with in recorded logs
+const REDACTED: &str = "***";
+
+/// Values shorter than this are too likely to appear by chance to be redacted, like a secret of `true`
+const MIN_REDACTED_LEN: usize = 4;
+
+/// Scrubs known secret values from the fields of recorded events, as a safety net against them leaking into
+/// the logs. The secrets of the project are learned as they are fetched through [SecretRedactor::secret_getter].
+#[derive(Clone, Debug, Default)]
+pub struct SecretRedactor {
+    values: Arc<RwLock<BTreeSet<String>>>,
+}
+
+impl SecretRedactor {
+    /// Create a redactor which always redacts the given `patterns`
+    pub fn new(patterns: impl IntoIterator<Item = String>) -> Self {
+        let redactor = Self::default();
+        redactor.add(patterns);
+
+        redactor
+    }
+
+    /// Start redacting the given values
+    pub fn add(&self, values: impl IntoIterator<Item = String>) {
+        self.values.write().unwrap().extend(
+            values
+                .into_iter()
+                .filter(|value| value.len() >= MIN_REDACTED_LEN),
+        );
+    }
+
+    /// Wrap a [SecretGetter] so every secret fetched through it gets redacted
+    pub fn secret_getter<SG: SecretGetter>(&self, inner: SG) -> RedactingSecretGetter<SG> {
+        RedactingSecretGetter {
+            inner,
+            redactor: self.clone(),
+        }
+    }
+
+    /// Replace the known values in all the strings of `value`
+    pub(crate) fn redact(&self, value: &mut serde_json::Value) {
+        let values = self.values.read().unwrap();
+
+        if !values.is_empty() {
+            redact_value(&values, value);
+        }
+    }
+}
+
+fn redact_value(values: &BTreeSet<String>, value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(string) => {
+            for secret in values {
+                if string.contains(secret.as_str()) {
+                    *string = string.replace(secret.as_str(), REDACTED);
+                }
+            }
+        }
+        serde_json::Value::Array(array) => array
+            .iter_mut()
+            .for_each(|value| redact_value(values, value)),
+        serde_json::Value::Object(map) => map
+            .values_mut()
+            .for_each(|value| redact_value(values, value)),
+        _ => {}
+    }
+}
+
+/// A [SecretGetter] which tells a [SecretRedactor] about every secret it gets
+#[derive(Clone)]
+pub struct RedactingSecretGetter<SG> {
+    inner: SG,
+    redactor: SecretRedactor,
+}
+
+#[async_trait::async_trait]
+impl<SG: SecretGetter> SecretGetter for RedactingSecretGetter<SG> {
+    type Err = SG::Err;
+
+    async fn get_secrets(&self, service_id: &Uuid) -> Result<Vec<Secret>, Self::Err> {
+        let secrets = self.inner.get_secrets(service_id).await?;
+        self.redactor
+            .add(secrets.iter().map(|secret| secret.value.clone()));
+
+        Ok(secrets)
+    }
 }
 
 /// Tracing subscriber layer which keeps track of a deployment's state
@@ -156,14 +314,28 @@ where
     R: LogRecorder + Send + Sync,
 {
     recorder: R,
+    filter: TargetFilter,
+    redactor: SecretRedactor,
 }
 
 impl<R> DeployLayer<R>
 where
     R: LogRecorder + Send + Sync,
 {
-    pub fn new(recorder: R) -> Self {
-        Self { recorder }
+    /// Record the state transitions of deployments and the events in their scope. Only events
+    /// with a target passing the `filter` are recorded; state transitions are always recorded.
+    pub fn new(recorder: R, filter: TargetFilter) -> Self {
+        Self {
+            recorder,
+            filter,
+            redactor: SecretRedactor::default(),
+        }
+    }
+
+    /// Redact the values known to the `redactor` from the fields of recorded events
+    pub fn with_redactor(mut self, redactor: SecretRedactor) -> Self {
+        self.redactor = redactor;
+        self
     }
 }
 
@@ -180,8 +352,9 @@ where
             return;
         };
 
-        // Find the first scope with the scope details containing the current state
-        for span in scope.from_root() {
+        // Find the closest scope with the scope details containing the current state, so events
+        // of a sub-state like provisioning are recorded with it
+        for span in scope {
             let extensions = span.extensions();
 
             if let Some(details) = extensions.get::<ScopeDetails>() {
@@ -189,6 +362,16 @@ where
 
                 event.record(&mut visitor);
                 let metadata = event.metadata();
+                let target = visitor
+                    .target
+                    .unwrap_or_else(|| metadata.target().to_string());
+
+                if !self.filter.is_recorded(&target) {
+                    break;
+                }
+
+                let mut fields = serde_json::Value::Object(visitor.fields);
+                self.redactor.redact(&mut fields);
 
                 self.recorder.record(Log {
                     id: details.id,
@@ -197,10 +380,9 @@ where
                     timestamp: Utc::now(),
                     file: visitor.file.or_else(|| metadata.file().map(str::to_string)),
                     line: visitor.line.or_else(|| metadata.line()),
-                    target: visitor
-                        .target
-                        .unwrap_or_else(|| metadata.target().to_string()),
-                    fields: serde_json::Value::Object(visitor.fields),
+                    target,
+                    fields,
+                    trace_id: extensions.get::<OtelData>().and_then(trace_id),
                     r#type: LogType::Event,
                 });
                 break;
@@ -223,13 +405,21 @@ where
 
         attrs.record(&mut visitor);
 
-        let details = visitor.details;
-
-        if details.id.is_nil() {
+        if visitor.id.is_nil() {
             warn!("scope details does not have a valid id");
             return;
         }
 
+        let Some(state) = visitor.state else {
+            warn!("scope details does not have a valid state");
+            return;
+        };
+
+        let details = ScopeDetails {
+            id: visitor.id,
+            state,
+        };
+
         // Safe to unwrap since this is the `on_new_span` method
         let span = ctx.span(id).unwrap();
         let mut extensions = span.extensions_mut();
@@ -244,6 +434,7 @@ where
             line: metadata.line(),
             target: metadata.target().to_string(),
             fields: Default::default(),
+            trace_id: None,
             r#type: LogType::State,
         });
 
@@ -251,6 +442,21 @@ where
     }
 }
 
+/// Id of the OpenTelemetry trace a span is part of. A span takes the trace of its parent context, which can be
+/// set after it was created to continue a trace from another service, or else starts a new trace.
+fn trace_id(otel_data: &OtelData) -> Option<String> {
+    let parent = otel_data.parent_cx.span();
+    let parent = parent.span_context();
+
+    let trace_id = if parent.is_valid() {
+        parent.trace_id()
+    } else {
+        otel_data.builder.trace_id?
+    };
+
+    Some(trace_id.to_string())
+}
+
 /// Used to keep track of the current state a deployment scope is in
 #[derive(Debug, Default)]
 struct ScopeDetails {
@@ -273,7 +479,8 @@ impl From<&tracing::Level> for LogLevel {
 /// This visitor is meant to extract the `ScopeDetails` for any scope with `name` and `status` fields
 #[derive(Default)]
 struct NewStateVisitor {
-    details: ScopeDetails,
+    id: Uuid,
+    state: Option<State>,
 }
 
 impl NewStateVisitor {
@@ -293,9 +500,9 @@ impl NewStateVisitor {
 impl Visit for NewStateVisitor {
     fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
         if field.name() == Self::STATE_IDENT {
-            self.details.state = State::from_str(&format!("{value:?}")).unwrap_or_default();
+            self.state = State::from_str(&format!("{value:?}")).ok();
         } else if field.name() == Self::ID_IDENT {
-            self.details.id = Uuid::try_parse(&format!("{value:?}")).unwrap_or_default();
+            self.id = Uuid::try_parse(&format!("{value:?}")).unwrap_or_default();
         }
     }
 }
@@ -303,6 +510,7 @@ impl Visit for NewStateVisitor {
 #[cfg(test)]
 mod tests {
     use std::{
+        collections::HashMap,
         fs::read_dir,
         net::{Ipv4Addr, SocketAddr},
         path::PathBuf,
@@ -316,9 +524,11 @@ mod tests {
     };
     use async_trait::async_trait;
     use axum::body::Bytes;
+    use chrono::Utc;
     use ctor::ctor;
     use flate2::{write::GzEncoder, Compression};
     use portpicker::pick_unused_port;
+    use serde_json::json;
     use shuttle_proto::provisioner::{
         provisioner_server::{Provisioner, ProvisionerServer},
         DatabaseDeletionResponse, DatabaseRequest, DatabaseResponse, Ping, Pong,
@@ -331,13 +541,22 @@ mod tests {
 
     use crate::{
         deployment::{
-            deploy_layer::LogType, gateway_client::BuildQueueClient, ActiveDeploymentsGetter,
-            Built, DeploymentManager, Queued,
+            deploy_layer::LogType,
+            gateway_client::{BuildQueueClient, BuildSettings},
+            ActiveDeploymentsGetter, Built, DeploymentManager, Queued, Source,
         },
-        persistence::{Secret, SecretGetter, SecretRecorder, State},
+        persistence::{ActiveDeployment, Secret, SecretGetter, SecretRecorder, State},
     };
 
-    use super::{DeployLayer, Log, LogRecorder};
+    use opentelemetry::{
+        trace::{
+            SpanBuilder, SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+        },
+        Context,
+    };
+    use tracing_opentelemetry::OtelData;
+
+    use super::{DeployLayer, Log, LogRecorder, SecretRedactor, TargetFilter};
 
     #[ctor]
     static RECORDER: Arc<Mutex<RecorderMock>> = {
@@ -379,7 +598,10 @@ mod tests {
             .unwrap();
 
         tracing_subscriber::registry()
-            .with(DeployLayer::new(Arc::clone(&recorder)))
+            .with(DeployLayer::new(
+                Arc::clone(&recorder),
+                TargetFilter::default(),
+            ))
             .with(filter_layer)
             .with(fmt_layer)
             .init();
@@ -387,6 +609,10 @@ mod tests {
         recorder
     };
 
+    /// Toolchain info recorded for each deployment by [StubDeploymentUpdater]
+    #[ctor]
+    static TOOLCHAIN_INFOS: Mutex<HashMap<Uuid, String>> = Mutex::new(HashMap::new());
+
     #[derive(Clone)]
     struct RecorderMock {
         states: Arc<Mutex<Vec<StateLog>>>,
@@ -477,7 +703,13 @@ mod tests {
         let path = tmp_dir.into_path();
         let (tx, _rx) = crossbeam_channel::unbounded();
 
-        RuntimeManager::new(path, format!("http://{}", provisioner_addr), None, tx)
+        RuntimeManager::new(
+            path,
+            format!("http://{}", provisioner_addr),
+            None,
+            tx,
+            SecretRedactor::default(),
+        )
     }
 
     #[async_trait::async_trait]
@@ -514,6 +746,23 @@ mod tests {
         async fn set_is_next(&self, _id: &Uuid, _is_next: bool) -> Result<(), Self::Err> {
             Ok(())
         }
+
+        async fn set_toolchain_info(
+            &self,
+            id: &Uuid,
+            toolchain_info: &str,
+        ) -> Result<(), Self::Err> {
+            TOOLCHAIN_INFOS
+                .lock()
+                .unwrap()
+                .insert(*id, toolchain_info.to_string());
+
+            Ok(())
+        }
+
+        async fn set_tests_passed(&self, _id: &Uuid, _tests_passed: bool) -> Result<(), Self::Err> {
+            Ok(())
+        }
     }
 
     #[derive(Clone)]
@@ -529,6 +778,12 @@ mod tests {
         ) -> std::result::Result<Vec<Uuid>, Self::Err> {
             Ok(vec![])
         }
+
+        async fn get_all_active_deployments(
+            &self,
+        ) -> std::result::Result<Vec<ActiveDeployment>, Self::Err> {
+            Ok(vec![])
+        }
     }
 
     #[derive(Clone)]
@@ -539,8 +794,8 @@ mod tests {
         async fn get_slot(
             &self,
             _id: Uuid,
-        ) -> Result<bool, crate::deployment::gateway_client::Error> {
-            Ok(true)
+        ) -> Result<Option<BuildSettings>, crate::deployment::gateway_client::Error> {
+            Ok(Some(BuildSettings::default()))
         }
 
         async fn release_slot(
@@ -622,6 +877,10 @@ mod tests {
                     id,
                     state: State::Loading,
                 },
+                StateLog {
+                    id,
+                    state: State::Provisioning,
+                },
                 StateLog {
                     id,
                     state: State::Running,
@@ -637,6 +896,12 @@ mod tests {
             _ = test => {}
         };
 
+        let toolchain_info = TOOLCHAIN_INFOS.lock().unwrap().get(&id).cloned();
+        assert!(
+            toolchain_info.map_or(false, |info| !info.is_empty()),
+            "the toolchain of a completed build should be recorded"
+        );
+
         // Send kill signal
         deployment_manager.kill(id).await;
 
@@ -659,6 +924,10 @@ mod tests {
                     id,
                     state: State::Loading,
                 },
+                StateLog {
+                    id,
+                    state: State::Provisioning,
+                },
                 StateLog {
                     id,
                     state: State::Running,
@@ -706,6 +975,10 @@ mod tests {
                     id,
                     state: State::Loading,
                 },
+                StateLog {
+                    id,
+                    state: State::Provisioning,
+                },
                 StateLog {
                     id,
                     state: State::Running,
@@ -753,6 +1026,10 @@ mod tests {
                     id,
                     state: State::Loading,
                 },
+                StateLog {
+                    id,
+                    state: State::Provisioning,
+                },
                 StateLog {
                     id,
                     state: State::Running,
@@ -800,6 +1077,10 @@ mod tests {
                     id,
                     state: State::Loading,
                 },
+                StateLog {
+                    id,
+                    state: State::Provisioning,
+                },
                 StateLog {
                     id,
                     state: State::Crashed,
@@ -859,6 +1140,109 @@ mod tests {
         };
     }
 
+    #[test]
+    fn trace_id_of_span() {
+        let new_trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+        let new_trace = OtelData {
+            parent_cx: Context::new(),
+            builder: SpanBuilder::from_name("new").with_trace_id(new_trace_id),
+        };
+        assert_eq!(
+            super::trace_id(&new_trace),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string())
+        );
+
+        // A span continuing a trace from another service takes the id of that trace
+        let remote = SpanContext::new(
+            TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let continued = OtelData {
+            parent_cx: Context::new().with_remote_span_context(remote),
+            builder: SpanBuilder::from_name("continued").with_trace_id(new_trace_id),
+        };
+        assert_eq!(
+            super::trace_id(&continued),
+            Some("0af7651916cd43dd8448eb211c80319c".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn secret_redactor() {
+        #[derive(Clone)]
+        struct StubSecretGetter;
+
+        #[async_trait]
+        impl SecretGetter for StubSecretGetter {
+            type Err = std::io::Error;
+
+            async fn get_secrets(&self, service_id: &Uuid) -> Result<Vec<Secret>, Self::Err> {
+                Ok(["hunter42", "true"]
+                    .into_iter()
+                    .map(|value| Secret {
+                        service_id: *service_id,
+                        key: "KEY".to_string(),
+                        value: value.to_string(),
+                        last_update: Utc::now(),
+                        created_at: Utc::now(),
+                    })
+                    .collect())
+            }
+        }
+
+        let redactor = SecretRedactor::new(["sk_live".to_string()]);
+        redactor
+            .secret_getter(StubSecretGetter)
+            .get_secrets(&Uuid::new_v4())
+            .await
+            .unwrap();
+
+        let mut fields = json!({
+            "message": "connecting with hunter42 and sk_live_123",
+            "nested": ["hunter42"],
+            "enabled": "true",
+            "count": 42,
+        });
+        redactor.redact(&mut fields);
+
+        assert_eq!(
+            fields,
+            json!({
+                "message": "connecting with *** and ***_123",
+                "nested": ["***"],
+                "enabled": "true",
+                "count": 42,
+            })
+        );
+    }
+
+    #[test]
+    fn target_filter() {
+        let everything = TargetFilter::default();
+        assert!(everything.is_recorded("hyper::proto"));
+        assert!(everything.is_recorded("shuttle_deployer"));
+
+        let filter = TargetFilter {
+            allow: Vec::new(),
+            deny: vec!["h2".to_string(), "hyper".to_string()],
+        };
+        assert!(!filter.is_recorded("h2"));
+        assert!(!filter.is_recorded("hyper::proto::h1"));
+        assert!(filter.is_recorded("h2o"));
+        assert!(filter.is_recorded("shuttle_deployer::deployment"));
+
+        let filter = TargetFilter {
+            allow: vec!["shuttle_deployer".to_string()],
+            deny: vec!["shuttle_deployer::proxy".to_string()],
+        };
+        assert!(filter.is_recorded("shuttle_deployer::deployment::run"));
+        assert!(!filter.is_recorded("shuttle_deployer::proxy"));
+        assert!(!filter.is_recorded("hyper"));
+    }
+
     #[tokio::test]
     async fn scope_with_nil_id() {
         let deployment_manager = get_deployment_manager();
@@ -869,8 +1253,9 @@ mod tests {
                 id,
                 service_name: "nil_id".to_string(),
                 service_id: Uuid::new_v4(),
-                data: Bytes::from("violets are red").to_vec(),
+                source: Source::Tarball(Bytes::from("violets are red").to_vec()),
                 will_run_tests: false,
+                dry_run: false,
                 tracing_context: Default::default(),
                 claim: Default::default(),
             })
@@ -928,8 +1313,9 @@ mod tests {
             id: Uuid::new_v4(),
             service_name: format!("deploy-layer-{name}"),
             service_id: Uuid::new_v4(),
-            data: bytes,
+            source: Source::Tarball(bytes),
             will_run_tests: false,
+            dry_run: false,
             tracing_context: Default::default(),
             claim: Default::default(),
         }
@@ -3,6 +3,7 @@ use opentelemetry::global;
 use opentelemetry_http::HeaderInjector;
 use serde::{de::DeserializeOwned, Serialize};
 use shuttle_common::models::stats;
+use shuttle_common::project::ProjectName;
 use thiserror::Error;
 use tracing::{trace, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
@@ -18,11 +19,18 @@ pub enum Error {
     Http(#[from] hyper::http::Error),
 }
 
+/// Settings of the project which apply to the build holding a slot
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BuildSettings {
+    /// Retries of a build failing on a transient error. The deployer's default is used when missing
+    pub max_build_retries: Option<u32>,
+}
+
 /// A client that can communicate with the build queue
 #[async_trait::async_trait]
 pub trait BuildQueueClient: Clone + Send + Sync + 'static {
-    /// Try to get a build slot. A false returned value means that the spot could not be acquire
-    async fn get_slot(&self, id: Uuid) -> Result<bool, Error>;
+    /// Try to get a build slot. A `None` returned value means that the slot could not be acquired
+    async fn get_slot(&self, id: Uuid) -> Result<Option<BuildSettings>, Error>;
 
     /// Release a build slot that was previously acquired
     async fn release_slot(&self, id: Uuid) -> Result<(), Error>;
@@ -33,13 +41,16 @@ pub trait BuildQueueClient: Clone + Send + Sync + 'static {
 pub struct GatewayClient {
     client: Client<HttpConnector>,
     base: Uri,
+    /// Project of this deployer, for the gateway to look up its settings
+    project_name: ProjectName,
 }
 
 impl GatewayClient {
-    pub fn new(uri: Uri) -> Self {
+    pub fn new(uri: Uri, project_name: ProjectName) -> Self {
         Self {
             client: Client::new(),
             base: uri,
+            project_name,
         }
     }
 
@@ -101,15 +112,23 @@ impl GatewayClient {
 
 #[async_trait::async_trait]
 impl BuildQueueClient for GatewayClient {
-    async fn get_slot(&self, id: Uuid) -> Result<bool, Error> {
-        let body = stats::LoadRequest { id };
+    async fn get_slot(&self, id: Uuid) -> Result<Option<BuildSettings>, Error> {
+        let body = stats::LoadRequest {
+            id,
+            project_name: Some(self.project_name.to_string()),
+        };
         let load: stats::LoadResponse = self.post("stats/load", Some(body)).await?;
 
-        Ok(load.has_capacity)
+        Ok(load.has_capacity.then_some(BuildSettings {
+            max_build_retries: load.max_build_retries,
+        }))
     }
 
     async fn release_slot(&self, id: Uuid) -> Result<(), Error> {
-        let body = stats::LoadRequest { id };
+        let body = stats::LoadRequest {
+            id,
+            project_name: None,
+        };
         let _load: stats::LoadResponse = self.delete("stats/load", Some(body)).await?;
 
         Ok(())
@@ -5,7 +5,7 @@ mod run;
 
 use std::{path::PathBuf, sync::Arc};
 
-pub use queue::Queued;
+pub use queue::{check_git_source_host, validate_archive, validate_git_source, Queued, Source};
 pub use run::{ActiveDeploymentsGetter, Built};
 use shuttle_common::storage_manager::ArtifactsStorageManager;
 use tracing::{instrument, Span};
@@ -36,6 +36,8 @@ pub struct DeploymentManagerBuilder<LR, SR, ADG, DU, SG, RM, QC> {
     secret_getter: Option<SG>,
     resource_manager: Option<RM>,
     queue_client: Option<QC>,
+    max_build_retries: u32,
+    max_concurrent_builds: usize,
 }
 
 impl<LR, SR, ADG, DU, SG, RM, QC> DeploymentManagerBuilder<LR, SR, ADG, DU, SG, RM, QC>
@@ -102,6 +104,21 @@ where
         self
     }
 
+    /// Number of times to retry a build that failed on a transient error, like a failed download
+    pub fn max_build_retries(mut self, max_build_retries: u32) -> Self {
+        self.max_build_retries = max_build_retries;
+
+        self
+    }
+
+    /// Number of builds allowed to run at once, defaults to one. Deployments pushed past this stay
+    /// queued until a build finishes
+    pub fn max_concurrent_builds(mut self, max_concurrent_builds: usize) -> Self {
+        self.max_concurrent_builds = max_concurrent_builds;
+
+        self
+    }
+
     /// Creates two Tokio tasks, one for building queued services, the other for
     /// executing/deploying built services. Two multi-producer, single consumer
     /// channels are also created which are for moving on-going service
@@ -138,6 +155,8 @@ where
             secret_recorder,
             storage_manager.clone(),
             queue_client,
+            self.max_build_retries,
+            self.max_concurrent_builds,
         ));
         set.spawn(run::task(
             run_recv,
@@ -197,6 +216,8 @@ impl DeploymentManager {
             secret_getter: None,
             resource_manager: None,
             queue_client: None,
+            max_build_retries: 0,
+            max_concurrent_builds: 1,
         }
     }
 
@@ -219,6 +240,12 @@ impl DeploymentManager {
         self.runtime_manager.lock().await.kill(&id).await;
     }
 
+    /// Ids of the deployments this deployer actually has a runtime up for, regardless of what
+    /// their state in the database claims
+    pub async fn running_deployment_ids(&self) -> Vec<Uuid> {
+        self.runtime_manager.lock().await.deployment_ids()
+    }
+
     pub fn storage_manager(&self) -> ArtifactsStorageManager {
         self.storage_manager.clone()
     }
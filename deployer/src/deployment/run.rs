@@ -30,7 +30,7 @@ use uuid::Uuid;
 use super::{RunReceiver, State};
 use crate::{
     error::{Error, Result},
-    persistence::{DeploymentUpdater, Resource, ResourceManager, SecretGetter},
+    persistence::{ActiveDeployment, DeploymentUpdater, Resource, ResourceManager, SecretGetter},
     RuntimeManager,
 };
 
@@ -197,6 +197,11 @@ pub trait ActiveDeploymentsGetter: Clone + Send + Sync + 'static {
         &self,
         service_id: &Uuid,
     ) -> std::result::Result<Vec<Uuid>, Self::Err>;
+
+    /// Get the loading and running deployments of all services
+    async fn get_all_active_deployments(
+        &self,
+    ) -> std::result::Result<Vec<ActiveDeployment>, Self::Err>;
 }
 
 #[derive(Clone, Debug)]
@@ -252,10 +257,15 @@ impl Built {
             .await
             .map_err(Error::Runtime)?;
 
+        // The old deployment is stopped before the new one is loaded, so requests fail in between.
+        // The gateway cannot hold them on the old runtime meanwhile: both runtimes live in this
+        // container, behind the one address it routes to. Switching over without downtime would
+        // have to happen here.
         kill_old_deployments.await?;
 
         // Execute loaded service
         load(
+            self.id,
             self.service_name.clone(),
             self.service_id,
             executable_path.clone(),
@@ -279,7 +289,11 @@ impl Built {
     }
 }
 
+/// Load the service in its runtime, which provisions the resources it requests and logs when each
+/// of them was requested and became ready. This is its own state so those logs are grouped with it.
+#[instrument(skip(service_name, service_id, executable_path, secret_getter, resource_manager, runtime_client, claim), fields(id = %id, state = %State::Provisioning))]
 async fn load(
+    id: Uuid,
     service_name: String,
     service_id: Uuid,
     executable_path: PathBuf,
@@ -340,6 +354,15 @@ async fn load(
 
             for resource in response.resources {
                 let resource: resource::Response = serde_json::from_slice(&resource).unwrap();
+
+                if let Err(message) = resource.validate() {
+                    error!(resource_type = %resource.r#type, %message, "invalid resource");
+                    return Err(Error::Load(format!(
+                        "invalid {} resource: {message}",
+                        resource.r#type
+                    )));
+                }
+
                 let resource = Resource {
                     service_id,
                     r#type: resource.r#type.into(),
@@ -450,7 +473,7 @@ mod tests {
 
     use crate::{
         persistence::{DeploymentUpdater, Resource, ResourceManager, Secret, SecretGetter},
-        RuntimeManager,
+        RuntimeManager, SecretRedactor,
     };
 
     use super::Built;
@@ -517,7 +540,13 @@ mod tests {
             }
         });
 
-        RuntimeManager::new(path, format!("http://{}", provisioner_addr), None, tx)
+        RuntimeManager::new(
+            path,
+            format!("http://{}", provisioner_addr),
+            None,
+            tx,
+            SecretRedactor::default(),
+        )
     }
 
     #[derive(Clone)]
@@ -561,6 +590,18 @@ mod tests {
         async fn set_is_next(&self, _id: &Uuid, _is_next: bool) -> Result<(), Self::Err> {
             Ok(())
         }
+
+        async fn set_toolchain_info(
+            &self,
+            _id: &Uuid,
+            _toolchain_info: &str,
+        ) -> Result<(), Self::Err> {
+            Ok(())
+        }
+
+        async fn set_tests_passed(&self, _id: &Uuid, _tests_passed: bool) -> Result<(), Self::Err> {
+            Ok(())
+        }
     }
 
     // This test uses the kill signal to make sure a service does stop when asked to
@@ -10,6 +10,8 @@ pub enum Error {
     InputOutput(#[from] io::Error),
     #[error("Build error: {0}")]
     Build(#[source] Box<dyn StdError + Send>),
+    #[error("Build error on a transient failure: {0}")]
+    TransientBuild(#[source] Box<dyn StdError + Send>),
     #[error("Load error: {0}")]
     Load(String),
     #[error("Prepare to run error: {0}")]
@@ -34,6 +36,24 @@ pub enum Error {
     Start(String),
 }
 
+/// Whether a failure is worth retrying
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The failure came from something outside of the user's code, like a network hiccup
+    Transient,
+    /// Retrying will fail the same way, like a compile error
+    Permanent,
+}
+
+impl Error {
+    pub fn failure_kind(&self) -> FailureKind {
+        match self {
+            Error::TransientBuild(_) | Error::GatewayClient(_) => FailureKind::Transient,
+            _ => FailureKind::Permanent,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum TestError {
     #[error("The deployment's tests failed.")]
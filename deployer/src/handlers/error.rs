@@ -23,6 +23,10 @@ pub enum Error {
     },
     #[error("{0}, try running `cargo shuttle deploy`")]
     NotFound(String),
+    #[error("Invalid deployment archive: {0}")]
+    InvalidArchive(String),
+    #[error("Invalid git source: {0}")]
+    InvalidGitSource(String),
     #[error("Custom error: {0}")]
     Custom(#[from] anyhow::Error),
 }
@@ -46,6 +50,10 @@ impl IntoResponse for Error {
 
         let code = match self {
             Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::InvalidArchive(_) | Error::InvalidGitSource(_) => StatusCode::BAD_REQUEST,
+            Error::Persistence(crate::persistence::PersistenceError::UnindexedLogField(_)) => {
+                StatusCode::BAD_REQUEST
+            }
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -1,9 +1,15 @@
 mod error;
 
-use crate::deployment::{Built, DeploymentManager, Queued};
-use crate::persistence::{Deployment, Log, Persistence, ResourceManager, SecretGetter, State};
+use crate::deployment::deploy_layer::{self, LogRecorder, LogType};
+use crate::deployment::{
+    check_git_source_host, validate_archive, validate_git_source, ActiveDeploymentsGetter, Built,
+    DeploymentManager, Queued, Source,
+};
+use crate::metrics::RequestMetrics;
+use crate::persistence::{Deployment, Persistence, ResourceManager, SecretGetter, State};
 use async_trait::async_trait;
 use axum::extract::{
+    rejection::BytesRejection,
     ws::{self, WebSocket},
     FromRequest,
 };
@@ -11,12 +17,14 @@ use axum::extract::{DefaultBodyLimit, Extension, Path, Query};
 use axum::handler::Handler;
 use axum::headers::HeaderMapExt;
 use axum::middleware::{self, from_extractor};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post, Router};
 use axum::Json;
 use bytes::Bytes;
-use chrono::{TimeZone, Utc};
+use chrono::Utc;
 use fqdn::FQDN;
-use hyper::{Request, StatusCode, Uri};
+use hyper::header::HeaderValue;
+use hyper::{HeaderMap, Request, StatusCode, Uri};
 use serde::{de::DeserializeOwned, Deserialize};
 use shuttle_common::backends::auth::{
     AdminSecretLayer, AuthPublicKey, JwtAuthenticationLayer, ScopedLayer,
@@ -25,8 +33,9 @@ use shuttle_common::backends::headers::XShuttleAccountName;
 use shuttle_common::backends::metrics::{Metrics, TraceLayer};
 use shuttle_common::claims::{Claim, Scope};
 use shuttle_common::models::deployment::{
-    DeploymentRequest, CREATE_SERVICE_BODY_LIMIT, GIT_STRINGS_MAX_LENGTH,
+    DeploymentRequest, RestartAttempt, CREATE_SERVICE_BODY_LIMIT, GIT_STRINGS_MAX_LENGTH,
 };
+use shuttle_common::models::error::ApiError;
 use shuttle_common::models::secret;
 use shuttle_common::project::ProjectName;
 use shuttle_common::storage_manager::StorageManager;
@@ -51,7 +60,12 @@ mod project;
         stop_service,
         get_service_resources,
         get_deployments,
+        get_active_deployments,
         get_deployment,
+        get_deployment_metrics,
+        post_restart_attempts,
+        get_deployment_resource_statuses,
+        get_deployment_resource_timeline,
         delete_deployment,
         get_logs_subscribe,
         get_logs,
@@ -62,12 +76,20 @@ mod project;
         shuttle_common::models::service::Summary,
         shuttle_common::resource::Response,
         shuttle_common::resource::Type,
+        shuttle_common::resource::StatusResponse,
+        shuttle_common::resource::ProvisioningStatus,
+        shuttle_common::models::resource::StatusChange,
         shuttle_common::database::Type,
         shuttle_common::database::AwsRdsEngine,
         shuttle_common::database::SharedEngine,
+        shuttle_common::queue::Type,
         shuttle_common::models::service::Response,
         shuttle_common::models::secret::Response,
         shuttle_common::models::deployment::Response,
+        shuttle_common::models::deployment::ActiveResponse,
+        shuttle_common::models::deployment::Metrics,
+        shuttle_common::models::deployment::RestartAttempt,
+        shuttle_common::models::deployment::RestartKind,
         shuttle_common::log::Item,
         shuttle_common::models::secret::Response,
         shuttle_common::log::Level,
@@ -84,6 +106,35 @@ pub struct PaginationDetails {
     pub limit: Option<u32>,
 }
 
+/// Header holding the cursor to pass as `after_cursor` to fetch the next page of logs
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// Header holding the total number of items across all pages
+pub const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+#[derive(Debug, Clone, Copy, Deserialize, IntoParams)]
+pub struct LogsCursor {
+    /// Only return logs after this cursor, as returned in the `x-next-cursor` header.
+    pub after_cursor: Option<i64>,
+    /// Maximum number of logs to return.
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+pub struct LogsFieldFilter {
+    /// Only return logs with this field set to `value`. Must be one of the indexed log fields.
+    pub field: Option<String>,
+    /// Value the `field` has to have.
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, IntoParams)]
+pub struct LogsTypeFilter {
+    /// Only return logs of this type: `event`, `state` or `build_output`.
+    #[param(value_type = Option<String>)]
+    pub log_type: Option<LogType>,
+}
+
 #[derive(Clone)]
 pub struct RouterBuilder {
     router: Router,
@@ -95,6 +146,7 @@ impl RouterBuilder {
     pub fn new(
         persistence: Persistence,
         deployment_manager: DeploymentManager,
+        request_metrics: RequestMetrics,
         proxy_fqdn: FQDN,
         project_name: ProjectName,
         auth_uri: Uri,
@@ -129,12 +181,34 @@ impl RouterBuilder {
                 "/projects/:project_name/deployments",
                 get(get_deployments).layer(ScopedLayer::new(vec![Scope::Service])),
             )
+            .route(
+                "/projects/:project_name/active-deployments",
+                get(get_active_deployments.layer(ScopedLayer::new(vec![Scope::Admin]))),
+            )
             .route(
                 "/projects/:project_name/deployments/:deployment_id",
                 get(get_deployment.layer(ScopedLayer::new(vec![Scope::Deployment])))
                     .delete(delete_deployment.layer(ScopedLayer::new(vec![Scope::DeploymentPush])))
                     .put(start_deployment.layer(ScopedLayer::new(vec![Scope::DeploymentPush]))),
             )
+            .route(
+                "/projects/:project_name/deployments/:deployment_id/metrics",
+                get(get_deployment_metrics.layer(ScopedLayer::new(vec![Scope::Deployment]))),
+            )
+            .route(
+                "/projects/:project_name/deployments/:deployment_id/restart-attempts",
+                post(post_restart_attempts.layer(ScopedLayer::new(vec![Scope::DeploymentPush]))),
+            )
+            .route(
+                "/projects/:project_name/deployments/:deployment_id/resources",
+                get(get_deployment_resource_statuses
+                    .layer(ScopedLayer::new(vec![Scope::Resources]))),
+            )
+            .route(
+                "/projects/:project_name/deployments/:deployment_id/resources/timeline",
+                get(get_deployment_resource_timeline
+                    .layer(ScopedLayer::new(vec![Scope::Resources]))),
+            )
             .route(
                 "/projects/:project_name/ws/deployments/:deployment_id/logs",
                 get(get_logs_subscribe.layer(ScopedLayer::new(vec![Scope::Logs]))),
@@ -153,6 +227,7 @@ impl RouterBuilder {
             )
             .layer(Extension(persistence))
             .layer(Extension(deployment_manager))
+            .layer(Extension(request_metrics))
             .layer(Extension(proxy_fqdn))
             .layer(JwtAuthenticationLayer::new(AuthPublicKey::new(
                 auth_uri.clone(),
@@ -291,13 +366,28 @@ pub async fn get_service_resources(
     Path((project_name, service_name)): Path<(String, String)>,
 ) -> Result<Json<Vec<shuttle_common::resource::Response>>> {
     if let Some(service) = persistence.get_service_by_name(&service_name).await? {
-        let resources = persistence
+        let mut resources: Vec<shuttle_common::resource::Response> = persistence
             .get_resources(&service.id)
             .await?
             .into_iter()
             .map(Into::into)
             .collect();
 
+        // Replace the secret values with when they were set
+        for resource in resources.iter_mut() {
+            if resource.r#type == shuttle_common::resource::Type::Secrets {
+                let data = secret::SecretsData {
+                    secrets: persistence
+                        .get_secrets(&service.id)
+                        .await?
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                };
+                resource.data = serde_json::to_value(data).expect("to serialize secrets data");
+            }
+        }
+
         Ok(Json(resources))
     } else {
         Err(Error::NotFound("service not found".to_string()))
@@ -310,6 +400,7 @@ pub async fn get_service_resources(
     path = "/projects/{project_name}/services/{service_name}",
     responses(
         (status = 200, description = "Creates a specific service owned by a specific project.", body = shuttle_common::models::deployment::Response),
+        (status = 400, description = "The deployment archive or git source is malformed.", body = String),
         (status = 500, description = "Database or streaming error.", body = String),
         (status = 404, description = "Record could not be found.", body = String),
     ),
@@ -325,6 +416,20 @@ pub async fn create_service(
     Path((project_name, service_name)): Path<(String, String)>,
     Rmp(deployment_req): Rmp<DeploymentRequest>,
 ) -> Result<Json<shuttle_common::models::deployment::Response>> {
+    let source = match deployment_req.git_source {
+        Some(git_source) => {
+            validate_git_source(&git_source).map_err(Error::InvalidGitSource)?;
+            check_git_source_host(&git_source)
+                .await
+                .map_err(Error::InvalidGitSource)?;
+            Source::Git(git_source)
+        }
+        None => {
+            validate_archive(&deployment_req.data).map_err(Error::InvalidArchive)?;
+            Source::Tarball(deployment_req.data)
+        }
+    };
+
     let service = persistence.get_or_create_service(&service_name).await?;
     let id = Uuid::new_v4();
 
@@ -345,6 +450,10 @@ pub async fn create_service(
             .git_branch
             .map(|s| s.chars().take(GIT_STRINGS_MAX_LENGTH).collect()),
         git_dirty: deployment_req.git_dirty,
+        logs_object_key: None,
+        toolchain_info: None,
+        dry_run: deployment_req.dry_run,
+        tests_passed: None,
     };
 
     persistence.insert_deployment(deployment.clone()).await?;
@@ -353,8 +462,9 @@ pub async fn create_service(
         id,
         service_name: service.name,
         service_id: service.id,
-        data: deployment_req.data,
+        source,
         will_run_tests: !deployment_req.no_test,
+        dry_run: deployment_req.dry_run,
         tracing_context: Default::default(),
         claim,
     };
@@ -410,7 +520,7 @@ pub async fn stop_service(
     get,
     path = "/projects/{project_name}/deployments",
     responses(
-        (status = 200, description = "Gets deployments information associated to a specific project.", body = shuttle_common::models::deployment::Response),
+        (status = 200, description = "Gets deployments information associated to a specific project. The total number of deployments is in the `x-total-count` header.", body = shuttle_common::models::deployment::Response),
         (status = 500, description = "Database error.", body = String),
         (status = 404, description = "Record could not be found.", body = String),
     ),
@@ -423,7 +533,10 @@ pub async fn get_deployments(
     Extension(persistence): Extension<Persistence>,
     Path(project_name): Path<String>,
     Query(PaginationDetails { page, limit }): Query<PaginationDetails>,
-) -> Result<Json<Vec<shuttle_common::models::deployment::Response>>> {
+) -> Result<(
+    HeaderMap,
+    Json<Vec<shuttle_common::models::deployment::Response>>,
+)> {
     if let Some(service) = persistence.get_service_by_name(&project_name).await? {
         let limit = limit.unwrap_or(u32::MAX);
         let page = page.unwrap_or(0);
@@ -434,12 +547,54 @@ pub async fn get_deployments(
             .map(Into::into)
             .collect();
 
-        Ok(Json(deployments))
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            TOTAL_COUNT_HEADER,
+            HeaderValue::from(persistence.count_deployments(&service.id).await?),
+        );
+
+        Ok((headers, Json(deployments)))
     } else {
         Err(Error::NotFound("service not found".to_string()))
     }
 }
 
+#[instrument(skip_all, fields(%project_name))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/active-deployments",
+    responses(
+        (status = 200, description = "Gets the loading and running deployments of all services, and whether a runtime is actually up for them.", body = [shuttle_common::models::deployment::ActiveResponse]),
+        (status = 500, description = "Database error.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the deployments."),
+    )
+)]
+pub async fn get_active_deployments(
+    Extension(persistence): Extension<Persistence>,
+    Extension(deployment_manager): Extension<DeploymentManager>,
+    Path(project_name): Path<String>,
+) -> Result<Json<Vec<shuttle_common::models::deployment::ActiveResponse>>> {
+    let running_ids = deployment_manager.running_deployment_ids().await;
+    let deployments = persistence
+        .get_all_active_deployments()
+        .await?
+        .into_iter()
+        .map(
+            |deployment| shuttle_common::models::deployment::ActiveResponse {
+                id: deployment.id,
+                service_id: deployment.service_id,
+                service_name: deployment.service_name,
+                state: deployment.state.into(),
+                has_runtime: running_ids.contains(&deployment.id),
+            },
+        )
+        .collect();
+
+    Ok(Json(deployments))
+}
+
 #[instrument(skip_all, fields(%project_name, %deployment_id))]
 #[instrument(skip(persistence))]
 #[utoipa::path(
@@ -466,6 +621,123 @@ pub async fn get_deployment(
     }
 }
 
+#[instrument(skip_all, fields(%project_name, %deployment_id))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/deployments/{deployment_id}/metrics",
+    responses(
+        (status = 200, description = "Gets the request metrics of a deployment.", body = shuttle_common::models::deployment::Metrics),
+        (status = 500, description = "Database error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the deployment."),
+        ("deployment_id" = String, Path, description = "The deployment id in uuid format.")
+    )
+)]
+pub async fn get_deployment_metrics(
+    Extension(persistence): Extension<Persistence>,
+    Extension(request_metrics): Extension<RequestMetrics>,
+    Path((project_name, deployment_id)): Path<(String, Uuid)>,
+) -> Result<Json<shuttle_common::models::deployment::Metrics>> {
+    if persistence.get_deployment(&deployment_id).await?.is_some() {
+        Ok(Json(request_metrics.get(&deployment_id)))
+    } else {
+        Err(Error::NotFound("deployment not found".to_string()))
+    }
+}
+
+#[instrument(skip_all, fields(%project_name, %deployment_id))]
+#[utoipa::path(
+    post,
+    path = "/projects/{project_name}/deployments/{deployment_id}/restart-attempts",
+    request_body = [shuttle_common::models::deployment::RestartAttempt],
+    responses(
+        (status = 200, description = "Recorded the attempts in the logs of the deployment."),
+        (status = 500, description = "Database error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the deployment."),
+        ("deployment_id" = String, Path, description = "The deployment id in uuid format.")
+    )
+)]
+pub async fn post_restart_attempts(
+    Extension(persistence): Extension<Persistence>,
+    Path((project_name, deployment_id)): Path<(String, Uuid)>,
+    Json(attempts): Json<Vec<RestartAttempt>>,
+) -> Result<()> {
+    if let Some(deployment) = persistence.get_deployment(&deployment_id).await? {
+        for attempt in attempts {
+            persistence.record(deploy_layer::Log::restart_attempt(
+                deployment.id,
+                deployment.state,
+                attempt,
+            ));
+        }
+
+        Ok(())
+    } else {
+        Err(Error::NotFound("deployment not found".to_string()))
+    }
+}
+
+#[instrument(skip_all, fields(%project_name, %deployment_id))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/deployments/{deployment_id}/resources",
+    responses(
+        (status = 200, description = "Gets the provisioning status of the resources of a deployment.", body = [shuttle_common::resource::StatusResponse]),
+        (status = 500, description = "Database error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the deployment."),
+        ("deployment_id" = String, Path, description = "The deployment id in uuid format.")
+    )
+)]
+pub async fn get_deployment_resource_statuses(
+    Extension(persistence): Extension<Persistence>,
+    Path((project_name, deployment_id)): Path<(String, Uuid)>,
+) -> Result<Json<Vec<shuttle_common::resource::StatusResponse>>> {
+    if let Some(deployment) = persistence.get_deployment(&deployment_id).await? {
+        let statuses = persistence
+            .get_resource_statuses(&deployment_id, &deployment.service_id)
+            .await?;
+
+        Ok(Json(statuses))
+    } else {
+        Err(Error::NotFound("deployment not found".to_string()))
+    }
+}
+
+#[instrument(skip_all, fields(%project_name, %deployment_id))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/deployments/{deployment_id}/resources/timeline",
+    responses(
+        (status = 200, description = "Gets every change of the provisioning status of the resources of a deployment.", body = [shuttle_common::models::resource::StatusChange]),
+        (status = 500, description = "Database error.", body = String),
+        (status = 404, description = "Record could not be found.", body = String),
+    ),
+    params(
+        ("project_name" = String, Path, description = "Name of the project that owns the deployment."),
+        ("deployment_id" = String, Path, description = "The deployment id in uuid format.")
+    )
+)]
+pub async fn get_deployment_resource_timeline(
+    Extension(persistence): Extension<Persistence>,
+    Path((project_name, deployment_id)): Path<(String, Uuid)>,
+) -> Result<Json<Vec<shuttle_common::models::resource::StatusChange>>> {
+    if persistence.get_deployment(&deployment_id).await?.is_some() {
+        let timeline = persistence.get_resource_timeline(&deployment_id).await?;
+
+        Ok(Json(timeline))
+    } else {
+        Err(Error::NotFound("deployment not found".to_string()))
+    }
+}
+
 #[instrument(skip_all, fields(%project_name, %deployment_id))]
 #[utoipa::path(
     delete,
@@ -542,21 +814,42 @@ pub async fn start_deployment(
     ),
     params(
         ("project_name" = String, Path, description = "Name of the project that owns the deployment."),
-        ("deployment_id" = String, Path, description = "The deployment id in uuid format.")
+        ("deployment_id" = String, Path, description = "The deployment id in uuid format."),
+        LogsCursor,
+        LogsFieldFilter,
+        LogsTypeFilter
     )
 )]
 pub async fn get_logs(
     Extension(persistence): Extension<Persistence>,
     Path((project_name, deployment_id)): Path<(String, Uuid)>,
-) -> Result<Json<Vec<LogItem>>> {
+    Query(LogsCursor {
+        after_cursor,
+        limit,
+    }): Query<LogsCursor>,
+    Query(LogsFieldFilter { field, value }): Query<LogsFieldFilter>,
+    Query(LogsTypeFilter { log_type }): Query<LogsTypeFilter>,
+) -> Result<(HeaderMap, Json<Vec<LogItem>>)> {
     if let Some(deployment) = persistence.get_deployment(&deployment_id).await? {
-        Ok(Json(
-            persistence
-                .get_deployment_logs(&deployment.id)
-                .await?
-                .into_iter()
-                .filter_map(Into::into)
-                .collect(),
+        let limit = limit.unwrap_or(u32::MAX);
+        let field_filter = field
+            .as_deref()
+            .map(|field| (field, value.as_deref().unwrap_or_default()));
+        let logs = persistence
+            .get_deployment_logs_after(&deployment.id, after_cursor, limit, field_filter, log_type)
+            .await?;
+
+        // A full page means there might be more logs to fetch
+        let mut headers = HeaderMap::new();
+        if logs.len() as u32 == limit {
+            if let Some(last) = logs.last() {
+                headers.insert(NEXT_CURSOR_HEADER, HeaderValue::from(last.sequence));
+            }
+        }
+
+        Ok((
+            headers,
+            Json(logs.into_iter().filter_map(Into::into).collect()),
         ))
     } else {
         Err(Error::NotFound("deployment not found".to_string()))
@@ -600,11 +893,11 @@ async fn logs_websocket_handler(mut s: WebSocket, persistence: Persistence, id:
         }
     };
 
-    // Unwrap is safe because it only returns None for out of range numbers or invalid nanosecond
-    let mut last_timestamp = Utc.timestamp_opt(0, 0).unwrap();
+    // Logs can share a timestamp, so the sequence is used to skip the logs already sent from the backlog
+    let mut last_sequence = 0;
 
     for log in backlog.into_iter() {
-        last_timestamp = log.timestamp;
+        last_sequence = log.sequence;
         if let Some(log_item) = Option::<LogItem>::from(log) {
             let msg = serde_json::to_string(&log_item).expect("to convert log item to json");
             let sent = s.send(ws::Message::Text(msg)).await;
@@ -619,8 +912,8 @@ async fn logs_websocket_handler(mut s: WebSocket, persistence: Persistence, id:
     while let Ok(log) = log_recv.recv().await {
         trace!(?log, "received log from broadcast channel");
 
-        if log.id == id && log.timestamp > last_timestamp {
-            if let Some(log_item) = Option::<LogItem>::from(Log::from(log)) {
+        if log.id == id && log.sequence > last_sequence {
+            if let Some(log_item) = Option::<LogItem>::from(log) {
                 let msg = serde_json::to_string(&log_item).expect("to convert log item to json");
                 let sent = s.send(ws::Message::Text(msg)).await;
 
@@ -698,30 +991,126 @@ async fn get_status() -> String {
 
 pub struct Rmp<T>(T);
 
+/// Why a MessagePack request body was rejected by the [Rmp] extractor
+#[derive(Debug, thiserror::Error)]
+pub enum RmpRejection {
+    #[error("failed to read the request body, is it too large? {0}")]
+    Body(#[from] BytesRejection),
+    #[error("request body is not valid MessagePack: {0}")]
+    InvalidMessagePack(rmp_serde::decode::Error),
+    #[error("request body does not match the expected schema: {0}")]
+    Schema(rmp_serde::decode::Error),
+}
+
+impl From<rmp_serde::decode::Error> for RmpRejection {
+    fn from(error: rmp_serde::decode::Error) -> Self {
+        use rmp_serde::decode::Error;
+
+        match error {
+            Error::TypeMismatch(_) | Error::OutOfRange | Error::Syntax(_) => Self::Schema(error),
+            _ => Self::InvalidMessagePack(error),
+        }
+    }
+}
+
+impl IntoResponse for RmpRejection {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::Body(rejection) => rejection.status(),
+            Self::InvalidMessagePack(_) | Self::Schema(_) => StatusCode::BAD_REQUEST,
+        };
+
+        (
+            status,
+            Json(ApiError {
+                message: self.to_string(),
+                status_code: status.as_u16(),
+            }),
+        )
+            .into_response()
+    }
+}
+
 #[async_trait]
 impl<S, B, T> FromRequest<S, B> for Rmp<T>
 where
     S: Send + Sync,
     B: Send + 'static,
-    Bytes: FromRequest<S, B>,
+    Bytes: FromRequest<S, B, Rejection = BytesRejection>,
     T: DeserializeOwned,
 {
-    type Rejection = StatusCode;
+    type Rejection = RmpRejection;
 
     async fn from_request(
         req: Request<B>,
         state: &S,
     ) -> std::result::Result<Self, Self::Rejection> {
-        let bytes = Bytes::from_request(req, state).await.map_err(|_| {
-            error!("failed to collect body bytes, is the body too large?");
-            StatusCode::PAYLOAD_TOO_LARGE
+        let bytes = Bytes::from_request(req, state).await.map_err(|rejection| {
+            error!(error = %rejection, "failed to collect body bytes, is the body too large?");
+            rejection
         })?;
 
         let t = rmp_serde::from_slice::<T>(&bytes).map_err(|error| {
             error!(error = %error, "failed to deserialize request body");
-            StatusCode::BAD_REQUEST
+            error
         })?;
 
         Ok(Self(t))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::FromRequest;
+    use axum::response::IntoResponse;
+    use hyper::body::to_bytes;
+    use hyper::{Request, StatusCode};
+    use serde::Deserialize;
+    use shuttle_common::models::error::ApiError;
+
+    use super::Rmp;
+
+    #[derive(Deserialize)]
+    struct Message {
+        #[allow(dead_code)]
+        text: String,
+    }
+
+    async fn rejection_of(body: Vec<u8>) -> (StatusCode, ApiError) {
+        let req = Request::builder().body(Body::from(body)).unwrap();
+        let Err(rejection) = Rmp::<Message>::from_request(req, &()).await else {
+            panic!("body should be rejected");
+        };
+
+        let resp = rejection.into_response();
+        let status = resp.status();
+        let body = to_bytes(resp.into_body()).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn rmp_rejections_describe_the_error() {
+        let (status, error) = rejection_of(Vec::new()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.status_code, 400);
+        assert!(
+            error
+                .message
+                .starts_with("request body is not valid MessagePack"),
+            "{}",
+            error.message
+        );
+
+        let (status, error) = rejection_of(rmp_serde::to_vec(&42).unwrap()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(
+            error
+                .message
+                .starts_with("request body does not match the expected schema"),
+            "{}",
+            error.message
+        );
+    }
+}
@@ -1,14 +1,16 @@
 use std::{convert::Infallible, net::SocketAddr, sync::Arc};
 
 pub use args::Args;
-pub use deployment::deploy_layer::DeployLayer;
+pub use deployment::deploy_layer::{DeployLayer, SecretRedactor, TargetFilter};
 use deployment::DeploymentManager;
 use fqdn::FQDN;
 use hyper::{
     server::conn::AddrStream,
     service::{make_service_fn, service_fn},
 };
-pub use persistence::Persistence;
+use metrics::MetricsSender;
+pub use metrics::RequestMetrics;
+pub use persistence::{LogRetention, LogStorage, Persistence};
 use proxy::AddressGetter;
 pub use runtime_manager::RuntimeManager;
 use tokio::sync::Mutex;
@@ -20,6 +22,8 @@ mod args;
 mod deployment;
 mod error;
 pub mod handlers;
+pub mod log_archive;
+mod metrics;
 mod persistence;
 mod proxy;
 mod runtime_manager;
@@ -27,6 +31,8 @@ mod runtime_manager;
 pub async fn start(
     persistence: Persistence,
     runtime_manager: Arc<Mutex<RuntimeManager>>,
+    request_metrics: RequestMetrics,
+    secret_redactor: SecretRedactor,
     args: Args,
 ) {
     // when _set is dropped once axum exits, the deployment tasks will be aborted.
@@ -37,12 +43,19 @@ pub async fn start(
         .artifacts_path(args.artifacts_path)
         .runtime(runtime_manager)
         .deployment_updater(persistence.clone())
-        .secret_getter(persistence.clone())
+        .secret_getter(secret_redactor.secret_getter(persistence.clone()))
         .resource_manager(persistence.clone())
-        .queue_client(GatewayClient::new(args.gateway_uri))
+        .queue_client(GatewayClient::new(args.gateway_uri, args.project.clone()))
+        .max_build_retries(args.max_build_retries)
+        .max_concurrent_builds(args.max_concurrent_builds as usize)
         .build();
 
     persistence.cleanup_invalid_states().await.unwrap();
+    // The service is named after the project, which may have been renamed since the last start
+    persistence
+        .rename_service(args.project.as_str())
+        .await
+        .unwrap();
 
     let runnable_deployments = persistence.get_all_runnable_deployments().await.unwrap();
     info!(count = %runnable_deployments.len(), "stopping all but last running deploy");
@@ -58,6 +71,7 @@ pub async fn start(
     let mut builder = handlers::RouterBuilder::new(
         persistence,
         deployment_manager,
+        request_metrics,
         args.proxy_fqdn,
         args.project,
         args.auth_uri,
@@ -84,15 +98,23 @@ pub async fn start_proxy(
     proxy_address: SocketAddr,
     fqdn: FQDN,
     address_getter: impl AddressGetter,
+    metrics_sender: MetricsSender,
 ) {
     let make_service = make_service_fn(move |socket: &AddrStream| {
         let remote_address = socket.remote_addr();
         let address_getter = address_getter.clone();
+        let metrics_sender = metrics_sender.clone();
         let fqdn = fqdn.clone();
 
         async move {
             Ok::<_, Infallible>(service_fn(move |req| {
-                proxy::handle(remote_address, fqdn.clone(), req, address_getter.clone())
+                proxy::handle(
+                    remote_address,
+                    fqdn.clone(),
+                    req,
+                    address_getter.clone(),
+                    metrics_sender.clone(),
+                )
             }))
         }
     });
@@ -0,0 +1,107 @@
+//! Archiving of the logs of deployments to object storage.
+//!
+//! This is opt-in. When a bucket is configured, the full logs of a deployment are uploaded as ndjson
+//! once it reaches a terminal state, so they are retained beyond the lifetime of the deployer's database.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use aws_sdk_s3::primitives::ByteStream;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::persistence::Log;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A store of objects, like an S3 bucket
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Upload `bytes` as the object at `key`, replacing any existing object
+    async fn upload_bytes(&self, key: &str, bytes: Vec<u8>) -> Result<(), BoxError>;
+}
+
+/// An [ObjectStore] backed by an S3 bucket
+pub struct S3ObjectStore {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+impl S3ObjectStore {
+    /// Connect to `bucket` with the AWS credentials found in the environment
+    pub async fn new(bucket: String) -> Self {
+        let config = aws_config::load_from_env().await;
+
+        Self {
+            client: aws_sdk_s3::Client::new(&config),
+            bucket,
+        }
+    }
+}
+
+#[async_trait]
+impl ObjectStore for S3ObjectStore {
+    async fn upload_bytes(&self, key: &str, bytes: Vec<u8>) -> Result<(), BoxError> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type("application/x-ndjson")
+            .body(ByteStream::from(bytes))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Uploads the logs of finished deployments to an [ObjectStore]
+#[derive(Clone)]
+pub struct LogArchive {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+}
+
+impl LogArchive {
+    /// Archive the logs under `prefix` in `store`, usually the name of the project
+    pub fn new(store: impl ObjectStore + 'static, prefix: impl ToString) -> Self {
+        Self {
+            store: Arc::new(store),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    /// Key of the object holding the logs of a deployment
+    pub fn key(&self, deployment_id: &Uuid) -> String {
+        format!("{}/{deployment_id}.ndjson", self.prefix)
+    }
+
+    /// Upload the logs of a deployment as ndjson and return the key they were stored at
+    pub async fn upload(&self, deployment_id: &Uuid, logs: Vec<Log>) -> Result<String, BoxError> {
+        let mut body = Vec::new();
+
+        for log in logs {
+            let line = json!({
+                "id": log.id,
+                "timestamp": log.timestamp,
+                "state": shuttle_common::deployment::State::from(log.state),
+                "level": shuttle_common::log::Level::from(log.level),
+                "file": log.file,
+                "line": log.line,
+                "target": log.target,
+                "fields": log.fields,
+                "trace_id": log.trace_id,
+                "type": log.r#type,
+                "sequence": log.sequence,
+            });
+
+            serde_json::to_writer(&mut body, &line)?;
+            body.push(b'\n');
+        }
+
+        let key = self.key(deployment_id);
+        self.store.upload_bytes(&key, body).await?;
+
+        Ok(key)
+    }
+}
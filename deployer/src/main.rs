@@ -2,7 +2,11 @@ use std::process::exit;
 
 use clap::Parser;
 use shuttle_common::backends::tracing::setup_tracing;
-use shuttle_deployer::{start, start_proxy, Args, DeployLayer, Persistence, RuntimeManager};
+use shuttle_deployer::{
+    log_archive::{LogArchive, S3ObjectStore},
+    start, start_proxy, Args, DeployLayer, LogRetention, LogStorage, Persistence, RequestMetrics,
+    RuntimeManager, SecretRedactor, TargetFilter,
+};
 use tokio::select;
 use tracing::{error, trace};
 use tracing_subscriber::prelude::*;
@@ -15,9 +19,47 @@ async fn main() {
 
     trace!(args = ?args, "parsed args");
 
-    let (persistence, _) = Persistence::new(&args.state).await;
+    let log_archive = match &args.logs_bucket {
+        Some(bucket) => Some(LogArchive::new(
+            S3ObjectStore::new(bucket.clone()).await,
+            &args.project,
+        )),
+        None => None,
+    };
+
+    let (persistence, _) = Persistence::new(
+        &args.state,
+        log_archive,
+        LogStorage {
+            indexed_fields: args.indexed_log_fields.clone(),
+            compress_fields: args.compress_log_fields,
+        },
+    )
+    .await;
+
+    let log_retention = LogRetention {
+        max_age: args
+            .log_retention_days
+            .map(|days| chrono::Duration::days(days.into())),
+        max_lines: args.log_max_lines,
+        terminal_tail: args.log_terminal_tail,
+    };
+    if log_retention.is_enabled() {
+        persistence.start_log_retention(log_retention);
+    }
+
+    let secret_redactor = SecretRedactor::new(args.redact.clone());
     setup_tracing(
-        tracing_subscriber::registry().with(DeployLayer::new(persistence.clone())),
+        tracing_subscriber::registry().with(
+            DeployLayer::new(
+                persistence.clone(),
+                TargetFilter {
+                    allow: args.log_targets_allow.clone(),
+                    deny: args.log_targets_deny.clone(),
+                },
+            )
+            .with_redactor(secret_redactor.clone()),
+        ),
         "deployer",
     );
 
@@ -26,13 +68,17 @@ async fn main() {
         args.provisioner_address.uri().to_string(),
         Some(args.auth_uri.to_string()),
         persistence.get_log_sender(),
+        secret_redactor.clone(),
     );
 
+    let request_metrics = RequestMetrics::new();
+    let metrics_sender = request_metrics.sender();
+
     select! {
-        _ = start_proxy(args.proxy_address, args.proxy_fqdn.clone(), persistence.clone()) => {
+        _ = start_proxy(args.proxy_address, args.proxy_fqdn.clone(), persistence.clone(), metrics_sender) => {
             error!("Proxy stopped.")
         },
-        _ = start(persistence, runtime_manager, args) => {
+        _ = start(persistence, runtime_manager, request_metrics, secret_redactor, args) => {
             error!("Deployment service stopped.")
         },
     }
@@ -0,0 +1,231 @@
+//! Request metrics of the running deployments.
+//!
+//! The proxy in front of the runtimes measures every request it forwards and sends a [RequestMetric]
+//! on a channel. A background task aggregates these per deployment so they can be served by the API.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use shuttle_common::models::deployment;
+use tokio::sync::mpsc;
+use tracing::{info, trace, warn};
+use uuid::Uuid;
+
+const METRICS_BUFFER_SIZE: usize = 1024;
+
+type Deployments = Arc<Mutex<HashMap<Uuid, Aggregate>>>;
+
+/// Sends the metrics of requests to the task aggregating them
+#[derive(Clone)]
+pub struct MetricsSender {
+    send: mpsc::Sender<RequestMetric>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl MetricsSender {
+    /// Send a metric without waiting on the aggregating task. The metric is dropped, and counted
+    /// as such, when the task is falling behind.
+    pub fn send(&self, metric: RequestMetric) {
+        if let Err(error) = self.send.try_send(metric) {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(%error, dropped, "dropping request metric");
+        }
+    }
+}
+
+/// A single request served by a deployment
+#[derive(Debug, Clone, Copy)]
+pub struct RequestMetric {
+    pub deployment_id: Uuid,
+    pub status: u16,
+    pub latency: Duration,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Aggregate {
+    request_count: u64,
+    error_count: u64,
+    total_latency: Duration,
+    max_latency: Duration,
+}
+
+impl From<Aggregate> for deployment::Metrics {
+    fn from(aggregate: Aggregate) -> Self {
+        let average_latency = if aggregate.request_count == 0 {
+            Duration::ZERO
+        } else {
+            aggregate.total_latency / aggregate.request_count as u32
+        };
+
+        Self {
+            request_count: aggregate.request_count,
+            error_count: aggregate.error_count,
+            average_latency_ms: average_latency.as_secs_f64() * 1000.0,
+            max_latency_ms: aggregate.max_latency.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// The aggregated request metrics of every deployment
+#[derive(Clone)]
+pub struct RequestMetrics {
+    deployments: Deployments,
+    sender: MetricsSender,
+}
+
+impl RequestMetrics {
+    /// Start aggregating the metrics of requests in the background, for as long as these metrics or
+    /// one of their senders is alive. This has to be called from within a tokio runtime.
+    pub fn new() -> Self {
+        let deployments = Deployments::default();
+        let (send, mut recv) = mpsc::channel(METRICS_BUFFER_SIZE);
+
+        let aggregated = Arc::clone(&deployments);
+        tokio::spawn(async move {
+            while let Some(metric) = recv.recv().await {
+                trace!(?metric, "received request metric");
+                record(&aggregated, metric);
+            }
+
+            info!("request metrics channel closed");
+        });
+
+        Self {
+            deployments,
+            sender: MetricsSender {
+                send,
+                dropped: Default::default(),
+            },
+        }
+    }
+
+    /// Get a sender on which to send the metrics of requests
+    pub fn sender(&self) -> MetricsSender {
+        self.sender.clone()
+    }
+
+    /// Number of metrics dropped because the aggregating task was falling behind
+    pub fn dropped_count(&self) -> u64 {
+        self.sender.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Get the metrics of a deployment. Deployments which have not served any requests yet have
+    /// empty metrics.
+    pub fn get(&self, deployment_id: &Uuid) -> deployment::Metrics {
+        self.deployments
+            .lock()
+            .unwrap()
+            .get(deployment_id)
+            .copied()
+            .unwrap_or_default()
+            .into()
+    }
+}
+
+impl Default for RequestMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn record(deployments: &Deployments, metric: RequestMetric) {
+    let mut deployments = deployments.lock().unwrap();
+    let aggregate = deployments.entry(metric.deployment_id).or_default();
+
+    aggregate.request_count += 1;
+    if metric.status >= 500 {
+        aggregate.error_count += 1;
+    }
+    aggregate.total_latency += metric.latency;
+    aggregate.max_latency = aggregate.max_latency.max(metric.latency);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use shuttle_common::models::deployment;
+    use tokio::time::sleep;
+    use uuid::Uuid;
+
+    use super::{RequestMetric, RequestMetrics, METRICS_BUFFER_SIZE};
+
+    #[tokio::test]
+    async fn aggregates_metrics_per_deployment() {
+        let metrics = RequestMetrics::new();
+        let send = metrics.sender();
+        let id = Uuid::new_v4();
+        let other_id = Uuid::new_v4();
+
+        // Act as the runtime serving a few requests
+        for (deployment_id, status, latency) in [
+            (id, 200, 10),
+            (id, 404, 20),
+            (id, 500, 60),
+            (other_id, 200, 5),
+        ] {
+            send.send(RequestMetric {
+                deployment_id,
+                status,
+                latency: Duration::from_millis(latency),
+            });
+        }
+
+        // Give the aggregating task a chance to catch up
+        while metrics.get(&other_id).request_count == 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(
+            metrics.get(&id),
+            deployment::Metrics {
+                request_count: 3,
+                error_count: 1,
+                average_latency_ms: 30.0,
+                max_latency_ms: 60.0,
+            }
+        );
+        assert_eq!(metrics.get(&other_id).request_count, 1);
+        assert_eq!(metrics.get(&Uuid::new_v4()), deployment::Metrics::default());
+        assert_eq!(metrics.dropped_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn counts_dropped_metrics() {
+        let metrics = RequestMetrics::new();
+        let id = Uuid::new_v4();
+        let metric = RequestMetric {
+            deployment_id: id,
+            status: 200,
+            latency: Duration::from_millis(1),
+        };
+
+        // Every sender feeds the same aggregating task, so they share its buffer. Nothing is
+        // aggregated before this test yields, which lets the buffer fill up
+        for _ in 0..METRICS_BUFFER_SIZE {
+            metrics.sender().send(metric);
+        }
+        metrics.sender().send(metric);
+        metrics.sender().send(metric);
+        assert_eq!(metrics.dropped_count(), 2);
+
+        while metrics.get(&id).request_count < METRICS_BUFFER_SIZE as u64 {
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            metrics.get(&id).request_count,
+            METRICS_BUFFER_SIZE as u64,
+            "the dropped metrics should not be aggregated"
+        );
+
+        // Sending works again once the task caught up
+        metrics.sender().send(metric);
+        assert_eq!(metrics.dropped_count(), 2);
+    }
+}
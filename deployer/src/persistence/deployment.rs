@@ -22,6 +22,14 @@ pub struct Deployment {
     pub git_commit_msg: Option<String>,
     pub git_branch: Option<String>,
     pub git_dirty: Option<bool>,
+    /// Key of the logs in the log archive, once they are archived
+    pub logs_object_key: Option<String>,
+    /// Versions of `rustc` and `cargo` the deployment was built with
+    pub toolchain_info: Option<String>,
+    /// Whether the deployment is only built and tested, without being run
+    pub dry_run: bool,
+    /// Whether the tests of the deployment passed, once they ran
+    pub tests_passed: Option<bool>,
 }
 
 impl FromRow<'_, SqliteRow> for Deployment {
@@ -49,6 +57,10 @@ impl FromRow<'_, SqliteRow> for Deployment {
             git_commit_msg: row.try_get("git_commit_msg")?,
             git_branch: row.try_get("git_branch")?,
             git_dirty: row.try_get("git_dirty")?,
+            logs_object_key: row.try_get("logs_object_key")?,
+            toolchain_info: row.try_get("toolchain_info")?,
+            dry_run: row.try_get("dry_run")?,
+            tests_passed: row.try_get("tests_passed")?,
         })
     }
 }
@@ -64,6 +76,9 @@ impl From<Deployment> for shuttle_common::models::deployment::Response {
             git_commit_msg: deployment.git_commit_msg,
             git_branch: deployment.git_branch,
             git_dirty: deployment.git_dirty,
+            toolchain_info: deployment.toolchain_info,
+            dry_run: deployment.dry_run,
+            tests_passed: deployment.tests_passed,
         }
     }
 }
@@ -78,6 +93,12 @@ pub trait DeploymentUpdater: Clone + Send + Sync + 'static {
 
     /// Set if a deployment is build on shuttle-next
     async fn set_is_next(&self, id: &Uuid, is_next: bool) -> Result<(), Self::Err>;
+
+    /// Set the versions of the toolchain a deployment was built with
+    async fn set_toolchain_info(&self, id: &Uuid, toolchain_info: &str) -> Result<(), Self::Err>;
+
+    /// Set whether the tests of a deployment passed
+    async fn set_tests_passed(&self, id: &Uuid, tests_passed: bool) -> Result<(), Self::Err>;
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -87,6 +108,15 @@ pub struct DeploymentState {
     pub last_update: DateTime<Utc>,
 }
 
+/// A deployment which is loading or running, along with the service it belongs to
+#[derive(sqlx::FromRow, Debug, PartialEq, Eq)]
+pub struct ActiveDeployment {
+    pub id: Uuid,
+    pub service_id: Uuid,
+    pub service_name: String,
+    pub state: State,
+}
+
 #[derive(sqlx::FromRow, Debug, PartialEq, Eq)]
 pub struct DeploymentRunnable {
     pub id: Uuid,
@@ -2,6 +2,10 @@
 pub enum Error {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
+    #[error("Failed to compress log fields: {0}")]
+    Compression(#[from] std::io::Error),
+    #[error("Log field is not indexed: {0}")]
+    UnindexedLogField(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,11 +1,18 @@
+use std::io::Read;
+
 use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use serde_json::{json, Value};
-use shuttle_common::STATE_MESSAGE;
+use sqlx::{sqlite::SqliteRow, FromRow, Row};
 use uuid::Uuid;
 
 use super::State;
+use crate::deployment::deploy_layer::LogType;
+
+/// Magic bytes every gzip stream starts with, which a JSON document never does
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-#[derive(Clone, Debug, Eq, PartialEq, sqlx::FromRow)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Log {
     pub id: Uuid,
     pub timestamp: DateTime<Utc>,
@@ -15,6 +22,85 @@ pub struct Log {
     pub line: Option<u32>,
     pub target: String,
     pub fields: serde_json::Value,
+    pub trace_id: Option<String>,
+    pub r#type: LogType,
+    /// Increases with every log recorded, so logs with the same timestamp can still be told apart and ordered
+    pub sequence: i64,
+}
+
+impl<'r> FromRow<'r, SqliteRow> for Log {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let fields: Vec<u8> = row.try_get("fields")?;
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            timestamp: row.try_get("timestamp")?,
+            state: row.try_get("state")?,
+            level: row.try_get("level")?,
+            file: row.try_get("file")?,
+            line: row.try_get("line")?,
+            target: row.try_get("target")?,
+            fields: decode_fields(&fields).map_err(|error| sqlx::Error::ColumnDecode {
+                index: "fields".to_string(),
+                source: Box::new(error),
+            })?,
+            trace_id: row.try_get("trace_id")?,
+            r#type: row.try_get("type")?,
+            sequence: row.try_get("sequence")?,
+        })
+    }
+}
+
+/// Compress the fields of a log to store them
+pub fn compress_fields(fields: &Value) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, fields)?;
+
+    encoder.finish()
+}
+
+/// Decode the stored fields of a log, which might have been compressed by [compress_fields]
+fn decode_fields(stored: &[u8]) -> std::io::Result<Value> {
+    if stored.starts_with(&GZIP_MAGIC) {
+        let mut json = Vec::new();
+        GzDecoder::new(stored).read_to_end(&mut json)?;
+
+        Ok(serde_json::from_slice(&json)?)
+    } else {
+        Ok(serde_json::from_slice(stored)?)
+    }
+}
+
+/// How the logs of deployments are stored
+#[derive(Clone, Debug, Default)]
+pub struct LogStorage {
+    /// Fields which are extracted from the fields of every log into their own indexed column, so logs can
+    /// be filtered on them
+    pub indexed_fields: Vec<String>,
+
+    /// Whether to compress the fields of logs. Logs stored either way can always be read
+    pub compress_fields: bool,
+}
+
+/// How long the logs of deployments are kept around
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LogRetention {
+    /// Delete logs older than this
+    pub max_age: Option<chrono::Duration>,
+
+    /// Only keep this many of the latest logs of each deployment
+    pub max_lines: Option<u32>,
+
+    /// Number of the latest logs of a deployment in a terminal state to keep even when they are older than
+    /// [LogRetention::max_age], so the reason it stopped can still be found
+    pub terminal_tail: u32,
+}
+
+impl LogRetention {
+    /// Whether any logs will ever be deleted by this policy
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.max_lines.is_some()
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, sqlx::Type)]
@@ -28,12 +114,9 @@ pub enum Level {
 
 impl From<Log> for Option<shuttle_common::LogItem> {
     fn from(log: Log) -> Self {
-        if log.state == State::Building {
-            if let Value::String(str_value) = &log.fields {
-                if str_value == STATE_MESSAGE {
-                    return Some(log.into());
-                }
-            } else {
+        match log.r#type {
+            LogType::State => Some(log.into()),
+            LogType::BuildOutput => {
                 let msg = extract_message(&log.fields)?;
 
                 let item = shuttle_common::LogItem {
@@ -45,13 +128,15 @@ impl From<Log> for Option<shuttle_common::LogItem> {
                     line: log.line,
                     target: log.target,
                     fields: serde_json::to_vec(&json!({ "message": msg })).unwrap(),
+                    trace_id: log.trace_id,
                 };
 
-                return Some(item);
+                Some(item)
             }
+            // The events of the deployer itself while building are not of interest to users
+            LogType::Event if log.state == State::Building => None,
+            LogType::Event => Some(log.into()),
         }
-
-        Some(log.into())
     }
 }
 
@@ -66,6 +151,7 @@ impl From<Log> for shuttle_common::LogItem {
             line: log.line,
             target: log.target,
             fields: serde_json::to_vec(&log.fields).unwrap(),
+            trace_id: log.trace_id,
         }
     }
 }
@@ -9,6 +9,7 @@ mod user;
 
 use crate::deployment::deploy_layer::{self, LogRecorder, LogType};
 use crate::deployment::ActiveDeploymentsGetter;
+use crate::log_archive::LogArchive;
 use crate::proxy::AddressGetter;
 use error::{Error, Result};
 use sqlx::QueryBuilder;
@@ -16,21 +17,26 @@ use sqlx::QueryBuilder;
 use std::net::SocketAddr;
 use std::path::Path;
 use std::str::FromStr;
-
-use chrono::Utc;
-use serde_json::json;
-use shuttle_common::STATE_MESSAGE;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use shuttle_common::models::resource::StatusChange;
+use shuttle_common::resource::{
+    ProvisioningStatus, StatusResponse, RESOURCE_STATUS_FIELD, RESOURCE_TYPE_FIELD,
+};
 use sqlx::migrate::{MigrateDatabase, Migrator};
 use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqliteJournalMode, SqlitePool};
 use tokio::sync::broadcast::{self, Receiver, Sender};
 use tokio::task::JoinHandle;
-use tracing::{error, info, instrument, trace};
+use tracing::{error, info, instrument, trace, warn};
 use uuid::Uuid;
 
 use self::deployment::DeploymentRunnable;
-pub use self::deployment::{Deployment, DeploymentState, DeploymentUpdater};
+pub use self::deployment::{ActiveDeployment, Deployment, DeploymentState, DeploymentUpdater};
 pub use self::error::Error as PersistenceError;
-pub use self::log::{Level as LogLevel, Log};
+use self::log::compress_fields;
+pub use self::log::{Level as LogLevel, Log, LogRetention, LogStorage};
 pub use self::resource::{Resource, ResourceManager, Type as ResourceType};
 pub use self::secret::{Secret, SecretGetter, SecretRecorder};
 pub use self::service::Service;
@@ -39,11 +45,15 @@ pub use self::user::User;
 
 pub static MIGRATIONS: Migrator = sqlx::migrate!("./migrations");
 
+/// How often the log retention policy is enforced in the background
+const LOG_RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 #[derive(Clone)]
 pub struct Persistence {
     pool: SqlitePool,
     log_send: crossbeam_channel::Sender<deploy_layer::Log>,
-    stream_log_send: Sender<deploy_layer::Log>,
+    stream_log_send: Sender<Log>,
+    log_storage: Arc<LogStorage>,
 }
 
 impl Persistence {
@@ -51,7 +61,15 @@ impl Persistence {
     /// function creates all necessary tables and sets up a database connection
     /// pool - new connections should be made by cloning [`Persistence`] rather
     /// than repeatedly calling [`Persistence::new`].
-    pub async fn new(path: &str) -> (Self, JoinHandle<()>) {
+    ///
+    /// The logs of deployments reaching a terminal state are uploaded to `log_archive` when one is given.
+    ///
+    /// The logs are written as set out by `log_storage`.
+    pub async fn new(
+        path: &str,
+        log_archive: Option<LogArchive>,
+        log_storage: LogStorage,
+    ) -> (Self, JoinHandle<()>) {
         if !Path::new(path).exists() {
             Sqlite::create_database(path).await.unwrap();
         }
@@ -75,17 +93,33 @@ impl Persistence {
 
         let pool = SqlitePool::connect_with(sqlite_options).await.unwrap();
 
-        Self::from_pool(pool).await
+        Self::from_pool(pool, log_archive, log_storage).await
     }
 
     #[allow(dead_code)]
     async fn new_in_memory() -> (Self, JoinHandle<()>) {
         let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
-        Self::from_pool(pool).await
+        Self::from_pool(pool, None, LogStorage::default()).await
     }
 
-    async fn from_pool(pool: SqlitePool) -> (Self, JoinHandle<()>) {
+    async fn from_pool(
+        pool: SqlitePool,
+        log_archive: Option<LogArchive>,
+        log_storage: LogStorage,
+    ) -> (Self, JoinHandle<()>) {
         MIGRATIONS.run(&pool).await.unwrap();
+        add_log_field_columns(&pool, &log_storage.indexed_fields)
+            .await
+            .unwrap();
+
+        let log_storage = Arc::new(log_storage);
+        let log_storage_cloned = log_storage.clone();
+
+        // Carry on from the last log recorded before a restart
+        let (mut sequence,): (i64,) = sqlx::query_as("SELECT COALESCE(MAX(sequence), 0) FROM logs")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
 
         let (log_send, log_recv): (crossbeam_channel::Sender<deploy_layer::Log>, _) =
             crossbeam_channel::bounded(0);
@@ -100,9 +134,16 @@ impl Persistence {
         let handle = tokio::spawn(async move {
             while let Ok(log) = log_recv.recv() {
                 trace!(?log, "persistence received got log");
+
+                sequence += 1;
+                let persisted_log = Log {
+                    sequence,
+                    ..Log::from(log.clone())
+                };
+
                 match log.r#type {
-                    LogType::Event => {
-                        insert_log(&pool_cloned, log.clone())
+                    LogType::Event | LogType::BuildOutput => {
+                        insert_log(&pool_cloned, persisted_log.clone(), &log_storage_cloned)
                             .await
                             .unwrap_or_else(|error| {
                                 error!(
@@ -110,20 +151,23 @@ impl Persistence {
                                     "failed to insert event log"
                                 )
                             });
+                        update_resource_status(&pool_cloned, &log)
+                            .await
+                            .unwrap_or_else(|error| {
+                                error!(
+                                    error = &error as &dyn std::error::Error,
+                                    "failed to update resource status"
+                                )
+                            });
                     }
                     LogType::State => {
                         insert_log(
                             &pool_cloned,
                             Log {
-                                id: log.id,
-                                timestamp: log.timestamp,
-                                state: log.state,
-                                level: log.level.clone(),
-                                file: log.file.clone(),
-                                line: log.line,
                                 target: String::new(),
-                                fields: json!(STATE_MESSAGE),
+                                ..persisted_log.clone()
                             },
+                            &log_storage_cloned,
                         )
                         .await
                         .unwrap_or_else(|error| {
@@ -140,21 +184,38 @@ impl Persistence {
                                     "failed to update deployment state"
                                 )
                             });
+
+                        if let Some(log_archive) = log_archive.as_ref() {
+                            if log.state.is_terminal() {
+                                // Uploading can be slow, so don't hold up the logs which follow
+                                tokio::spawn(archive_deployment_logs(
+                                    pool_cloned.clone(),
+                                    log_archive.clone(),
+                                    log.id,
+                                ));
+                            }
+                        }
                     }
                 };
 
                 let receiver_count = stream_log_send_clone.receiver_count();
-                trace!(?log, receiver_count, "sending log to broadcast stream");
+                trace!(
+                    ?persisted_log,
+                    receiver_count,
+                    "sending log to broadcast stream"
+                );
 
                 if receiver_count > 0 {
-                    stream_log_send_clone.send(log).unwrap_or_else(|error| {
-                        error!(
-                            error = &error as &dyn std::error::Error,
-                            "failed to broadcast log"
-                        );
-
-                        0
-                    });
+                    stream_log_send_clone
+                        .send(persisted_log)
+                        .unwrap_or_else(|error| {
+                            error!(
+                                error = &error as &dyn std::error::Error,
+                                "failed to broadcast log"
+                            );
+
+                            0
+                        });
                 }
             }
         });
@@ -163,6 +224,7 @@ impl Persistence {
             pool,
             log_send,
             stream_log_send,
+            log_storage,
         };
 
         (persistence, handle)
@@ -171,7 +233,7 @@ impl Persistence {
     pub async fn insert_deployment(&self, deployment: impl Into<Deployment>) -> Result<()> {
         let deployment = deployment.into();
 
-        sqlx::query("INSERT INTO deployments VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        sqlx::query("INSERT INTO deployments VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
             .bind(deployment.id)
             .bind(deployment.service_id)
             .bind(deployment.state)
@@ -182,6 +244,10 @@ impl Persistence {
             .bind(deployment.git_commit_msg)
             .bind(deployment.git_branch)
             .bind(deployment.git_dirty)
+            .bind(deployment.logs_object_key)
+            .bind(deployment.toolchain_info)
+            .bind(deployment.dry_run)
+            .bind(deployment.tests_passed)
             .execute(&self.pool)
             .await
             .map(|_| ())
@@ -216,6 +282,15 @@ impl Persistence {
             .map_err(Error::from)
     }
 
+    /// Number of deployments a service has ever had
+    pub async fn count_deployments(&self, service_id: &Uuid) -> Result<u32> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM deployments WHERE service_id = ?")
+            .bind(service_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(Error::from)
+    }
+
     pub async fn get_active_deployment(&self, service_id: &Uuid) -> Result<Option<Deployment>> {
         sqlx::query_as("SELECT * FROM deployments WHERE service_id = ? AND state = ?")
             .bind(service_id)
@@ -227,12 +302,13 @@ impl Persistence {
 
     // Clean up all invalid states inside persistence
     pub async fn cleanup_invalid_states(&self) -> Result<()> {
-        sqlx::query("UPDATE deployments SET state = ? WHERE state IN(?, ?, ?, ?)")
+        sqlx::query("UPDATE deployments SET state = ? WHERE state IN(?, ?, ?, ?, ?)")
             .bind(State::Stopped)
             .bind(State::Queued)
             .bind(State::Built)
             .bind(State::Building)
             .bind(State::Loading)
+            .bind(State::Provisioning)
             .execute(&self.pool)
             .await?;
 
@@ -258,6 +334,20 @@ impl Persistence {
         }
     }
 
+    /// Name the service of the project after the project again, when the project was renamed since
+    /// its deployments were made. A project only has a single service, so nothing is renamed when
+    /// there are several.
+    pub async fn rename_service(&self, name: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE services SET name = ?1 WHERE name != ?1 AND (SELECT COUNT(*) FROM services) = 1",
+        )
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn get_service_by_name(&self, name: &str) -> Result<Option<Service>> {
         sqlx::query_as("SELECT * FROM services WHERE name = ?")
             .bind(name)
@@ -303,6 +393,7 @@ impl Persistence {
                 FROM deployments AS d
                 JOIN services AS s ON s.id = d.service_id
                 WHERE state IN (?, ?, ?)
+                AND NOT d.dry_run
                 AND d.id = ?"#,
         )
         .bind(State::Running)
@@ -319,8 +410,123 @@ impl Persistence {
         get_deployment_logs(&self.pool, id).await
     }
 
+    /// Get a page of logs for a deployment, starting right after the log with the `after` sequence.
+    ///
+    /// Logs are unique per deployment and sequence, so paging on the sequence never skips nor
+    /// duplicates a log across pages, even when logs share a timestamp.
+    ///
+    /// When a `field_filter` of a field name and value is given, only the logs with that value for the field
+    /// are returned. The field has to be one of the indexed log fields. When a `log_type` is given, only the
+    /// logs of that type are returned.
+    pub(crate) async fn get_deployment_logs_after(
+        &self,
+        id: &Uuid,
+        after: Option<i64>,
+        limit: u32,
+        field_filter: Option<(&str, &str)>,
+        log_type: Option<LogType>,
+    ) -> Result<Vec<Log>> {
+        let mut query = QueryBuilder::new("SELECT * FROM logs WHERE id = ");
+
+        query.push_bind(id);
+
+        if let Some(after) = after {
+            query.push(" AND sequence > ").push_bind(after);
+        }
+
+        if let Some((name, value)) = field_filter {
+            if !self
+                .log_storage
+                .indexed_fields
+                .iter()
+                .any(|field| field == name)
+            {
+                return Err(Error::UnindexedLogField(name.to_string()));
+            }
+
+            query
+                .push(format!(" AND \"{}\" = ", log_field_column(name)))
+                .push_bind(value.to_string());
+        }
+
+        if let Some(log_type) = log_type {
+            query.push(" AND type = ").push_bind(log_type);
+        }
+
+        query.push(" ORDER BY sequence LIMIT ").push_bind(limit);
+
+        query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(Error::from)
+    }
+
+    /// Delete the logs falling outside of the `retention` policy and return how many were deleted
+    pub async fn enforce_log_retention(&self, retention: &LogRetention) -> Result<u64> {
+        if !retention.is_enabled() {
+            return Ok(0);
+        }
+
+        let mut query = QueryBuilder::new(
+            "DELETE FROM logs WHERE rowid IN (SELECT rowid FROM (SELECT l.rowid, l.timestamp, d.state, \
+            ROW_NUMBER() OVER (PARTITION BY l.id ORDER BY l.sequence DESC) AS position \
+            FROM logs l LEFT JOIN deployments d ON l.id = d.id) WHERE ",
+        );
+
+        let mut conditions = query.separated(" OR ");
+
+        if let Some(max_age) = retention.max_age {
+            conditions
+                .push("(timestamp < ")
+                .push_bind_unseparated(Utc::now() - max_age)
+                .push_unseparated(" AND NOT (position <= ")
+                .push_bind_unseparated(retention.terminal_tail)
+                .push_unseparated(" AND state IN (")
+                .push_bind_unseparated(State::Completed)
+                .push_unseparated(", ")
+                .push_bind_unseparated(State::Stopped)
+                .push_unseparated(", ")
+                .push_bind_unseparated(State::Crashed)
+                .push_unseparated(")))");
+        }
+
+        if let Some(max_lines) = retention.max_lines {
+            conditions
+                .push("position > ")
+                .push_bind_unseparated(max_lines);
+        }
+
+        query.push(")");
+
+        let result = query.build().execute(&self.pool).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Enforce the log `retention` policy every [LOG_RETENTION_INTERVAL] in the background
+    pub fn start_log_retention(&self, retention: LogRetention) -> JoinHandle<()> {
+        let persistence = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(LOG_RETENTION_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                match persistence.enforce_log_retention(&retention).await {
+                    Ok(deleted) => info!(deleted, "enforced log retention"),
+                    Err(error) => error!(
+                        error = &error as &dyn std::error::Error,
+                        "failed to enforce log retention"
+                    ),
+                }
+            }
+        })
+    }
+
     /// Get a broadcast channel for listening to logs that are being stored into persistence
-    pub fn get_log_subscriber(&self) -> Receiver<deploy_layer::Log> {
+    pub fn get_log_subscriber(&self) -> Receiver<Log> {
         self.stream_log_send.subscribe()
     }
 
@@ -329,6 +535,66 @@ impl Persistence {
         self.log_send.clone()
     }
 
+    /// Get the provisioning status of the resources of a deployment. Resources the service used
+    /// before, but which this deployment has not requested yet, are [ProvisioningStatus::Pending].
+    pub async fn get_resource_statuses(
+        &self,
+        deployment_id: &Uuid,
+        service_id: &Uuid,
+    ) -> Result<Vec<StatusResponse>> {
+        let tracked: Vec<(ResourceType, String)> = sqlx::query_as(
+            "SELECT type, status FROM resource_statuses WHERE deployment_id = ? ORDER BY type",
+        )
+        .bind(deployment_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut statuses: Vec<StatusResponse> = tracked
+            .into_iter()
+            .filter_map(|(r#type, status)| {
+                Some(StatusResponse {
+                    r#type: r#type.into(),
+                    status: ProvisioningStatus::from_str(&status).ok()?,
+                })
+            })
+            .collect();
+
+        for resource in self.get_resources(service_id).await? {
+            let r#type = resource.r#type.into();
+
+            if !statuses.iter().any(|status| status.r#type == r#type) {
+                statuses.push(StatusResponse {
+                    r#type,
+                    status: ProvisioningStatus::Pending,
+                });
+            }
+        }
+
+        Ok(statuses)
+    }
+
+    /// Get every change of the provisioning status of the resources of a deployment, in the order
+    /// they happened
+    pub async fn get_resource_timeline(&self, deployment_id: &Uuid) -> Result<Vec<StatusChange>> {
+        let changes: Vec<(ResourceType, String, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT type, status, timestamp FROM resource_status_history WHERE deployment_id = ? ORDER BY rowid",
+        )
+        .bind(deployment_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(changes
+            .into_iter()
+            .filter_map(|(r#type, status, timestamp)| {
+                Some(StatusChange {
+                    r#type: r#type.into(),
+                    status: ProvisioningStatus::from_str(&status).ok()?,
+                    timestamp,
+                })
+            })
+            .collect())
+    }
+
     pub async fn stop_running_deployment(&self, deployable: DeploymentRunnable) -> Result<()> {
         update_deployment(
             &self.pool,
@@ -363,26 +629,190 @@ async fn get_deployment(pool: &SqlitePool, id: &Uuid) -> Result<Option<Deploymen
         .map_err(Error::from)
 }
 
-async fn insert_log(pool: &SqlitePool, log: impl Into<Log>) -> Result<()> {
+/// Name of the column holding the values of an indexed log field. Characters which are not valid in an
+/// identifier, like the `.` in `request.path`, are replaced.
+fn log_field_column(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("field_{name}")
+}
+
+/// Add an indexed column to the logs table for each of the `fields` which does not have one yet
+async fn add_log_field_columns(pool: &SqlitePool, fields: &[String]) -> Result<()> {
+    let existing: Vec<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info('logs')")
+        .fetch_all(pool)
+        .await?;
+
+    for field in fields {
+        let column = log_field_column(field);
+
+        if !existing.iter().any(|(name,)| name == &column) {
+            info!(%field, %column, "adding column for indexed log field");
+
+            sqlx::query(&format!("ALTER TABLE logs ADD COLUMN \"{column}\" TEXT"))
+                .execute(pool)
+                .await?;
+        }
+
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS \"logs_{column}\" ON logs (id, \"{column}\")"
+        ))
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn insert_log(pool: &SqlitePool, log: impl Into<Log>, storage: &LogStorage) -> Result<()> {
     let log = log.into();
+    let indexed_fields = &storage.indexed_fields;
+
+    let mut query = QueryBuilder::new(
+        "INSERT INTO logs (id, timestamp, state, level, file, line, target, fields, trace_id, type, sequence",
+    );
+
+    for field in indexed_fields {
+        query.push(format!(", \"{}\"", log_field_column(field)));
+    }
+
+    query.push(") VALUES (");
+
+    // Strings are stored as is so they can be compared to a filter value without their JSON quotes
+    let values: Vec<Option<String>> = indexed_fields
+        .iter()
+        .map(|field| match log.fields.get(field)? {
+            serde_json::Value::String(value) => Some(value.clone()),
+            value => Some(value.to_string()),
+        })
+        .collect();
+
+    let mut separated = query.separated(", ");
+    separated
+        .push_bind(log.id)
+        .push_bind(log.timestamp)
+        .push_bind(log.state)
+        .push_bind(log.level)
+        .push_bind(log.file)
+        .push_bind(log.line)
+        .push_bind(log.target);
+
+    if storage.compress_fields {
+        separated.push_bind(compress_fields(&log.fields)?);
+    } else {
+        separated.push_bind(log.fields);
+    }
+
+    separated
+        .push_bind(log.trace_id)
+        .push_bind(log.r#type)
+        .push_bind(log.sequence);
+
+    for value in values {
+        separated.push_bind(value);
+    }
 
-    sqlx::query("INSERT INTO logs (id, timestamp, state, level, file, line, target, fields) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
-        .bind(log.id)
-        .bind(log.timestamp)
-        .bind(log.state)
-        .bind(log.level)
-        .bind(log.file)
-        .bind(log.line)
-        .bind(log.target)
-        .bind(log.fields)
+    query
+        .push(")")
+        .build()
         .execute(pool)
         .await
         .map(|_| ())
         .map_err(Error::from)
 }
 
+/// Record the provisioning status of a resource when the log is about one
+async fn update_resource_status(pool: &SqlitePool, log: &deploy_layer::Log) -> Result<()> {
+    let (r#type, status) = match (
+        log.fields.get(RESOURCE_TYPE_FIELD).and_then(|v| v.as_str()),
+        log.fields
+            .get(RESOURCE_STATUS_FIELD)
+            .and_then(|v| v.as_str()),
+    ) {
+        (Some(r#type), Some(status)) => (r#type, status),
+        _ => return Ok(()),
+    };
+
+    let (r#type, status) = match (
+        ResourceType::from_str(r#type),
+        ProvisioningStatus::from_str(status),
+    ) {
+        (Ok(r#type), Ok(status)) => (r#type, status),
+        _ => {
+            warn!(
+                resource_type = r#type,
+                resource_status = status,
+                "ignoring unknown resource status"
+            );
+            return Ok(());
+        }
+    };
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO resource_statuses (deployment_id, type, status, last_update) VALUES (?, ?, ?, ?)",
+    )
+    .bind(log.id)
+    .bind(&r#type)
+    .bind(status.to_string())
+    .bind(log.timestamp)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO resource_status_history (deployment_id, type, status, timestamp) VALUES (?, ?, ?, ?)",
+    )
+    .bind(log.id)
+    .bind(r#type)
+    .bind(status.to_string())
+    .bind(log.timestamp)
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(Error::from)
+}
+
+/// Upload all the logs of a deployment to the archive and record where they were stored
+async fn archive_deployment_logs(pool: SqlitePool, log_archive: LogArchive, id: Uuid) {
+    let logs = match get_deployment_logs(&pool, &id).await {
+        Ok(logs) => logs,
+        Err(error) => {
+            error!(
+                error = &error as &dyn std::error::Error,
+                "failed to get logs to archive"
+            );
+            return;
+        }
+    };
+
+    let key = match log_archive.upload(&id, logs).await {
+        Ok(key) => key,
+        Err(error) => {
+            error!(%error, deployment_id = %id, "failed to archive deployment logs");
+            return;
+        }
+    };
+
+    info!(deployment_id = %id, %key, "archived deployment logs");
+
+    sqlx::query("UPDATE deployments SET logs_object_key = ? WHERE id = ?")
+        .bind(key)
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map(|_| ())
+        .unwrap_or_else(|error| {
+            error!(
+                error = &error as &dyn std::error::Error,
+                "failed to record the key of the archived logs"
+            )
+        });
+}
+
 async fn get_deployment_logs(pool: &SqlitePool, id: &Uuid) -> Result<Vec<Log>> {
-    sqlx::query_as("SELECT * FROM logs WHERE id = ? ORDER BY timestamp")
+    sqlx::query_as("SELECT * FROM logs WHERE id = ? ORDER BY sequence")
         .bind(id)
         .fetch_all(pool)
         .await
@@ -429,8 +859,12 @@ impl SecretRecorder for Persistence {
     type Err = Error;
 
     async fn insert_secret(&self, service_id: &Uuid, key: &str, value: &str) -> Result<()> {
+        // Keep the update time when the same value is set again, since all secrets are set on every deploy
         sqlx::query(
-            "INSERT OR REPLACE INTO secrets (service_id, key, value, last_update) VALUES (?, ?, ?, ?)",
+            r#"INSERT INTO secrets (service_id, key, value, last_update, created_at) VALUES (?1, ?2, ?3, ?4, ?4)
+                ON CONFLICT (service_id, key) DO UPDATE SET
+                    value = excluded.value,
+                    last_update = CASE WHEN value = excluded.value THEN last_update ELSE excluded.last_update END"#,
         )
         .bind(service_id)
         .bind(key)
@@ -462,9 +896,9 @@ impl AddressGetter for Persistence {
     async fn get_address_for_service(
         &self,
         service_name: &str,
-    ) -> crate::handlers::Result<Option<std::net::SocketAddr>> {
-        let address_str = sqlx::query_as::<_, (String,)>(
-            r#"SELECT d.address
+    ) -> crate::handlers::Result<Option<(Uuid, std::net::SocketAddr)>> {
+        let address_str = sqlx::query_as::<_, (Uuid, String)>(
+            r#"SELECT d.id, d.address
                 FROM deployments AS d
                 JOIN services AS s ON d.service_id = s.id
                 WHERE s.name = ? AND d.state = ?
@@ -477,14 +911,14 @@ impl AddressGetter for Persistence {
         .map_err(Error::from)
         .map_err(crate::handlers::Error::Persistence)?;
 
-        if let Some((address_str,)) = address_str {
-            SocketAddr::from_str(&address_str).map(Some).map_err(|err| {
-                crate::handlers::Error::Convert {
+        if let Some((id, address_str)) = address_str {
+            SocketAddr::from_str(&address_str)
+                .map(|address| Some((id, address)))
+                .map_err(|err| crate::handlers::Error::Convert {
                     from: "String".to_string(),
                     to: "SocketAddr".to_string(),
                     message: err.to_string(),
-                }
-            })
+                })
         } else {
             Ok(None)
         }
@@ -514,6 +948,26 @@ impl DeploymentUpdater for Persistence {
             .map(|_| ())
             .map_err(Error::from)
     }
+
+    async fn set_toolchain_info(&self, id: &Uuid, toolchain_info: &str) -> Result<()> {
+        sqlx::query("UPDATE deployments SET toolchain_info = ? WHERE id = ?")
+            .bind(toolchain_info)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    async fn set_tests_passed(&self, id: &Uuid, tests_passed: bool) -> Result<()> {
+        sqlx::query("UPDATE deployments SET tests_passed = ? WHERE id = ?")
+            .bind(tests_passed)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(Error::from)
+    }
 }
 
 #[async_trait::async_trait]
@@ -538,17 +992,40 @@ impl ActiveDeploymentsGetter for Persistence {
 
         Ok(ids)
     }
+
+    async fn get_all_active_deployments(
+        &self,
+    ) -> std::result::Result<Vec<ActiveDeployment>, Self::Err> {
+        sqlx::query_as(
+            r#"SELECT d.id, service_id, s.name AS service_name, d.state
+                FROM deployments AS d
+                JOIN services AS s ON s.id = d.service_id
+                WHERE state IN (?, ?, ?)
+                ORDER BY last_update DESC"#,
+        )
+        .bind(State::Loading)
+        .bind(State::Provisioning)
+        .bind(State::Running)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(Error::from)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::net::{Ipv4Addr, SocketAddr};
+    use std::sync::{Arc, Mutex};
 
     use chrono::{Duration, TimeZone, Utc};
     use rand::Rng;
     use serde_json::json;
 
+    use shuttle_common::models::deployment::{RestartAttempt, RestartKind};
+    use shuttle_common::STATE_MESSAGE;
+
     use super::*;
+    use crate::log_archive::{BoxError, ObjectStore};
     use crate::persistence::{
         deployment::{Deployment, DeploymentRunnable, DeploymentState},
         log::{Level, Log},
@@ -614,6 +1091,10 @@ mod tests {
                 git_commit_msg: None,
                 git_branch: None,
                 git_dirty: None,
+                logs_object_key: None,
+                toolchain_info: None,
+                dry_run: false,
+                tests_passed: None,
             })
             .collect();
 
@@ -632,6 +1113,7 @@ mod tests {
             deployments[5..10]
         );
         assert_eq!(p.get_deployments(&service_id, 20, 5).await.unwrap(), vec![]);
+        assert_eq!(p.count_deployments(&service_id).await.unwrap(), 10);
     }
 
     #[tokio::test(flavor = "multi_thread")]
@@ -877,6 +1359,7 @@ mod tests {
         let id_2 = Uuid::new_v4();
         let id_3 = Uuid::new_v4();
         let id_crashed = Uuid::new_v4();
+        let id_dry_run = Uuid::new_v4();
 
         for deployment in [
             Deployment {
@@ -924,6 +1407,15 @@ mod tests {
                 is_next: false,
                 ..Default::default()
             },
+            Deployment {
+                id: id_dry_run,
+                service_id: foo_id,
+                state: State::Completed,
+                last_update: Utc.with_ymd_and_hms(2022, 4, 25, 4, 43, 10).unwrap(),
+                dry_run: true,
+                tests_passed: Some(true),
+                ..Default::default()
+            },
         ] {
             p.insert_deployment(deployment).await.unwrap();
         }
@@ -942,6 +1434,10 @@ mod tests {
         let runnable = p.get_runnable_deployment(&id_crashed).await.unwrap();
         assert_eq!(runnable, None);
 
+        // Dry runs were never meant to run
+        let runnable = p.get_runnable_deployment(&id_dry_run).await.unwrap();
+        assert_eq!(runnable, None);
+
         let runnable = p.get_all_runnable_deployments().await.unwrap();
         assert_eq!(
             runnable,
@@ -982,9 +1478,14 @@ mod tests {
             line: Some(12),
             target: "tests::log_insert".to_string(),
             fields: json!({"message": "job queued"}),
+            trace_id: None,
+            r#type: LogType::Event,
+            sequence: 1,
         };
 
-        insert_log(&p.pool, log.clone()).await.unwrap();
+        insert_log(&p.pool, log.clone(), &LogStorage::default())
+            .await
+            .unwrap();
 
         let logs = p.get_deployment_logs(&deployment_id).await.unwrap();
         assert!(!logs.is_empty(), "there should be one log");
@@ -1007,6 +1508,9 @@ mod tests {
             line: Some(5),
             target: "tests::logs_for_deployment".to_string(),
             fields: json!({"message": "job queued"}),
+            trace_id: None,
+            r#type: LogType::Event,
+            sequence: 1,
         };
         let log_b = Log {
             id: deployment_b,
@@ -1017,6 +1521,9 @@ mod tests {
             line: Some(5),
             target: "tests::logs_for_deployment".to_string(),
             fields: json!({"message": "job queued"}),
+            trace_id: None,
+            r#type: LogType::Event,
+            sequence: 2,
         };
         let log_a2 = Log {
             id: deployment_a,
@@ -1027,10 +1534,15 @@ mod tests {
             line: None,
             target: String::new(),
             fields: json!({"message": "unused Result"}),
+            trace_id: None,
+            r#type: LogType::Event,
+            sequence: 3,
         };
 
         for log in [log_a1.clone(), log_b, log_a2.clone()] {
-            insert_log(&p.pool, log).await.unwrap();
+            insert_log(&p.pool, log, &LogStorage::default())
+                .await
+                .unwrap();
         }
 
         let logs = p.get_deployment_logs(&deployment_a).await.unwrap();
@@ -1040,60 +1552,465 @@ mod tests {
     }
 
     #[tokio::test(flavor = "multi_thread")]
-    async fn log_recorder_event() {
-        let (p, handle) = Persistence::new_in_memory().await;
+    async fn logs_compressed() {
+        let (p, _) = Persistence::new_in_memory().await;
         let deployment_id = add_deployment(&p.pool).await.unwrap();
+        let start = Utc::now();
 
-        let event = deploy_layer::Log {
-            id: deployment_id,
-            timestamp: Utc::now(),
-            state: State::Queued,
-            level: Level::Info,
-            file: Some("file.rs".to_string()),
-            line: Some(5),
-            target: "tests::log_recorder_event".to_string(),
-            fields: json!({"message": "job queued"}),
-            r#type: deploy_layer::LogType::Event,
-        };
-
-        p.record(event);
-
-        // Drop channel and wait for it to finish
-        drop(p.log_send);
-        assert!(handle.await.is_ok());
+        let logs: Vec<_> = (0..2)
+            .map(|i| Log {
+                id: deployment_id,
+                timestamp: start + chrono::Duration::milliseconds(i),
+                state: State::Running,
+                level: Level::Info,
+                file: None,
+                line: None,
+                target: "tests::logs_compressed".to_string(),
+                fields: json!({ "message": "a chatty line ".repeat(100) }),
+                trace_id: None,
+                r#type: LogType::Event,
+                sequence: i + 1,
+            })
+            .collect();
 
-        let logs = get_deployment_logs(&p.pool, &deployment_id).await.unwrap();
+        // Logs stored before compression was turned on can still be read
+        insert_log(&p.pool, logs[0].clone(), &LogStorage::default())
+            .await
+            .unwrap();
+        insert_log(
+            &p.pool,
+            logs[1].clone(),
+            &LogStorage {
+                compress_fields: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
 
-        assert!(!logs.is_empty(), "there should be one log");
+        let sizes: Vec<(i64,)> =
+            sqlx::query_as("SELECT length(fields) FROM logs WHERE id = ? ORDER BY timestamp")
+                .bind(deployment_id)
+                .fetch_all(&p.pool)
+                .await
+                .unwrap();
+        assert!(sizes[1].0 < sizes[0].0 / 10, "fields should be compressed");
 
-        let log = logs.first().unwrap();
-        assert_eq!(log.id, deployment_id);
-        assert_eq!(log.state, State::Queued);
-        assert_eq!(log.level, Level::Info);
-        assert_eq!(log.file, Some("file.rs".to_string()));
-        assert_eq!(log.line, Some(5));
-        assert_eq!(log.fields, json!({"message": "job queued"}));
+        assert_eq!(p.get_deployment_logs(&deployment_id).await.unwrap(), logs);
     }
 
     #[tokio::test(flavor = "multi_thread")]
-    async fn log_recorder_state() {
-        let (p, handle) = Persistence::new_in_memory().await;
+    async fn logs_for_deployment_by_cursor() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let deployment_id = add_deployment(&p.pool).await.unwrap();
+        let start = Utc::now();
+
+        // Pages end in the middle of logs sharing a timestamp
+        let mut expected = Vec::new();
+        for i in 0..7 {
+            let log = Log {
+                id: deployment_id,
+                timestamp: start + chrono::Duration::milliseconds(i / 2),
+                state: State::Running,
+                level: Level::Info,
+                file: None,
+                line: None,
+                target: "tests::logs_for_deployment_by_cursor".to_string(),
+                fields: json!({ "message": format!("line {i}") }),
+                trace_id: None,
+                r#type: LogType::Event,
+                sequence: i + 1,
+            };
+            insert_log(&p.pool, log.clone(), &LogStorage::default())
+                .await
+                .unwrap();
+            expected.push(log);
+        }
 
-        let id = Uuid::new_v4();
-        let service_id = add_service(&p.pool).await.unwrap();
+        let mut logs = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = p
+                .get_deployment_logs_after(&deployment_id, cursor, 3, None, None)
+                .await
+                .unwrap();
+            assert!(page.len() <= 3);
 
-        p.insert_deployment(Deployment {
-            id,
-            service_id,
-            state: State::Queued, // Should be different from the state recorded below
-            last_update: Utc.with_ymd_and_hms(2022, 4, 29, 2, 39, 39).unwrap(),
-            address: None,
-            is_next: false,
-            ..Default::default()
-        })
-        .await
-        .unwrap();
-        let state = deploy_layer::Log {
+            match page.last() {
+                Some(last) => cursor = Some(last.sequence),
+                None => break,
+            }
+
+            logs.extend(page);
+        }
+
+        assert_eq!(logs, expected);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn logs_for_deployment_by_type() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let deployment_id = add_deployment(&p.pool).await.unwrap();
+        let start = Utc::now();
+
+        let logs: Vec<_> = [
+            (LogType::State, json!(STATE_MESSAGE)),
+            (
+                LogType::BuildOutput,
+                json!({"build_line": "Compiling hello"}),
+            ),
+            (LogType::Event, json!({"message": "building"})),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (r#type, fields))| Log {
+            id: deployment_id,
+            timestamp: start + chrono::Duration::milliseconds(i as i64),
+            state: State::Building,
+            level: Level::Info,
+            file: None,
+            line: None,
+            target: String::new(),
+            fields,
+            trace_id: None,
+            r#type,
+            sequence: i as i64 + 1,
+        })
+        .collect();
+
+        for log in logs.iter().cloned() {
+            insert_log(&p.pool, log, &LogStorage::default())
+                .await
+                .unwrap();
+        }
+
+        let build_output = p
+            .get_deployment_logs_after(
+                &deployment_id,
+                None,
+                u32::MAX,
+                None,
+                Some(LogType::BuildOutput),
+            )
+            .await
+            .unwrap();
+        assert_eq!(build_output, vec![logs[1].clone()]);
+
+        // Only the state change and compiler output are shown while building
+        let items: Vec<Option<shuttle_common::LogItem>> =
+            logs.into_iter().map(Into::into).collect();
+        assert!(items[0].is_some());
+        assert_eq!(
+            items[1].as_ref().map(|item| item.fields.clone()),
+            Some(serde_json::to_vec(&json!({"message": "Compiling hello"})).unwrap())
+        );
+        assert!(items[2].is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn logs_for_deployment_by_indexed_field() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let (p, _) = Persistence::from_pool(
+            pool,
+            None,
+            LogStorage {
+                indexed_fields: vec!["request.path".to_string()],
+                ..Default::default()
+            },
+        )
+        .await;
+        let deployment_id = add_deployment(&p.pool).await.unwrap();
+        let start = Utc::now();
+
+        let logs: Vec<_> = [
+            json!({"message": "request", "request.path": "/hello"}),
+            json!({"message": "request", "request.path": "/world"}),
+            json!({"message": "no request"}),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, fields)| Log {
+            id: deployment_id,
+            timestamp: start + chrono::Duration::milliseconds(i as i64),
+            state: State::Running,
+            level: Level::Info,
+            file: None,
+            line: None,
+            target: "tests::logs_for_deployment_by_indexed_field".to_string(),
+            fields,
+            trace_id: None,
+            r#type: LogType::Event,
+            sequence: i as i64 + 1,
+        })
+        .collect();
+
+        for log in logs.iter().cloned() {
+            insert_log(&p.pool, log, &p.log_storage).await.unwrap();
+        }
+
+        let filtered = p
+            .get_deployment_logs_after(
+                &deployment_id,
+                None,
+                u32::MAX,
+                Some(("request.path", "/hello")),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(filtered, vec![logs[0].clone()]);
+
+        // The full fields are still stored
+        let all = p.get_deployment_logs(&deployment_id).await.unwrap();
+        assert_eq!(all, logs);
+
+        // Adding the columns again on a restart leaves them be
+        add_log_field_columns(&p.pool, &p.log_storage.indexed_fields)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            p.get_deployment_logs_after(
+                &deployment_id,
+                None,
+                u32::MAX,
+                Some(("target", "tests")),
+                None
+            )
+            .await,
+            Err(Error::UnindexedLogField(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn logs_retention() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let running = add_deployment(&p.pool).await.unwrap();
+        let crashed = add_deployment(&p.pool).await.unwrap();
+        update_deployment(
+            &p.pool,
+            DeploymentState {
+                id: crashed,
+                state: State::Crashed,
+                last_update: Utc::now(),
+            },
+        )
+        .await
+        .unwrap();
+
+        // Five old logs and two recent logs for each deployment
+        let start = Utc::now() - Duration::days(10);
+        for id in [running, crashed] {
+            for i in 0..7 {
+                let timestamp = if i < 5 {
+                    start + Duration::seconds(i)
+                } else {
+                    Utc::now() - Duration::seconds(10 - i)
+                };
+
+                let log = Log {
+                    id,
+                    timestamp,
+                    state: State::Running,
+                    level: Level::Info,
+                    file: None,
+                    line: None,
+                    target: "tests::logs_retention".to_string(),
+                    fields: json!({ "message": format!("line {i}") }),
+                    trace_id: None,
+                    r#type: LogType::Event,
+                    sequence: i + 1,
+                };
+                insert_log(&p.pool, log, &LogStorage::default())
+                    .await
+                    .unwrap();
+            }
+        }
+
+        // Nothing is deleted by default
+        let deleted = p
+            .enforce_log_retention(&LogRetention::default())
+            .await
+            .unwrap();
+        assert_eq!(deleted, 0);
+
+        let retention = LogRetention {
+            max_age: Some(Duration::days(1)),
+            max_lines: None,
+            terminal_tail: 3,
+        };
+        let deleted = p.enforce_log_retention(&retention).await.unwrap();
+        assert_eq!(deleted, 5 + 4);
+
+        let messages = |logs: Vec<Log>| -> Vec<String> {
+            logs.into_iter()
+                .map(|log| log.fields["message"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        // The crashed deployment keeps its tail, even though one of those logs is old
+        assert_eq!(
+            messages(p.get_deployment_logs(&running).await.unwrap()),
+            vec!["line 5", "line 6"]
+        );
+        assert_eq!(
+            messages(p.get_deployment_logs(&crashed).await.unwrap()),
+            vec!["line 4", "line 5", "line 6"]
+        );
+
+        let retention = LogRetention {
+            max_age: None,
+            max_lines: Some(1),
+            terminal_tail: 3,
+        };
+        let deleted = p.enforce_log_retention(&retention).await.unwrap();
+        assert_eq!(deleted, 1 + 2);
+        assert_eq!(
+            messages(p.get_deployment_logs(&crashed).await.unwrap()),
+            vec!["line 6"]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn log_recorder_event() {
+        let (p, handle) = Persistence::new_in_memory().await;
+        let deployment_id = add_deployment(&p.pool).await.unwrap();
+
+        let event = deploy_layer::Log {
+            id: deployment_id,
+            timestamp: Utc::now(),
+            state: State::Queued,
+            level: Level::Info,
+            file: Some("file.rs".to_string()),
+            line: Some(5),
+            target: "tests::log_recorder_event".to_string(),
+            fields: json!({"message": "job queued"}),
+            trace_id: None,
+            r#type: deploy_layer::LogType::Event,
+        };
+
+        p.record(event);
+
+        // Drop channel and wait for it to finish
+        drop(p.log_send);
+        assert!(handle.await.is_ok());
+
+        let logs = get_deployment_logs(&p.pool, &deployment_id).await.unwrap();
+
+        assert!(!logs.is_empty(), "there should be one log");
+
+        let log = logs.first().unwrap();
+        assert_eq!(log.id, deployment_id);
+        assert_eq!(log.state, State::Queued);
+        assert_eq!(log.level, Level::Info);
+        assert_eq!(log.file, Some("file.rs".to_string()));
+        assert_eq!(log.line, Some(5));
+        assert_eq!(log.fields, json!({"message": "job queued"}));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn log_recorder_sequence() {
+        let (p, handle) = Persistence::new_in_memory().await;
+        let deployment_id = add_deployment(&p.pool).await.unwrap();
+        let start = Utc::now();
+
+        for i in 0..3 {
+            p.record(deploy_layer::Log {
+                id: deployment_id,
+                timestamp: start + Duration::milliseconds(i),
+                state: State::Running,
+                level: Level::Info,
+                file: None,
+                line: None,
+                target: "tests::log_recorder_sequence".to_string(),
+                fields: json!({ "message": format!("line {i}") }),
+                trace_id: None,
+                r#type: deploy_layer::LogType::Event,
+            });
+        }
+
+        // Drop channel and wait for it to finish
+        drop(p.log_send);
+        assert!(handle.await.is_ok());
+
+        let sequences: Vec<i64> = get_deployment_logs(&p.pool, &deployment_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|log| log.sequence)
+            .collect();
+        assert_eq!(sequences, vec![1, 2, 3]);
+
+        // A restart carries on from the last sequence
+        let (p, handle) = Persistence::from_pool(p.pool, None, LogStorage::default()).await;
+        p.record(deploy_layer::Log {
+            id: deployment_id,
+            timestamp: start + Duration::milliseconds(3),
+            state: State::Running,
+            level: Level::Info,
+            file: None,
+            line: None,
+            target: "tests::log_recorder_sequence".to_string(),
+            fields: json!({ "message": "line 3" }),
+            trace_id: None,
+            r#type: deploy_layer::LogType::Event,
+        });
+
+        drop(p.log_send);
+        assert!(handle.await.is_ok());
+
+        let logs = get_deployment_logs(&p.pool, &deployment_id).await.unwrap();
+        assert_eq!(logs.last().unwrap().sequence, 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn log_recorder_restart_attempt() {
+        let (p, handle) = Persistence::new_in_memory().await;
+        let deployment_id = add_deployment(&p.pool).await.unwrap();
+
+        p.record(deploy_layer::Log::restart_attempt(
+            deployment_id,
+            State::Running,
+            RestartAttempt {
+                kind: RestartKind::Restart,
+                attempt: 3,
+            },
+        ));
+
+        // Drop channel and wait for it to finish
+        drop(p.log_send);
+        assert!(handle.await.is_ok());
+
+        let logs = get_deployment_logs(&p.pool, &deployment_id).await.unwrap();
+        let log = logs.first().expect("there should be one log");
+
+        assert_eq!(log.state, State::Running);
+        assert_eq!(log.level, Level::Warn);
+        assert_eq!(
+            log.fields,
+            json!({"message": "restarting (attempt 3)", "restart_kind": "restart", "attempt": 3})
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn log_recorder_state() {
+        let (p, handle) = Persistence::new_in_memory().await;
+
+        let id = Uuid::new_v4();
+        let service_id = add_service(&p.pool).await.unwrap();
+
+        p.insert_deployment(Deployment {
+            id,
+            service_id,
+            state: State::Queued, // Should be different from the state recorded below
+            last_update: Utc.with_ymd_and_hms(2022, 4, 29, 2, 39, 39).unwrap(),
+            address: None,
+            is_next: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let state = deploy_layer::Log {
             id,
             timestamp: Utc.with_ymd_and_hms(2022, 4, 29, 2, 39, 59).unwrap(),
             state: State::Running,
@@ -1102,6 +2019,7 @@ mod tests {
             line: None,
             target: String::new(),
             fields: serde_json::Value::Null,
+            trace_id: None,
             r#type: deploy_layer::LogType::State,
         };
 
@@ -1135,6 +2053,78 @@ mod tests {
         );
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn logs_archived_on_completion() {
+        let store = MockObjectStore::default();
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let (p, _) = Persistence::from_pool(
+            pool,
+            Some(LogArchive::new(store.clone(), "matrix")),
+            LogStorage::default(),
+        )
+        .await;
+        let id = add_deployment(&p.pool).await.unwrap();
+
+        let deployment_log = |state, fields, r#type| deploy_layer::Log {
+            id,
+            timestamp: Utc::now(),
+            state,
+            level: Level::Info,
+            file: None,
+            line: None,
+            target: String::new(),
+            fields,
+            trace_id: None,
+            r#type,
+        };
+
+        p.record(deployment_log(
+            State::Running,
+            json!({"message": "serving requests"}),
+            deploy_layer::LogType::Event,
+        ));
+        p.record(deployment_log(
+            State::Completed,
+            serde_json::Value::Null,
+            deploy_layer::LogType::State,
+        ));
+
+        // The upload happens in the background
+        let mut deployment = get_deployment(&p.pool, &id).await.unwrap().unwrap();
+        for _ in 0..100 {
+            if deployment.logs_object_key.is_some() {
+                break;
+            }
+
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            deployment = get_deployment(&p.pool, &id).await.unwrap().unwrap();
+        }
+
+        let key = format!("matrix/{id}.ndjson");
+        assert_eq!(deployment.logs_object_key, Some(key.clone()));
+
+        let uploads = store.uploads.lock().unwrap();
+        assert_eq!(
+            uploads.len(),
+            1,
+            "only the terminal state triggers an upload"
+        );
+        assert_eq!(uploads[0].0, key);
+
+        let lines: Vec<serde_json::Value> = uploads[0]
+            .1
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["state"], "running");
+        assert_eq!(lines[0]["fields"], json!({"message": "serving requests"}));
+        assert_eq!(lines[1]["state"], "completed");
+        assert_eq!(lines[1]["fields"], json!(STATE_MESSAGE));
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn deployment_resources() {
         let (p, _) = Persistence::new_in_memory().await;
@@ -1184,6 +2174,119 @@ mod tests {
         assert_eq!(resources, vec![resource2, resource4]);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resource_statuses() {
+        let (p, _) = Persistence::new_in_memory().await;
+        let deployment_id = add_deployment(&p.pool).await.unwrap();
+        let service_id = p
+            .get_deployment(&deployment_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .service_id;
+        let database = ResourceType::Database(resource::DatabaseType::Shared(
+            resource::database::SharedType::Postgres,
+        ));
+
+        // A resource from a previous deployment of the service which is not requested yet
+        p.insert_resource(&Resource {
+            service_id,
+            r#type: ResourceType::Secrets,
+            config: json!({}),
+            data: json!({}),
+        })
+        .await
+        .unwrap();
+
+        // Act as a slow provisioner, which only finishes once it is released
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let provisioner = {
+            let p = p.clone();
+            let database = database.clone();
+
+            tokio::spawn(async move {
+                p.record(resource_status_log(
+                    deployment_id,
+                    &database,
+                    ProvisioningStatus::Provisioning,
+                ));
+                released.await.unwrap();
+                p.record(resource_status_log(
+                    deployment_id,
+                    &database,
+                    ProvisioningStatus::Ready,
+                ));
+            })
+        };
+
+        wait_for_resource_statuses(
+            &p,
+            &deployment_id,
+            &service_id,
+            vec![
+                StatusResponse {
+                    r#type: database.clone().into(),
+                    status: ProvisioningStatus::Provisioning,
+                },
+                StatusResponse {
+                    r#type: ResourceType::Secrets.into(),
+                    status: ProvisioningStatus::Pending,
+                },
+            ],
+        )
+        .await;
+
+        release.send(()).unwrap();
+        provisioner.await.unwrap();
+
+        wait_for_resource_statuses(
+            &p,
+            &deployment_id,
+            &service_id,
+            vec![
+                StatusResponse {
+                    r#type: database.clone().into(),
+                    status: ProvisioningStatus::Ready,
+                },
+                StatusResponse {
+                    r#type: ResourceType::Secrets.into(),
+                    status: ProvisioningStatus::Pending,
+                },
+            ],
+        )
+        .await;
+
+        // The timeline keeps every change, in order
+        let timeline = p.get_resource_timeline(&deployment_id).await.unwrap();
+        assert_eq!(
+            timeline
+                .iter()
+                .map(|change| (change.r#type.clone(), change.status))
+                .collect::<Vec<_>>(),
+            vec![
+                (database.clone().into(), ProvisioningStatus::Provisioning),
+                (database.into(), ProvisioningStatus::Ready),
+            ]
+        );
+        assert!(timeline[0].timestamp <= timeline[1].timestamp);
+
+        // Statuses are tracked per deployment
+        assert!(p
+            .get_resource_timeline(&Uuid::new_v4())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            p.get_resource_statuses(&Uuid::new_v4(), &service_id)
+                .await
+                .unwrap(),
+            vec![StatusResponse {
+                r#type: ResourceType::Secrets.into(),
+                status: ProvisioningStatus::Pending,
+            }]
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn secrets() {
         let (p, _) = Persistence::new_in_memory().await;
@@ -1212,6 +2315,7 @@ mod tests {
             .map(|mut i| {
                 // Reset dates for test
                 i.last_update = Default::default();
+                i.created_at = Default::default();
                 i
             })
             .collect();
@@ -1221,18 +2325,53 @@ mod tests {
                 key: "key1".to_string(),
                 value: "value1_updated".to_string(),
                 last_update: Default::default(),
+                created_at: Default::default(),
             },
             Secret {
                 service_id,
                 key: "key3".to_string(),
                 value: "value3".to_string(),
                 last_update: Default::default(),
+                created_at: Default::default(),
             },
         ];
 
         assert_eq!(actual, expected);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn secret_metadata_follows_value_updates() {
+        let (p, _) = Persistence::new_in_memory().await;
+
+        let service_id = add_service(&p.pool).await.unwrap();
+        let get_secret = || async {
+            p.get_secrets(&service_id)
+                .await
+                .unwrap()
+                .into_iter()
+                .next()
+                .unwrap()
+        };
+
+        p.insert_secret(&service_id, "key", "value").await.unwrap();
+        let created = get_secret().await;
+        assert_eq!(created.created_at, created.last_update);
+
+        // Setting the same value again, like a new deploy does, is not an update
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        p.insert_secret(&service_id, "key", "value").await.unwrap();
+        assert_eq!(get_secret().await, created);
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        p.insert_secret(&service_id, "key", "new value")
+            .await
+            .unwrap();
+        let updated = get_secret().await;
+        assert_eq!(updated.value, "new value");
+        assert_eq!(updated.created_at, created.created_at);
+        assert!(updated.last_update > created.last_update);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn service() {
         let (p, _) = Persistence::new_in_memory().await;
@@ -1257,17 +2396,39 @@ mod tests {
             .is_none());
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn service_renamed_with_project() {
+        let (p, _) = Persistence::new_in_memory().await;
+
+        let service = p.get_or_create_service("old-name").await.unwrap();
+
+        p.rename_service("new-name").await.unwrap();
+        assert!(p.get_service_by_name("old-name").await.unwrap().is_none());
+        let renamed = p.get_service_by_name("new-name").await.unwrap().unwrap();
+        assert_eq!(
+            renamed.id, service.id,
+            "the deployments of the service are kept"
+        );
+
+        // With several services there is no telling which one the project is
+        p.get_or_create_service("other-name").await.unwrap();
+        p.rename_service("newer-name").await.unwrap();
+        assert!(p.get_service_by_name("newer-name").await.unwrap().is_none());
+        assert!(p.get_service_by_name("new-name").await.unwrap().is_some());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn address_getter() {
         let (p, _) = Persistence::new_in_memory().await;
         let service_id = add_service_named(&p.pool, "service-name").await.unwrap();
         let service_other_id = add_service_named(&p.pool, "other-name").await.unwrap();
+        let id = Uuid::new_v4();
 
         sqlx::query(
             "INSERT INTO deployments (id, service_id, state, last_update, address) VALUES (?, ?, ?, ?, ?), (?, ?, ?, ?, ?), (?, ?, ?, ?, ?)",
         )
         // This running item should match
-        .bind(Uuid::new_v4())
+        .bind(id)
         .bind(service_id)
         .bind(State::Running)
         .bind(Utc::now())
@@ -1289,7 +2450,7 @@ mod tests {
         .unwrap();
 
         assert_eq!(
-            SocketAddr::from(([10, 0, 0, 5], 12356)),
+            (id, SocketAddr::from(([10, 0, 0, 5], 12356))),
             p.get_address_for_service("service-name")
                 .await
                 .unwrap()
@@ -1359,6 +2520,152 @@ mod tests {
         assert_eq!(actual, vec![id_1, id_2]);
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_all_active_deployments() {
+        let (p, _) = Persistence::new_in_memory().await;
+
+        let service_id = add_service_named(&p.pool, "matrix").await.unwrap();
+        let other_service_id = add_service_named(&p.pool, "zion").await.unwrap();
+        let id = Uuid::new_v4();
+        let loading_id = Uuid::new_v4();
+
+        for deployment in [
+            Deployment {
+                id,
+                service_id,
+                state: State::Built,
+                last_update: Utc.with_ymd_and_hms(2022, 4, 25, 4, 29, 33).unwrap(),
+                ..Default::default()
+            },
+            Deployment {
+                id: Uuid::new_v4(),
+                service_id,
+                state: State::Stopped,
+                last_update: Utc.with_ymd_and_hms(2022, 4, 25, 4, 29, 44).unwrap(),
+                ..Default::default()
+            },
+            Deployment {
+                id: loading_id,
+                service_id: other_service_id,
+                state: State::Loading,
+                last_update: Utc.with_ymd_and_hms(2022, 4, 25, 4, 33, 48).unwrap(),
+                ..Default::default()
+            },
+        ] {
+            p.insert_deployment(deployment).await.unwrap();
+        }
+
+        let actual = p.get_all_active_deployments().await.unwrap();
+        assert_eq!(
+            actual,
+            vec![ActiveDeployment {
+                id: loading_id,
+                service_id: other_service_id,
+                service_name: "zion".to_string(),
+                state: State::Loading,
+            }]
+        );
+
+        // The built deployment becomes active once it reaches running
+        update_deployment(
+            &p.pool,
+            DeploymentState {
+                id,
+                state: State::Running,
+                last_update: Utc.with_ymd_and_hms(2022, 4, 25, 4, 43, 33).unwrap(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let actual = p.get_all_active_deployments().await.unwrap();
+        assert_eq!(
+            actual,
+            vec![
+                ActiveDeployment {
+                    id,
+                    service_id,
+                    service_name: "matrix".to_string(),
+                    state: State::Running,
+                },
+                ActiveDeployment {
+                    id: loading_id,
+                    service_id: other_service_id,
+                    service_name: "zion".to_string(),
+                    state: State::Loading,
+                },
+            ]
+        );
+    }
+
+    /// Keeps the uploaded objects in memory instead of sending them to S3
+    #[derive(Clone, Default)]
+    struct MockObjectStore {
+        uploads: Arc<Mutex<Vec<(String, Vec<u8>)>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ObjectStore for MockObjectStore {
+        async fn upload_bytes(
+            &self,
+            key: &str,
+            bytes: Vec<u8>,
+        ) -> std::result::Result<(), BoxError> {
+            self.uploads.lock().unwrap().push((key.to_string(), bytes));
+            Ok(())
+        }
+    }
+
+    fn resource_status_log(
+        id: Uuid,
+        r#type: &ResourceType,
+        status: ProvisioningStatus,
+    ) -> deploy_layer::Log {
+        deploy_layer::Log {
+            id,
+            timestamp: Utc::now(),
+            state: State::Loading,
+            level: Level::Info,
+            file: None,
+            line: None,
+            target: "shuttle_runtime::provisioner_factory".to_string(),
+            fields: json!({
+                "message": "provisioning update",
+                RESOURCE_TYPE_FIELD: r#type.to_string(),
+                RESOURCE_STATUS_FIELD: status.to_string(),
+            }),
+            trace_id: None,
+            r#type: deploy_layer::LogType::Event,
+        }
+    }
+
+    /// The logs are persisted in the background, so poll until the statuses catch up
+    async fn wait_for_resource_statuses(
+        p: &Persistence,
+        deployment_id: &Uuid,
+        service_id: &Uuid,
+        expected: Vec<StatusResponse>,
+    ) {
+        for _ in 0..100 {
+            if p.get_resource_statuses(deployment_id, service_id)
+                .await
+                .unwrap()
+                == expected
+            {
+                return;
+            }
+
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(
+            p.get_resource_statuses(deployment_id, service_id)
+                .await
+                .unwrap(),
+            expected
+        );
+    }
+
     async fn add_deployment(pool: &SqlitePool) -> Result<Uuid> {
         let service_id = add_service(pool).await?;
         let deployment_id = Uuid::new_v4();
@@ -14,6 +14,7 @@ pub enum AwsRdsType {
     Postgres,
     MySql,
     MariaDB,
+    SqlServer,
 }
 
 #[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
@@ -21,6 +22,8 @@ pub enum AwsRdsType {
 pub enum SharedType {
     Postgres,
     MongoDb,
+    MySql,
+    Sqlite,
 }
 
 impl From<Type> for shuttle_common::database::Type {
@@ -38,6 +41,7 @@ impl From<AwsRdsType> for shuttle_common::database::AwsRdsEngine {
             AwsRdsType::Postgres => Self::Postgres,
             AwsRdsType::MySql => Self::MySql,
             AwsRdsType::MariaDB => Self::MariaDB,
+            AwsRdsType::SqlServer => Self::SqlServer,
         }
     }
 }
@@ -47,6 +51,8 @@ impl From<SharedType> for shuttle_common::database::SharedEngine {
         match shared_type {
             SharedType::Postgres => Self::Postgres,
             SharedType::MongoDb => Self::MongoDb,
+            SharedType::MySql => Self::MySql,
+            SharedType::Sqlite => Self::Sqlite,
         }
     }
 }
@@ -66,6 +72,7 @@ impl From<shuttle_common::database::AwsRdsEngine> for AwsRdsType {
             shuttle_common::database::AwsRdsEngine::Postgres => Self::Postgres,
             shuttle_common::database::AwsRdsEngine::MySql => Self::MySql,
             shuttle_common::database::AwsRdsEngine::MariaDB => Self::MariaDB,
+            shuttle_common::database::AwsRdsEngine::SqlServer => Self::SqlServer,
         }
     }
 }
@@ -75,6 +82,8 @@ impl From<shuttle_common::database::SharedEngine> for SharedType {
         match shared_type {
             shuttle_common::database::SharedEngine::Postgres => Self::Postgres,
             shuttle_common::database::SharedEngine::MongoDb => Self::MongoDb,
+            shuttle_common::database::SharedEngine::MySql => Self::MySql,
+            shuttle_common::database::SharedEngine::Sqlite => Self::Sqlite,
         }
     }
 }
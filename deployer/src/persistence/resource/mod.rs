@@ -1,4 +1,5 @@
 pub mod database;
+pub mod queue;
 
 use sqlx::{
     sqlite::{SqliteArgumentValue, SqliteValueRef},
@@ -8,6 +9,7 @@ use std::{borrow::Cow, fmt::Display, str::FromStr};
 use uuid::Uuid;
 
 pub use self::database::Type as DatabaseType;
+pub use self::queue::Type as QueueType;
 
 /// Types that can record and retrieve resource allocations
 #[async_trait::async_trait]
@@ -43,6 +45,8 @@ pub enum Type {
     StaticFolder,
     Persist,
     Turso,
+    Redis,
+    Queue(QueueType),
     Custom,
 }
 
@@ -54,6 +58,8 @@ impl From<Type> for shuttle_common::resource::Type {
             Type::StaticFolder => Self::StaticFolder,
             Type::Persist => Self::Persist,
             Type::Turso => Self::Turso,
+            Type::Redis => Self::Redis,
+            Type::Queue(r#type) => Self::Queue(r#type.into()),
             Type::Custom => Self::Custom,
         }
     }
@@ -67,6 +73,8 @@ impl From<shuttle_common::resource::Type> for Type {
             shuttle_common::resource::Type::StaticFolder => Self::StaticFolder,
             shuttle_common::resource::Type::Persist => Self::Persist,
             shuttle_common::resource::Type::Turso => Self::Turso,
+            shuttle_common::resource::Type::Redis => Self::Redis,
+            shuttle_common::resource::Type::Queue(r#type) => Self::Queue(r#type.into()),
             shuttle_common::resource::Type::Custom => Self::Custom,
         }
     }
@@ -80,6 +88,8 @@ impl Display for Type {
             Type::StaticFolder => write!(f, "static_folder"),
             Type::Persist => write!(f, "persist"),
             Type::Turso => write!(f, "turso"),
+            Type::Redis => write!(f, "redis"),
+            Type::Queue(queue_type) => write!(f, "queue::{queue_type}"),
             Type::Custom => write!(f, "custom"),
         }
     }
@@ -92,6 +102,9 @@ impl FromStr for Type {
         if let Some((prefix, rest)) = s.split_once("::") {
             match prefix {
                 "database" => Ok(Self::Database(DatabaseType::from_str(rest)?)),
+                "queue" => Ok(Self::Queue(
+                    QueueType::from_str(rest).map_err(|e| e.to_string())?,
+                )),
                 _ => Err(format!("'{prefix}' is an unknown resource type")),
             }
         } else {
@@ -100,6 +113,7 @@ impl FromStr for Type {
                 "static_folder" => Ok(Self::StaticFolder),
                 "persist" => Ok(Self::Persist),
                 "turso" => Ok(Self::Turso),
+                "redis" => Ok(Self::Redis),
                 "custom" => Ok(Self::Custom),
                 _ => Err(format!("'{s}' is an unknown resource type")),
             }
@@ -136,7 +150,7 @@ impl<'r> sqlx::Decode<'r, Sqlite> for Type {
 mod tests {
     use std::str::FromStr;
 
-    use super::{database, Type};
+    use super::{database, queue, Type};
 
     #[test]
     fn to_string_and_back() {
@@ -144,12 +158,18 @@ mod tests {
             Type::Database(database::Type::AwsRds(database::AwsRdsType::Postgres)),
             Type::Database(database::Type::AwsRds(database::AwsRdsType::MySql)),
             Type::Database(database::Type::AwsRds(database::AwsRdsType::MariaDB)),
+            Type::Database(database::Type::AwsRds(database::AwsRdsType::SqlServer)),
             Type::Database(database::Type::Shared(database::SharedType::Postgres)),
             Type::Database(database::Type::Shared(database::SharedType::MongoDb)),
+            Type::Database(database::Type::Shared(database::SharedType::MySql)),
+            Type::Database(database::Type::Shared(database::SharedType::Sqlite)),
             Type::Secrets,
             Type::StaticFolder,
             Type::Persist,
             Type::Turso,
+            Type::Redis,
+            Type::Queue(queue::Type::RabbitMq),
+            Type::Queue(queue::Type::Sqs),
             Type::Custom,
         ];
 
@@ -157,5 +177,14 @@ mod tests {
             let actual = Type::from_str(&input.to_string()).unwrap();
             assert_eq!(input, actual, ":{} should map back to itself", input);
         }
+
+        assert_eq!(
+            Type::from_str("database::aws_rds::sqlserver").unwrap(),
+            Type::Database(database::Type::AwsRds(database::AwsRdsType::SqlServer))
+        );
+        assert_eq!(
+            database::Type::from_str("shared::mysql").unwrap(),
+            database::Type::Shared(database::SharedType::MySql)
+        );
     }
 }
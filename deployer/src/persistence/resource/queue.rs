@@ -0,0 +1,26 @@
+use strum::{Display, EnumString};
+
+#[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum Type {
+    RabbitMq,
+    Sqs,
+}
+
+impl From<Type> for shuttle_common::queue::Type {
+    fn from(r#type: Type) -> Self {
+        match r#type {
+            Type::RabbitMq => Self::RabbitMq,
+            Type::Sqs => Self::Sqs,
+        }
+    }
+}
+
+impl From<shuttle_common::queue::Type> for Type {
+    fn from(r#type: shuttle_common::queue::Type) -> Self {
+        match r#type {
+            shuttle_common::queue::Type::RabbitMq => Self::RabbitMq,
+            shuttle_common::queue::Type::Sqs => Self::Sqs,
+        }
+    }
+}
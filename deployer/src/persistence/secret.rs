@@ -28,6 +28,7 @@ pub struct Secret {
     pub key: String,
     pub value: String,
     pub last_update: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
 }
 
 impl From<Secret> for shuttle_common::models::secret::Response {
@@ -38,3 +39,13 @@ impl From<Secret> for shuttle_common::models::secret::Response {
         }
     }
 }
+
+impl From<Secret> for shuttle_common::models::secret::Metadata {
+    fn from(secret: Secret) -> Self {
+        Self {
+            key: secret.key,
+            created_at: secret.created_at,
+            last_update: secret.last_update,
+        }
+    }
+}
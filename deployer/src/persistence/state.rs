@@ -1,38 +1,57 @@
 use strum::{Display, EnumString};
 use utoipa::ToSchema;
 
-/// States a deployment can be in
+/// States a deployment can be in. Parsing a state ignores case and accepts the verb it is named after,
+/// like "run" for [State::Running]
 #[derive(sqlx::Type, Debug, Display, Clone, Copy, EnumString, PartialEq, Eq, ToSchema)]
 #[strum(ascii_case_insensitive)]
 pub enum State {
     /// Deployment is queued to be build
+    #[strum(to_string = "Queued", serialize = "queue")]
     Queued,
 
     /// Deployment is building, but is not done yet
+    #[strum(to_string = "Building", serialize = "build")]
     Building,
 
     /// Deployment is built, but has not been started yet
     Built,
 
-    /// Deployment is being loaded and resources are provisioned
+    /// Deployment is being loaded
+    #[strum(to_string = "Loading", serialize = "load")]
     Loading,
 
+    /// The resources of the deployment are being provisioned, after which it starts running
+    #[strum(to_string = "Provisioning", serialize = "provision")]
+    Provisioning,
+
     /// Deployment is running - ie. its thread is active
+    #[strum(to_string = "Running", serialize = "run")]
     Running,
 
     /// Deployment was running, but stopped running all by itself. This is expected for things like background workers
+    #[strum(to_string = "Completed", serialize = "complete")]
     Completed,
 
     /// Deployment was running, but has been stopped by the user.
+    #[strum(to_string = "Stopped", serialize = "stop")]
     Stopped,
 
     /// Something in the deployment process failed
+    #[strum(to_string = "Crashed", serialize = "crash")]
     Crashed,
 
     /// We never expect this state and entering this state should be considered a bug
     Unknown,
 }
 
+impl State {
+    /// Whether the deployment is done and will not change state anymore
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Completed | Self::Stopped | Self::Crashed)
+    }
+}
+
 impl Default for State {
     fn default() -> Self {
         Self::Unknown
@@ -46,6 +65,7 @@ impl From<State> for shuttle_common::deployment::State {
             State::Building => Self::Building,
             State::Built => Self::Built,
             State::Loading => Self::Loading,
+            State::Provisioning => Self::Provisioning,
             State::Running => Self::Running,
             State::Completed => Self::Completed,
             State::Stopped => Self::Stopped,
@@ -62,6 +82,7 @@ impl From<shuttle_common::deployment::State> for State {
             shuttle_common::deployment::State::Building => Self::Building,
             shuttle_common::deployment::State::Built => Self::Built,
             shuttle_common::deployment::State::Loading => Self::Loading,
+            shuttle_common::deployment::State::Provisioning => Self::Provisioning,
             shuttle_common::deployment::State::Running => Self::Running,
             shuttle_common::deployment::State::Completed => Self::Completed,
             shuttle_common::deployment::State::Stopped => Self::Stopped,
@@ -82,5 +103,14 @@ mod tests {
         assert_eq!(State::Building, State::from_str("builDing").unwrap());
         assert_eq!(State::Queued, State::from_str("queued").unwrap());
         assert_eq!(State::Stopped, State::from_str("Stopped").unwrap());
+        assert_eq!(State::Running, State::from_str("run").unwrap());
+        assert_eq!(State::Building, State::from_str("BUILD").unwrap());
+        assert!(State::from_str("runing").is_err());
+    }
+
+    #[test]
+    fn test_state_display() {
+        assert_eq!(State::Running.to_string(), "Running");
+        assert_eq!(State::Provisioning.to_string(), "Provisioning");
     }
 }
@@ -1,6 +1,7 @@
 use std::{
     convert::Infallible,
     net::{IpAddr, SocketAddr},
+    time::Instant,
 };
 
 use async_trait::async_trait;
@@ -18,17 +19,21 @@ use opentelemetry_http::HeaderExtractor;
 use shuttle_common::backends::headers::XShuttleProject;
 use tracing::{error, field, instrument, trace, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+use crate::metrics::{MetricsSender, RequestMetric};
 
 static PROXY_CLIENT: Lazy<ReverseProxy<HttpConnector<GaiResolver>>> =
     Lazy::new(|| ReverseProxy::new(Client::new()));
 static SERVER_HEADER: Lazy<HeaderValue> = Lazy::new(|| "shuttle.rs".parse().unwrap());
 
-#[instrument(name = "proxy_request", skip(address_getter), fields(http.method = %req.method(), http.uri = %req.uri(), http.status_code = field::Empty, service = field::Empty))]
+#[instrument(name = "proxy_request", skip(address_getter, metrics_sender), fields(http.method = %req.method(), http.uri = %req.uri(), http.status_code = field::Empty, service = field::Empty))]
 pub async fn handle(
     remote_address: SocketAddr,
     fqdn: FQDN,
     req: Request<Body>,
     address_getter: impl AddressGetter,
+    metrics_sender: MetricsSender,
 ) -> Result<Response<Body>, Infallible> {
     let span = Span::current();
     let parent_context = global::get_text_map_propagator(|propagator| {
@@ -52,6 +57,7 @@ pub async fn handle(
         }
     };
 
+    // The gateway forwards requests to the other domains of the project as this fqdn
     if host != fqdn {
         trace!(?host, "proxy won't serve foreign domain");
         return Ok(Response::builder()
@@ -76,28 +82,37 @@ pub async fn handle(
     // Record current service for tracing purposes
     span.record("service", &service);
 
-    let proxy_address = match address_getter.get_address_for_service(&service).await {
-        Ok(Some(address)) => address,
-        Ok(None) => {
-            trace!(?host, service, "service not found on this server");
-            let response_body = format!("could not find service: {}", service);
-            return Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(response_body.into())
-                .unwrap());
-        }
-        Err(err) => {
-            error!(error = %err, service, "proxy failed to find address for host");
+    let (deployment_id, proxy_address) =
+        match address_getter.get_address_for_service(&service).await {
+            Ok(Some(found)) => found,
+            Ok(None) => {
+                trace!(?host, service, "service not found on this server");
+                let response_body = format!("could not find service: {}", service);
+                return Ok(Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(response_body.into())
+                    .unwrap());
+            }
+            Err(err) => {
+                error!(error = %err, service, "proxy failed to find address for host");
 
-            let response_body = format!("failed to find service for host: {}", host);
-            return Ok(Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(response_body.into())
-                .unwrap());
-        }
+                let response_body = format!("failed to find service for host: {}", host);
+                return Ok(Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(response_body.into())
+                    .unwrap());
+            }
+        };
+
+    let start = Instant::now();
+    let response = reverse_proxy(remote_address.ip(), &proxy_address.to_string(), req).await;
+    let status = match &response {
+        Ok(response) => response.status(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
     };
+    record_metric(&metrics_sender, deployment_id, status, start);
 
-    match reverse_proxy(remote_address.ip(), &proxy_address.to_string(), req).await {
+    match response {
         Ok(response) => {
             Span::current().record("http.status_code", response.status().as_u16());
             Ok(response)
@@ -125,12 +140,29 @@ pub async fn handle(
     }
 }
 
+/// Send the metric of a request without ever holding up the response
+fn record_metric(
+    metrics_sender: &MetricsSender,
+    deployment_id: Uuid,
+    status: StatusCode,
+    start: Instant,
+) {
+    let metric = RequestMetric {
+        deployment_id,
+        status: status.as_u16(),
+        latency: start.elapsed(),
+    };
+
+    metrics_sender.send(metric);
+}
+
 #[async_trait]
 pub trait AddressGetter: Clone + Send + Sync + 'static {
+    /// Get the id and address of the deployment currently running the service
     async fn get_address_for_service(
         &self,
         service_name: &str,
-    ) -> crate::handlers::Result<Option<SocketAddr>>;
+    ) -> crate::handlers::Result<Option<(Uuid, SocketAddr)>>;
 }
 
 #[instrument(skip(req))]
@@ -7,10 +7,10 @@ use shuttle_proto::runtime::{
 };
 use tokio::{process, sync::Mutex};
 use tonic::transport::Channel;
-use tracing::{debug, info, trace};
+use tracing::{debug, info, trace, warn};
 use uuid::Uuid;
 
-use crate::deployment::deploy_layer;
+use crate::deployment::deploy_layer::{self, SecretRedactor};
 
 const MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
 
@@ -35,6 +35,7 @@ pub struct RuntimeManager {
     provisioner_address: String,
     auth_uri: Option<String>,
     log_sender: crossbeam_channel::Sender<deploy_layer::Log>,
+    redactor: SecretRedactor,
 }
 
 impl RuntimeManager {
@@ -43,6 +44,7 @@ impl RuntimeManager {
         provisioner_address: String,
         auth_uri: Option<String>,
         log_sender: crossbeam_channel::Sender<deploy_layer::Log>,
+        redactor: SecretRedactor,
     ) -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(Self {
             runtimes: Default::default(),
@@ -50,6 +52,7 @@ impl RuntimeManager {
             provisioner_address,
             auth_uri,
             log_sender,
+            redactor,
         }))
     }
 
@@ -119,6 +122,7 @@ impl RuntimeManager {
         .context("failed to start shuttle runtime")?;
 
         let sender = self.log_sender.clone();
+        let redactor = self.redactor.clone();
         let mut stream = runtime_client
             .clone()
             .subscribe_logs(tonic::Request::new(SubscribeLogsRequest {}))
@@ -128,9 +132,14 @@ impl RuntimeManager {
 
         tokio::spawn(async move {
             while let Ok(Some(log)) = stream.message().await {
-                if let Ok(mut log) = deploy_layer::Log::try_from(log) {
-                    log.id = id;
-                    sender.send(log).expect("to send log to persistence");
+                match runtime_log(log, id, &redactor) {
+                    Ok(log) => {
+                        sender.send(log).expect("to send log to persistence");
+                    }
+                    Err(error) => warn!(
+                        error = &error as &dyn std::error::Error,
+                        "failed to parse runtime log"
+                    ),
                 }
             }
         });
@@ -143,6 +152,11 @@ impl RuntimeManager {
         Ok(runtime_client)
     }
 
+    /// Ids of the deployments with a runtime currently up
+    pub fn deployment_ids(&self) -> Vec<Uuid> {
+        self.runtimes.lock().unwrap().keys().copied().collect()
+    }
+
     /// Send a kill / stop signal for a deployment to its running runtime
     pub async fn kill(&mut self, id: &Uuid) -> bool {
         let value = self.runtimes.lock().unwrap().remove(id);
@@ -175,3 +189,59 @@ impl Drop for RuntimeManager {
         }
     }
 }
+
+/// Record a log of the runtime of a deployment, with the values known to the `redactor` scrubbed
+/// the same way they are from the events of the deployer
+fn runtime_log(
+    log: runtime::LogItem,
+    id: Uuid,
+    redactor: &SecretRedactor,
+) -> Result<deploy_layer::Log, shuttle_common::ParseError> {
+    let mut log = deploy_layer::Log::try_from(log)?;
+    log.id = id;
+    redactor.redact(&mut log.fields);
+
+    Ok(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use shuttle_proto::runtime;
+    use uuid::Uuid;
+
+    use super::runtime_log;
+    use crate::deployment::deploy_layer::SecretRedactor;
+
+    #[test]
+    fn runtime_logs_are_redacted() {
+        let redactor = SecretRedactor::new(["hunter42".to_string()]);
+        let id = Uuid::new_v4();
+
+        let log = runtime_log(
+            runtime::LogItem {
+                state: "Running".to_string(),
+                level: runtime::LogLevel::Info as i32,
+                target: "my_service".to_string(),
+                fields: serde_json::to_vec(&json!({
+                    "message": "connecting with hunter42",
+                    "password": "hunter42",
+                }))
+                .unwrap(),
+                ..Default::default()
+            },
+            id,
+            &redactor,
+        )
+        .unwrap();
+
+        assert_eq!(log.id, id);
+        assert_eq!(
+            log.fields,
+            json!({
+                "message": "connecting with ***",
+                "password": "***",
+            })
+        );
+    }
+}
@@ -0,0 +1 @@
+ALTER TABLE projects ADD COLUMN total_restarts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1 @@
+ALTER TABLE projects ADD COLUMN tags JSON NOT NULL DEFAULT '[]';
@@ -0,0 +1 @@
+ALTER TABLE projects ADD COLUMN volume_retained_until INTEGER;
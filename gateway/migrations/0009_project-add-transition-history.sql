@@ -0,0 +1 @@
+ALTER TABLE projects ADD COLUMN transition_history JSON NOT NULL DEFAULT '[]';
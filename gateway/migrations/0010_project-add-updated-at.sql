@@ -0,0 +1,3 @@
+-- Unix timestamp, in milliseconds, of the last change to the project. Projects not changed since
+-- this column was added have none.
+ALTER TABLE projects ADD COLUMN updated_at INTEGER;
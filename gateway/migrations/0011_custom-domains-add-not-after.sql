@@ -0,0 +1,3 @@
+-- Unix timestamp of when the certificate of a custom domain expires, so renewals can be found without
+-- parsing every stored certificate
+ALTER TABLE custom_domains ADD COLUMN not_after INTEGER;
@@ -0,0 +1,3 @@
+-- Seconds a request proxied to the project can take before it is cut off. Projects without one are
+-- only bound by the proxy's defaults.
+ALTER TABLE projects ADD COLUMN request_timeout_secs INTEGER;
@@ -0,0 +1,6 @@
+-- Unix timestamp, in milliseconds, of when the project was created. Existing projects get the
+-- timestamp their ULID was generated with.
+ALTER TABLE projects ADD COLUMN created_at INTEGER;
+UPDATE projects
+SET created_at = CAST(ROUND((julianday(ulid_datetime(project_id)) - 2440587.5) * 86400000) AS INTEGER)
+WHERE created_at IS NULL;
@@ -0,0 +1,13 @@
+-- Record of the lifecycle operations on projects. Entries are keyed by the id of the project so
+-- its log follows it through renames.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id TEXT NOT NULL,
+    project_name TEXT NOT NULL,
+    account_name TEXT NOT NULL,
+    action TEXT NOT NULL,
+    details TEXT,
+    created_at INTEGER NOT NULL -- Unix timestamp, in milliseconds
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_project_id ON audit_log (project_id);
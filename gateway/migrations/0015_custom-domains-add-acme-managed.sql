@@ -0,0 +1,3 @@
+-- Whether the certificate of a custom domain is issued and renewed through ACME. Certificates
+-- uploaded by users are left alone by the renewals.
+ALTER TABLE custom_domains ADD COLUMN acme_managed BOOLEAN NOT NULL DEFAULT 1;
@@ -0,0 +1,4 @@
+-- Requests a second routed to the project on average, and on top of which it can take a burst of
+-- requests. Projects without a rate are not limited.
+ALTER TABLE projects ADD COLUMN rate_limit_per_second INTEGER;
+ALTER TABLE projects ADD COLUMN rate_limit_burst INTEGER;
@@ -0,0 +1,12 @@
+-- Samples of the resources used by projects, kept for a retention window when enabled. Keyed by the
+-- id of the project so its history follows it through renames.
+CREATE TABLE IF NOT EXISTS project_stats (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id TEXT NOT NULL,
+    cpu_usage INTEGER NOT NULL, -- CPU time used since the container started, in nanoseconds
+    memory_usage INTEGER,
+    memory_limit INTEGER,
+    sampled_at INTEGER NOT NULL -- Unix timestamp, in milliseconds
+);
+
+CREATE INDEX IF NOT EXISTS project_stats_project_id_sampled_at ON project_stats (project_id, sampled_at);
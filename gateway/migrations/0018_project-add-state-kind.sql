@@ -0,0 +1,8 @@
+-- Denormalized from `project_state`, which stays the source of truth, so requests can be proxied to
+-- ready projects without parsing their whole state
+ALTER TABLE projects ADD COLUMN state_kind TEXT;
+-- Address of the container of a ready project, NULL otherwise
+ALTER TABLE projects ADD COLUMN target_ip TEXT;
+
+-- The address is only filled in by the next update of each project
+UPDATE projects SET state_kind = (SELECT key FROM json_each(projects.project_state) LIMIT 1);
@@ -0,0 +1,3 @@
+-- Bumped on every write of `project_state`, so writes based on a stale read of the state can be
+-- refused instead of clobbering newer ones
+ALTER TABLE projects ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Denormalized from `project_state` along with `target_ip`: the domain the runtime of a ready project
+-- serves, NULL otherwise
+ALTER TABLE projects ADD COLUMN runtime_fqdn TEXT;
+
+UPDATE projects SET runtime_fqdn = (
+    SELECT fqdn.value
+    FROM json_each(projects.project_state, '$.ready.container.Args') AS arg
+    JOIN json_each(projects.project_state, '$.ready.container.Args') AS fqdn ON fqdn.key = arg.key + 1
+    WHERE arg.value = '--proxy-fqdn'
+)
+WHERE state_kind = 'ready';
@@ -0,0 +1,3 @@
+-- Times the builds of the project are retried when they fail on a transient error. Projects without
+-- one use the default of their deployer.
+ALTER TABLE projects ADD COLUMN max_build_retries INTEGER;
@@ -5,15 +5,15 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use axum::body::Body;
-use axum::extract::{Extension, Path, Query, State};
+use axum::extract::{ConnectInfo, Extension, FromRequestParts, Path, Query, State};
 use axum::handler::Handler;
 use axum::http::Request;
-use axum::middleware::from_extractor;
+use axum::middleware::{from_extractor, from_fn_with_state};
 use axum::response::Response;
 use axum::routing::{any, get, post};
 use axum::{Json as AxumJson, Router};
-use fqdn::FQDN;
-use futures::Future;
+use chrono::{DateTime, Utc};
+use futures::{Future, TryStreamExt};
 use http::Uri;
 use instant_acme::{AccountCredentials, ChallengeType};
 use serde::{Deserialize, Serialize};
@@ -42,16 +42,17 @@ use x509_parser::parse_x509_certificate;
 use x509_parser::pem::parse_x509_pem;
 use x509_parser::time::ASN1Time;
 
-use crate::acme::{AcmeClient, CustomDomain};
+use crate::acme::{custom_domain_identifier, parse_custom_domain, AcmeClient, CustomDomain};
 use crate::auth::{ScopedUser, User};
 use crate::project::{ContainerInspectResponseExt, Project, ProjectCreating};
-use crate::service::GatewayService;
+use crate::service::{GatewayService, MAX_CONTAINER_LOG_LINES};
 use crate::task::{self, BoxedTask, TaskResult};
-use crate::tls::{GatewayCertResolver, RENEWAL_VALIDITY_THRESHOLD_IN_DAYS};
+use crate::tls::GatewayCertResolver;
 use crate::worker::WORKER_QUEUE_SIZE;
-use crate::{Error, ProjectName, AUTH_CLIENT};
+use crate::{AccountName, DockerContext, Error, ProjectName, AUTH_CLIENT};
 
 use super::auth_layer::ShuttleAuthLayer;
+use super::monitoring_layer::{monitoring_token, Monitoring};
 
 pub const SVC_DEGRADED_THRESHOLD: usize = 128;
 pub const SHUTTLE_GATEWAY_VARIANT: &str = "shuttle-gateway";
@@ -77,6 +78,70 @@ pub struct PaginationDetails {
     pub limit: Option<u32>,
 }
 
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+pub struct ProjectTagsFilter {
+    /// Comma separated tags, to only list the projects which have all of them.
+    pub tags: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+pub struct ProjectSearchDetails {
+    /// Text to find in the project or account names.
+    pub query: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, IntoParams)]
+pub struct StartProjectDetails {
+    /// Start the project without starting its last deployment, for when that deployment crashes
+    /// on startup.
+    #[serde(default)]
+    pub safe_mode: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, IntoParams)]
+pub struct CertificateChallengeDetails {
+    /// Complete a dns-01 challenge through the configured DNS provider instead of the default
+    /// http-01 challenge.
+    #[serde(default)]
+    pub dns01: bool,
+}
+
+impl CertificateChallengeDetails {
+    fn challenge_type(
+        &self,
+        acme_client: &AcmeClient,
+        wildcard: bool,
+    ) -> Result<ChallengeType, Error> {
+        if wildcard && !self.dns01 {
+            Err(Error::custom(
+                ErrorKind::InvalidOperation,
+                "certificates of wildcard domains need a dns-01 challenge",
+            ))
+        } else if !self.dns01 {
+            Ok(ChallengeType::Http01)
+        } else if acme_client.has_dns_provider() {
+            Ok(ChallengeType::Dns01)
+        } else {
+            Err(Error::custom(
+                ErrorKind::InvalidOperation,
+                "dns-01 challenges need a DNS provider to be configured",
+            ))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, IntoParams)]
+pub struct StatsHistoryDetails {
+    /// Only return the samples taken since this time. All the stored samples by default.
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, IntoParams)]
+pub struct ContainerLogsDetails {
+    /// Number of lines to fetch from the end of the logs, capped to 1000.
+    pub tail: Option<usize>,
+}
+
 impl StatusResponse {
     pub fn healthy() -> Self {
         Self {
@@ -113,10 +178,11 @@ async fn get_project(
     State(RouterState { service, .. }): State<RouterState>,
     ScopedUser { scope, .. }: ScopedUser,
 ) -> Result<AxumJson<project::Response>, Error> {
-    let state = service.find_project(&scope).await?.into();
+    let project = service.find_user_project(&scope).await?;
+    let transition_history = service.project_transition_history(&scope).await?.into();
     let response = project::Response {
-        name: scope.to_string(),
-        state,
+        transition_history,
+        ..project.into()
     };
 
     Ok(AxumJson(response))
@@ -130,29 +196,386 @@ async fn get_project(
         (status = 500, description = "Server internal error.")
     ),
     params(
-        PaginationDetails
+        PaginationDetails,
+        ProjectTagsFilter
     )
 )]
 async fn get_projects_list(
     State(RouterState { service, .. }): State<RouterState>,
     User { name, .. }: User,
     Query(PaginationDetails { page, limit }): Query<PaginationDetails>,
+    Query(ProjectTagsFilter { tags }): Query<ProjectTagsFilter>,
 ) -> Result<AxumJson<Vec<project::Response>>, Error> {
     let limit = limit.unwrap_or(u32::MAX);
     let page = page.unwrap_or(0);
+    let tags: Vec<String> = tags
+        .iter()
+        .flat_map(|tags| tags.split(','))
+        .filter(|tag| !tag.is_empty())
+        .map(ToString::to_string)
+        .collect();
     let projects = service
         // The `offset` is page size * amount of pages
-        .iter_user_projects_detailed(&name, limit * page, limit)
+        .iter_user_projects_detailed(&name, limit * page, limit, &tags)
         .await?
-        .map(|project| project::Response {
-            name: project.0.to_string(),
-            state: project.1.into(),
-        })
+        .map(Into::into)
         .collect();
 
     Ok(AxumJson(projects))
 }
 
+#[instrument(skip(service))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/domains",
+    responses(
+        (status = 200, description = "Successfully got the custom domains of a project.", body = [shuttle_common::models::project::CustomDomainResponse]),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_project_custom_domains(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+) -> Result<AxumJson<Vec<project::CustomDomainResponse>>, Error> {
+    let domains = service
+        .iter_custom_domains_for_project(&scope)
+        .await?
+        .map(|domain| project::CustomDomainResponse {
+            fqdn: domain.identifier(),
+            certificate_expiry: domain.certificate_expiry(),
+        })
+        .collect();
+
+    Ok(AxumJson(domains))
+}
+
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/request-timeout",
+    responses(
+        (status = 200, description = "Successfully got the request timeout of a project.", body = shuttle_common::models::project::RequestTimeout),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_project_request_timeout(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+) -> Result<AxumJson<project::RequestTimeout>, Error> {
+    let timeout_secs = service
+        .project_request_timeout(&scope)
+        .await?
+        .map(|timeout| timeout.as_secs());
+
+    Ok(AxumJson(project::RequestTimeout { timeout_secs }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/build-retries",
+    responses(
+        (status = 200, description = "Successfully got the build retries of a project.", body = shuttle_common::models::project::BuildRetries),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_project_build_retries(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+) -> Result<AxumJson<project::BuildRetries>, Error> {
+    let max_build_retries = service.project_max_build_retries(&scope).await?;
+
+    Ok(AxumJson(project::BuildRetries { max_build_retries }))
+}
+
+#[instrument(skip_all, fields(project_name = %scope))]
+#[utoipa::path(
+    put,
+    path = "/projects/{project_name}/build-retries",
+    request_body = shuttle_common::models::project::BuildRetries,
+    responses(
+        (status = 200, description = "Successfully set the build retries of a project.", body = shuttle_common::models::project::BuildRetries),
+        (status = 400, description = "The build retries are invalid."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn set_project_build_retries(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+    AxumJson(build_retries): AxumJson<project::BuildRetries>,
+) -> Result<AxumJson<project::BuildRetries>, Error> {
+    service
+        .set_project_max_build_retries(&scope, build_retries.max_build_retries)
+        .await?;
+
+    Ok(AxumJson(build_retries))
+}
+
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/tags",
+    responses(
+        (status = 200, description = "Successfully got the tags of a project.", body = shuttle_common::models::project::Tags),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_project_tags(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+) -> Result<AxumJson<project::Tags>, Error> {
+    let tags = service.project_tags(&scope).await?;
+
+    Ok(AxumJson(project::Tags { tags }))
+}
+
+#[instrument(skip_all, fields(project_name = %scope))]
+#[utoipa::path(
+    put,
+    path = "/projects/{project_name}/tags",
+    request_body = shuttle_common::models::project::Tags,
+    responses(
+        (status = 200, description = "Successfully set the tags of a project.", body = shuttle_common::models::project::Tags),
+        (status = 400, description = "A tag is invalid."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn set_project_tags(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+    AxumJson(tags): AxumJson<project::Tags>,
+) -> Result<AxumJson<project::Tags>, Error> {
+    service.set_project_tags(&scope, &tags.tags).await?;
+
+    Ok(AxumJson(tags))
+}
+
+#[instrument(skip_all, fields(project_name = %scope))]
+#[utoipa::path(
+    put,
+    path = "/projects/{project_name}/request-timeout",
+    request_body = shuttle_common::models::project::RequestTimeout,
+    responses(
+        (status = 200, description = "Successfully set the request timeout of a project.", body = shuttle_common::models::project::RequestTimeout),
+        (status = 400, description = "The request timeout is invalid."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn set_project_request_timeout(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+    AxumJson(request_timeout): AxumJson<project::RequestTimeout>,
+) -> Result<AxumJson<project::RequestTimeout>, Error> {
+    service
+        .set_project_request_timeout(&scope, request_timeout.timeout_secs)
+        .await?;
+
+    Ok(AxumJson(request_timeout))
+}
+
+#[instrument(skip_all, fields(project_name = %scope))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/stats",
+    responses(
+        (status = 200, description = "Successfully got the recent resource usage of a project. Only about the last `idle_minutes` worth of samples are kept, and none for projects which never idle.", body = [shuttle_common::models::project::StatsSample]),
+        (status = 503, description = "The project is not running."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_project_stats(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+) -> Result<AxumJson<Vec<project::StatsSample>>, Error> {
+    let project = service.find_project(&scope).await?;
+
+    // Only running projects are sampled, so there is nothing to report for the others
+    if !project.is_ready() {
+        return Err(Error::from_kind(ErrorKind::ProjectNotReady));
+    }
+
+    Ok(AxumJson(project.stats()))
+}
+
+#[instrument(skip_all, fields(project_name = %scope))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/stats/history",
+    responses(
+        (status = 200, description = "Successfully got the stored resource usage of a project, oldest first.", body = [shuttle_common::models::project::StatsSample]),
+        (status = 400, description = "The gateway does not store the resource usage of projects."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+        StatsHistoryDetails
+    )
+)]
+async fn get_project_stats_history(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+    Query(StatsHistoryDetails { since }): Query<StatsHistoryDetails>,
+) -> Result<AxumJson<Vec<project::StatsSample>>, Error> {
+    let history = service
+        .project_stats_history(&scope, since.unwrap_or_default())
+        .await?;
+
+    Ok(AxumJson(history))
+}
+
+#[instrument(skip_all, fields(project_name = %scope))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/rate-limit",
+    responses(
+        (status = 200, description = "Successfully got the rate limit of a project.", body = shuttle_common::models::project::RateLimit),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_project_rate_limit(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+) -> Result<AxumJson<project::RateLimit>, Error> {
+    let rate_limit = service.project_rate_limit(&scope).await?;
+
+    Ok(AxumJson(rate_limit))
+}
+
+#[instrument(skip_all, fields(project_name = %scope))]
+#[utoipa::path(
+    put,
+    path = "/projects/{project_name}/rate-limit",
+    request_body = shuttle_common::models::project::RateLimit,
+    responses(
+        (status = 200, description = "Successfully set the rate limit of a project.", body = shuttle_common::models::project::RateLimit),
+        (status = 400, description = "The rate limit is invalid."),
+        (status = 403, description = "Only admins can set the rate limit of a project."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn set_project_rate_limit(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+    AxumJson(rate_limit): AxumJson<project::RateLimit>,
+) -> Result<AxumJson<project::RateLimit>, Error> {
+    service.set_project_rate_limit(&scope, &rate_limit).await?;
+
+    Ok(AxumJson(rate_limit))
+}
+
+#[instrument(skip_all, fields(project_name = %scope))]
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/egress-policy",
+    responses(
+        (status = 200, description = "Successfully got the egress policy of a project.", body = shuttle_common::models::project::EgressPolicy),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_project_egress_policy(
+    State(RouterState { service, .. }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+) -> Result<AxumJson<project::EgressPolicy>, Error> {
+    let egress_policy = service
+        .find_project(&scope)
+        .await?
+        .container()
+        .map(|container| container.egress_policy())
+        .unwrap_or_default();
+
+    Ok(AxumJson(egress_policy))
+}
+
+#[instrument(skip_all, fields(project_name = %scope))]
+#[utoipa::path(
+    put,
+    path = "/projects/{project_name}/egress-policy",
+    request_body = shuttle_common::models::project::EgressPolicy,
+    responses(
+        (status = 200, description = "Successfully set the egress policy of a project.", body = shuttle_common::models::project::EgressPolicy),
+        (status = 400, description = "The project is destroyed."),
+        (status = 403, description = "Only admins can set the egress policy of a project."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn set_project_egress_policy(
+    State(RouterState {
+        service, sender, ..
+    }): State<RouterState>,
+    ScopedUser { scope, .. }: ScopedUser,
+    AxumJson(egress_policy): AxumJson<project::EgressPolicy>,
+) -> Result<AxumJson<project::EgressPolicy>, Error> {
+    let project = service.find_project(&scope).await?;
+    let container = match project.container() {
+        Some(container) if !project.is_destroyed() => container,
+        _ => {
+            return Err(Error::custom(
+                ErrorKind::InvalidOperation,
+                "the egress policy of a destroyed project cannot be changed",
+            ))
+        }
+    };
+
+    if container.egress_policy() == egress_policy {
+        return Ok(AxumJson(egress_policy));
+    }
+
+    let idle_minutes =
+        container.idle_minutes(service.context().container_settings().default_idle_minutes);
+
+    // The policy decides the network the container joins, so the project is recreated with it
+    service
+        .new_task()
+        .project(scope.clone())
+        .and_then(task::destroy())
+        .and_then(task::run_until_done())
+        .and_then(task::run(move |ctx| {
+            let creating =
+                ProjectCreating::new_with_random_initial_key(ctx.project_name, idle_minutes)
+                    .with_settings_of(&container)
+                    .with_egress_policy(egress_policy);
+            async move { TaskResult::Done(Project::Creating(creating)) }
+        }))
+        .and_then(task::run_until_done())
+        .send(&sender)
+        .await?;
+
+    Ok(AxumJson(egress_policy))
+}
+
 #[instrument(skip_all, fields(%project))]
 #[utoipa::path(
     post,
@@ -176,7 +599,7 @@ async fn create_project(
     let is_admin = claim.scopes.contains(&Scope::Admin);
 
     let state = service
-        .create_project(project.clone(), name.clone(), is_admin, config.idle_minutes)
+        .create_project(project.clone(), name.clone(), is_admin, config.into())
         .await?;
 
     service
@@ -190,6 +613,9 @@ async fn create_project(
     let response = project::Response {
         name: project.to_string(),
         state: state.into(),
+        transition_history: Vec::new(),
+        created_at: None,
+        updated_at: None,
     };
 
     Ok(AxumJson(response))
@@ -218,6 +644,9 @@ async fn destroy_project(
     let mut response = project::Response {
         name: project.to_string(),
         state: state.into(),
+        transition_history: Vec::new(),
+        created_at: None,
+        updated_at: None,
     };
 
     if response.state == shuttle_common::models::project::State::Destroyed {
@@ -243,42 +672,141 @@ async fn route_project(
         service, sender, ..
     }): State<RouterState>,
     scoped_user: ScopedUser,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     req: Request<Body>,
 ) -> Result<Response<Body>, Error> {
     let project_name = scoped_user.scope;
     let project = service.find_or_start_project(&project_name, sender).await?;
+    let client_ip = connect_info.map(|ConnectInfo(addr)| addr.ip());
 
     service
-        .route(&project, &project_name, &scoped_user.user.name, req)
+        .route(
+            &project,
+            &project_name,
+            &scoped_user.user.name,
+            client_ip,
+            req,
+        )
         .await
 }
 
+#[instrument(skip_all, fields(project_name = %scoped_user.scope, safe_mode = details.safe_mode))]
 #[utoipa::path(
-    get,
-    path = "/",
+    post,
+    path = "/projects/{project_name}/start",
     responses(
-        (status = 200, description = "Get the gateway operational status."),
+        (status = 200, description = "Successfully started the project.", body = shuttle_common::models::project::Response),
         (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+        StartProjectDetails,
     )
 )]
-async fn get_status(
+async fn start_project(
     State(RouterState {
-        sender, service, ..
+        service, sender, ..
     }): State<RouterState>,
-) -> Response<Body> {
-    let mut statuses = Vec::new();
-    // Compute gateway status.
-    if sender.is_closed() || sender.capacity() == 0 {
-        statuses.push((SHUTTLE_GATEWAY_VARIANT, StatusResponse::unhealthy()));
-    } else if sender.capacity() < WORKER_QUEUE_SIZE - SVC_DEGRADED_THRESHOLD {
-        statuses.push((SHUTTLE_GATEWAY_VARIANT, StatusResponse::degraded()));
+    scoped_user: ScopedUser,
+    Query(details): Query<StartProjectDetails>,
+) -> Result<AxumJson<project::Response>, Error> {
+    let project_name = scoped_user.scope;
+
+    let project = if details.safe_mode {
+        service
+            .find_or_start_project_in_safe_mode(&project_name, sender)
+            .await?
     } else {
-        statuses.push((SHUTTLE_GATEWAY_VARIANT, StatusResponse::healthy()));
+        service.find_or_start_project(&project_name, sender).await?
     };
 
-    // Compute provisioner status.
-    let provisioner_status = if let Ok(channel) = service.provisioner_host().connect().await {
-        let channel = ServiceBuilder::new().service(channel);
+    let transition_history = service
+        .project_transition_history(&project_name)
+        .await?
+        .into();
+    let response = project::Response {
+        name: project_name.to_string(),
+        state: project.into(),
+        transition_history,
+        created_at: None,
+        updated_at: None,
+    };
+
+    Ok(AxumJson(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/projects/{project_name}/status",
+    responses(
+        (status = 200, description = "The deployer of the project is up."),
+        (status = 404, description = "Project not found."),
+        (status = 503, description = "Project is not ready."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_project_status(
+    State(state): State<RouterState>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+    let (mut parts, body) = req.into_parts();
+
+    // Uptime tooling only probes the project, so it does not start it when idle
+    if parts.extensions.get::<Monitoring>().is_some() {
+        let Path(project_name) = Path::<ProjectName>::from_request_parts(&mut parts, &state)
+            .await
+            .map_err(|_| Error::from_kind(ErrorKind::ProjectNotFound))?;
+        let project = state.service.find_project(&project_name).await?;
+        let account_name = state
+            .service
+            .account_name_from_project(&project_name)
+            .await?;
+
+        return state
+            .service
+            .route(
+                &project,
+                &project_name,
+                &account_name,
+                Request::from_parts(parts, body),
+            )
+            .await;
+    }
+
+    let scoped_user = ScopedUser::from_request_parts(&mut parts, &state).await?;
+
+    route_project(State(state), scoped_user, Request::from_parts(parts, body)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    responses(
+        (status = 200, description = "Get the gateway operational status."),
+        (status = 500, description = "Server internal error.")
+    )
+)]
+async fn get_status(
+    State(RouterState {
+        sender, service, ..
+    }): State<RouterState>,
+) -> Response<Body> {
+    let mut statuses = Vec::new();
+    // Compute gateway status.
+    if sender.is_closed() || sender.capacity() == 0 {
+        statuses.push((SHUTTLE_GATEWAY_VARIANT, StatusResponse::unhealthy()));
+    } else if sender.capacity() < WORKER_QUEUE_SIZE - SVC_DEGRADED_THRESHOLD {
+        statuses.push((SHUTTLE_GATEWAY_VARIANT, StatusResponse::degraded()));
+    } else {
+        statuses.push((SHUTTLE_GATEWAY_VARIANT, StatusResponse::healthy()));
+    };
+
+    // Compute provisioner status.
+    let provisioner_status = if let Ok(channel) = service.provisioner_host().connect().await {
+        let channel = ServiceBuilder::new().service(channel);
         let mut provisioner_client = ProvisionerClient::new(channel);
         if provisioner_client.health_check(Ping {}).await.is_ok() {
             StatusResponse::healthy()
@@ -318,13 +846,32 @@ async fn get_status(
     )
 )]
 async fn post_load(
-    State(RouterState { running_builds, .. }): State<RouterState>,
+    State(RouterState {
+        service,
+        running_builds,
+        ..
+    }): State<RouterState>,
     AxumJson(build): AxumJson<stats::LoadRequest>,
 ) -> Result<AxumJson<stats::LoadResponse>, Error> {
+    // Looked up before taking the queue lock so a slow database does not hold up other builds.
+    // The deployer falls back to its default when the project is unknown
+    let max_build_retries = match build
+        .project_name
+        .as_deref()
+        .and_then(|name| name.parse::<ProjectName>().ok())
+    {
+        Some(project_name) => service
+            .project_max_build_retries(&project_name)
+            .await
+            .unwrap_or_default(),
+        None => None,
+    };
+
     let mut running_builds = running_builds.lock().await;
 
     trace!(id = %build.id, "checking build queue");
     let mut load = calculate_capacity(&mut running_builds);
+    load.max_build_retries = max_build_retries;
 
     if load.has_capacity
         && running_builds
@@ -399,6 +946,27 @@ async fn delete_load_admin(
     Ok(AxumJson(load))
 }
 
+#[instrument(skip_all)]
+#[utoipa::path(
+    get,
+    path = "/admin/stats/tasks",
+    responses(
+        (status = 200, description = "Successfully gets the size of the task router.", body = shuttle_common::models::stats::TaskRouterResponse),
+        (status = 500, description = "Server internal error.")
+    )
+)]
+async fn get_task_router_admin(
+    State(RouterState { service, .. }): State<RouterState>,
+) -> Result<AxumJson<stats::TaskRouterResponse>, Error> {
+    let task_router = service.task_router();
+
+    Ok(AxumJson(stats::TaskRouterResponse {
+        tracked_count: task_router.tracked_count().await,
+        active_count: task_router.active_count().await,
+        limit: task_router.limit(),
+    }))
+}
+
 fn calculate_capacity(running_builds: &mut MutexGuard<TtlCache<Uuid, ()>>) -> stats::LoadResponse {
     let active = running_builds.iter().count();
     let capacity = running_builds.capacity();
@@ -407,6 +975,7 @@ fn calculate_capacity(running_builds: &mut MutexGuard<TtlCache<Uuid, ()>>) -> st
     stats::LoadResponse {
         builds_count: active,
         has_capacity,
+        max_build_retries: None,
     }
 }
 
@@ -471,6 +1040,31 @@ async fn create_acme_account(
     Ok(AxumJson(res))
 }
 
+#[instrument(skip_all, fields(%email, ?acme_server))]
+#[utoipa::path(
+    post,
+    path = "/admin/acme/rotate/{email}",
+    responses(
+        (status = 200, description = "Rotated the acme account of the gateway.", content_type = "application/json", body = String),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("email" = String, Path, description = "An email the new acme account binds to."),
+    ),
+)]
+async fn rotate_acme_account(
+    State(RouterState { service, .. }): State<RouterState>,
+    Extension(acme_client): Extension<AcmeClient>,
+    Path(email): Path<String>,
+    AxumJson(acme_server): AxumJson<Option<String>>,
+) -> Result<AxumJson<serde_json::Value>, Error> {
+    let res = service
+        .rotate_acme_account(&acme_client, &email, acme_server)
+        .await?;
+
+    Ok(AxumJson(res))
+}
+
 #[instrument(skip_all, fields(%project_name, %fqdn))]
 #[utoipa::path(
     post,
@@ -481,7 +1075,8 @@ async fn create_acme_account(
     ),
     params(
         ("project_name" = String, Path, description = "The project name associated to the requested custom domain."),
-        ("fqdn" = String, Path, description = "The fqdn that represents the requested custom domain."),
+        ("fqdn" = String, Path, description = "The fqdn that represents the requested custom domain, or a wildcard like `*.example.com` to route every subdomain of a domain to the project."),
+        CertificateChallengeDetails,
     )
 )]
 async fn request_custom_domain_acme_certificate(
@@ -491,18 +1086,44 @@ async fn request_custom_domain_acme_certificate(
     Extension(acme_client): Extension<AcmeClient>,
     Extension(resolver): Extension<Arc<GatewayCertResolver>>,
     Path((project_name, fqdn)): Path<(ProjectName, String)>,
+    Query(challenge): Query<CertificateChallengeDetails>,
     AxumJson(credentials): AxumJson<AccountCredentials<'_>>,
 ) -> Result<String, Error> {
-    let fqdn: FQDN = fqdn
-        .parse()
-        .map_err(|_err| Error::from(ErrorKind::InvalidCustomDomain))?;
+    let (fqdn, wildcard) = parse_custom_domain(&fqdn, &service.public_fqdn())?;
+    let challenge_type = challenge.challenge_type(&acme_client, wildcard)?;
 
+    let renewal = service.lock_certificate_renewal().await;
     let (certs, private_key) = service
-        .create_custom_domain_certificate(&fqdn, &acme_client, &project_name, credentials)
+        .create_custom_domain_certificate(
+            &fqdn,
+            wildcard,
+            &acme_client,
+            &project_name,
+            challenge_type,
+            credentials,
+        )
         .await?;
 
+    let identifier = custom_domain_identifier(&fqdn, wildcard);
+    let mut buf = Vec::new();
+    buf.extend(certs.as_bytes());
+    buf.extend(private_key.as_bytes());
+    resolver.serve_pem(&identifier, Cursor::new(buf)).await?;
+    drop(renewal);
+
+    // A wildcard domain only routes its subdomains to the project, which keeps being reached at
+    // its current fqdn
+    if wildcard {
+        return Ok(format!(
+            r#""New certificate created for {} project.""#,
+            project_name
+        ));
+    }
+
     let project = service.find_project(&project_name).await?;
-    let idle_minutes = project.container().unwrap().idle_minutes();
+    let container = project.container().unwrap();
+    let idle_minutes =
+        container.idle_minutes(service.context().container_settings().default_idle_minutes);
 
     // Destroy and recreate the project with the new domain.
     service
@@ -513,28 +1134,96 @@ async fn request_custom_domain_acme_certificate(
         .and_then(task::run({
             let fqdn = fqdn.to_string();
             move |ctx| {
-                let fqdn = fqdn.clone();
-                async move {
-                    let creating = ProjectCreating::new_with_random_initial_key(
-                        ctx.project_name,
-                        idle_minutes,
-                    )
-                    .with_fqdn(fqdn);
-                    TaskResult::Done(Project::Creating(creating))
-                }
+                let creating =
+                    ProjectCreating::new_with_random_initial_key(ctx.project_name, idle_minutes)
+                        .with_settings_of(&container)
+                        .with_fqdn(fqdn.clone());
+                async move { TaskResult::Done(Project::Creating(creating)) }
             }
         }))
         .send(&sender)
         .await?;
 
-    let mut buf = Vec::new();
-    buf.extend(certs.as_bytes());
-    buf.extend(private_key.as_bytes());
+    Ok(format!(
+        r#""New certificate created for {} project.""#,
+        project_name
+    ))
+}
+
+#[instrument(skip_all, fields(%project_name, %fqdn))]
+#[utoipa::path(
+    delete,
+    path = "/admin/acme/request/{project_name}/{fqdn}",
+    responses(
+        (status = 200, description = "Successfully removed the custom domain of the project."),
+        (status = 404, description = "Custom domain not found for the project."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The project name associated to the custom domain."),
+        ("fqdn" = String, Path, description = "The fqdn of the custom domain to remove."),
+    )
+)]
+async fn delete_custom_domain(
+    State(RouterState { service, .. }): State<RouterState>,
+    Extension(resolver): Extension<Arc<GatewayCertResolver>>,
+    Path((project_name, fqdn)): Path<(ProjectName, String)>,
+) -> Result<String, Error> {
+    let (fqdn, wildcard) = parse_custom_domain(&fqdn, &service.public_fqdn())?;
+    let identifier = custom_domain_identifier(&fqdn, wildcard);
+
+    service
+        .delete_custom_domain(&project_name, &fqdn, wildcard)
+        .await?;
+    resolver.remove(&identifier).await;
+
+    Ok(format!(
+        r#""Removed the custom domain {} of {} project.""#,
+        identifier, project_name
+    ))
+}
+
+#[instrument(skip_all, fields(%project_name, %fqdn))]
+#[utoipa::path(
+    post,
+    path = "/admin/custom-domains/{project_name}/{fqdn}",
+    request_body = shuttle_common::models::project::CustomDomainCertificate,
+    responses(
+        (status = 200, description = "Successfully added the custom domain with the uploaded certificate."),
+        (status = 400, description = "The certificate is invalid or does not cover the custom domain."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The project name associated to the custom domain."),
+        ("fqdn" = String, Path, description = "The fqdn of the custom domain, or a wildcard like `*.example.com`."),
+    )
+)]
+async fn upload_custom_domain_certificate(
+    State(RouterState { service, .. }): State<RouterState>,
+    Extension(resolver): Extension<Arc<GatewayCertResolver>>,
+    Path((project_name, fqdn)): Path<(ProjectName, String)>,
+    AxumJson(project::CustomDomainCertificate {
+        certificate,
+        private_key,
+    }): AxumJson<project::CustomDomainCertificate>,
+) -> Result<String, Error> {
+    let (fqdn, wildcard) = parse_custom_domain(&fqdn, &service.public_fqdn())?;
+
+    let chain = service
+        .upload_custom_domain_certificate(
+            &project_name,
+            &fqdn,
+            wildcard,
+            &certificate,
+            &private_key,
+        )
+        .await?;
     resolver
-        .serve_pem(&fqdn.to_string(), Cursor::new(buf))
+        .serve_der(&custom_domain_identifier(&fqdn, wildcard), chain)
         .await?;
+
     Ok(format!(
-        r#""New certificate created for {} project.""#,
+        r#""Uploaded certificate added for {} project.""#,
         project_name
     ))
 }
@@ -550,6 +1239,7 @@ async fn request_custom_domain_acme_certificate(
     params(
         ("project_name" = String, Path, description = "The project name associated to the requested custom domain."),
         ("fqdn" = String, Path, description = "The fqdn that represents the requested custom domain."),
+        CertificateChallengeDetails,
     )
 )]
 async fn renew_custom_domain_acme_certificate(
@@ -557,13 +1247,15 @@ async fn renew_custom_domain_acme_certificate(
     Extension(acme_client): Extension<AcmeClient>,
     Extension(resolver): Extension<Arc<GatewayCertResolver>>,
     Path((project_name, fqdn)): Path<(ProjectName, String)>,
+    Query(challenge): Query<CertificateChallengeDetails>,
     AxumJson(credentials): AxumJson<AccountCredentials<'_>>,
 ) -> Result<String, Error> {
-    let fqdn: FQDN = fqdn
-        .parse()
-        .map_err(|_err| Error::from(ErrorKind::InvalidCustomDomain))?;
+    let (fqdn, wildcard) = parse_custom_domain(&fqdn, &service.public_fqdn())?;
+    let identifier = custom_domain_identifier(&fqdn, wildcard);
+    let challenge_type = challenge.challenge_type(&acme_client, wildcard)?;
+    let _renewal = service.lock_certificate_renewal().await;
     // Try retrieve the current certificate if any.
-    match service.project_details_for_custom_domain(&fqdn).await {
+    match service.find_custom_domain(&fqdn, wildcard).await {
         Ok(CustomDomain { certificate, .. }) => {
             let (_, pem) = parse_x509_pem(certificate.as_bytes()).unwrap_or_else(|_| {
                 panic!(
@@ -584,21 +1276,29 @@ async fn renew_custom_domain_acme_certificate(
                 .sub(ASN1Time::now())
                 .unwrap();
 
-            // If current certificate validity less_or_eq than 30 days, attempt renewal.
-            if diff.whole_days() <= RENEWAL_VALIDITY_THRESHOLD_IN_DAYS {
+            // If current certificate validity less_or_eq than the renewal threshold, attempt renewal.
+            if diff.whole_days() <= service.renewal_threshold_days() {
                 return match acme_client
-                    .create_certificate(&fqdn.to_string(), ChallengeType::Http01, credentials)
+                    .create_certificate(&identifier, challenge_type, credentials)
                     .await
                 {
-                    // If successfuly created, save the certificate in memory to be
-                    // served in the future.
+                    // If successfuly created, store the certificate and save it in memory
+                    // to be served in the future.
                     Ok((certs, private_key)) => {
+                        service
+                            .create_custom_domain(
+                                &project_name,
+                                &fqdn,
+                                wildcard,
+                                &certs,
+                                &private_key,
+                            )
+                            .await?;
+
                         let mut buf = Vec::new();
                         buf.extend(certs.as_bytes());
                         buf.extend(private_key.as_bytes());
-                        resolver
-                            .serve_pem(&fqdn.to_string(), Cursor::new(buf))
-                            .await?;
+                        resolver.serve_pem(&identifier, Cursor::new(buf)).await?;
                         Ok(format!(
                             r#""Certificate renewed for {} project.""#,
                             project_name
@@ -634,7 +1334,7 @@ async fn renew_gateway_acme_certificate(
 ) -> Result<String, Error> {
     service
         .renew_certificate(&acme_client, resolver, credentials)
-        .await;
+        .await?;
     Ok(r#""Renewed the gateway certificate.""#.to_string())
 }
 
@@ -651,6 +1351,36 @@ async fn get_projects(
 ) -> Result<AxumJson<Vec<project::AdminResponse>>, Error> {
     let projects = service
         .iter_projects_detailed()
+        .map_ok(project::AdminResponse::from)
+        .try_collect()
+        .await?;
+
+    Ok(AxumJson(projects))
+}
+
+#[instrument(skip(service))]
+#[utoipa::path(
+    get,
+    path = "/admin/projects/search",
+    responses(
+        (status = 200, description = "Successfully searched the projects.", body = [shuttle_common::models::project::AdminResponse]),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ProjectSearchDetails,
+        PaginationDetails
+    )
+)]
+async fn search_projects(
+    State(RouterState { service, .. }): State<RouterState>,
+    Query(ProjectSearchDetails { query }): Query<ProjectSearchDetails>,
+    Query(PaginationDetails { page, limit }): Query<PaginationDetails>,
+) -> Result<AxumJson<Vec<project::AdminResponse>>, Error> {
+    let limit = limit.unwrap_or(u32::MAX);
+    let page = page.unwrap_or(0);
+    let projects = service
+        // The `offset` is page size * amount of pages
+        .search_projects_detailed(&query, limit * page, limit)
         .await?
         .map(Into::into)
         .collect();
@@ -658,6 +1388,83 @@ async fn get_projects(
     Ok(AxumJson(projects))
 }
 
+#[instrument(skip(service))]
+#[utoipa::path(
+    get,
+    path = "/admin/projects/{project_name}/logs",
+    responses(
+        (status = 200, description = "Successfully fetched the tail of the project container's logs.", body = [String]),
+        (status = 404, description = "Project not found."),
+        (status = 503, description = "Project has no container yet."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+        ContainerLogsDetails
+    )
+)]
+async fn get_project_container_logs(
+    State(RouterState { service, .. }): State<RouterState>,
+    Path(project_name): Path<ProjectName>,
+    Query(ContainerLogsDetails { tail }): Query<ContainerLogsDetails>,
+) -> Result<AxumJson<Vec<String>>, Error> {
+    let logs = service
+        .project_container_logs(&project_name, tail.unwrap_or(MAX_CONTAINER_LOG_LINES))
+        .await?;
+
+    Ok(AxumJson(logs))
+}
+
+#[instrument(skip(service))]
+#[utoipa::path(
+    post,
+    path = "/admin/projects/{project_name}/transfer/{account_name}",
+    responses(
+        (status = 200, description = "Successfully transferred the project to the account."),
+        (status = 404, description = "Project or account not found."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+        ("account_name" = String, Path, description = "The account to transfer the project to."),
+    )
+)]
+async fn transfer_project(
+    State(RouterState { service, .. }): State<RouterState>,
+    Path((project_name, account_name)): Path<(ProjectName, AccountName)>,
+) -> Result<String, Error> {
+    service
+        .transfer_project(&project_name, &account_name)
+        .await?;
+
+    Ok(format!(
+        r#""Transferred {} project to {}.""#,
+        project_name, account_name
+    ))
+}
+
+#[instrument(skip(service))]
+#[utoipa::path(
+    get,
+    path = "/admin/projects/{project_name}/audit-log",
+    responses(
+        (status = 200, description = "Successfully fetched the audit log of the project.", body = [shuttle_common::models::project::AuditEntry]),
+        (status = 404, description = "Project not found."),
+        (status = 500, description = "Server internal error.")
+    ),
+    params(
+        ("project_name" = String, Path, description = "The name of the project."),
+    )
+)]
+async fn get_project_audit_log(
+    State(RouterState { service, .. }): State<RouterState>,
+    Path(project_name): Path<ProjectName>,
+) -> Result<AxumJson<Vec<project::AuditEntry>>, Error> {
+    let audit_log = service.project_audit_log(&project_name).await?;
+
+    Ok(AxumJson(audit_log))
+}
+
 struct SecurityAddon;
 
 impl Modify for SecurityAddon {
@@ -675,28 +1482,63 @@ impl Modify for SecurityAddon {
 #[openapi(
     paths(
         create_acme_account,
+        rotate_acme_account,
         request_custom_domain_acme_certificate,
+        delete_custom_domain,
+        upload_custom_domain_certificate,
         renew_custom_domain_acme_certificate,
         renew_gateway_acme_certificate,
         get_status,
+        get_project_status,
+        start_project,
         get_projects_list,
         get_project,
+        get_project_custom_domains,
+        get_project_request_timeout,
+        set_project_request_timeout,
+        get_project_build_retries,
+        set_project_build_retries,
+        get_project_tags,
+        set_project_tags,
+        get_project_stats,
+        get_project_stats_history,
+        get_project_rate_limit,
+        set_project_rate_limit,
+        get_project_egress_policy,
+        set_project_egress_policy,
         destroy_project,
         create_project,
         post_load,
         delete_load,
         get_projects,
+        search_projects,
+        get_project_container_logs,
+        transfer_project,
+        get_project_audit_log,
         revive_projects,
         destroy_projects,
         get_load_admin,
-        delete_load_admin
+        delete_load_admin,
+        get_task_router_admin
     ),
     modifiers(&SecurityAddon),
     components(schemas(
         shuttle_common::models::project::Response,
+        shuttle_common::models::project::Transition,
         shuttle_common::models::stats::LoadResponse,
         shuttle_common::models::project::AdminResponse,
+        shuttle_common::models::project::CustomDomainResponse,
+        shuttle_common::models::project::CustomDomainCertificate,
+        shuttle_common::models::project::RequestTimeout,
+        shuttle_common::models::project::BuildRetries,
+        shuttle_common::models::project::Tags,
+        shuttle_common::models::project::StatsSample,
+        shuttle_common::models::project::RateLimit,
+        shuttle_common::models::project::EgressPolicy,
+        shuttle_common::models::project::AuditEntry,
+        shuttle_common::models::project::AuditAction,
         shuttle_common::models::stats::LoadResponse,
+        shuttle_common::models::stats::TaskRouterResponse,
         shuttle_common::models::project::State
     ))
 )]
@@ -739,11 +1581,25 @@ impl ApiBuilder {
                 "/admin/acme/:email",
                 post(create_acme_account.layer(ScopedLayer::new(vec![Scope::AcmeCreate]))),
             )
+            .route(
+                "/admin/acme/rotate/:email",
+                post(rotate_acme_account.layer(ScopedLayer::new(vec![Scope::AcmeCreate]))),
+            )
             .route(
                 "/admin/acme/request/:project_name/:fqdn",
                 post(
                     request_custom_domain_acme_certificate
                         .layer(ScopedLayer::new(vec![Scope::CustomDomainCreate])),
+                )
+                .delete(
+                    delete_custom_domain.layer(ScopedLayer::new(vec![Scope::CustomDomainDelete])),
+                ),
+            )
+            .route(
+                "/admin/custom-domains/:project_name/:fqdn",
+                post(
+                    upload_custom_domain_certificate
+                        .layer(ScopedLayer::new(vec![Scope::CustomDomainCreate])),
                 ),
             )
             .route(
@@ -799,9 +1655,23 @@ impl ApiBuilder {
     pub fn with_default_routes(mut self) -> Self {
         let admin_routes = Router::new()
             .route("/projects", get(get_projects))
+            .route("/projects/search", get(search_projects))
+            .route(
+                "/projects/:project_name/logs",
+                get(get_project_container_logs),
+            )
+            .route(
+                "/projects/:project_name/transfer/:account_name",
+                post(transfer_project),
+            )
+            .route(
+                "/projects/:project_name/audit-log",
+                get(get_project_audit_log),
+            )
             .route("/revive", post(revive_projects))
             .route("/destroy", post(destroy_projects))
             .route("/stats/load", get(get_load_admin).delete(delete_load_admin))
+            .route("/stats/tasks", get(get_task_router_admin))
             // TODO: The `/swagger-ui` responds with a 303 See Other response which is followed in
             // browsers but leads to 404 Not Found. This must be investigated.
             .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
@@ -820,6 +1690,50 @@ impl ApiBuilder {
                     .delete(destroy_project.layer(ScopedLayer::new(vec![Scope::ProjectCreate])))
                     .post(create_project.layer(ScopedLayer::new(vec![Scope::ProjectCreate]))),
             )
+            .route(
+                "/projects/:project_name/domains",
+                get(get_project_custom_domains.layer(ScopedLayer::new(vec![Scope::Project]))),
+            )
+            .route(
+                "/projects/:project_name/request-timeout",
+                get(get_project_request_timeout.layer(ScopedLayer::new(vec![Scope::Project]))).put(
+                    set_project_request_timeout.layer(ScopedLayer::new(vec![Scope::ProjectCreate])),
+                ),
+            )
+            .route(
+                "/projects/:project_name/build-retries",
+                get(get_project_build_retries.layer(ScopedLayer::new(vec![Scope::Project]))).put(
+                    set_project_build_retries.layer(ScopedLayer::new(vec![Scope::ProjectCreate])),
+                ),
+            )
+            .route(
+                "/projects/:project_name/tags",
+                get(get_project_tags.layer(ScopedLayer::new(vec![Scope::Project])))
+                    .put(set_project_tags.layer(ScopedLayer::new(vec![Scope::ProjectCreate]))),
+            )
+            .route(
+                "/projects/:project_name/stats",
+                get(get_project_stats.layer(ScopedLayer::new(vec![Scope::Project]))),
+            )
+            .route(
+                "/projects/:project_name/stats/history",
+                get(get_project_stats_history.layer(ScopedLayer::new(vec![Scope::Project]))),
+            )
+            .route(
+                "/projects/:project_name/rate-limit",
+                get(get_project_rate_limit.layer(ScopedLayer::new(vec![Scope::Project])))
+                    .put(set_project_rate_limit.layer(ScopedLayer::new(vec![Scope::Admin]))),
+            )
+            .route(
+                "/projects/:project_name/egress-policy",
+                get(get_project_egress_policy.layer(ScopedLayer::new(vec![Scope::Project])))
+                    .put(set_project_egress_policy.layer(ScopedLayer::new(vec![Scope::Admin]))),
+            )
+            .route(
+                "/projects/:project_name/start",
+                post(start_project.layer(ScopedLayer::new(vec![Scope::Project]))),
+            )
+            .route("/projects/:project_name/status", get(get_project_status))
             .route("/projects/:project_name/*any", any(route_project))
             .route("/stats/load", post(post_load).delete(delete_load))
             .nest("/admin", admin_routes);
@@ -843,6 +1757,16 @@ impl ApiBuilder {
         self
     }
 
+    /// Let uptime tooling holding this token call the status endpoints, and only those
+    pub fn with_monitoring_token(mut self, token: String) -> Self {
+        self.router = self.router.layer(from_fn_with_state(
+            Arc::<str>::from(token),
+            monitoring_token,
+        ));
+
+        self
+    }
+
     pub fn into_router(self) -> Router {
         let service = self.service.expect("a GatewayService is required");
         let sender = self.sender.expect("a task Sender is required");
@@ -865,7 +1789,7 @@ impl ApiBuilder {
     pub fn serve(self) -> impl Future<Output = Result<(), hyper::Error>> {
         let bind = self.bind.expect("a socket address to bind to is required");
         let router = self.into_router();
-        axum::Server::bind(&bind).serve(router.into_make_service())
+        axum::Server::bind(&bind).serve(router.into_make_service_with_connect_info::<SocketAddr>())
     }
 }
 
@@ -885,6 +1809,8 @@ pub mod tests {
     use tower::Service;
 
     use super::*;
+    use crate::project::tests::{container_stats, ready_project};
+    use crate::service::tests::never_idle;
     use crate::service::GatewayService;
     use crate::tests::{RequestBuilderExt, World};
 
@@ -1070,6 +1996,498 @@ pub mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn api_set_project_rate_limit_as_admin() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let service = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let (sender, mut receiver) = channel::<BoxedTask>(256);
+        tokio::spawn(async move {
+            while receiver.recv().await.is_some() {
+                // do not do any work with inbound requests
+            }
+        });
+
+        let mut router = ApiBuilder::new()
+            .with_service(Arc::clone(&service))
+            .with_sender(sender)
+            .with_default_routes()
+            .with_auth_service(world.context().auth_uri)
+            .into_router();
+
+        let neo_key = world.create_user("neo");
+        service
+            .create_project(
+                "matrix".parse().unwrap(),
+                "neo".parse().unwrap(),
+                false,
+                never_idle(),
+            )
+            .await
+            .unwrap();
+
+        let set_rate_limit = || {
+            Request::builder()
+                .method("PUT")
+                .uri("/projects/matrix/rate-limit")
+                .header("Content-Type", "application/json")
+                .body("{\"requests_per_second\": 10}".into())
+                .unwrap()
+        };
+
+        // Owners cannot lift the limit put on their own project
+        let authorization = Authorization::bearer(&neo_key).unwrap();
+        router
+            .call(set_rate_limit().with_header(&authorization))
+            .map_ok(|resp| assert_eq!(resp.status(), StatusCode::FORBIDDEN))
+            .await
+            .unwrap();
+
+        let admin_neo_key = world.create_user("admin-neo");
+        world.set_super_user("admin-neo");
+        let authorization = Authorization::bearer(&admin_neo_key).unwrap();
+        router
+            .call(set_rate_limit().with_header(&authorization))
+            .map_ok(|resp| assert_eq!(resp.status(), StatusCode::OK))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            service
+                .project_rate_limit(&"matrix".parse().unwrap())
+                .await
+                .unwrap()
+                .requests_per_second,
+            Some(10)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn api_set_project_egress_policy_as_admin() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let service = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let (sender, mut receiver) = channel::<BoxedTask>(256);
+        tokio::spawn(async move {
+            while receiver.recv().await.is_some() {
+                // do not do any work with inbound requests
+            }
+        });
+
+        let mut router = ApiBuilder::new()
+            .with_service(Arc::clone(&service))
+            .with_sender(sender)
+            .with_default_routes()
+            .with_auth_service(world.context().auth_uri)
+            .into_router();
+
+        let neo_key = world.create_user("neo");
+        service
+            .create_project(
+                "matrix".parse().unwrap(),
+                "neo".parse().unwrap(),
+                false,
+                never_idle(),
+            )
+            .await
+            .unwrap();
+        service
+            .update_project(&"matrix".parse().unwrap(), &ready_project(Vec::new()))
+            .await
+            .unwrap();
+
+        let set_egress_policy = || {
+            Request::builder()
+                .method("PUT")
+                .uri("/projects/matrix/egress-policy")
+                .header("Content-Type", "application/json")
+                .body("\"restricted\"".into())
+                .unwrap()
+        };
+
+        // Owners cannot lift the restriction put on their own project
+        let authorization = Authorization::bearer(&neo_key).unwrap();
+        router
+            .call(set_egress_policy().with_header(&authorization))
+            .map_ok(|resp| assert_eq!(resp.status(), StatusCode::FORBIDDEN))
+            .await
+            .unwrap();
+
+        let admin_neo_key = world.create_user("admin-neo");
+        world.set_super_user("admin-neo");
+        let authorization = Authorization::bearer(&admin_neo_key).unwrap();
+        router
+            .call(set_egress_policy().with_header(&authorization))
+            .map_ok(|resp| assert_eq!(resp.status(), StatusCode::OK))
+            .await
+            .unwrap();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn api_tag_projects() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let service = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let (sender, mut receiver) = channel::<BoxedTask>(256);
+        tokio::spawn(async move {
+            while receiver.recv().await.is_some() {
+                // do not do any work with inbound requests
+            }
+        });
+
+        let mut router = ApiBuilder::new()
+            .with_service(Arc::clone(&service))
+            .with_sender(sender)
+            .with_default_routes()
+            .with_auth_service(world.context().auth_uri)
+            .into_router();
+
+        let neo_key = world.create_user("neo");
+        for project_name in ["matrix", "reloaded"] {
+            service
+                .create_project(
+                    project_name.parse().unwrap(),
+                    "neo".parse().unwrap(),
+                    false,
+                    never_idle(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let authorization = Authorization::bearer(&neo_key).unwrap();
+        let set_tags = |project: &str, tags: &str| {
+            Request::put(format!("/projects/{project}/tags"))
+                .header("Content-Type", "application/json")
+                .body(format!("{{\"tags\": {tags}}}").into())
+                .unwrap()
+        };
+        let list_projects = |uri: &str| {
+            Request::get(uri)
+                .body(Body::empty())
+                .unwrap()
+                .with_header(&authorization)
+        };
+        let project_names = |resp: Response| async move {
+            let projects: Vec<project::Response> =
+                serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap();
+            let mut names: Vec<_> = projects.into_iter().map(|project| project.name).collect();
+            names.sort();
+            names
+        };
+
+        let resp = router
+            .call(set_tags("matrix", r#"["team-a", "production"]"#).with_header(&authorization))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = router
+            .call(set_tags("reloaded", r#"["team-a"]"#).with_header(&authorization))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = router
+            .call(list_projects("/projects/matrix/tags"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let tags: project::Tags =
+            serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        assert_eq!(tags.tags, vec!["team-a", "production"]);
+
+        let resp = router.call(list_projects("/projects")).await.unwrap();
+        assert_eq!(project_names(resp).await, vec!["matrix", "reloaded"]);
+        let resp = router
+            .call(list_projects("/projects?tags=team-a"))
+            .await
+            .unwrap();
+        assert_eq!(project_names(resp).await, vec!["matrix", "reloaded"]);
+        let resp = router
+            .call(list_projects("/projects?tags=team-a,production"))
+            .await
+            .unwrap();
+        assert_eq!(project_names(resp).await, vec!["matrix"]);
+
+        let resp = router
+            .call(set_tags("matrix", r#"["team-a,production"]"#).with_header(&authorization))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // Only the owner of a project can tag it
+        let trinity = Authorization::bearer(&world.create_user("trinity")).unwrap();
+        let resp = router
+            .call(set_tags("matrix", r#"["hacked"]"#).with_header(&trinity))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = router
+            .call(set_tags("matrix", r#"["hacked"]"#))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn api_set_project_build_retries() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let service = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let (sender, mut receiver) = channel::<BoxedTask>(256);
+        tokio::spawn(async move {
+            while receiver.recv().await.is_some() {
+                // do not do any work with inbound requests
+            }
+        });
+
+        let mut router = ApiBuilder::new()
+            .with_service(Arc::clone(&service))
+            .with_sender(sender)
+            .with_default_routes()
+            .with_auth_service(world.context().auth_uri)
+            .into_router();
+
+        let neo_key = world.create_user("neo");
+        service
+            .create_project(
+                "matrix".parse().unwrap(),
+                "neo".parse().unwrap(),
+                false,
+                never_idle(),
+            )
+            .await
+            .unwrap();
+
+        let authorization = Authorization::bearer(&neo_key).unwrap();
+        let set_build_retries = |body: &str| {
+            Request::put("/projects/matrix/build-retries")
+                .header("Content-Type", "application/json")
+                .body(body.to_string().into())
+                .unwrap()
+                .with_header(&authorization)
+        };
+        let request_slot = |project_name: Option<&str>| {
+            let body = stats::LoadRequest {
+                id: Uuid::new_v4(),
+                project_name: project_name.map(ToString::to_string),
+            };
+            Request::post("/stats/load")
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_vec(&body).unwrap().into())
+                .unwrap()
+                .with_header(&authorization)
+        };
+        let load_response = |resp: Response| async move {
+            assert_eq!(resp.status(), StatusCode::OK);
+            serde_json::from_slice::<stats::LoadResponse>(
+                &to_bytes(resp.into_body()).await.unwrap(),
+            )
+            .unwrap()
+        };
+
+        let resp = router.call(request_slot(Some("matrix"))).await.unwrap();
+        assert_eq!(load_response(resp).await.max_build_retries, None);
+
+        let resp = router
+            .call(set_build_retries(r#"{"max_build_retries": 3}"#))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = router
+            .call(
+                Request::get("/projects/matrix/build-retries")
+                    .body(Body::empty())
+                    .unwrap()
+                    .with_header(&authorization),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let build_retries: project::BuildRetries =
+            serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        assert_eq!(build_retries.max_build_retries, Some(3));
+
+        // The deployer gets the retries of its project along with its build slot
+        let resp = router.call(request_slot(Some("matrix"))).await.unwrap();
+        assert_eq!(load_response(resp).await.max_build_retries, Some(3));
+        let resp = router.call(request_slot(Some("oracle"))).await.unwrap();
+        assert_eq!(load_response(resp).await.max_build_retries, None);
+        let resp = router.call(request_slot(None)).await.unwrap();
+        assert_eq!(load_response(resp).await.max_build_retries, None);
+
+        let resp = router
+            .call(set_build_retries(r#"{"max_build_retries": 100}"#))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn api_get_project_stats() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.stats_retention_days = Some(7);
+        let service = Arc::new(GatewayService::init(args, world.pool(), "".into()).await);
+
+        let (sender, mut receiver) = channel::<BoxedTask>(256);
+        tokio::spawn(async move {
+            while receiver.recv().await.is_some() {
+                // do not do any work with inbound requests
+            }
+        });
+
+        let mut router = ApiBuilder::new()
+            .with_service(Arc::clone(&service))
+            .with_sender(sender)
+            .with_default_routes()
+            .with_auth_service(world.context().auth_uri)
+            .into_router();
+
+        let neo_key = world.create_user("neo");
+        for project_name in ["matrix", "reloaded"] {
+            service
+                .create_project(
+                    project_name.parse().unwrap(),
+                    "neo".parse().unwrap(),
+                    false,
+                    never_idle(),
+                )
+                .await
+                .unwrap();
+        }
+        service
+            .update_project(
+                &"matrix".parse().unwrap(),
+                &ready_project(vec![container_stats("2023-06-01T12:00:00Z", 100, 1024)]),
+            )
+            .await
+            .unwrap();
+
+        let authorization = Authorization::bearer(&neo_key).unwrap();
+        let get_stats = |project: &str| {
+            Request::get(format!("/projects/{project}/stats"))
+                .body(Body::empty())
+                .unwrap()
+                .with_header(&authorization)
+        };
+
+        let sample = project::StatsSample {
+            timestamp: "2023-06-01T12:00:00Z".parse().unwrap(),
+            cpu_usage: 100,
+            memory_usage: Some(1024),
+            memory_limit: Some(1 << 30),
+        };
+
+        let resp = router.call(get_stats("matrix")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let stats: Vec<project::StatsSample> =
+            serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        assert_eq!(stats, vec![sample.clone()]);
+
+        // The sample was also stored
+        let resp = router
+            .call(
+                Request::get("/projects/matrix/stats/history?since=2023-06-01T11:00:00Z")
+                    .body(Body::empty())
+                    .unwrap()
+                    .with_header(&authorization),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let history: Vec<project::StatsSample> =
+            serde_json::from_slice(&to_bytes(resp.into_body()).await.unwrap()).unwrap();
+        assert_eq!(history, vec![sample]);
+
+        // The other project never got to run
+        let resp = router.call(get_stats("reloaded")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn monitoring_token() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let service = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let (sender, mut receiver) = channel::<BoxedTask>(256);
+        tokio::spawn(async move {
+            while receiver.recv().await.is_some() {
+                // do not do any work with inbound requests
+            }
+        });
+
+        let mut router = ApiBuilder::new()
+            .with_service(Arc::clone(&service))
+            .with_sender(sender)
+            .with_default_routes()
+            .with_auth_service(world.context().auth_uri)
+            .with_monitoring_token("uptime".to_string())
+            .into_router();
+
+        let monitoring = Authorization::bearer("uptime").unwrap();
+        let request = |method: &str, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // The status endpoints are open to the monitoring token
+        let resp = router
+            .call(request("GET", "/").with_header(&monitoring))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = router
+            .call(request("GET", "/projects/matrix/status").with_header(&monitoring))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // But nothing else is, starting with `create_service`
+        let resp = router
+            .call(request("POST", "/projects/matrix/services/neo").with_header(&monitoring))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = router
+            .call(request("GET", "/projects/matrix").with_header(&monitoring))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let resp = router
+            .call(request("GET", "/admin/projects").with_header(&monitoring))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        // Other credentials are unaffected by the monitoring token
+        let neo = Authorization::bearer(&world.create_user("neo")).unwrap();
+        let resp = router
+            .call(request("GET", "/projects").with_header(&neo))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn status() {
         let world = World::new().await;
@@ -1,3 +1,4 @@
 mod auth_layer;
+mod monitoring_layer;
 
 pub mod latest;
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    headers::{authorization::Bearer, Authorization, HeaderMapExt},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::{header::AUTHORIZATION, Method, Request, StatusCode};
+use hyper::Body;
+use tracing::{trace, warn};
+
+/// Marks a request as coming from uptime tooling holding the monitoring token
+#[derive(Clone, Copy, Debug)]
+pub struct Monitoring;
+
+/// Whether the monitoring token may call this path. Only the read-only status endpoints are open
+/// to it.
+fn is_monitoring_path(path: &str) -> bool {
+    let segments: Vec<_> = path.trim_end_matches('/').split('/').skip(1).collect();
+
+    matches!(segments.as_slice(), [] | ["projects", _, "status"])
+}
+
+/// Let requests holding the monitoring token through to the status endpoints, without them having
+/// to be upgraded to a JWT by the auth service. The token is rejected on every other endpoint.
+pub async fn monitoring_token(
+    State(token): State<Arc<str>>,
+    mut req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let is_monitoring = req
+        .headers()
+        .typed_get::<Authorization<Bearer>>()
+        .map(|bearer| bearer.token().trim() == &*token)
+        .unwrap_or_default();
+
+    if !is_monitoring {
+        return next.run(req).await;
+    }
+
+    if req.method() != Method::GET || !is_monitoring_path(req.uri().path()) {
+        warn!(
+            path = req.uri().path(),
+            "monitoring token used outside of the status endpoints"
+        );
+
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    trace!("letting monitoring request through");
+
+    // The token is not a JWT, so it should not reach the authentication layers
+    req.headers_mut().remove(AUTHORIZATION);
+    req.extensions_mut().insert(Monitoring);
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_monitoring_path;
+
+    #[test]
+    fn monitoring_paths() {
+        assert!(is_monitoring_path("/"));
+        assert!(is_monitoring_path("/projects/matrix/status"));
+        assert!(is_monitoring_path("/projects/matrix/status/"));
+
+        assert!(!is_monitoring_path("/projects/matrix"));
+        assert!(!is_monitoring_path("/projects/matrix/services/neo"));
+        assert!(!is_monitoring_path("/admin/projects"));
+        assert!(!is_monitoring_path("/projects/matrix/status/more"));
+    }
+}
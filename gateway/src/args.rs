@@ -3,6 +3,15 @@ use std::{net::SocketAddr, path::PathBuf};
 use clap::{Parser, Subcommand, ValueEnum};
 use fqdn::FQDN;
 use http::Uri;
+use shuttle_common::models::project::IDLE_MINUTES;
+
+use crate::acme::CERTIFICATE_ISSUANCE_TIMEOUT_SECS;
+use crate::project::{CREATED_TIMEOUT_SECS, VOLUME_RETENTION_DAYS};
+use crate::service::{
+    COLD_START_TIMEOUT_SECS, DB_BUSY_TIMEOUT_MS, DB_MAX_CONNECTIONS, ROUTE_TIMEOUT_SECS,
+};
+use crate::tls::RENEWAL_VALIDITY_THRESHOLD_IN_DAYS;
+use crate::worker::TASK_ROUTER_LIMIT;
 
 #[derive(Parser, Debug)]
 pub struct Args {
@@ -20,6 +29,40 @@ pub enum UseTls {
     Enable,
 }
 
+/// When to pull the image of a project before creating its container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PullPolicy {
+    /// Always pull the image, picking up tags which were updated in-place
+    Always,
+    /// Only pull the image if it is missing locally
+    IfNotPresent,
+    /// Never pull the image, it has to be present locally
+    Never,
+}
+
+/// How the readiness of a starting project is checked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReadinessCheck {
+    /// Poll the health path of the runtime over HTTP
+    #[default]
+    Http,
+    /// Use the status of the HEALTHCHECK of the image, polling the
+    /// health path when the image does not define one
+    Docker,
+}
+
+/// The type of the key of the certificates issued through ACME
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CertificateKeyType {
+    /// ECDSA on the P-256 curve, for smaller certificates and
+    /// faster handshakes
+    #[default]
+    EcdsaP256,
+    /// RSA with a 2048 bits key, for clients which do not support
+    /// ECDSA
+    Rsa2048,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     Start(StartArgs),
@@ -39,6 +82,41 @@ pub struct StartArgs {
     /// Allows to disable the use of TLS in the user proxy service (DANGEROUS)
     #[arg(long, default_value = "enable")]
     pub use_tls: UseTls,
+    /// Token letting uptime tooling call the read-only status
+    /// endpoints, without full credentials
+    #[arg(long)]
+    pub monitoring_token: Option<String>,
+    /// Api token to publish the records of dns-01 challenges on
+    /// Cloudflare with. Without it the records have to be added
+    /// manually
+    #[arg(long, requires = "cloudflare_zone_id")]
+    pub cloudflare_api_token: Option<String>,
+    /// Cloudflare zone to publish the records of dns-01 challenges in
+    #[arg(long, requires = "cloudflare_api_token")]
+    pub cloudflare_zone_id: Option<String>,
+    /// Create ACME accounts on the Let's Encrypt staging directory,
+    /// which issues untrusted certificates without using up the
+    /// rate limits of production. For development only. The gateway
+    /// does not start with an account on the production directory
+    #[arg(long, default_value_t = false)]
+    pub acme_staging: bool,
+    /// Type of the key of the certificates issued through ACME
+    #[arg(long, value_enum, default_value_t = CertificateKeyType::EcdsaP256)]
+    pub certificate_key_type: CertificateKeyType,
+    /// Maximum time in seconds a certificate issuance can take before
+    /// it is cancelled, so a challenge which is never validated does
+    /// not keep polling the ACME server
+    #[arg(long, default_value_t = CERTIFICATE_ISSUANCE_TIMEOUT_SECS)]
+    pub certificate_issuance_timeout_secs: u64,
+    /// Compress the responses of projects served by the user proxy
+    /// which are larger than this many bytes, when the client accepts
+    /// it. Responses are passed as is when missing
+    #[arg(long)]
+    pub compression_min_size: Option<u16>,
+    /// Address to serve the Prometheus metrics of the requests
+    /// routed to projects on. They are not collected when missing
+    #[arg(long)]
+    pub metrics: Option<SocketAddr>,
     #[command(flatten)]
     pub context: ContextArgs,
 }
@@ -48,6 +126,9 @@ pub struct ContextArgs {
     /// Default image to deploy user runtimes into
     #[arg(long, default_value = "public.ecr.aws/shuttle/deployer:latest")]
     pub image: String,
+    /// When to pull the image before creating a user runtime
+    #[arg(long, default_value = "never")]
+    pub image_pull_policy: PullPolicy,
     /// Prefix to add to the name of all docker resources managed by
     /// this service
     #[arg(long, default_value = "shuttle_prod_")]
@@ -62,6 +143,22 @@ pub struct ContextArgs {
     /// The Docker Network name in which to deploy user runtimes
     #[arg(long, default_value = "shuttle_default")]
     pub network_name: String,
+    /// The Docker Network name, without outbound access, in which to
+    /// deploy user runtimes with a restricted egress policy. It is
+    /// created as an internal network when missing
+    #[arg(long, default_value = "shuttle_restricted")]
+    pub restricted_network_name: String,
+    /// Name or id of a container of the internal services the runtimes
+    /// use, like the provisioner and auth. These join the restricted
+    /// network along with the gateway container so that restricted
+    /// projects can still reach them. Can be given multiple times
+    #[arg(long = "restricted-network-service")]
+    pub restricted_network_services: Vec<String>,
+    /// Name or id of the container the gateway runs in. It joins the
+    /// network of each project created with an isolated network, so
+    /// such projects are refused when missing
+    #[arg(long)]
+    pub gateway_container: Option<String>,
     /// FQDN where the proxy can be reached at
     #[arg(long, default_value = "shuttleapp.rs")]
     pub proxy_fqdn: FQDN,
@@ -71,4 +168,74 @@ pub struct ContextArgs {
     /// Api key for the user that has rights to start deploys
     #[arg(long, default_value = "gateway4deployes")]
     pub deploys_api_key: String,
+    /// Path of the readiness endpoint on user runtimes, `{name}` is
+    /// replaced by the name of the project
+    #[arg(long, default_value = "/projects/{name}/status")]
+    pub health_path: String,
+    /// How to check whether a starting project is ready
+    #[arg(long, value_enum, default_value_t = ReadinessCheck::Http)]
+    pub readiness_check: ReadinessCheck,
+    /// Add a header with the id of the active deployment to the
+    /// responses of projects
+    #[arg(long, default_value_t = false)]
+    pub expose_deployment_id: bool,
+    /// Url to POST to whenever a project becomes ready, errored,
+    /// stopped or destroyed, or is about to be stopped for being idle.
+    /// The `event` field of the payload tells these apart
+    #[arg(long)]
+    pub webhook_url: Option<String>,
+    /// Minutes of inactivity after which projects created without an
+    /// explicit value are stopped. `0` keeps them always on
+    #[arg(long, default_value_t = IDLE_MINUTES)]
+    pub default_idle_minutes: u64,
+    /// Health checks an idle project is given to see traffic again
+    /// before it is stopped. A warning is sent to the webhook when
+    /// the grace starts. `0` stops idle projects straight away
+    #[arg(long, default_value_t = 0)]
+    pub idle_grace_checks: u32,
+    /// Days the volume of a destroyed project is kept for, so that
+    /// recreating the project within this window restores its state
+    #[arg(long, default_value_t = VOLUME_RETENTION_DAYS)]
+    pub volume_retention_days: u64,
+    /// Seconds a project container may stay in the created state
+    /// while starting before it is recreated
+    #[arg(long, default_value_t = CREATED_TIMEOUT_SECS)]
+    pub created_timeout_secs: u64,
+    /// Maximum number of projects, not counting destroyed ones, an
+    /// account can have. Admins are not limited
+    #[arg(long)]
+    pub project_quota: Option<u32>,
+    /// Number of projects to keep a task worker around for. Past
+    /// it, the workers of projects without pending tasks are evicted
+    #[arg(long, default_value_t = TASK_ROUTER_LIMIT)]
+    pub task_router_limit: usize,
+    /// Renew certificates once they are valid for this many days or
+    /// less
+    #[arg(long, default_value_t = RENEWAL_VALIDITY_THRESHOLD_IN_DAYS)]
+    pub renewal_threshold_days: i64,
+    /// Seconds to cache the state of the projects requests are
+    /// proxied to, instead of reading it from the database on every
+    /// request. Not cached when missing
+    #[arg(long)]
+    pub project_cache_ttl_secs: Option<u64>,
+    /// Maximum number of connections to the state database
+    #[arg(long, default_value_t = DB_MAX_CONNECTIONS)]
+    pub db_max_connections: u32,
+    /// Milliseconds a connection waits for the state database to
+    /// be unlocked before failing
+    #[arg(long, default_value_t = DB_BUSY_TIMEOUT_MS)]
+    pub db_busy_timeout_ms: u64,
+    /// Seconds a request routed to a project can take before it is
+    /// cut off with a gateway timeout, when the project has no
+    /// request timeout of its own. Deployment uploads are not cut off
+    #[arg(long, default_value_t = ROUTE_TIMEOUT_SECS)]
+    pub route_timeout_secs: u64,
+    /// Seconds a request to a waking project is held for, before it
+    /// is turned away with a hint to retry later
+    #[arg(long, default_value_t = COLD_START_TIMEOUT_SECS)]
+    pub cold_start_timeout_secs: u64,
+    /// Days to keep the sampled resource usage of projects in the
+    /// state database, for history graphs. Not stored when missing
+    #[arg(long)]
+    pub stats_retention_days: Option<u64>,
 }
@@ -0,0 +1,183 @@
+//! Circuit breakers cutting off the requests routed to projects which keep failing.
+//!
+//! Much like the health checks of a project, the outcome of every response routed to it is
+//! recorded. Once too many of the recent ones are failures the breaker of the project opens, and
+//! requests are refused straight away until a cooldown has passed. This keeps a broken project from
+//! tying up the gateway while it cannot serve anything anyway.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+/// Window over which the failure rate of a project is measured
+const WINDOW: Duration = Duration::from_secs(30);
+/// Fewest requests in a window before the breaker can open, so a couple of failures do not cut
+/// off a quiet project
+const MIN_REQUESTS: u32 = 10;
+/// Share of failed requests in a window at which the breaker opens
+const FAILURE_RATIO: f64 = 0.5;
+/// How long an open breaker refuses requests
+const COOLDOWN: Duration = Duration::from_secs(30);
+
+/// The outcomes of the latest requests routed to a project
+struct Record {
+    window_start: Instant,
+    requests: u32,
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl Record {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            requests: 0,
+            failures: 0,
+            open_until: None,
+        }
+    }
+
+    fn reset(&mut self, now: Instant) {
+        self.window_start = now;
+        self.requests = 0;
+        self.failures = 0;
+    }
+}
+
+/// The circuit breakers of all the projects requests are routed to
+#[derive(Default)]
+pub struct CircuitBreakers {
+    projects: Mutex<HashMap<String, Record>>,
+}
+
+impl CircuitBreakers {
+    /// Check whether requests can go through to a project, or else how long until they can
+    pub fn check(&self, project_name: &str) -> Result<(), Duration> {
+        self.check_at(project_name, Instant::now())
+    }
+
+    /// Record whether a request routed to a project failed
+    pub fn record(&self, project_name: &str, is_failure: bool) {
+        self.record_at(project_name, is_failure, Instant::now())
+    }
+
+    /// Drop the breaker of a project which is gone
+    pub fn forget(&self, project_name: &str) {
+        self.projects.lock().unwrap().remove(project_name);
+    }
+
+    fn check_at(&self, project_name: &str, now: Instant) -> Result<(), Duration> {
+        let mut projects = self.projects.lock().unwrap();
+        let Some(record) = projects.get_mut(project_name) else {
+            return Ok(());
+        };
+
+        match record.open_until {
+            Some(open_until) if now < open_until => Err(open_until - now),
+            Some(_) => {
+                info!(project_name, "closing circuit breaker after its cooldown");
+                record.open_until = None;
+                record.reset(now);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn record_at(&self, project_name: &str, is_failure: bool, now: Instant) {
+        let mut projects = self.projects.lock().unwrap();
+        let record = projects
+            .entry(project_name.to_string())
+            .or_insert_with(|| Record::new(now));
+
+        if now.duration_since(record.window_start) >= WINDOW {
+            record.reset(now);
+        }
+
+        record.requests += 1;
+        if is_failure {
+            record.failures += 1;
+        }
+
+        if record.requests >= MIN_REQUESTS
+            && record.failures as f64 / record.requests as f64 >= FAILURE_RATIO
+        {
+            warn!(
+                project_name,
+                requests = record.requests,
+                failures = record.failures,
+                "opening circuit breaker of failing project"
+            );
+            record.open_until = Some(now + COOLDOWN);
+            record.reset(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{CircuitBreakers, COOLDOWN, MIN_REQUESTS, WINDOW};
+
+    #[test]
+    fn opens_on_failures_and_closes_after_cooldown() {
+        let breakers = CircuitBreakers::default();
+        let now = Instant::now();
+
+        // Failures under the minimum number of requests are tolerated
+        for _ in 1..MIN_REQUESTS {
+            breakers.record_at("matrix", true, now);
+        }
+        assert_eq!(breakers.check_at("matrix", now), Ok(()));
+
+        breakers.record_at("matrix", true, now);
+        assert_eq!(breakers.check_at("matrix", now), Err(COOLDOWN));
+
+        // Other projects are not affected
+        assert_eq!(breakers.check_at("zion", now), Ok(()));
+
+        let later = now + Duration::from_secs(10);
+        assert_eq!(
+            breakers.check_at("matrix", later),
+            Err(COOLDOWN - Duration::from_secs(10))
+        );
+
+        assert_eq!(breakers.check_at("matrix", now + COOLDOWN), Ok(()));
+    }
+
+    #[test]
+    fn forgotten_projects_start_closed() {
+        let breakers = CircuitBreakers::default();
+        let now = Instant::now();
+
+        for _ in 0..MIN_REQUESTS {
+            breakers.record_at("matrix", true, now);
+        }
+        assert_eq!(breakers.check_at("matrix", now), Err(COOLDOWN));
+
+        breakers.forget("matrix");
+        assert_eq!(breakers.check_at("matrix", now), Ok(()));
+        assert!(breakers.projects.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn stays_closed_when_mostly_succeeding() {
+        let breakers = CircuitBreakers::default();
+        let now = Instant::now();
+
+        for i in 0..(MIN_REQUESTS * 2) {
+            breakers.record_at("matrix", i % 3 == 0, now);
+        }
+        assert_eq!(breakers.check_at("matrix", now), Ok(()));
+
+        // Old failures do not count towards a new window
+        for _ in 1..MIN_REQUESTS {
+            breakers.record_at("zion", true, now);
+        }
+        breakers.record_at("zion", true, now + WINDOW);
+        assert_eq!(breakers.check_at("zion", now + WINDOW), Ok(()));
+    }
+}
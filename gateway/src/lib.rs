@@ -26,11 +26,16 @@ pub mod acme;
 pub mod api;
 pub mod args;
 pub mod auth;
+pub mod breaker;
+pub mod metrics;
 pub mod project;
 pub mod proxy;
+pub mod rate_limit;
 pub mod service;
 pub mod task;
 pub mod tls;
+pub mod upgrade;
+pub mod webhook;
 pub mod worker;
 
 static AUTH_CLIENT: Lazy<Client<HttpConnector>> = Lazy::new(Client::new);
@@ -210,6 +215,33 @@ impl<'de> Deserialize<'de> for AccountName {
 pub struct ProjectDetails {
     pub project_name: ProjectName,
     pub account_name: AccountName,
+    /// Number of times the project was restarted over its whole lifetime
+    pub total_restarts: u32,
+    /// When the project was last changed, if it was since this started being tracked
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A project as listed to the account owning it
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserProject {
+    pub project_name: ProjectName,
+    pub project: project::Project,
+    /// When the project was created
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// When the project was last changed, if it was since this started being tracked
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<UserProject> for shuttle_common::models::project::Response {
+    fn from(project: UserProject) -> Self {
+        Self {
+            name: project.project_name.to_string(),
+            state: project.project.into(),
+            transition_history: Vec::new(),
+            created_at: project.created_at,
+            updated_at: project.updated_at,
+        }
+    }
 }
 
 impl From<ProjectDetails> for shuttle_common::models::project::AdminResponse {
@@ -217,6 +249,8 @@ impl From<ProjectDetails> for shuttle_common::models::project::AdminResponse {
         Self {
             project_name: project.project_name.to_string(),
             account_name: project.account_name.to_string(),
+            total_restarts: project.total_restarts,
+            updated_at: project.updated_at,
         }
     }
 }
@@ -338,6 +372,8 @@ pub mod tests {
     use axum::headers::Authorization;
     use axum::routing::get;
     use axum::{extract, Router, TypedHeader};
+    use bollard::container::{Config, CreateContainerOptions};
+    use bollard::errors::Error as DockerError;
     use bollard::Docker;
     use fqdn::FQDN;
     use futures::prelude::*;
@@ -357,7 +393,9 @@ pub mod tests {
 
     use crate::acme::AcmeClient;
     use crate::api::latest::ApiBuilder;
-    use crate::args::{ContextArgs, StartArgs, UseTls};
+    use crate::args::{
+        CertificateKeyType, ContextArgs, PullPolicy, ReadinessCheck, StartArgs, UseTls,
+    };
     use crate::proxy::UserServiceBuilder;
     use crate::service::{ContainerSettings, GatewayService, MIGRATIONS};
     use crate::worker::Worker;
@@ -548,6 +586,38 @@ pub mod tests {
         }
     }
 
+    /// A container standing in for the gateway when the tests do not run in one. It is only
+    /// created, never started, which is enough for it to join the networks of projects. It is
+    /// shared by all the tests.
+    async fn stand_in_gateway_container(docker: &Docker, image: &str) -> String {
+        let name = "shuttle_test_gateway".to_string();
+
+        if docker.inspect_container(&name, None).await.is_err() {
+            let created = docker
+                .create_container(
+                    Some(CreateContainerOptions {
+                        name: name.clone(),
+                        platform: None,
+                    }),
+                    Config {
+                        image: Some(image.to_string()),
+                        ..Default::default()
+                    },
+                )
+                .await;
+            match created {
+                // Another test may have just created it
+                Ok(_)
+                | Err(DockerError::DockerResponseServerError {
+                    status_code: 409, ..
+                }) => {}
+                Err(error) => panic!("failed to create the stand-in gateway container: {error}"),
+            }
+        }
+
+        name
+    }
+
     pub struct World {
         docker: Docker,
         settings: ContainerSettings,
@@ -605,6 +675,14 @@ pub mod tests {
             let network_name =
                 env::var("SHUTTLE_TESTS_NETWORK").unwrap_or_else(|_| "shuttle_default".to_string());
 
+            let restricted_network_name = env::var("SHUTTLE_TESTS_RESTRICTED_NETWORK")
+                .unwrap_or_else(|_| "shuttle_restricted".to_string());
+
+            let gateway_container = match env::var("SHUTTLE_TESTS_GATEWAY_CONTAINER") {
+                Ok(gateway_container) => gateway_container,
+                Err(_) => stand_in_gateway_container(&docker, &image).await,
+            };
+
             let provisioner_host = "provisioner".to_string();
 
             let docker_host = "/var/run/docker.sock".to_string();
@@ -614,15 +692,44 @@ pub mod tests {
                 user,
                 bouncer,
                 use_tls: UseTls::Disable,
+                monitoring_token: None,
+                cloudflare_api_token: None,
+                cloudflare_zone_id: None,
+                acme_staging: false,
+                certificate_key_type: CertificateKeyType::EcdsaP256,
+                certificate_issuance_timeout_secs: crate::acme::CERTIFICATE_ISSUANCE_TIMEOUT_SECS,
+                compression_min_size: None,
+                metrics: None,
                 context: ContextArgs {
                     docker_host,
                     image,
+                    image_pull_policy: PullPolicy::Never,
                     prefix,
                     provisioner_host,
                     auth_uri: auth_uri.clone(),
                     network_name,
+                    restricted_network_name,
+                    restricted_network_services: Vec::new(),
+                    gateway_container: Some(gateway_container),
                     proxy_fqdn: FQDN::from_str("test.shuttleapp.rs").unwrap(),
                     deploys_api_key: "gateway".to_string(),
+                    health_path: "/projects/{name}/status".to_string(),
+                    readiness_check: ReadinessCheck::Http,
+                    expose_deployment_id: false,
+                    webhook_url: None,
+                    default_idle_minutes: project::IDLE_MINUTES,
+                    idle_grace_checks: 0,
+                    volume_retention_days: crate::project::VOLUME_RETENTION_DAYS,
+                    created_timeout_secs: crate::project::CREATED_TIMEOUT_SECS,
+                    project_quota: None,
+                    task_router_limit: crate::worker::TASK_ROUTER_LIMIT,
+                    renewal_threshold_days: crate::tls::RENEWAL_VALIDITY_THRESHOLD_IN_DAYS,
+                    project_cache_ttl_secs: None,
+                    db_max_connections: crate::service::DB_MAX_CONNECTIONS,
+                    db_busy_timeout_ms: crate::service::DB_BUSY_TIMEOUT_MS,
+                    route_timeout_secs: crate::service::ROUTE_TIMEOUT_SECS,
+                    cold_start_timeout_secs: crate::service::COLD_START_TIMEOUT_SECS,
+                    stats_retention_days: None,
                 },
             };
 
@@ -755,6 +862,16 @@ pub mod tests {
                         }
                     }),
                 )
+                .route(
+                    "/users/:account_name",
+                    get(|extract::State(state): extract::State<Arc<Mutex<Self>>>, extract::Path(account_name): extract::Path<String>| async move {
+                        if state.lock().unwrap().users.contains_key(&account_name) {
+                            StatusCode::OK
+                        } else {
+                            StatusCode::NOT_FOUND
+                        }
+                    }),
+                )
                 .with_state(this.clone());
 
             tokio::spawn(async move {
@@ -2,17 +2,18 @@ use clap::Parser;
 use futures::prelude::*;
 
 use shuttle_common::backends::tracing::setup_tracing;
-use shuttle_gateway::acme::{AcmeClient, CustomDomain};
+use shuttle_gateway::acme::{AcmeClient, CloudflareDnsProvider};
 use shuttle_gateway::api::latest::{ApiBuilder, SVC_DEGRADED_THRESHOLD};
 use shuttle_gateway::args::StartArgs;
 use shuttle_gateway::args::{Args, Commands, UseTls};
+use shuttle_gateway::metrics;
 use shuttle_gateway::proxy::UserServiceBuilder;
 use shuttle_gateway::service::{GatewayService, MIGRATIONS};
 use shuttle_gateway::task;
 use shuttle_gateway::tls::make_tls_acceptor;
 use shuttle_gateway::worker::{Worker, WORKER_QUEUE_SIZE};
 use sqlx::migrate::MigrateDatabase;
-use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
 use sqlx::{Sqlite, SqlitePool};
 use std::io::{self, Cursor};
 
@@ -30,6 +31,12 @@ async fn main() -> io::Result<()> {
 
     setup_tracing(tracing_subscriber::registry(), "gateway");
 
+    // The state is kept in sqlite only. Most queries are plain SQL, but another backend like
+    // postgres would need handling of:
+    // - the `ulid()` function of the ulid0 sqlite extension, which generates the ids of projects;
+    // - `json_each`, used to filter projects on their tags;
+    // - the sqlite style `?N` placeholders and the pool and query builder typed to sqlite;
+    // - its own migrations, since the current ones rely on sqlite types and table rebuilds.
     let db_path = args.state.join("gateway.sqlite");
     let db_uri = db_path.to_str().unwrap();
 
@@ -44,16 +51,23 @@ async fn main() -> io::Result<()> {
             .to_string_lossy()
     );
 
+    let Commands::Start(StartArgs { context, .. }) = &args.command;
+
     let sqlite_options = SqliteConnectOptions::from_str(db_uri)
         .unwrap()
         .journal_mode(SqliteJournalMode::Wal)
         .synchronous(SqliteSynchronous::Normal)
+        .busy_timeout(Duration::from_millis(context.db_busy_timeout_ms))
         // Set the ulid0 extension for generating ULID's in migrations.
         // This uses the ulid0.so file in the crate root, with the
         // LD_LIBRARY_PATH env set in build.rs.
         .extension("ulid0");
 
-    let db = SqlitePool::connect_with(sqlite_options).await.unwrap();
+    let db = SqlitePoolOptions::new()
+        .max_connections(context.db_max_connections)
+        .connect_with(sqlite_options)
+        .await
+        .unwrap();
     MIGRATIONS.run(&db).await.unwrap();
 
     match args.command {
@@ -75,10 +89,8 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
             .map_err(|err| error!("worker error: {}", err)),
     );
 
-    for (project_name, _) in gateway
-        .iter_projects()
-        .await
-        .expect("could not list projects")
+    let mut projects = gateway.iter_projects();
+    while let Some((project_name, _)) = projects.try_next().await.expect("could not list projects")
     {
         gateway
             .clone()
@@ -110,7 +122,9 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
                     continue;
                 }
 
-                if let Ok(projects) = gateway.iter_projects().await {
+                // Collected up front since waiting on every check would keep a read of the
+                // database open for the whole round
+                if let Ok(projects) = gateway.collect_projects().await {
                     let span = info_span!(
                         "running health checks",
                         healthcheck.num_projects = projects.len()
@@ -140,7 +154,42 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
         }
     });
 
-    let acme_client = AcmeClient::new();
+    // Every hour remove the volumes of projects destroyed for longer than the retention window, and
+    // the stored stats which are past theirs.
+    let reaper_handle = tokio::spawn({
+        let gateway = Arc::clone(&gateway);
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+
+            loop {
+                interval.tick().await;
+
+                match gateway.reap_expired_volumes().await {
+                    Ok(0) => {}
+                    Ok(removed) => info!(removed, "removed expired project volumes"),
+                    Err(error) => error!(%error, "failed to remove expired project volumes"),
+                }
+
+                match gateway.reap_expired_stats().await {
+                    Ok(0) => {}
+                    Ok(removed) => info!(removed, "removed expired project stats"),
+                    Err(error) => error!(%error, "failed to remove expired project stats"),
+                }
+            }
+        }
+    });
+
+    let mut acme_client = AcmeClient::new();
+    if let (Some(api_token), Some(zone_id)) = (args.cloudflare_api_token, args.cloudflare_zone_id) {
+        acme_client = acme_client.with_dns_provider(CloudflareDnsProvider::new(api_token, zone_id));
+    }
+    acme_client = acme_client
+        .with_key_type(args.certificate_key_type)
+        .with_issuance_timeout(Duration::from_secs(args.certificate_issuance_timeout_secs));
+    if args.acme_staging {
+        acme_client = acme_client.with_staging();
+        warn!("ACME is in staging mode: accounts are created on the Let's Encrypt staging directory and their certificates are NOT trusted. This should *never* be used in production.");
+    }
 
     let mut api_builder = ApiBuilder::new()
         .with_service(Arc::clone(&gateway))
@@ -154,6 +203,10 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
         .with_user_proxy_binding_to(args.user)
         .with_bouncer(args.bouncer);
 
+    if let Some(min_size) = args.compression_min_size {
+        user_builder = user_builder.with_compression(min_size);
+    }
+
     if let UseTls::Enable = args.use_tls {
         let (resolver, tls_acceptor) = make_tls_acceptor();
 
@@ -163,41 +216,93 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
 
         api_builder = api_builder.with_acme(acme_client.clone(), resolver.clone());
 
-        for CustomDomain {
-            fqdn,
-            certificate,
-            private_key,
-            ..
-        } in gateway.iter_custom_domains().await.unwrap()
-        {
+        // Certificates are issued on the directory of the account of the gateway, whatever the
+        // ACME mode, so an account on the other Let's Encrypt directory has to be rotated first
+        if gateway.has_credentials() {
+            acme_client
+                .check_account_directory(&gateway.credentials())
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        }
+
+        for custom_domain in gateway.iter_custom_domains().await.unwrap() {
             let mut buf = Vec::new();
-            buf.extend(certificate.as_bytes());
-            buf.extend(private_key.as_bytes());
+            buf.extend(custom_domain.certificate.as_bytes());
+            buf.extend(custom_domain.private_key.as_bytes());
             resolver
-                .serve_pem(&fqdn.to_string(), Cursor::new(buf))
+                .serve_pem(&custom_domain.identifier(), Cursor::new(buf))
                 .await
                 .unwrap();
         }
 
         tokio::spawn(async move {
-            // Make sure we have a certificate for ourselves.
-            let certs = gateway
-                .fetch_certificate(&acme_client, gateway.credentials())
-                .await;
-            resolver
-                .serve_default_der(certs)
-                .await
-                .expect("failed to set certs to be served as default");
+            // Twice a day, starting now, renew the certificates of the gateway and of the custom
+            // domains which are about to expire. Until the gateway serves a certificate of its
+            // own, it first tries to fetch one. A failed fetch is tried again on the next tick
+            // and never stops the renewals.
+            let mut interval = tokio::time::interval(Duration::from_secs(12 * 60 * 60));
+            let mut serving_default = false;
+
+            loop {
+                interval.tick().await;
+
+                if !gateway.has_credentials() {
+                    error!("the gateway has no ACME account, so its certificates are neither fetched nor renewed");
+                    continue;
+                }
+
+                if !serving_default {
+                    let fetched = match gateway
+                        .fetch_certificate(&acme_client, gateway.credentials())
+                        .await
+                    {
+                        Ok(certs) => resolver.serve_default_der(certs).await,
+                        Err(error) => Err(error),
+                    };
+
+                    match fetched {
+                        Ok(()) => serving_default = true,
+                        Err(error) => error!(%error, "failed to fetch the gateway certificate"),
+                    }
+                }
+
+                match gateway
+                    .renew_expiring_certificates(&acme_client, &resolver)
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(renewed) => info!(renewed, "renewed certificates"),
+                    Err(error) => error!(%error, "failed to renew certificates"),
+                }
+            }
         });
     } else {
         warn!("TLS is disabled in the proxy service. This is only acceptable in testing, and should *never* be used in deployments.");
     };
 
-    let api_handle = api_builder
+    let mut api_builder = api_builder
         .with_default_routes()
-        .with_auth_service(args.context.auth_uri)
-        .with_default_traces()
-        .serve();
+        .with_auth_service(args.context.auth_uri);
+
+    if let Some(monitoring_token) = args.monitoring_token {
+        api_builder = api_builder.with_monitoring_token(monitoring_token);
+    }
+
+    if let Some(metrics_bind) = args.metrics {
+        let handle = metrics::install_recorder().expect("to install the metrics recorder");
+
+        tokio::spawn(async move {
+            info!(%metrics_bind, "serving metrics");
+
+            if let Err(error) = axum::Server::bind(&metrics_bind)
+                .serve(metrics::router(handle).into_make_service())
+                .await
+            {
+                error!(%error, "metrics server failed");
+            }
+        });
+    }
+
+    let api_handle = api_builder.with_default_traces().serve();
 
     let user_handle = user_builder.serve();
 
@@ -208,6 +313,7 @@ async fn start(db: SqlitePool, fs: PathBuf, args: StartArgs) -> io::Result<()> {
         _ = api_handle => error!("api handle finished"),
         _ = user_handle => error!("user handle finished"),
         _ = ambulance_handle => error!("ambulance handle finished"),
+        _ = reaper_handle => error!("reaper handle finished"),
     );
 
     Ok(())
@@ -0,0 +1,113 @@
+//! Prometheus metrics of the requests routed to projects.
+//!
+//! Every request the [UserProxy](crate::proxy::UserProxy) forwards to a project records how long
+//! the project took to answer and the class of its status code, labelled by project. They are
+//! recorded through the `metrics` facade and rendered for scraping by the [router] of this module.
+
+use std::future::ready;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::ProjectName;
+
+/// Counter of the requests routed to projects, by project and status class
+pub const ROUTED_REQUESTS: &str = "gateway_routed_requests_total";
+/// Histogram of the time projects take to answer routed requests, in seconds
+pub const UPSTREAM_LATENCY: &str = "gateway_upstream_latency_seconds";
+
+/// Buckets of [UPSTREAM_LATENCY], up to the default route timeout
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// Builder of the recorder the metrics of the gateway are collected into
+pub fn builder() -> Result<PrometheusBuilder, BuildError> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full(UPSTREAM_LATENCY.to_string()), LATENCY_BUCKETS)
+}
+
+/// Install the recorder the metrics of the gateway are collected into. Can only be done once per
+/// process.
+pub fn install_recorder() -> Result<PrometheusHandle, BuildError> {
+    builder()?.install_recorder()
+}
+
+/// Router serving the collected metrics on `/metrics` in the Prometheus text format
+pub fn router(handle: PrometheusHandle) -> Router {
+    Router::new().route("/metrics", get(move || ready(handle.render())))
+}
+
+/// The class of a status code, like `2xx`, to keep the number of series low
+pub fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// Record a request routed to a project which answered with `status` after `latency`
+pub fn record_routed(project_name: &ProjectName, status: StatusCode, latency: Duration) {
+    let project = project_name.to_string();
+
+    histogram!(UPSTREAM_LATENCY, "project" => project.clone()).record(latency);
+    counter!(
+        ROUTED_REQUESTS,
+        "project" => project,
+        "status" => status_class(status)
+    )
+    .increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn status_classes() {
+        assert_eq!(status_class(StatusCode::SWITCHING_PROTOCOLS), "1xx");
+        assert_eq!(status_class(StatusCode::OK), "2xx");
+        assert_eq!(status_class(StatusCode::PERMANENT_REDIRECT), "3xx");
+        assert_eq!(status_class(StatusCode::TOO_MANY_REQUESTS), "4xx");
+        assert_eq!(status_class(StatusCode::GATEWAY_TIMEOUT), "5xx");
+    }
+
+    #[test]
+    fn renders_routed_requests() {
+        let recorder = builder().unwrap().build_recorder();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            record_routed(&project_name, StatusCode::OK, Duration::from_millis(20));
+            record_routed(&project_name, StatusCode::OK, Duration::from_millis(40));
+            record_routed(
+                &project_name,
+                StatusCode::BAD_GATEWAY,
+                Duration::from_secs(2),
+            );
+        });
+
+        let rendered = recorder.handle().render();
+
+        assert!(
+            rendered.contains(r#"gateway_routed_requests_total{project="matrix",status="2xx"} 2"#)
+        );
+        assert!(
+            rendered.contains(r#"gateway_routed_requests_total{project="matrix",status="5xx"} 1"#)
+        );
+        assert!(rendered
+            .contains(r#"gateway_upstream_latency_seconds_bucket{project="matrix",le="0.025"} 1"#));
+        assert!(rendered.contains(r#"gateway_upstream_latency_seconds_count{project="matrix"} 3"#));
+    }
+}
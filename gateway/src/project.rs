@@ -8,8 +8,9 @@ use bollard::container::{
     StatsOptions, StopContainerOptions,
 };
 use bollard::errors::Error as DockerError;
-use bollard::models::{ContainerInspectResponse, ContainerStateStatusEnum};
-use bollard::network::{ConnectNetworkOptions, DisconnectNetworkOptions};
+use bollard::image::CreateImageOptions;
+use bollard::models::{ContainerInspectResponse, ContainerStateStatusEnum, HealthStatusEnum};
+use bollard::network::{ConnectNetworkOptions, CreateNetworkOptions, DisconnectNetworkOptions};
 use bollard::system::EventsOptions;
 use fqdn::FQDN;
 use futures::prelude::*;
@@ -22,13 +23,16 @@ use once_cell::sync::Lazy;
 use rand::distributions::{Alphanumeric, DistString};
 use serde::{Deserialize, Serialize};
 use shuttle_common::backends::headers::{X_SHUTTLE_ACCOUNT_NAME, X_SHUTTLE_ADMIN_SECRET};
-use shuttle_common::models::project::{idle_minutes, IDLE_MINUTES};
+use shuttle_common::models::deployment::RestartAttempt;
+use shuttle_common::models::project::{self, idle_minutes, EgressPolicy};
 use shuttle_common::models::service;
 use tokio::time::{sleep, timeout};
-use tracing::{debug, error, info, instrument, trace};
+use tracing::{debug, error, info, instrument, trace, warn};
 use uuid::Uuid;
 
+use crate::args::{PullPolicy, ReadinessCheck};
 use crate::service::ContainerSettings;
+use crate::webhook;
 use crate::{
     DockerContext, EndState, Error, ErrorKind, IntoTryState, ProjectName, Refresh, State, TryState,
 };
@@ -72,6 +76,14 @@ const RUNTIME_API_PORT: u16 = 8001;
 const MAX_RECREATES: usize = 5;
 const MAX_RESTARTS: usize = 5;
 const MAX_REBOOTS: usize = 3;
+// Time given to a project to exit after a `SIGTERM` before it is killed
+const GRACEFUL_STOP_TIMEOUT_SECS: i64 = 30;
+/// Default number of days the volume of a destroyed project is kept for
+pub const VOLUME_RETENTION_DAYS: u64 = 7;
+/// Number of state transitions kept in the history of a project
+pub const MAX_TRANSITION_HISTORY: usize = 20;
+/// Default number of seconds a container may stay `CREATED` while starting before it is recreated
+pub const CREATED_TIMEOUT_SECS: u64 = 120;
 
 // Client used for health checks
 static CLIENT: Lazy<Client<HttpConnector>> = Lazy::new(Client::new);
@@ -103,18 +115,89 @@ pub trait ContainerInspectResponseExt {
             .map_err(|_| ProjectError::internal("invalid project name"))
     }
 
-    fn idle_minutes(&self) -> u64 {
+    /// The idle minutes of the project, falling back to `default` when the label is missing
+    /// or invalid
+    fn idle_minutes(&self, default: u64) -> u64 {
         let container = self.container();
 
         if let Some(config) = &container.config {
             if let Some(labels) = &config.labels {
                 if let Some(idle_minutes) = labels.get("shuttle.idle_minutes") {
-                    return idle_minutes.parse::<u64>().unwrap_or(IDLE_MINUTES);
+                    return idle_minutes.parse::<u64>().unwrap_or(default);
                 }
             }
         }
 
-        IDLE_MINUTES
+        default
+    }
+
+    fn graceful_stop(&self) -> bool {
+        let container = self.container();
+
+        container
+            .config
+            .as_ref()
+            .and_then(|config| config.labels.as_ref())
+            .and_then(|labels| labels.get("shuttle.graceful_stop"))
+            .and_then(|graceful_stop| graceful_stop.parse::<bool>().ok())
+            .unwrap_or_default()
+    }
+
+    fn isolated_network(&self) -> bool {
+        let container = self.container();
+
+        container
+            .config
+            .as_ref()
+            .and_then(|config| config.labels.as_ref())
+            .and_then(|labels| labels.get("shuttle.isolated_network"))
+            .and_then(|isolated_network| isolated_network.parse::<bool>().ok())
+            .unwrap_or_default()
+    }
+
+    fn egress_policy(&self) -> EgressPolicy {
+        let container = self.container();
+
+        container
+            .config
+            .as_ref()
+            .and_then(|config| config.labels.as_ref())
+            .and_then(|labels| labels.get("shuttle.egress_policy"))
+            .and_then(|egress_policy| egress_policy.parse::<EgressPolicy>().ok())
+            .unwrap_or_default()
+    }
+
+    /// Health of the container according to the HEALTHCHECK of its image. `None` when the image
+    /// does not define one, and `Some(false)` while the check is still starting.
+    fn docker_health(&self) -> Option<bool> {
+        let container = self.container();
+
+        match container.state.as_ref()?.health.as_ref()?.status? {
+            HealthStatusEnum::HEALTHY => Some(true),
+            HealthStatusEnum::STARTING | HealthStatusEnum::UNHEALTHY => Some(false),
+            HealthStatusEnum::NONE | HealthStatusEnum::EMPTY => None,
+        }
+    }
+
+    /// Name of the volume holding the deployer state
+    fn volume_name(&self) -> Option<String> {
+        let container = self.container();
+
+        container
+            .mounts
+            .as_ref()?
+            .iter()
+            .find(|mount| mount.destination.as_deref() == Some("/opt/shuttle"))
+            .and_then(|mount| mount.name.clone())
+    }
+
+    /// Name of the network owned by this project when it is isolated from other projects
+    fn isolated_network_name(&self) -> Result<String, ProjectError> {
+        let container = self.container();
+        let prefix = safe_unwrap!(container.config.labels.get("shuttle.prefix"));
+        let project_name = self.project_name()?;
+
+        Ok(format!("{prefix}{project_name}_net"))
     }
 
     fn find_arg_and_then<'s, F, O>(&'s self, find: &str, and_then: F) -> Result<O, ProjectError>
@@ -226,10 +309,7 @@ impl Project {
 
     pub fn start(self) -> Result<Self, Error> {
         if let Some(container) = self.container() {
-            Ok(Self::Starting(ProjectStarting {
-                container,
-                restart_count: 0,
-            }))
+            Ok(Self::Starting(ProjectStarting::new(container, 0)))
         } else {
             Err(Error::custom(
                 ErrorKind::InvalidOperation,
@@ -242,6 +322,20 @@ impl Project {
         matches!(self, Self::Ready(_))
     }
 
+    /// Whether the project is on its way to being ready, like after being started
+    pub fn is_waking(&self) -> bool {
+        matches!(
+            self,
+            Self::Creating(_)
+                | Self::Attaching(_)
+                | Self::Recreating(_)
+                | Self::Starting(_)
+                | Self::Restarting(_)
+                | Self::Started(_)
+                | Self::Rebooting(_)
+        )
+    }
+
     pub fn is_destroyed(&self) -> bool {
         matches!(self, Self::Destroyed(_))
     }
@@ -303,6 +397,25 @@ impl Project {
         }
     }
 
+    /// Name of the state, as it is serialized
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Creating(_) => "creating",
+            Self::Attaching(_) => "attaching",
+            Self::Recreating(_) => "recreating",
+            Self::Starting(_) => "starting",
+            Self::Restarting(_) => "restarting",
+            Self::Started(_) => "started",
+            Self::Ready(_) => "ready",
+            Self::Stopping(_) => "stopping",
+            Self::Stopped(_) => "stopped",
+            Self::Rebooting(_) => "rebooting",
+            Self::Destroying(_) => "destroying",
+            Self::Destroyed(_) => "destroyed",
+            Self::Errored(_) => "errored",
+        }
+    }
+
     pub fn container(&self) -> Option<ContainerInspectResponse> {
         match self {
             Self::Starting(ProjectStarting { container, .. })
@@ -320,6 +433,32 @@ impl Project {
         }
     }
 
+    /// The samples of resource usage taken to tell whether the project is idle, oldest first.
+    ///
+    /// A sample is taken at every health check of a started project, so about once a minute. Only
+    /// the last `idle_minutes` worth are kept, and none for projects which never idle.
+    pub fn stats(&self) -> Vec<project::StatsSample> {
+        let stats = match self {
+            Self::Started(ProjectStarted { stats, .. })
+            | Self::Ready(ProjectReady { stats, .. }) => stats,
+            _ => return Vec::new(),
+        };
+
+        stats
+            .iter()
+            .filter_map(|stats| {
+                let timestamp = chrono::DateTime::parse_from_rfc3339(&stats.read).ok()?;
+
+                Some(project::StatsSample {
+                    timestamp: timestamp.with_timezone(&chrono::Utc),
+                    cpu_usage: stats.cpu_stats.cpu_usage.total_usage,
+                    memory_usage: stats.memory_stats.usage,
+                    memory_limit: stats.memory_stats.limit,
+                })
+            })
+            .collect()
+    }
+
     pub fn initial_key(&self) -> Option<&str> {
         if let Self::Creating(creating) = self {
             Some(creating.initial_key())
@@ -328,6 +467,20 @@ impl Project {
         }
     }
 
+    /// The secret needed to communicate with the project's deployer, in any state
+    /// where it can still be known
+    pub fn admin_secret(&self) -> Option<String> {
+        match self {
+            Self::Creating(creating) => Some(creating.initial_key().to_string()),
+            Self::Destroyed(ProjectDestroyed {
+                destroyed: Some(container),
+            }) => container.initial_key().ok(),
+            other => other
+                .container()
+                .and_then(|container| container.initial_key().ok()),
+        }
+    }
+
     pub fn container_id(&self) -> Option<String> {
         self.container().and_then(|container| container.id)
     }
@@ -440,6 +593,42 @@ where
     }
 }
 
+/// The most recent state transitions of a project, as `(from, to, at)` and oldest first. Useful
+/// to debug projects which keep flapping between states.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TransitionHistory(VecDeque<(String, String, chrono::DateTime<chrono::Utc>)>);
+
+impl TransitionHistory {
+    /// Record a transition, dropping the oldest one once [MAX_TRANSITION_HISTORY] is reached.
+    /// Staying in the same state is not a transition.
+    pub fn record(&mut self, from: String, to: String) {
+        if from == to {
+            return;
+        }
+
+        self.0.push_back((from, to, chrono::Utc::now()));
+
+        while self.0.len() > MAX_TRANSITION_HISTORY {
+            self.0.pop_front();
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, String, chrono::DateTime<chrono::Utc>)> {
+        self.0.iter()
+    }
+}
+
+impl From<TransitionHistory> for Vec<shuttle_common::models::project::Transition> {
+    fn from(history: TransitionHistory) -> Self {
+        history
+            .0
+            .into_iter()
+            .map(|(from, to, at)| shuttle_common::models::project::Transition { from, to, at })
+            .collect()
+    }
+}
+
 impl<Ctx> EndState<Ctx> for Project
 where
     Ctx: DockerContext,
@@ -479,7 +668,7 @@ where
         let refreshed = match self {
             Self::Creating(creating) => Self::Creating(creating),
             Self::Attaching(attaching) => Self::Attaching(attaching),
-            Self::Starting(ProjectStarting { container, restart_count }) => match container
+            Self::Starting(starting) => match starting.container
                 .clone()
                 .refresh(ctx)
                 .await
@@ -488,9 +677,15 @@ where
                     ContainerStateStatusEnum::RUNNING => {
                         Self::Started(ProjectStarted::new(container, VecDeque::new()))
                     }
+                    // A container which never leaves `CREATED` will not start by itself, so force a fresh create
+                    ContainerStateStatusEnum::CREATED
+                        if starting.is_stuck(ctx.container_settings().created_timeout_secs) =>
+                    {
+                        Self::Recreating(ProjectRecreating { container, recreate_count: 0 })
+                    }
                     ContainerStateStatusEnum::CREATED => Self::Starting(ProjectStarting {
                         container,
-                        restart_count,
+                        ..starting
                     }),
                     ContainerStateStatusEnum::EXITED => Self::Restarting(ProjectRestarting  { container, restart_count: 0 }),
                     _ => {
@@ -505,21 +700,26 @@ where
                 }) => {
                     // container not found, let's try to recreate it
                     // with the same image
-                    Self::Creating(ProjectCreating::from_container(container, 0)?)
+                    Self::Creating(ProjectCreating::from_container(
+                        starting.container,
+                        0,
+                        ctx.container_settings().default_idle_minutes,
+                    )?)
                 }
                 Err(err) => return Err(err.into()),
             },
-            Self::Started(ProjectStarted { container, stats, .. })
-            | Self::Ready(ProjectReady { container, stats, .. })
+            Self::Started(ProjectStarted { container, stats, idle_checks, .. })
+            | Self::Ready(ProjectReady { container, stats, idle_checks, .. })
              => match container
                 .clone()
                 .refresh(ctx)
                 .await
             {
                 Ok(container) => match safe_unwrap!(container.state.status) {
-                    ContainerStateStatusEnum::RUNNING => {
-                        Self::Started(ProjectStarted::new(container, stats))
-                    }
+                    ContainerStateStatusEnum::RUNNING => Self::Started(ProjectStarted {
+                        idle_checks,
+                        ..ProjectStarted::new(container, stats)
+                    }),
                     // Restart the container if it went down
                     ContainerStateStatusEnum::EXITED => Self::Restarting(ProjectRestarting  { container, restart_count: 0 }),
                     _ => {
@@ -534,7 +734,11 @@ where
                 }) => {
                     // container not found, let's try to recreate it
                     // with the same image
-                    Self::Creating(ProjectCreating::from_container(container, 0)?)
+                    Self::Creating(ProjectCreating::from_container(
+                        container,
+                        0,
+                        ctx.container_settings().default_idle_minutes,
+                    )?)
                 }
                 Err(err) => return Err(err.into()),
             },
@@ -588,6 +792,21 @@ pub struct ProjectCreating {
     /// Label set on container as to how many minutes to wait before a project is considered idle
     #[serde(default = "idle_minutes")]
     idle_minutes: u64,
+    /// Label set on container as to whether it should get a `SIGTERM` before being killed when stopping
+    #[serde(default)]
+    graceful_stop: bool,
+    /// Label set on container as to whether it should join its own network instead of the shared
+    /// one. An isolated project cannot reach other projects, but it also cannot reach the shared
+    /// internal services (like the provisioner) which only live on the shared network.
+    #[serde(default)]
+    isolated_network: bool,
+    /// Label set on container as to what outbound network access it has
+    #[serde(default)]
+    egress_policy: EgressPolicy,
+    /// Override the default volume (`${prefix}${project_name}_vol`), which a renamed project keeps
+    /// using since volumes cannot be renamed
+    #[serde(default)]
+    volume_name: Option<String>,
 }
 
 impl ProjectCreating {
@@ -600,15 +819,24 @@ impl ProjectCreating {
             from: None,
             recreate_count: 0,
             idle_minutes,
+            graceful_stop: false,
+            isolated_network: false,
+            egress_policy: EgressPolicy::Open,
+            volume_name: None,
         }
     }
 
     pub fn from_container(
         container: ContainerInspectResponse,
         recreate_count: usize,
+        default_idle_minutes: u64,
     ) -> Result<Self, ProjectError> {
         let project_name = container.project_name()?;
-        let idle_minutes = container.idle_minutes();
+        let idle_minutes = container.idle_minutes(default_idle_minutes);
+        let graceful_stop = container.graceful_stop();
+        let isolated_network = container.isolated_network();
+        let egress_policy = container.egress_policy();
+        let volume_name = container.volume_name();
         let initial_key = container.initial_key()?;
 
         Ok(Self {
@@ -619,6 +847,10 @@ impl ProjectCreating {
             from: Some(container),
             recreate_count,
             idle_minutes,
+            graceful_stop,
+            isolated_network,
+            egress_policy,
+            volume_name,
         })
     }
 
@@ -642,6 +874,50 @@ impl ProjectCreating {
         self
     }
 
+    pub fn with_graceful_stop(mut self, graceful_stop: bool) -> Self {
+        self.graceful_stop = graceful_stop;
+        self
+    }
+
+    pub fn with_isolated_network(mut self, isolated_network: bool) -> Self {
+        self.isolated_network = isolated_network;
+        self
+    }
+
+    pub fn with_egress_policy(mut self, egress_policy: EgressPolicy) -> Self {
+        self.egress_policy = egress_policy;
+        self
+    }
+
+    pub fn with_volume_name(mut self, volume_name: String) -> Self {
+        self.volume_name = Some(volume_name);
+        self
+    }
+
+    /// Carry over the settings of a previous container of the project, for when the project is
+    /// recreated from scratch with a new configuration
+    pub fn with_settings_of(mut self, container: &ContainerInspectResponse) -> Self {
+        self.graceful_stop = container.graceful_stop();
+        self.isolated_network = container.isolated_network();
+        self.egress_policy = container.egress_policy();
+        if let Ok(fqdn) = container.find_arg_and_then("--proxy-fqdn", str::to_owned) {
+            self.fqdn = Some(fqdn);
+        }
+        if let Some(volume_name) = container.volume_name() {
+            self.volume_name = Some(volume_name);
+        }
+        self
+    }
+
+    /// The same project under a new name. The configuration of the previous container is dropped
+    /// since its labels and arguments carry the old name, but its volume is kept.
+    pub fn renamed(mut self, project_name: ProjectName, volume_name: String) -> Self {
+        self.project_name = project_name;
+        self.from = None;
+        self.volume_name.get_or_insert(volume_name);
+        self
+    }
+
     pub fn project_name(&self) -> &ProjectName {
         &self.project_name
     }
@@ -654,6 +930,10 @@ impl ProjectCreating {
         &self.fqdn
     }
 
+    pub fn idle_minutes(&self) -> u64 {
+        self.idle_minutes
+    }
+
     fn container_name<C: DockerContext>(&self, ctx: &C) -> String {
         let prefix = &ctx.container_settings().prefix;
 
@@ -681,6 +961,10 @@ impl ProjectCreating {
             fqdn,
             image,
             idle_minutes,
+            graceful_stop,
+            isolated_network,
+            egress_policy,
+            volume_name,
             ..
         } = &self;
 
@@ -701,6 +985,9 @@ impl ProjectCreating {
                         "shuttle.prefix": prefix,
                         "shuttle.project": project_name,
                         "shuttle.idle_minutes": format!("{idle_minutes}"),
+                        "shuttle.graceful_stop": format!("{graceful_stop}"),
+                        "shuttle.isolated_network": format!("{isolated_network}"),
+                        "shuttle.egress_policy": format!("{egress_policy}"),
                     },
                     "Cmd": [
                         "--admin-secret",
@@ -733,7 +1020,9 @@ impl ProjectCreating {
         config.host_config = deserialize_json!({
             "Mounts": [{
                 "Target": "/opt/shuttle",
-                "Source": format!("{prefix}{project_name}_vol"),
+                "Source": volume_name
+                    .clone()
+                    .unwrap_or_else(|| ctx.container_settings().volume_name(project_name)),
                 "Type": "volume"
             }],
             // https://docs.docker.com/config/containers/resource_constraints/#memory
@@ -753,6 +1042,42 @@ Config: {config:#?}
 
         (create_container_options, config)
     }
+
+    /// Pull the image according to the configured [PullPolicy]
+    async fn pull_image<C: DockerContext>(ctx: &C, image: &str) -> Result<(), ProjectError> {
+        let should_pull = match ctx.container_settings().image_pull_policy {
+            PullPolicy::Always => true,
+            PullPolicy::IfNotPresent => match ctx.docker().inspect_image(image).await {
+                Ok(_) => false,
+                Err(DockerError::DockerResponseServerError {
+                    status_code: 404, ..
+                }) => true,
+                Err(err) => return Err(err.into()),
+            },
+            PullPolicy::Never => false,
+        };
+
+        if should_pull {
+            debug!(image, "pulling image");
+
+            ctx.docker()
+                .create_image(
+                    Some(CreateImageOptions {
+                        from_image: image,
+                        ..Default::default()
+                    }),
+                    None,
+                    None,
+                )
+                .try_collect::<Vec<_>>()
+                .await
+                .map_err(|err| {
+                    ProjectError::internal(format!("failed to pull image `{image}`: {err}"))
+                })?;
+        }
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -768,23 +1093,26 @@ where
         let container_name = self.container_name(ctx);
         let Self { recreate_count, .. } = self;
 
-        let container = ctx
-            .docker()
-            // If container already exists, use that
-            .inspect_container(&container_name.clone(), None)
+        // If container already exists, use that
+        let container = match ctx.docker().inspect_container(&container_name, None).await {
+            Ok(container) => container,
             // Otherwise create it
-            .or_else(|err| async move {
-                if matches!(err, DockerError::DockerResponseServerError { status_code, .. } if status_code == 404) {
-                    let (opts, config) = self.generate_container_config(ctx);
-                    ctx.docker()
-                        .create_container(Some(opts), config)
-                        .and_then(|_| ctx.docker().inspect_container(&container_name, None))
-                        .await
-                } else {
-                    Err(err)
+            Err(DockerError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => {
+                let (opts, config) = self.generate_container_config(ctx);
+
+                if let Some(image) = config.image.as_ref() {
+                    Self::pull_image(ctx, image).await?;
                 }
-            })
-            .await?;
+
+                ctx.docker()
+                    .create_container(Some(opts), config)
+                    .and_then(|_| ctx.docker().inspect_container(&container_name, None))
+                    .await?
+            }
+            Err(err) => return Err(err.into()),
+        };
         Ok(ProjectAttaching {
             container,
             recreate_count,
@@ -813,63 +1141,147 @@ where
         let Self { container, .. } = self;
 
         let container_id = safe_unwrap!(container.id);
-        let ContainerSettings { network_name, .. } = ctx.container_settings();
+        let ContainerSettings {
+            network_name,
+            restricted_network_name,
+            restricted_network_services,
+            gateway_container,
+            ..
+        } = ctx.container_settings();
 
         // Disconnect the bridge network before trying to start up
         // For docker bug https://github.com/docker/cli/issues/1891
         //
         // Also disconnecting from all network because docker just losses track of their IDs sometimes when restarting
-        for network in safe_unwrap!(container.network_settings.networks).keys() {
-            ctx.docker().disconnect_network(network, DisconnectNetworkOptions{
-            container: container_id,
-            force: true,
-        })
-            .await
-            .or_else(|err| {
-                if matches!(err, DockerError::DockerResponseServerError { status_code, .. } if status_code == 500) {
-                    info!("already disconnected from the {network} network");
-                    Ok(())
-                } else {
-                    Err(err)
+        future::try_join_all(
+            safe_unwrap!(container.network_settings.networks)
+                .keys()
+                .map(|network| async move {
+                    ctx.docker().disconnect_network(network, DisconnectNetworkOptions{
+                        container: container_id,
+                        force: true,
+                    })
+                    .await
+                    .or_else(|err| {
+                        if matches!(err, DockerError::DockerResponseServerError { status_code, .. } if status_code == 500) {
+                            info!("already disconnected from the {network} network");
+                            Ok(())
+                        } else {
+                            Err(err)
+                        }
+                    })
+                }),
+        )
+        .await?;
+
+        // Isolated projects get a network of their own and restricted projects join the network
+        // without outbound access, everything else joins the shared user network. The restriction
+        // wins when a project is both, so its own network gets no outbound access either.
+        let restricted = container.egress_policy() == EgressPolicy::Restricted;
+        let network_name = if container.isolated_network() {
+            let isolated_network_name = container.isolated_network_name()?;
+            ensure_network(ctx, &isolated_network_name, restricted).await?;
+            // The gateway has to be on the network as well to route requests to the project
+            match gateway_container {
+                Some(gateway_container) => {
+                    connect_network(ctx, &isolated_network_name, gateway_container).await?
                 }
-            })?;
-        }
+                None => warn!("no gateway container is set to join the isolated network"),
+            }
+            isolated_network_name
+        } else if restricted {
+            ensure_network(ctx, restricted_network_name, true).await?;
+            // The gateway routes requests to the project over the network, and the project reaches
+            // the internal services like the provisioner and auth over it
+            match gateway_container {
+                Some(gateway_container) => {
+                    connect_network(ctx, restricted_network_name, gateway_container).await?
+                }
+                None => warn!("no gateway container is set to join the restricted network"),
+            }
+            for service_container in restricted_network_services {
+                connect_network(ctx, restricted_network_name, service_container).await?;
+            }
+            restricted_network_name.clone()
+        } else {
+            network_name.clone()
+        };
 
         // Make sure the container is connected to the user network
-        let network_config = ConnectNetworkOptions {
-            container: container_id,
-            endpoint_config: Default::default(),
-        };
-        ctx.docker()
-            .connect_network(network_name, network_config)
-            .await
-            .or_else(|err| {
-                if matches!(
-                    err,
-                    DockerError::DockerResponseServerError { status_code, .. } if status_code == 409
-                ) {
-                    info!("already connected to the shuttle network");
-                    Ok(())
-                } else {
-                    error!(
-                        error = &err as &dyn std::error::Error,
-                        "failed to connect to shuttle network"
-                    );
-                    Err(ProjectError::no_network(
-                        "failed to connect to shuttle network",
-                    ))
-                }
-            })?;
+        connect_network(ctx, &network_name, container_id).await?;
 
         let container = container.refresh(ctx).await?;
 
-        Ok(ProjectStarting {
-            container,
-            restart_count: 0,
-        })
+        Ok(ProjectStarting::new(container, 0))
     }
 }
 
+/// Connect a container to the network with the given name, if it is not connected to it yet
+async fn connect_network<C: DockerContext>(
+    ctx: &C,
+    network_name: &str,
+    container: &str,
+) -> Result<(), ProjectError> {
+    let network_config = ConnectNetworkOptions {
+        container,
+        endpoint_config: Default::default(),
+    };
+    ctx.docker()
+        .connect_network(network_name, network_config)
+        .await
+        .or_else(|err| {
+            // Older docker daemons answer with a 403 when the endpoint already exists
+            if matches!(
+                err,
+                DockerError::DockerResponseServerError { status_code, .. } if status_code == 409 || status_code == 403
+            ) {
+                info!("already connected to the {network_name} network");
+                Ok(())
+            } else {
+                error!(
+                    error = &err as &dyn std::error::Error,
+                    "failed to connect to the {network_name} network"
+                );
+                Err(ProjectError::no_network(
+                    "failed to connect to shuttle network",
+                ))
+            }
+        })
+}
+
+/// Create the network with the given name if it does not exist yet. An `internal` network has no
+/// route outside of the host.
+async fn ensure_network<C: DockerContext>(
+    ctx: &C,
+    name: &str,
+    internal: bool,
+) -> Result<(), ProjectError> {
+    ctx.docker()
+        .create_network(CreateNetworkOptions {
+            name,
+            check_duplicate: true,
+            internal,
+            ..Default::default()
+        })
+        .await
+        .map(|_| ())
+        .or_else(|err| {
+            if matches!(
+                err,
+                DockerError::DockerResponseServerError { status_code, .. } if status_code == 409
+            ) {
+                info!("the {name} network already exists");
+                Ok(())
+            } else {
+                error!(
+                    error = &err as &dyn std::error::Error,
+                    "failed to create the {name} network"
+                );
+                Err(ProjectError::no_network("failed to create network"))
+            }
+        })
+}
+
 // Special state to try and recreate a container if it failed to be created
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ProjectRecreating {
@@ -913,6 +1325,7 @@ where
             Ok(ProjectCreating::from_container(
                 container,
                 recreate_count + 1,
+                ctx.container_settings().default_idle_minutes,
             )?)
         } else {
             Err(ProjectError::internal("too many recreates"))
@@ -926,6 +1339,24 @@ pub struct ProjectStarting {
     // Use default for backward compatibility. Can be removed when all projects in the DB have this property set
     #[serde(default)]
     restart_count: usize,
+    /// When the project entered this state, to detect containers stuck in `CREATED`
+    #[serde(default = "chrono::Utc::now")]
+    starting_since: chrono::DateTime<chrono::Utc>,
+}
+
+impl ProjectStarting {
+    pub fn new(container: ContainerInspectResponse, restart_count: usize) -> Self {
+        Self {
+            container,
+            restart_count,
+            starting_since: chrono::Utc::now(),
+        }
+    }
+
+    /// Whether the container has been `CREATED` for longer than `timeout_secs` without starting
+    fn is_stuck(&self, timeout_secs: u64) -> bool {
+        chrono::Utc::now() - self.starting_since > chrono::Duration::seconds(timeout_secs as i64)
+    }
 }
 
 #[async_trait]
@@ -993,10 +1424,7 @@ where
 
         if restart_count < MAX_RESTARTS {
             sleep(Duration::from_secs(5)).await;
-            Ok(ProjectStarting {
-                container,
-                restart_count: restart_count + 1,
-            })
+            Ok(ProjectStarting::new(container, restart_count + 1))
         } else {
             Err(ProjectError::internal("too many restarts"))
         }
@@ -1010,6 +1438,9 @@ pub struct ProjectStarted {
     // Use default for backward compatibility. Can be removed when all projects in the DB have this property set
     #[serde(default)]
     stats: VecDeque<Stats>,
+    /// Health checks in a row the project was found idle, while it is given a grace
+    #[serde(default)]
+    idle_checks: u32,
 }
 
 impl ProjectStarted {
@@ -1018,6 +1449,30 @@ impl ProjectStarted {
             container,
             service: None,
             stats,
+            idle_checks: 0,
+        }
+    }
+}
+
+/// What to do with a project found idle on a health check
+#[derive(Debug, PartialEq, Eq)]
+enum IdleAction {
+    /// The grace starts: warn that the project will be stopped
+    Warn,
+    /// The grace is running: give last minute traffic a chance to keep the project up
+    Wait,
+    Stop,
+}
+
+impl IdleAction {
+    /// The action for a project which was already idle on the last `idle_checks` checks
+    fn after(idle_checks: u32, grace_checks: u32) -> Self {
+        if idle_checks >= grace_checks {
+            Self::Stop
+        } else if idle_checks == 0 {
+            Self::Warn
+        } else {
+            Self::Wait
         }
     }
 }
@@ -1043,6 +1498,7 @@ where
             container,
             service,
             mut stats,
+            idle_checks,
         } = self;
         let container = container.refresh(ctx).await?;
         let mut service = match service {
@@ -1050,8 +1506,25 @@ where
             None => Service::from_container(container.clone())?,
         };
 
-        if service.is_healthy().await {
-            let idle_minutes = container.idle_minutes();
+        let docker_health = match ctx.container_settings().readiness_check {
+            ReadinessCheck::Docker => container.docker_health(),
+            ReadinessCheck::Http => None,
+        };
+        let is_healthy = match docker_health {
+            Some(is_healthy) => {
+                service.last_check = Some(HealthCheckRecord::new(is_healthy));
+                is_healthy
+            }
+            None => {
+                service
+                    .is_healthy(&ctx.container_settings().health_path)
+                    .await
+            }
+        };
+
+        if is_healthy {
+            let idle_minutes =
+                container.idle_minutes(ctx.container_settings().default_idle_minutes);
 
             // Idle minutes of `0` means it is disabled and the project will always stay up
             if idle_minutes < 1 {
@@ -1059,6 +1532,7 @@ where
                     container,
                     service,
                     stats,
+                    idle_checks: 0,
                 }))
             } else {
                 let new_stat = ctx
@@ -1108,12 +1582,38 @@ where
                     // discord will have more traffic in anyway. So using the 100_000_000 threshold of an
                     // active framework for now
                     if cpu_per_minute < 100_000_000 {
-                        Ok(Self::Next::Idle(ProjectStopping { container }))
+                        let grace_checks = ctx.container_settings().idle_grace_checks;
+
+                        match IdleAction::after(idle_checks, grace_checks) {
+                            IdleAction::Stop => {
+                                return Ok(Self::Next::Idle(ProjectStopping { container }));
+                            }
+                            IdleAction::Warn => {
+                                info!(
+                                    project_name = %service.name,
+                                    grace_checks,
+                                    "project is idle and will be stopped unless it sees traffic"
+                                );
+
+                                if let Some(url) = &ctx.container_settings().webhook_url {
+                                    webhook::warn_idle(url.clone(), &service.name, grace_checks);
+                                }
+                            }
+                            IdleAction::Wait => {}
+                        }
+
+                        Ok(Self::Next::Ready(ProjectReady {
+                            container,
+                            service,
+                            stats,
+                            idle_checks: idle_checks + 1,
+                        }))
                     } else {
                         Ok(Self::Next::Ready(ProjectReady {
                             container,
                             service,
                             stats,
+                            idle_checks: 0,
                         }))
                     }
                 } else {
@@ -1121,6 +1621,7 @@ where
                         container,
                         service,
                         stats,
+                        idle_checks: 0,
                     }))
                 }
             }
@@ -1141,6 +1642,7 @@ where
                 container,
                 service: Some(service),
                 stats,
+                idle_checks,
             }))
         }
     }
@@ -1153,6 +1655,9 @@ pub struct ProjectReady {
     // Use default for backward compatibility. Can be removed when all projects in the DB have this property set
     #[serde(default)]
     stats: VecDeque<Stats>,
+    /// Health checks in a row the project was found idle, while it is given a grace
+    #[serde(default)]
+    idle_checks: u32,
 }
 
 #[async_trait]
@@ -1178,8 +1683,17 @@ impl ProjectReady {
         &self.service.target
     }
 
-    pub async fn is_healthy(&mut self) -> bool {
-        self.service.is_healthy().await
+    pub async fn is_healthy(&mut self, health_path: &str) -> bool {
+        self.service.is_healthy(health_path).await
+    }
+
+    /// Id of the deployment currently running in this project, if any
+    pub async fn active_deployment_id(
+        &self,
+        jwt: &str,
+        admin_secret: &str,
+    ) -> Result<Option<Uuid>, Box<dyn std::error::Error>> {
+        self.service.get_running_deploy(jwt, admin_secret).await
     }
 
     pub async fn start_last_deploy(&mut self, jwt: String, admin_secret: String) {
@@ -1187,6 +1701,22 @@ impl ProjectReady {
             error!(error, "failed to start last running deploy");
         };
     }
+
+    /// Record the attempts it took to bring this project back up in the logs of its running deployment
+    pub async fn report_restart_attempts(
+        &self,
+        jwt: String,
+        admin_secret: String,
+        attempts: Vec<RestartAttempt>,
+    ) {
+        if let Err(error) = self
+            .service
+            .report_restart_attempts(&jwt, &admin_secret, &attempts)
+            .await
+        {
+            error!(error, "failed to report restart attempts");
+        };
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -1207,6 +1737,10 @@ impl HealthCheckRecord {
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Service {
     name: ProjectName,
+    /// Address of the container serving the project. It is the only one: the deployer in it keeps
+    /// the builds, the address of the running deployment and the logs in the sqlite database on
+    /// the volume of the project, so replicas of it on the same volume would overwrite each
+    /// other's state instead of sharing the load.
     target: IpAddr,
     last_check: Option<HealthCheckRecord>,
 }
@@ -1214,11 +1748,15 @@ pub struct Service {
 impl Service {
     pub fn from_container(container: ContainerInspectResponse) -> Result<Self, ProjectError> {
         let resource_name = container.project_name()?;
+        let networks = safe_unwrap!(container.network_settings.networks);
 
-        let network = safe_unwrap!(container.network_settings.networks)
-            .values()
-            .next()
-            .ok_or_else(|| ProjectError::internal("project was not linked to a network"))?;
+        // An isolated project is only reachable through its own network
+        let network = if container.isolated_network() {
+            networks.get(&container.isolated_network_name()?)
+        } else {
+            networks.values().next()
+        }
+        .ok_or_else(|| ProjectError::internal("project was not linked to a network"))?;
 
         let target = safe_unwrap!(network.ip_address)
             .parse()
@@ -1237,8 +1775,16 @@ impl Service {
             .map_err(|err| err.into())
     }
 
-    pub async fn is_healthy(&mut self) -> bool {
-        let uri = self.uri(format!("/projects/{}/status", self.name)).unwrap();
+    /// Check the readiness of the runtime at `health_path`, in which `{name}` is replaced by the
+    /// project name
+    pub async fn is_healthy(&mut self, health_path: &str) -> bool {
+        let uri = match self.uri(health_path.replace("{name}", self.name.as_str())) {
+            Ok(uri) => uri,
+            Err(error) => {
+                error!(%error, health_path, "invalid health path");
+                return false;
+            }
+        };
         let resp = timeout(IS_HEALTHY_TIMEOUT, CLIENT.get(uri)).await;
         let is_healthy = matches!(resp, Ok(Ok(res)) if res.status().is_success());
         self.last_check = Some(HealthCheckRecord::new(is_healthy));
@@ -1277,6 +1823,36 @@ impl Service {
         Ok(())
     }
 
+    /// Send the `attempts` to the last running deployment, if there is one
+    pub async fn report_restart_attempts(
+        &self,
+        jwt: &str,
+        admin_secret: &str,
+        attempts: &[RestartAttempt],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(running_id) = self.get_running_deploy(jwt, admin_secret).await? else {
+            return Ok(());
+        };
+
+        let uri = self.uri(format!(
+            "/projects/{}/deployments/{}/restart-attempts",
+            self.name, running_id
+        ))?;
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(AUTHORIZATION, format!("Bearer {}", jwt))
+            .header(X_SHUTTLE_ACCOUNT_NAME.clone(), "gateway")
+            .header(X_SHUTTLE_ADMIN_SECRET.clone(), admin_secret)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(attempts)?))?;
+
+        timeout(IS_HEALTHY_TIMEOUT, CLIENT.request(req)).await??;
+
+        Ok(())
+    }
+
     /// Get the last running deployment
     async fn get_running_deploy(
         &self,
@@ -1358,10 +1934,7 @@ where
 
         // If stopped, and has not restarted too much, try to restart
         if start_event_count < MAX_REBOOTS {
-            Ok(ProjectStarting {
-                container,
-                restart_count: 0,
-            })
+            Ok(ProjectStarting::new(container, 0))
         } else {
             Err(ProjectError::internal(
                 "too many restarts in the last 15 minutes",
@@ -1388,19 +1961,32 @@ where
     async fn next(self, ctx: &Ctx) -> Result<Self::Next, Self::Error> {
         let Self { container } = self;
 
-        // Stopping a docker containers sends a SIGTERM which will stop the tokio runtime that deployer starts up.
-        // Killing this runtime causes the deployment to enter the `completed` state and it therefore does not
-        // start up again when starting up the project's container. Luckily the kill command allows us to change the
-        // signal to prevent this from happening.
-        //
-        // In some future state when all deployers hadle `SIGTERM` correctly, this can be changed to docker stop
-        // safely.
-        ctx.docker()
-            .kill_container(
-                safe_unwrap!(container.id),
-                Some(KillContainerOptions { signal: "SIGKILL" }),
-            )
-            .await?;
+        if container.graceful_stop() {
+            // Projects which opted in handle `SIGTERM` themselves. Docker stop will send it and only fall back to
+            // `SIGKILL` if the project has not exited after the timeout.
+            ctx.docker()
+                .stop_container(
+                    safe_unwrap!(container.id),
+                    Some(StopContainerOptions {
+                        t: GRACEFUL_STOP_TIMEOUT_SECS,
+                    }),
+                )
+                .await?;
+        } else {
+            // Stopping a docker containers sends a SIGTERM which will stop the tokio runtime that deployer starts up.
+            // Killing this runtime causes the deployment to enter the `completed` state and it therefore does not
+            // start up again when starting up the project's container. Luckily the kill command allows us to change the
+            // signal to prevent this from happening.
+            //
+            // In some future state when all deployers hadle `SIGTERM` correctly, this can be changed to docker stop
+            // safely.
+            ctx.docker()
+                .kill_container(
+                    safe_unwrap!(container.id),
+                    Some(KillContainerOptions { signal: "SIGKILL" }),
+                )
+                .await?;
+        }
         Ok(Self::Next {
             container: container.refresh(ctx).await?,
         })
@@ -1447,6 +2033,9 @@ where
             .stop_container(container_id, Some(StopContainerOptions { t: 1 }))
             .await
             .unwrap_or(());
+        // The volume is kept on purpose: it holds the deployer state (secrets, resources and
+        // deployments) which a project recreated within the retention window mounts again. The
+        // gateway removes it once the window ends
         ctx.docker()
             .remove_container(
                 container_id,
@@ -1457,6 +2046,26 @@ where
             )
             .await
             .unwrap_or(());
+        if container.isolated_network() {
+            let isolated_network_name = container.isolated_network_name()?;
+            // The network cannot be removed while the gateway is still connected to it
+            if let Some(gateway_container) = &ctx.container_settings().gateway_container {
+                ctx.docker()
+                    .disconnect_network(
+                        &isolated_network_name,
+                        DisconnectNetworkOptions {
+                            container: gateway_container.as_str(),
+                            force: true,
+                        },
+                    )
+                    .await
+                    .unwrap_or(());
+            }
+            ctx.docker()
+                .remove_network(&isolated_network_name)
+                .await
+                .unwrap_or(());
+        }
         Ok(Self::Next {
             destroyed: Some(container),
         })
@@ -1468,8 +2077,15 @@ pub struct ProjectDestroyed {
     destroyed: Option<ContainerInspectResponse>,
 }
 
-#[async_trait]
-impl<Ctx> State<Ctx> for ProjectDestroyed
+impl ProjectDestroyed {
+    /// The container the project was running in before it got destroyed
+    pub fn container(&self) -> Option<&ContainerInspectResponse> {
+        self.destroyed.as_ref()
+    }
+}
+
+#[async_trait]
+impl<Ctx> State<Ctx> for ProjectDestroyed
 where
     Ctx: DockerContext,
 {
@@ -1595,10 +2211,9 @@ pub mod exec {
         gateway: Arc<GatewayService>,
         sender: Sender<BoxedTask>,
     ) -> Result<(), ProjectError> {
-        for (project_name, _) in gateway
-            .iter_projects()
-            .await
-            .expect("could not list projects")
+        let mut projects = gateway.iter_projects();
+        while let Some((project_name, _)) =
+            projects.try_next().await.expect("could not list projects")
         {
             match gateway.find_project(&project_name).await.unwrap() {
                 Project::Errored(ProjectError { ctx: Some(ctx), .. }) => {
@@ -1642,10 +2257,12 @@ pub mod exec {
                                         .new_task()
                                         .project(project_name)
                                         .and_then(task::run(|ctx| async move {
-                                            TaskResult::Done(Project::Starting(ProjectStarting {
-                                                container: ctx.state.container().unwrap(),
-                                                restart_count: 0,
-                                            }))
+                                            TaskResult::Done(Project::Starting(
+                                                ProjectStarting::new(
+                                                    ctx.state.container().unwrap(),
+                                                    0,
+                                                ),
+                                            ))
                                         }))
                                         .send(&sender)
                                         .await;
@@ -1688,10 +2305,9 @@ pub mod exec {
         gateway: Arc<GatewayService>,
         sender: Sender<BoxedTask>,
     ) -> Result<(), ProjectError> {
-        for (project_name, _) in gateway
-            .iter_projects()
-            .await
-            .expect("could not list projects")
+        let mut projects = gateway.iter_projects();
+        while let Some((project_name, _)) =
+            projects.try_next().await.expect("could not list projects")
         {
             let _ = gateway
                 .new_task()
@@ -1708,15 +2324,267 @@ pub mod exec {
 #[cfg(test)]
 pub mod tests {
 
+    use bollard::container::{DownloadFromContainerOptions, UploadToContainerOptions};
     use bollard::models::ContainerState;
     use bollard::service::NetworkSettings;
+    use bollard::system::EventsOptions;
     use futures::prelude::*;
     use hyper::{Body, Request, StatusCode};
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+    use sqlx::{query, query_as, Executor};
 
     use super::*;
-    use crate::tests::{assert_matches, assert_stream_matches, World};
+    use crate::tests::{assert_matches, assert_stream_matches, World, WorldContext};
     use crate::EndStateExt;
 
+    /// A sample of the resources used by a container, as docker reports it
+    pub fn container_stats(read: &str, cpu_usage: u64, memory_usage: u64) -> Stats {
+        let cpu_stats = serde_json::json!({
+            "cpu_usage": {
+                "total_usage": cpu_usage,
+                "usage_in_usermode": cpu_usage,
+                "usage_in_kernelmode": 0,
+            },
+            "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 },
+        });
+
+        deserialize_json!({
+            "read": read,
+            "preread": read,
+            "num_procs": 0,
+            "pids_stats": {},
+            "memory_stats": { "usage": memory_usage, "limit": 1 << 30 },
+            "blkio_stats": {},
+            "cpu_stats": cpu_stats,
+            "precpu_stats": cpu_stats,
+            "storage_stats": {},
+            "name": "matrix",
+            "id": "matrix-container",
+        })
+    }
+
+    /// The `matrix` project, ready with the samples its health checks took
+    pub fn ready_project(stats: Vec<Stats>) -> Project {
+        let container: ContainerInspectResponse = deserialize_json!({
+            "Id": "matrix-container",
+            "Config": {
+                "Labels": {
+                    "shuttle.project": "matrix",
+                },
+            },
+        });
+
+        Project::Ready(ProjectReady {
+            container,
+            service: Service {
+                name: "matrix".parse().unwrap(),
+                target: "10.0.0.2".parse().unwrap(),
+                last_check: None,
+            },
+            stats: stats.into(),
+            idle_checks: 0,
+        })
+    }
+
+    #[test]
+    fn admin_secret_is_available_when_ready() {
+        let container: ContainerInspectResponse = deserialize_json!({
+            "Id": "matrix-container",
+            "Args": ["--admin-secret", "neo-secret", "--project", "matrix"],
+            "Config": {
+                "Labels": {
+                    "shuttle.project": "matrix",
+                },
+            },
+        });
+
+        let project = Project::Ready(ProjectReady {
+            container,
+            service: Service {
+                name: "matrix".parse().unwrap(),
+                target: "10.0.0.2".parse().unwrap(),
+                last_check: None,
+            },
+            stats: VecDeque::new(),
+            idle_checks: 0,
+        });
+
+        assert_eq!(project.admin_secret(), Some("neo-secret".to_string()));
+        assert_eq!(project.initial_key(), None);
+    }
+
+    #[test]
+    fn stats_of_running_project() {
+        let project = ready_project(vec![
+            container_stats("2023-06-01T12:00:00Z", 100, 1024),
+            container_stats("not a date", 150, 1536),
+            container_stats("2023-06-01T12:01:00+02:00", 200, 2048),
+        ]);
+
+        // Samples without a valid time are skipped
+        assert_eq!(
+            project.stats(),
+            vec![
+                project::StatsSample {
+                    timestamp: "2023-06-01T12:00:00Z".parse().unwrap(),
+                    cpu_usage: 100,
+                    memory_usage: Some(1024),
+                    memory_limit: Some(1 << 30),
+                },
+                project::StatsSample {
+                    timestamp: "2023-06-01T10:01:00Z".parse().unwrap(),
+                    cpu_usage: 200,
+                    memory_usage: Some(2048),
+                    memory_limit: Some(1 << 30),
+                },
+            ]
+        );
+
+        let container = project.container().unwrap();
+        assert!(Project::Stopped(ProjectStopped { container })
+            .stats()
+            .is_empty());
+    }
+
+    #[test]
+    fn idle_project_is_warned_once_before_it_is_stopped() {
+        let actions: Vec<_> = (0..4).map(|checks| IdleAction::after(checks, 3)).collect();
+
+        assert_eq!(
+            actions,
+            vec![
+                IdleAction::Warn,
+                IdleAction::Wait,
+                IdleAction::Wait,
+                IdleAction::Stop
+            ]
+        );
+
+        // Without a grace the project is stopped straight away, unwarned
+        assert_eq!(IdleAction::after(0, 0), IdleAction::Stop);
+    }
+
+    #[test]
+    fn docker_health() {
+        let with_health = |status: Option<&str>| -> ContainerInspectResponse {
+            match status {
+                Some(status) => deserialize_json!({
+                    "Id": "matrix-container",
+                    "State": { "Health": { "Status": status } },
+                }),
+                None => deserialize_json!({
+                    "Id": "matrix-container",
+                    "State": { "Status": "running" },
+                }),
+            }
+        };
+
+        assert_eq!(with_health(Some("healthy")).docker_health(), Some(true));
+        assert_eq!(with_health(Some("starting")).docker_health(), Some(false));
+        assert_eq!(with_health(Some("unhealthy")).docker_health(), Some(false));
+        assert_eq!(with_health(Some("none")).docker_health(), None);
+        assert_eq!(with_health(None).docker_health(), None);
+    }
+
+    #[test]
+    fn isolated_service_targets_its_own_network() {
+        let with_isolation = |isolated_network: bool| -> ContainerInspectResponse {
+            deserialize_json!({
+                "Id": "matrix-container",
+                "Config": {
+                    "Labels": {
+                        "shuttle.prefix": "shuttle_",
+                        "shuttle.project": "matrix",
+                        "shuttle.isolated_network": format!("{isolated_network}"),
+                    },
+                },
+                "NetworkSettings": {
+                    "Networks": {
+                        "shuttle_default": { "IPAddress": "10.0.0.2" },
+                        "shuttle_matrix_net": { "IPAddress": "10.1.0.2" },
+                    },
+                },
+            })
+        };
+
+        let service = Service::from_container(with_isolation(true)).unwrap();
+        assert_eq!(service.target, "10.1.0.2".parse::<IpAddr>().unwrap());
+
+        let service = Service::from_container(with_isolation(false)).unwrap();
+        assert_eq!(service.target, "10.0.0.2".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn recreated_project_keeps_the_settings_of_its_container() {
+        let container: ContainerInspectResponse = deserialize_json!({
+            "Id": "matrix-container",
+            "Args": ["--admin-secret", "neo-secret", "--proxy-fqdn", "matrix.example.com"],
+            "Config": {
+                "Labels": {
+                    "shuttle.project": "matrix",
+                    "shuttle.graceful_stop": "true",
+                    "shuttle.isolated_network": "true",
+                    "shuttle.egress_policy": "restricted",
+                },
+            },
+            "Mounts": [{
+                "Name": "shuttle_neo_vol",
+                "Destination": "/opt/shuttle",
+            }],
+        });
+
+        let creating = ProjectCreating::new_with_random_initial_key("matrix".parse().unwrap(), 30)
+            .with_settings_of(&container);
+
+        assert!(creating.graceful_stop);
+        assert!(creating.isolated_network);
+        assert_eq!(creating.egress_policy, EgressPolicy::Restricted);
+        assert_eq!(creating.fqdn, Some("matrix.example.com".to_string()));
+        assert_eq!(creating.volume_name, Some("shuttle_neo_vol".to_string()));
+
+        // Settings given afterwards take precedence
+        let creating = creating.with_egress_policy(EgressPolicy::Open);
+        assert_eq!(creating.egress_policy, EgressPolicy::Open);
+    }
+
+    #[test]
+    fn kind_is_serialized_name() {
+        let container: ContainerInspectResponse = deserialize_json!({
+            "Id": "matrix-container",
+            "Config": {
+                "Labels": {
+                    "shuttle.project": "matrix",
+                },
+            },
+        });
+
+        let projects = [
+            Project::Creating(ProjectCreating::new_with_random_initial_key(
+                "matrix".parse().unwrap(),
+                0,
+            )),
+            Project::Ready(ProjectReady {
+                container: container.clone(),
+                service: Service {
+                    name: "matrix".parse().unwrap(),
+                    target: "10.0.0.2".parse().unwrap(),
+                    last_check: None,
+                },
+                stats: VecDeque::new(),
+                idle_checks: 0,
+            }),
+            Project::Stopped(ProjectStopped { container }),
+            Project::Errored(ProjectError::internal("out of band")),
+        ];
+
+        for project in projects {
+            let serialized = serde_json::to_value(&project).unwrap();
+            let name = serialized.as_object().unwrap().keys().next().unwrap();
+
+            assert_eq!(name, project.kind());
+        }
+    }
+
     #[tokio::test]
     async fn create_start_stop_destroy_project() -> anyhow::Result<()> {
         let world = World::new().await;
@@ -1733,6 +2601,10 @@ pub mod tests {
                 from: None,
                 recreate_count: 0,
                 idle_minutes: 0,
+                graceful_stop: false,
+                isolated_network: false,
+                egress_policy: EgressPolicy::Open,
+                volume_name: None,
             }),
             #[assertion = "Container created, attach network"]
             Ok(Project::Attaching(ProjectAttaching {
@@ -1763,7 +2635,8 @@ pub mod tests {
                     }),
                     ..
                 },
-                restart_count: 0
+                restart_count: 0,
+                ..
             })) if networks.keys().collect::<Vec<_>>() == vec![&ctx.container_settings.network_name],
             #[assertion = "Container started, in a running state"]
             Ok(Project::Started(ProjectStarted {
@@ -1846,4 +2719,571 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn restricted_egress_project_joins_restricted_network() -> anyhow::Result<()> {
+        let world = World::new().await;
+
+        let ctx = world.context();
+
+        let project_starting = assert_matches!(
+            ctx,
+            Project::Creating(
+                ProjectCreating::new("my-restricted-test".parse().unwrap(), "test".to_string(), 0)
+                    .with_egress_policy(EgressPolicy::Restricted)
+            ),
+            #[assertion = "Container created, attach network"]
+            Ok(Project::Attaching(_)),
+            #[assertion = "Container attached to the restricted network only"]
+            Ok(Project::Starting(ProjectStarting {
+                container: ContainerInspectResponse {
+                    network_settings: Some(NetworkSettings {
+                        networks: Some(networks),
+                        ..
+                    }),
+                    ..
+                },
+                ..
+            })) if networks.keys().collect::<Vec<_>>() == vec![&ctx.container_settings.restricted_network_name],
+        );
+
+        // The gateway joined the network to be able to route to the project
+        let gateway_container = ctx.container_settings.gateway_container.as_ref().unwrap();
+        let gateway = ctx
+            .docker
+            .inspect_container(gateway_container, None)
+            .await?;
+        assert!(gateway
+            .network_settings
+            .and_then(|settings| settings.networks)
+            .unwrap_or_default()
+            .contains_key(&ctx.container_settings.restricted_network_name));
+
+        let delay = sleep(Duration::from_secs(10));
+        futures::pin_mut!(delay);
+        let mut project_readying = project_starting
+            .unwrap()
+            .into_stream(&ctx)
+            .take_until(delay)
+            .try_skip_while(|state| future::ready(Ok(!matches!(state, Project::Ready(_)))));
+
+        let project_ready = assert_stream_matches!(
+            project_readying,
+            #[assertion = "Container is ready on the restricted network"]
+            Ok(Project::Ready(_)),
+        );
+
+        assert_matches!(
+            ctx,
+            project_ready.unwrap().destroy().unwrap(),
+            #[assertion = "Container is destroyed"]
+            Ok(Project::Destroyed(ProjectDestroyed { destroyed: _ })),
+        )
+        .unwrap();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn isolated_and_restricted_project_joins_its_own_internal_network() -> anyhow::Result<()>
+    {
+        let world = World::new().await;
+
+        let ctx = world.context();
+        let isolated_network_name = format!(
+            "{}my-isolated-restricted-test_net",
+            ctx.container_settings.prefix
+        );
+
+        let project_starting = assert_matches!(
+            ctx,
+            Project::Creating(
+                ProjectCreating::new(
+                    "my-isolated-restricted-test".parse().unwrap(),
+                    "test".to_string(),
+                    0
+                )
+                .with_isolated_network(true)
+                .with_egress_policy(EgressPolicy::Restricted)
+            ),
+            #[assertion = "Container created, attach network"]
+            Ok(Project::Attaching(_)),
+            #[assertion = "Container attached to its own network only"]
+            Ok(Project::Starting(ProjectStarting {
+                container: ContainerInspectResponse {
+                    network_settings: Some(NetworkSettings {
+                        networks: Some(networks),
+                        ..
+                    }),
+                    ..
+                },
+                ..
+            })) if networks.keys().collect::<Vec<_>>() == vec![&isolated_network_name],
+        );
+
+        // The restriction wins, so its own network has no outbound access either
+        let network = ctx
+            .docker
+            .inspect_network::<String>(&isolated_network_name, None)
+            .await?;
+        assert_eq!(network.internal, Some(true));
+
+        assert_matches!(
+            ctx,
+            project_starting.unwrap().destroy().unwrap(),
+            #[assertion = "Container is destroyed"]
+            Ok(Project::Destroyed(ProjectDestroyed { destroyed: _ })),
+        )
+        .unwrap();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn isolated_project_joins_its_own_network() -> anyhow::Result<()> {
+        let world = World::new().await;
+
+        let ctx = world.context();
+        let isolated_network_name =
+            format!("{}my-isolated-test_net", ctx.container_settings.prefix);
+
+        let project_starting = assert_matches!(
+            ctx,
+            Project::Creating(
+                ProjectCreating::new("my-isolated-test".parse().unwrap(), "test".to_string(), 0)
+                    .with_isolated_network(true)
+            ),
+            #[assertion = "Container created, attach network"]
+            Ok(Project::Attaching(_)),
+            #[assertion = "Container attached to its own network only"]
+            Ok(Project::Starting(ProjectStarting {
+                container: ContainerInspectResponse {
+                    network_settings: Some(NetworkSettings {
+                        networks: Some(networks),
+                        ..
+                    }),
+                    ..
+                },
+                ..
+            })) if networks.keys().collect::<Vec<_>>() == vec![&isolated_network_name],
+        );
+
+        // The gateway joined the network to be able to route to the project
+        let gateway_container = ctx.container_settings.gateway_container.as_ref().unwrap();
+        let gateway = ctx
+            .docker
+            .inspect_container(gateway_container, None)
+            .await?;
+        assert!(gateway
+            .network_settings
+            .and_then(|settings| settings.networks)
+            .unwrap_or_default()
+            .contains_key(&isolated_network_name));
+
+        assert_matches!(
+            ctx,
+            project_starting.unwrap().destroy().unwrap(),
+            #[assertion = "Container is destroyed"]
+            Ok(Project::Destroyed(ProjectDestroyed { destroyed: _ })),
+        )
+        .unwrap();
+
+        // The network went away with the project
+        assert!(ctx
+            .docker
+            .inspect_network::<String>(&isolated_network_name, None)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn container_stuck_in_created_is_recreated() -> anyhow::Result<()> {
+        let world = World::new().await;
+
+        let ctx = world.context();
+
+        let project_starting = assert_matches!(
+            ctx,
+            Project::Creating(ProjectCreating::new(
+                "my-stuck-test".parse().unwrap(),
+                "test".to_string(),
+                0
+            )),
+            #[assertion = "Container created, attach network"]
+            Ok(Project::Attaching(_)),
+            #[assertion = "Container attached, not started yet"]
+            Ok(Project::Starting(_)),
+        );
+
+        let mut starting = match project_starting.unwrap() {
+            Project::Starting(starting) => starting,
+            otherwise => panic!("expected a starting project, got {otherwise:?}"),
+        };
+
+        // Within the timeout the container is given a chance to start
+        let project = Project::Starting(starting.clone()).refresh(&ctx).await?;
+        assert!(matches!(project, Project::Starting(_)), "{project:?}");
+
+        // Pretend the container never left `CREATED` past the timeout
+        starting.starting_since -=
+            chrono::Duration::seconds(ctx.container_settings.created_timeout_secs as i64 + 1);
+        let project = Project::Starting(starting).refresh(&ctx).await?;
+        assert!(
+            matches!(
+                project,
+                Project::Recreating(ProjectRecreating {
+                    container: ContainerInspectResponse {
+                        state: Some(ContainerState {
+                            status: Some(ContainerStateStatusEnum::CREATED),
+                            ..
+                        }),
+                        ..
+                    },
+                    recreate_count: 0,
+                })
+            ),
+            "{project:?}"
+        );
+
+        assert_matches!(
+            ctx,
+            project.destroy().unwrap(),
+            #[assertion = "Container is destroyed"]
+            Ok(Project::Destroyed(ProjectDestroyed { destroyed: _ })),
+        )
+        .unwrap();
+
+        Ok(())
+    }
+
+    /// Drive a project until its container is ready
+    async fn until_ready(ctx: &WorldContext, project: Project) -> Project {
+        let delay = sleep(Duration::from_secs(10));
+        futures::pin_mut!(delay);
+        let project_readying = project
+            .into_stream(ctx)
+            .take_until(delay)
+            .try_skip_while(|state| future::ready(Ok(!matches!(state, Project::Ready(_)))));
+        futures::pin_mut!(project_readying);
+
+        project_readying
+            .try_next()
+            .await
+            .unwrap()
+            .expect("project to be ready in time")
+    }
+
+    /// Copy the state of a deployer into the volume of its project, through its container
+    async fn upload_deployer_state(
+        ctx: &WorldContext,
+        container_id: &str,
+        state: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        let mut archive = tar::Builder::new(Vec::new());
+        archive.append_path_with_name(state, "deployer.sqlite")?;
+
+        ctx.docker()
+            .upload_to_container(
+                container_id,
+                Some(UploadToContainerOptions {
+                    path: "/opt/shuttle",
+                    ..Default::default()
+                }),
+                archive.into_inner()?.into(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Copy the state of a deployer out of the volume of its project, through its container
+    async fn download_deployer_state(
+        ctx: &WorldContext,
+        container_id: &str,
+        dir: &std::path::Path,
+    ) -> anyhow::Result<std::path::PathBuf> {
+        let archive = ctx
+            .docker()
+            .download_from_container(
+                container_id,
+                Some(DownloadFromContainerOptions {
+                    path: "/opt/shuttle",
+                }),
+            )
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await?;
+        // The write ahead log of the deployer comes along, so the copy has all it committed
+        tar::Archive::new(archive.as_slice()).unpack(dir)?;
+
+        Ok(dir.join("shuttle").join("deployer.sqlite"))
+    }
+
+    #[tokio::test]
+    async fn destroy_and_recreate_keeps_the_deployer_state() -> anyhow::Result<()> {
+        let world = World::new().await;
+
+        let ctx = world.context();
+        let project_name: ProjectName = "my-recreated-test".parse().unwrap();
+        let dir = tempfile::tempdir()?;
+
+        let project_attaching = assert_matches!(
+            ctx,
+            Project::Creating(ProjectCreating::new(
+                project_name.clone(),
+                "test".to_string(),
+                0
+            )),
+            #[assertion = "Container created, attach network"]
+            Ok(Project::Attaching(_)),
+        )
+        .unwrap();
+
+        // The deployer first starts with a secret and a database set for the service of the project
+        let seed_path = dir.path().join("deployer.sqlite");
+        let seed = SqlitePool::connect_with(
+            SqliteConnectOptions::new()
+                .filename(&seed_path)
+                .create_if_missing(true),
+        )
+        .await?;
+        seed.execute(include_str!("../../deployer/migrations/0000_init.sql"))
+            .await?;
+        let service_id = Uuid::new_v4();
+        query("INSERT INTO services (id, name) VALUES (?1, ?2)")
+            .bind(service_id.as_bytes().as_slice())
+            .bind(project_name.as_str())
+            .execute(&seed)
+            .await?;
+        query("INSERT INTO secrets (service_id, key, value, last_update) VALUES (?1, ?2, ?3, ?4)")
+            .bind(service_id.as_bytes().as_slice())
+            .bind("MORPHEUS")
+            .bind("red pill")
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(&seed)
+            .await?;
+        query("INSERT INTO resources (service_id, type, data) VALUES (?1, ?2, ?3)")
+            .bind(service_id.as_bytes().as_slice())
+            .bind("database::shared::postgres")
+            .bind(
+                serde_json::json!({
+                    "role_name": "user-my-recreated-test",
+                    "role_password": "password",
+                    "database_name": "db-my-recreated-test",
+                    "port": "5432",
+                    "address_private": "postgres",
+                    "address_public": "localhost",
+                })
+                .to_string(),
+            )
+            .execute(&seed)
+            .await?;
+        seed.close().await;
+
+        let container_id = project_attaching
+            .container()
+            .and_then(|container| container.id)
+            .unwrap();
+        upload_deployer_state(&ctx, &container_id, &seed_path).await?;
+
+        let project_ready = until_ready(&ctx, project_attaching).await;
+
+        let project_stopped = assert_matches!(
+            ctx,
+            project_ready.stop().unwrap(),
+            #[assertion = "Container is stopped"]
+            Ok(Project::Stopped(_)),
+        )
+        .unwrap();
+
+        assert_matches!(
+            ctx,
+            project_stopped.destroy().unwrap(),
+            #[assertion = "Container is destroyed"]
+            Ok(Project::Destroyed(ProjectDestroyed { destroyed: _ })),
+        )
+        .unwrap();
+
+        let volume_name = format!("{}my-recreated-test_vol", ctx.container_settings.prefix);
+        ctx.docker().inspect_volume(&volume_name).await?;
+
+        // Recreated the way `GatewayService::create_project` does it, under the same name
+        let project_attaching = assert_matches!(
+            ctx,
+            Project::Creating(ProjectCreating::new(
+                project_name.clone(),
+                "test".to_string(),
+                0
+            )),
+            #[assertion = "Container recreated, attach network"]
+            Ok(Project::Attaching(_)),
+        )
+        .unwrap();
+
+        let project_ready = until_ready(&ctx, project_attaching).await;
+        let container_id = project_ready
+            .container()
+            .and_then(|container| container.id)
+            .unwrap();
+
+        let project_stopped = assert_matches!(
+            ctx,
+            project_ready.stop().unwrap(),
+            #[assertion = "Recreated container is stopped"]
+            Ok(Project::Stopped(_)),
+        )
+        .unwrap();
+
+        // The recreated deployer started on the state it left and serves the same records, which
+        // are read the way its secrets and resources handlers do
+        let state_path = download_deployer_state(&ctx, &container_id, dir.path()).await?;
+        let state =
+            SqlitePool::connect_with(SqliteConnectOptions::new().filename(state_path)).await?;
+        let secrets: Vec<(String, String)> = query_as(
+            "SELECT key, value FROM secrets JOIN services ON services.id = secrets.service_id WHERE services.name = ?1",
+        )
+        .bind(project_name.as_str())
+        .fetch_all(&state)
+        .await?;
+        assert_eq!(
+            secrets,
+            vec![("MORPHEUS".to_string(), "red pill".to_string())]
+        );
+
+        let resources: Vec<(String,)> = query_as(
+            "SELECT type FROM resources JOIN services ON services.id = resources.service_id WHERE services.name = ?1",
+        )
+        .bind(project_name.as_str())
+        .fetch_all(&state)
+        .await?;
+        assert_eq!(resources, vec![("database::shared::postgres".to_string(),)]);
+        state.close().await;
+
+        assert_matches!(
+            ctx,
+            project_stopped.destroy().unwrap(),
+            #[assertion = "Recreated container is destroyed"]
+            Ok(Project::Destroyed(_)),
+        )
+        .unwrap();
+
+        ctx.docker().remove_volume(&volume_name, None).await?;
+
+        Ok(())
+    }
+
+    /// The `kill` signals docker sent to a container between `since` and now, with the second each
+    /// was sent at, and whether the container was stopped rather than only killed
+    async fn stop_signals(
+        ctx: &WorldContext,
+        container_id: &str,
+        since: i64,
+    ) -> anyhow::Result<(Vec<(String, i64)>, bool)> {
+        let events: Vec<_> = ctx
+            .docker()
+            .events(Some(EventsOptions {
+                since: Some(since.to_string()),
+                // Docker only stops streaming at `until`, which is rounded down to the second
+                until: Some((chrono::Utc::now().timestamp() + 1).to_string()),
+                filters: HashMap::from([("container".to_string(), vec![container_id.to_string()])]),
+            }))
+            .try_collect()
+            .await?;
+
+        let signals = events
+            .iter()
+            .filter(|event| event.action.as_deref() == Some("kill"))
+            .filter_map(|event| {
+                let signal = event.actor.as_ref()?.attributes.as_ref()?.get("signal")?;
+                Some((signal.clone(), event.time?))
+            })
+            .collect();
+        let stopped = events
+            .iter()
+            .any(|event| event.action.as_deref() == Some("stop"));
+
+        Ok((signals, stopped))
+    }
+
+    #[tokio::test]
+    async fn graceful_stop_terminates_before_killing() -> anyhow::Result<()> {
+        let world = World::new().await;
+
+        let ctx = world.context();
+
+        for graceful_stop in [true, false] {
+            let project_name: ProjectName =
+                format!("my-graceful-{graceful_stop}-test").parse().unwrap();
+
+            let project_ready = until_ready(
+                &ctx,
+                Project::Creating(
+                    ProjectCreating::new(project_name, "test".to_string(), 0)
+                        .with_graceful_stop(graceful_stop),
+                ),
+            )
+            .await;
+            let container_id = project_ready
+                .container()
+                .and_then(|container| container.id)
+                .unwrap();
+
+            let since = chrono::Utc::now().timestamp();
+            let project_stopped = assert_matches!(
+                ctx,
+                project_ready.stop().unwrap(),
+                #[assertion = "Container is stopped"]
+                Ok(Project::Stopped(ProjectStopped {
+                    container: ContainerInspectResponse {
+                        state: Some(ContainerState {
+                            status: Some(ContainerStateStatusEnum::EXITED),
+                            ..
+                        }),
+                        ..
+                    },
+                })),
+            )
+            .unwrap();
+
+            let (signals, stopped) = stop_signals(&ctx, &container_id, since).await?;
+            if graceful_stop {
+                // Docker stop sends `SIGTERM`, and only falls back to `SIGKILL` once the project
+                // had the whole timeout to exit
+                assert!(stopped, "the container should be stopped, not killed");
+                let (first_signal, terminated_at) = signals.first().expect("a signal to be sent");
+                assert_eq!(first_signal, "15", "got the signals {signals:?}");
+                if let Some((second_signal, killed_at)) = signals.get(1) {
+                    assert_eq!(second_signal, "9", "got the signals {signals:?}");
+                    assert!(killed_at - terminated_at >= GRACEFUL_STOP_TIMEOUT_SECS);
+                }
+            } else {
+                assert!(!stopped, "the container should be killed, not stopped");
+                assert_eq!(
+                    signals
+                        .iter()
+                        .map(|(signal, _)| signal.as_str())
+                        .collect::<Vec<_>>(),
+                    vec!["9"]
+                );
+            }
+
+            assert_matches!(
+                ctx,
+                project_stopped.destroy().unwrap(),
+                #[assertion = "Container is destroyed"]
+                Ok(Project::Destroyed(_)),
+            )
+            .unwrap();
+
+            let volume_name = format!(
+                "{}my-graceful-{graceful_stop}-test_vol",
+                ctx.container_settings.prefix
+            );
+            ctx.docker().remove_volume(&volume_name, None).await?;
+        }
+
+        Ok(())
+    }
 }
@@ -1,10 +1,11 @@
 use std::convert::Infallible;
 use std::future::Future;
 use std::io;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use axum::headers::{HeaderMapExt, Host};
 use axum::response::{IntoResponse, Response};
@@ -16,24 +17,39 @@ use futures::prelude::*;
 use hyper::body::{Body, HttpBody};
 use hyper::client::connect::dns::GaiResolver;
 use hyper::client::HttpConnector;
+use hyper::header::{HeaderMap, HeaderValue, FORWARDED, HOST};
 use hyper::server::conn::AddrStream;
+use hyper::service::service_fn;
 use hyper::{Client, Request};
 use hyper_reverse_proxy::ReverseProxy;
 use once_cell::sync::Lazy;
 use opentelemetry::global;
 use opentelemetry_http::HeaderInjector;
-use shuttle_common::backends::headers::XShuttleProject;
+use shuttle_common::backends::headers::{
+    XShuttleDeploymentId, XShuttleProject, XShuttleRequestTimeout, X_SHUTTLE_REQUEST_TIMEOUT,
+};
+use shuttle_common::models::error::ApiError;
 use tokio::sync::mpsc::Sender;
-use tower::{Service, ServiceBuilder};
+use tokio::time::timeout;
+use tower::{Layer, Service, ServiceBuilder};
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
 use tower_sanitize_path::SanitizePath;
-use tracing::{debug_span, error, field, trace};
+use tracing::{debug, debug_span, error, field, trace};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::acme::{AcmeClient, ChallengeResponderLayer, CustomDomain};
-use crate::service::GatewayService;
+use crate::metrics;
+use crate::project::ContainerInspectResponseExt;
+use crate::service::{retry_later_response, GatewayService, COLD_START_RETRY_AFTER};
 use crate::task::BoxedTask;
+use crate::upgrade;
 use crate::{Error, ErrorKind};
 
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
 static PROXY_CLIENT: Lazy<ReverseProxy<HttpConnector<GaiResolver>>> =
     Lazy::new(|| ReverseProxy::new(Client::new()));
 
@@ -75,6 +91,10 @@ pub struct UserProxy {
     task_sender: Sender<BoxedTask>,
     remote_addr: SocketAddr,
     public: FQDN,
+    /// Scheme clients reach this proxy with, passed on to projects in `X-Forwarded-Proto`
+    forwarded_proto: &'static str,
+    /// Responses of projects over this many bytes are compressed when the client accepts it
+    compression_min_size: Option<u16>,
 }
 
 impl<'r> AsResponderTo<&'r AddrStream> for UserProxy {
@@ -112,37 +132,96 @@ impl UserProxy {
             .map(|host| fqdn!(host.hostname()))
             .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))?;
 
-        let project_name =
+        let (project_name, is_custom_domain) =
             if fqdn.is_subdomain_of(&self.public) && fqdn.depth() - self.public.depth() == 1 {
-                fqdn.labels()
+                let project_name = fqdn
+                    .labels()
                     .next()
                     .unwrap()
                     .to_owned()
                     .parse()
-                    .map_err(|_| Error::from_kind(ErrorKind::ProjectNotFound))?
+                    .map_err(|_| Error::from_kind(ErrorKind::ProjectNotFound))?;
+                (project_name, false)
             } else if let Ok(CustomDomain { project_name, .. }) =
                 self.gateway.project_details_for_custom_domain(&fqdn).await
             {
-                project_name
+                (project_name, true)
             } else {
                 return Err(Error::from_kind(ErrorKind::ProjectNotFound));
             };
 
         req.headers_mut()
             .typed_insert(XShuttleProject(project_name.to_string()));
+        set_forwarding_headers(req.headers_mut(), self.forwarded_proto);
+
+        if let Err(retry_after) = self.gateway.circuit_breakers().check(project_name.as_str()) {
+            debug!(%project_name, "refusing request to failing project");
+            return Ok(retry_later_response(ErrorKind::ProjectFailing, retry_after).into_response());
+        }
 
-        let project = self
+        let rate_limit = self.gateway.project_rate_limit(&project_name).await?;
+        if let Err(retry_after) = self
             .gateway
-            .find_or_start_project(&project_name, task_sender)
-            .await?;
+            .rate_limiters()
+            .acquire(project_name.as_str(), &rate_limit)
+        {
+            debug!(%project_name, "refusing request over the rate limit of project");
+            return Ok(
+                retry_later_response(ErrorKind::ProjectRateLimited, retry_after).into_response(),
+            );
+        }
+
+        // Ready projects are routed to without parsing their whole state
+        let (target_ip, runtime_fqdn) = match self.gateway.find_ready_target(&project_name).await? {
+            Some(target_ip) if is_custom_domain => (
+                target_ip,
+                self.gateway.find_runtime_fqdn(&project_name).await?,
+            ),
+            Some(target_ip) => (target_ip, None),
+            None => {
+                let project = self
+                    .gateway
+                    .find_or_start_project(&project_name, task_sender)
+                    .await?;
+
+                // The request was held for as long as it could be
+                if project.is_waking() {
+                    debug!(%project_name, "project is still waking up");
+                    return Ok(retry_later_response(
+                        ErrorKind::ProjectNotReady,
+                        COLD_START_RETRY_AFTER,
+                    )
+                    .into_response());
+                }
+
+                let target_ip = project
+                    .target_ip()?
+                    .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotReady))?;
+                let runtime_fqdn = project
+                    .container()
+                    .and_then(|container| container.fqdn().ok())
+                    .filter(|_| is_custom_domain);
+
+                (target_ip, runtime_fqdn)
+            }
+        };
+
+        // The runtime only serves the fqdn its container was created with, so requests to the
+        // other domains of the project are forwarded as that one
+        if let Some(runtime_fqdn) = runtime_fqdn.filter(|runtime_fqdn| runtime_fqdn != &fqdn) {
+            let host = runtime_fqdn
+                .to_string()
+                .parse()
+                .map_err(|_| Error::from_kind(ErrorKind::Internal))?;
+            // The domain the client asked for stays in `X-Forwarded-Host`
+            req.headers_mut().insert(HOST, host);
+        }
 
         // Record current project for tracing purposes
         span.record("project", &project_name.to_string());
 
-        let target_ip = project
-            .target_ip()?
-            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotReady))?;
-
+        // Every request to the project reaches its one container, so sessions stay on the same
+        // runtime without any affinity to keep
         let target_url = format!("http://{}:{}", target_ip, 8000);
 
         let cx = span.context();
@@ -151,10 +230,38 @@ impl UserProxy {
             propagator.inject_context(&cx, &mut HeaderInjector(req.headers_mut()))
         });
 
-        let proxy = PROXY_CLIENT
-            .call(self.remote_addr.ip(), &target_url, req)
-            .await
-            .map_err(|_| Error::from_kind(ErrorKind::ProjectUnavailable))?;
+        let started_at = Instant::now();
+        let proxy = if upgrade::is_upgrade(&req) {
+            // Upgraded connections outlive the handshake, so the request timeout does not apply
+            req.headers_mut().remove(&X_SHUTTLE_REQUEST_TIMEOUT);
+            upgrade::forward(self.remote_addr.ip(), &target_url, req).await
+        } else {
+            let request_timeout = self
+                .gateway
+                .project_request_timeout(&project_name)
+                .await?
+                .unwrap_or(self.gateway.route_timeout());
+            forward(self.remote_addr.ip(), &target_url, req, request_timeout).await
+        };
+
+        let status = match &proxy {
+            Ok(resp) => resp.status(),
+            Err(error) => ApiError::from(error.kind()).status(),
+        };
+        metrics::record_routed(&project_name, status, started_at.elapsed());
+
+        let is_failure = !matches!(&proxy, Ok(resp) if !resp.status().is_server_error());
+        self.gateway
+            .circuit_breakers()
+            .record(project_name.as_str(), is_failure);
+
+        let mut proxy = proxy?;
+
+        if let Some(deployment_id) = self.gateway.exposed_deployment_id(&project_name).await {
+            proxy
+                .headers_mut()
+                .typed_insert(XShuttleDeploymentId(deployment_id.to_string()));
+        }
 
         let (parts, body) = proxy.into_parts();
         let body = <Body as HttpBody>::map_err(body, axum::Error::new).boxed_unsync();
@@ -165,6 +272,41 @@ impl UserProxy {
     }
 }
 
+/// Let projects know the scheme and host the client used to reach the gateway. The gateway is the
+/// first hop of the requests it routes, so any forwarding headers sent by the client are dropped
+/// rather than trusted. The client address is added to `X-Forwarded-For` when the request is
+/// forwarded.
+pub(crate) fn set_forwarding_headers(headers: &mut HeaderMap, forwarded_proto: &'static str) {
+    headers.remove(FORWARDED);
+    headers.remove(X_FORWARDED_FOR);
+    headers.remove(X_FORWARDED_HOST);
+
+    headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(forwarded_proto));
+    if let Some(host) = headers.get(HOST).cloned() {
+        headers.insert(X_FORWARDED_HOST, host);
+    }
+}
+
+/// Forward a request to a project, cutting it off after `request_timeout`. The timeout is sent along
+/// in a header, in place of any the client sent, so the runtime can align its handlers with it.
+async fn forward(
+    remote_ip: IpAddr,
+    target_url: &str,
+    mut req: Request<Body>,
+    request_timeout: Duration,
+) -> Result<hyper::Response<Body>, Error> {
+    req.headers_mut()
+        .typed_insert(XShuttleRequestTimeout(request_timeout.as_secs()));
+
+    timeout(
+        request_timeout,
+        PROXY_CLIENT.call(remote_ip, target_url, req),
+    )
+    .await
+    .map_err(|_| Error::from_kind(ErrorKind::ProjectTimedOut))?
+    .map_err(|_| Error::from_kind(ErrorKind::ProjectUnavailable))
+}
+
 impl Service<Request<Body>> for UserProxy {
     type Response = Response;
     type Error = Error;
@@ -176,14 +318,39 @@ impl Service<Request<Body>> for UserProxy {
     }
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
-        let task_sender = self.task_sender.clone();
-        self.clone()
-            .proxy(task_sender, req)
-            .or_else(|err: Error| future::ready(Ok(err.into_response())))
-            .boxed()
+        let user_proxy = self.clone();
+        let respond = move |req: Request<Body>| {
+            let task_sender = user_proxy.task_sender.clone();
+            user_proxy
+                .clone()
+                .proxy(task_sender, req)
+                .or_else(|err: Error| future::ready(Ok(err.into_response())))
+        };
+
+        match self.compression_min_size {
+            // The encoding is negotiated from the request, so compression wraps the whole
+            // exchange. Upgraded connections have no body to compress.
+            Some(min_size) if !upgrade::is_upgrade(&req) => compression_layer(min_size)
+                .layer(service_fn(respond))
+                .call(req)
+                .map_ok(|resp| resp.map(axum::body::boxed))
+                .boxed(),
+            _ => respond(req).boxed(),
+        }
     }
 }
 
+/// Compress responses over `min_size` bytes with the best encoding the client accepts. Responses
+/// projects already compressed are left alone, as are those which do not benefit from it.
+fn compression_layer(min_size: u16) -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(
+        SizeAbove::new(min_size)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE),
+    )
+}
+
 #[derive(Clone)]
 pub struct Bouncer {
     gateway: Arc<GatewayService>,
@@ -249,6 +416,7 @@ pub struct UserServiceBuilder {
     bouncer_binds_to: Option<SocketAddr>,
     user_binds_to: Option<SocketAddr>,
     public: Option<FQDN>,
+    compression_min_size: Option<u16>,
 }
 
 impl Default for UserServiceBuilder {
@@ -267,6 +435,7 @@ impl UserServiceBuilder {
             tls_acceptor: None,
             bouncer_binds_to: None,
             user_binds_to: None,
+            compression_min_size: None,
         }
     }
 
@@ -305,6 +474,13 @@ impl UserServiceBuilder {
         self
     }
 
+    /// Compress the responses of projects which are over `min_size` bytes, when the client
+    /// accepts it
+    pub fn with_compression(mut self, min_size: u16) -> Self {
+        self.compression_min_size = Some(min_size);
+        self
+    }
+
     pub fn serve(self) -> impl Future<Output = Result<(), io::Error>> {
         let service = self.service.expect("a GatewayService is required");
         let task_sender = self.task_sender.expect("a task sender is required");
@@ -313,13 +489,14 @@ impl UserServiceBuilder {
             .user_binds_to
             .expect("a socket address to bind to is required");
 
-        let user_proxy = SanitizePath::sanitize_paths(UserProxy {
+        let user_proxy = UserProxy {
             gateway: service.clone(),
             task_sender,
             remote_addr: "127.0.0.1:80".parse().unwrap(),
             public: public.clone(),
-        })
-        .into_make_service();
+            forwarded_proto: "http",
+            compression_min_size: self.compression_min_size,
+        };
 
         let bouncer = self.bouncer_binds_to.as_ref().map(|_| Bouncer {
             gateway: service.clone(),
@@ -349,7 +526,13 @@ impl UserServiceBuilder {
 
             let user_with_tls = axum_server::Server::bind(user_binds_to)
                 .acceptor(tls_acceptor)
-                .serve(user_proxy)
+                .serve(
+                    SanitizePath::sanitize_paths(UserProxy {
+                        forwarded_proto: "https",
+                        ..user_proxy
+                    })
+                    .into_make_service(),
+                )
                 .map(|handle| ("user proxy (with TLS)", handle))
                 .boxed();
             futs.push(user_with_tls);
@@ -365,7 +548,7 @@ impl UserServiceBuilder {
             }
 
             let user_without_tls = axum_server::Server::bind(user_binds_to)
-                .serve(user_proxy)
+                .serve(SanitizePath::sanitize_paths(user_proxy).into_make_service())
                 .map(|handle| ("user proxy (no TLS)", handle))
                 .boxed();
             futs.push(user_without_tls);
@@ -377,3 +560,623 @@ impl UserServiceBuilder {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::extract::ws::{WebSocket, WebSocketUpgrade};
+    use axum::extract::Path;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use futures::{SinkExt, StreamExt};
+    use rand::Rng;
+    use shuttle_common::deployment::State;
+    use shuttle_common::models::project::RateLimit;
+    use shuttle_common::models::{deployment, service};
+    use sqlx::types::Json as SqlxJson;
+    use sqlx::{query, Row};
+    use tokio::runtime::Handle;
+    use tokio::sync::mpsc::channel;
+    use tokio::time::sleep;
+    use tokio_tungstenite::tungstenite;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::args::ContextArgs;
+    use crate::project::Project;
+    use crate::service::tests::never_idle;
+    use crate::tests::World;
+    use crate::{AccountName, ProjectName};
+
+    /// A user proxy in front of a ready project named `matrix`, whose runtime is served by `router`
+    async fn proxy_to(world: &World, router: Router) -> UserProxy {
+        proxy_with_args_to(world, world.args(), router).await
+    }
+
+    /// Same as [proxy_to], with a gateway started from `args`
+    async fn proxy_with_args_to(world: &World, args: ContextArgs, router: Router) -> UserProxy {
+        let gateway = Arc::new(GatewayService::init(args, world.pool(), "".into()).await);
+
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        gateway
+            .create_project(matrix.clone(), neo, false, never_idle())
+            .await
+            .unwrap();
+
+        // Any address of the loopback network is local, so the runtime can listen on the port
+        // requests are proxied to without clashing with other tests
+        let mut rng = rand::thread_rng();
+        let target_ip: IpAddr =
+            Ipv4Addr::new(127, rng.gen_range(1..255), rng.gen(), rng.gen_range(1..255)).into();
+        tokio::spawn(
+            axum::Server::bind(&SocketAddr::new(target_ip, 8000)).serve(router.into_make_service()),
+        );
+
+        query("UPDATE projects SET state_kind = 'ready', target_ip = ?1 WHERE project_name = ?2")
+            .bind(target_ip.to_string())
+            .bind(&matrix)
+            .execute(&world.pool())
+            .await
+            .unwrap();
+
+        let (task_sender, _) = channel(1);
+
+        UserProxy {
+            gateway,
+            task_sender,
+            remote_addr: "10.0.0.1:4242".parse().unwrap(),
+            public: world.fqdn(),
+            forwarded_proto: "https",
+            compression_min_size: None,
+        }
+    }
+
+    fn user_request(path: &str) -> Request<Body> {
+        Request::get(path)
+            .header(HOST, "matrix.test.shuttleapp.rs")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn user_proxy_replaces_forwarding_headers() {
+        let world = World::new().await;
+
+        let router = Router::new().route(
+            "/",
+            get(|headers: HeaderMap| async move {
+                let header = |name: &str| {
+                    headers
+                        .get(name)
+                        .map(|value| value.to_str().unwrap().to_string())
+                };
+                axum::Json((
+                    header(X_FORWARDED_FOR),
+                    header(X_FORWARDED_PROTO),
+                    header(X_FORWARDED_HOST),
+                    header(FORWARDED.as_str()),
+                ))
+            }),
+        );
+        let mut proxy = proxy_to(&world, router).await;
+
+        let mut request = user_request("/");
+        let headers = request.headers_mut();
+        headers.insert(X_FORWARDED_FOR, "1.2.3.4".parse().unwrap());
+        headers.insert(X_FORWARDED_PROTO, "gopher".parse().unwrap());
+        headers.insert(X_FORWARDED_HOST, "evil.example".parse().unwrap());
+        headers.insert(FORWARDED, "for=1.2.3.4".parse().unwrap());
+
+        let response = proxy.call(request).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let forwarded: (
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+        ) = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(
+            forwarded,
+            (
+                Some("10.0.0.1".to_string()),
+                Some("https".to_string()),
+                Some("matrix.test.shuttleapp.rs".to_string()),
+                None,
+            )
+        );
+    }
+
+    #[test]
+    fn forwarding_headers() {
+        // The control API routes requests with the same headers as the user proxy
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, "api.shuttle.rs".parse().unwrap());
+        headers.insert(X_FORWARDED_FOR, "1.2.3.4".parse().unwrap());
+        headers.insert(X_FORWARDED_PROTO, "https".parse().unwrap());
+        headers.insert(X_FORWARDED_HOST, "evil.example".parse().unwrap());
+        headers.insert(FORWARDED, "for=1.2.3.4".parse().unwrap());
+        set_forwarding_headers(&mut headers, "http");
+
+        assert_eq!(headers[X_FORWARDED_PROTO], "http");
+        assert_eq!(headers[X_FORWARDED_HOST], "api.shuttle.rs");
+        assert!(!headers.contains_key(X_FORWARDED_FOR));
+        assert!(!headers.contains_key(FORWARDED));
+    }
+
+    #[tokio::test]
+    async fn user_proxy_compresses_responses() {
+        let world = World::new().await;
+
+        let router = Router::new().route(
+            "/:size",
+            get(|Path(size): Path<usize>| async move { "a".repeat(size) }),
+        );
+        let mut proxy = proxy_to(&world, router).await;
+        proxy.compression_min_size = Some(1024);
+
+        let mut get = |size: usize, accept_encoding: &str| {
+            let mut request = user_request(&format!("/{size}"));
+            request
+                .headers_mut()
+                .insert("Accept-Encoding", accept_encoding.parse().unwrap());
+            proxy.call(request)
+        };
+
+        let resp = get(4096, "gzip").await.unwrap();
+        assert_eq!(resp.headers()["Content-Encoding"], "gzip");
+
+        let resp = get(4096, "br, gzip;q=0.5").await.unwrap();
+        assert_eq!(resp.headers()["Content-Encoding"], "br");
+
+        // Small responses and clients which do not accept compression get the response as is
+        let resp = get(100, "gzip").await.unwrap();
+        assert!(resp.headers().get("Content-Encoding").is_none());
+
+        let resp = get(4096, "identity").await.unwrap();
+        assert!(resp.headers().get("Content-Encoding").is_none());
+        assert_eq!(
+            hyper::body::to_bytes(resp.into_body()).await.unwrap().len(),
+            4096
+        );
+    }
+
+    #[tokio::test]
+    async fn user_proxy_bridges_websockets() {
+        let world = World::new().await;
+
+        let router = Router::new().route(
+            "/ws",
+            get(|ws: WebSocketUpgrade| async move {
+                ws.on_upgrade(|mut socket: WebSocket| async move {
+                    while let Some(Ok(msg)) = socket.recv().await {
+                        if socket.send(msg).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            }),
+        );
+        let proxy = proxy_to(&world, router).await;
+
+        // Upgrades need a real connection to take over, so the proxy is served like in `serve`
+        let proxy_addr =
+            SocketAddr::from((Ipv4Addr::LOCALHOST, portpicker::pick_unused_port().unwrap()));
+        tokio::spawn(axum_server::Server::bind(proxy_addr).serve(proxy.into_make_service()));
+        sleep(Duration::from_millis(100)).await;
+
+        let mut request = format!("ws://{proxy_addr}/ws")
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert("host", "matrix.test.shuttleapp.rs".parse().unwrap());
+
+        let (mut socket, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+        assert_eq!(response.status().as_u16(), 101);
+
+        socket
+            .send(tungstenite::Message::Text(
+                "follow the white rabbit".to_string(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            socket.next().await.unwrap().unwrap(),
+            tungstenite::Message::Text("follow the white rabbit".to_string())
+        );
+
+        socket
+            .send(tungstenite::Message::Close(None))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn user_proxy_enforces_rate_limit() {
+        let world = World::new().await;
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let router = Router::new().route(
+            "/",
+            get({
+                let hits = hits.clone();
+                move || async move {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                }
+            }),
+        );
+        let mut proxy = proxy_to(&world, router).await;
+
+        proxy
+            .gateway
+            .set_project_rate_limit(
+                &"matrix".parse().unwrap(),
+                &RateLimit {
+                    requests_per_second: Some(1),
+                    burst: Some(2),
+                },
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            let response = proxy.call(user_request("/")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = proxy.call(user_request("/")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(http::header::RETRY_AFTER));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn user_proxy_asks_to_retry_while_project_wakes_up() {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.cold_start_timeout_secs = 1;
+        let gateway = Arc::new(GatewayService::init(args, world.pool(), "".into()).await);
+
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
+        gateway
+            .create_project("matrix".parse().unwrap(), neo, false, never_idle())
+            .await
+            .unwrap();
+
+        // Nothing picks up the tasks, so the project never gets past being created
+        let (task_sender, _receiver) = channel(256);
+
+        let mut proxy = UserProxy {
+            gateway,
+            task_sender,
+            remote_addr: "10.0.0.1:4242".parse().unwrap(),
+            public: world.fqdn(),
+            forwarded_proto: "https",
+            compression_min_size: None,
+        };
+
+        let response = proxy.call(user_request("/")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers()[http::header::RETRY_AFTER],
+            COLD_START_RETRY_AFTER.as_secs().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn user_proxy_times_out_hung_project() {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.route_timeout_secs = 1;
+
+        let router = Router::new()
+            .route("/hang", get(|| future::pending::<()>()))
+            .route(
+                "/slow",
+                get(|| async {
+                    sleep(Duration::from_secs(2)).await;
+                    "done"
+                }),
+            );
+        let mut proxy = proxy_with_args_to(&world, args, router).await;
+
+        let response = proxy.call(user_request("/hang")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        // The request timeout of the project takes over from the default one
+        proxy
+            .gateway
+            .set_project_request_timeout(&"matrix".parse().unwrap(), Some(5))
+            .await
+            .unwrap();
+        let response = proxy.call(user_request("/slow")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn user_proxy_exposes_active_deployment_id() {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.expose_deployment_id = true;
+
+        let router = Router::new().route("/", get(|| async { "hello" }));
+        let mut proxy = proxy_with_args_to(&world, args, router).await;
+
+        let target_ip: String =
+            query("SELECT target_ip FROM projects WHERE project_name = 'matrix'")
+                .fetch_one(&world.pool())
+                .await
+                .unwrap()
+                .get("target_ip");
+
+        // The deployer of the project answers on its own port of the same address
+        let deployment_id = Uuid::new_v4();
+        let deployer = Router::new().route(
+            "/projects/matrix/services/matrix",
+            get(move || async move {
+                axum::Json(service::Summary {
+                    name: "matrix".to_string(),
+                    deployment: Some(deployment::Response {
+                        id: deployment_id,
+                        service_id: Uuid::new_v4(),
+                        state: State::Running,
+                        last_update: chrono::Utc::now(),
+                        git_commit_id: None,
+                        git_commit_msg: None,
+                        git_branch: None,
+                        git_dirty: None,
+                        toolchain_info: None,
+                        dry_run: false,
+                        tests_passed: None,
+                    }),
+                    uri: "https://matrix.test.shuttleapp.rs".to_string(),
+                })
+            }),
+        );
+        tokio::spawn(
+            axum::Server::bind(&SocketAddr::new(target_ip.parse().unwrap(), 8001))
+                .serve(deployer.into_make_service()),
+        );
+
+        let project: Project = serde_json::from_value(serde_json::json!({
+            "ready": {
+                "container": {
+                    "Id": "matrix-container",
+                    "Args": ["--admin-secret", "neo-secret", "--project", "matrix"],
+                    "Config": {
+                        "Labels": {
+                            "shuttle.project": "matrix",
+                        },
+                    },
+                },
+                "service": {
+                    "name": "matrix",
+                    "target": target_ip,
+                    "last_check": null,
+                },
+            },
+        }))
+        .unwrap();
+        query("UPDATE projects SET project_state = ?1 WHERE project_name = 'matrix'")
+            .bind(SqlxJson(&project))
+            .execute(&world.pool())
+            .await
+            .unwrap();
+
+        let response = proxy.call(user_request("/")).await.unwrap();
+
+        assert_eq!(
+            response
+                .headers()
+                .typed_get::<XShuttleDeploymentId>()
+                .unwrap()
+                .0,
+            deployment_id.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn user_proxy_serves_custom_domain_as_runtime_fqdn() {
+        let world = World::new().await;
+
+        let router = Router::new().route(
+            "/",
+            get(|headers: HeaderMap| async move {
+                let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+                axum::Json((header(HOST.as_str()), header(X_FORWARDED_HOST)))
+            }),
+        );
+        let mut proxy = proxy_to(&world, router).await;
+
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let target_ip: String =
+            query("SELECT target_ip FROM projects WHERE project_name = 'matrix'")
+                .fetch_one(&world.pool())
+                .await
+                .unwrap()
+                .get("target_ip");
+        let project: Project = serde_json::from_value(serde_json::json!({
+            "ready": {
+                "container": {
+                    "Id": "matrix-container",
+                    "Args": ["--proxy-fqdn", "matrix.test.shuttleapp.rs", "--project", "matrix"],
+                    "Config": {
+                        "Labels": {
+                            "shuttle.project": "matrix",
+                        },
+                    },
+                },
+                "service": {
+                    "name": "matrix",
+                    "target": target_ip,
+                    "last_check": null,
+                },
+            },
+        }))
+        .unwrap();
+        proxy
+            .gateway
+            .update_project(&matrix, &project)
+            .await
+            .unwrap();
+        proxy
+            .gateway
+            .create_custom_domain(
+                &matrix,
+                &fqdn!("matrix.example.com"),
+                false,
+                "certificate",
+                "private key",
+            )
+            .await
+            .unwrap();
+
+        // The state of ready projects is not read to route to them, so this one can not be parsed
+        query("UPDATE projects SET project_state = 'garbage' WHERE project_name = 'matrix'")
+            .execute(&world.pool())
+            .await
+            .unwrap();
+
+        let request = Request::get("/")
+            .header(HOST, "matrix.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let response = proxy.call(request).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let hosts: (String, String) = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(
+            hosts,
+            (
+                "matrix.test.shuttleapp.rs".to_string(),
+                "matrix.example.com".to_string(),
+            )
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn user_proxy_records_metrics() {
+        let world = World::new().await;
+
+        let router = Router::new()
+            .route("/", get(|| async { "follow the white rabbit" }))
+            .route("/fail", get(|| async { StatusCode::INTERNAL_SERVER_ERROR }));
+        let mut proxy = proxy_to(&world, router).await;
+
+        // The recorder is only set on this thread, so the requests are driven to completion on it
+        let recorder = crate::metrics::builder().unwrap().build_recorder();
+        tokio::task::block_in_place(|| {
+            ::metrics::with_local_recorder(&recorder, || {
+                Handle::current().block_on(async {
+                    for path in ["/", "/", "/fail"] {
+                        proxy.call(user_request(path)).await.unwrap();
+                    }
+                })
+            })
+        });
+
+        let rendered = recorder.handle().render();
+        assert!(
+            rendered.contains(r#"gateway_routed_requests_total{project="matrix",status="2xx"} 2"#)
+        );
+        assert!(
+            rendered.contains(r#"gateway_routed_requests_total{project="matrix",status="5xx"} 1"#)
+        );
+        assert!(rendered.contains(r#"gateway_upstream_latency_seconds_count{project="matrix"} 3"#));
+    }
+
+    #[tokio::test]
+    async fn user_proxy_opens_circuit_breaker_of_failing_project() {
+        let world = World::new().await;
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let router = Router::new().route(
+            "/",
+            get({
+                let hits = hits.clone();
+                move || async move {
+                    hits.fetch_add(1, Ordering::SeqCst);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            }),
+        );
+        let mut proxy = proxy_to(&world, router).await;
+
+        // Enough failures for the breaker to open
+        for _ in 0..10 {
+            let response = proxy.call(user_request("/")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+
+        let response = proxy.call(user_request("/")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.headers().contains_key(http::header::RETRY_AFTER));
+        assert_eq!(hits.load(Ordering::SeqCst), 10);
+    }
+
+    #[tokio::test]
+    async fn forward_applies_and_forwards_request_timeout() {
+        let port = portpicker::pick_unused_port().unwrap();
+        let addr: SocketAddr = format!("127.0.0.1:{port}").parse().unwrap();
+
+        let router = Router::new()
+            .route(
+                "/timeout",
+                get(|headers: HeaderMap| async move {
+                    headers
+                        .typed_get::<XShuttleRequestTimeout>()
+                        .map(|XShuttleRequestTimeout(secs)| secs.to_string())
+                        .unwrap_or_default()
+                }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    sleep(Duration::from_secs(5)).await;
+                    "too late"
+                }),
+            );
+
+        tokio::spawn(axum::Server::bind(&addr).serve(router.into_make_service()));
+
+        let target_url = format!("http://{addr}");
+        let remote_ip = Ipv4Addr::LOCALHOST.into();
+        let request = |path: &str| {
+            Request::get(format!("http://matrix.unveil.sh{path}"))
+                .header(&X_SHUTTLE_REQUEST_TIMEOUT, "3600")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let body = |response: hyper::Response<Body>| async move {
+            let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            String::from_utf8(bytes.to_vec()).unwrap()
+        };
+
+        // The applied timeout replaces any sent by the client
+        let response = forward(
+            remote_ip,
+            &target_url,
+            request("/timeout"),
+            Duration::from_secs(2),
+        )
+        .await
+        .unwrap();
+        assert_eq!(body(response).await, "2");
+
+        let error = forward(
+            remote_ip,
+            &target_url,
+            request("/slow"),
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ProjectTimedOut);
+    }
+}
@@ -0,0 +1,149 @@
+//! Limits on the rate of requests routed to projects.
+//!
+//! Every project with a limit gets a token bucket holding up to its burst of requests, which is
+//! refilled at its rate. A request takes a token, and is refused when the bucket is empty so a
+//! single noisy project cannot overwhelm its container and the docker host it shares.
+//!
+//! The limits are read from the database once and then kept alongside the buckets, so checking
+//! one does not cost a query on every request.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use shuttle_common::models::project::RateLimit;
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// The token buckets of all the projects with a rate limit
+#[derive(Default)]
+pub struct RateLimiters {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    limits: Mutex<HashMap<String, RateLimit>>,
+}
+
+impl RateLimiters {
+    /// The limit of a project, when it was kept
+    pub fn limit(&self, project_name: &str) -> Option<RateLimit> {
+        self.limits.lock().unwrap().get(project_name).copied()
+    }
+
+    /// Keep the limit of a project for the next requests
+    pub fn set_limit(&self, project_name: &str, limit: RateLimit) {
+        self.limits
+            .lock()
+            .unwrap()
+            .insert(project_name.to_string(), limit);
+    }
+
+    /// Drop the limit and bucket of a project which is gone
+    pub fn forget(&self, project_name: &str) {
+        self.limits.lock().unwrap().remove(project_name);
+        self.buckets.lock().unwrap().remove(project_name);
+    }
+
+    /// Take a token from the bucket of a project, or else tell how long until one is available
+    pub fn acquire(&self, project_name: &str, limit: &RateLimit) -> Result<(), Duration> {
+        self.acquire_at(project_name, limit, Instant::now())
+    }
+
+    fn acquire_at(
+        &self,
+        project_name: &str,
+        limit: &RateLimit,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let Some(requests_per_second) = limit.requests_per_second else {
+            buckets.remove(project_name);
+            return Ok(());
+        };
+        let rate = requests_per_second as f64;
+        let capacity = limit.burst.unwrap_or(requests_per_second) as f64;
+
+        let bucket = buckets
+            .entry(project_name.to_string())
+            .or_insert_with(|| Bucket {
+                tokens: capacity,
+                last_refill: now,
+            });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use shuttle_common::models::project::RateLimit;
+
+    use super::RateLimiters;
+
+    #[test]
+    fn token_bucket() {
+        let limiters = RateLimiters::default();
+        let limit = RateLimit {
+            requests_per_second: Some(2),
+            burst: Some(3),
+        };
+        let now = Instant::now();
+
+        // The whole burst goes through at once
+        for _ in 0..3 {
+            assert_eq!(limiters.acquire_at("matrix", &limit, now), Ok(()));
+        }
+        assert_eq!(
+            limiters.acquire_at("matrix", &limit, now),
+            Err(Duration::from_millis(500))
+        );
+
+        // Other projects have their own bucket
+        assert_eq!(limiters.acquire_at("zion", &limit, now), Ok(()));
+
+        // Then the bucket refills at the rate
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiters.acquire_at("matrix", &limit, later), Ok(()));
+        assert!(limiters.acquire_at("matrix", &limit, later).is_err());
+
+        let unlimited = RateLimit {
+            requests_per_second: None,
+            burst: None,
+        };
+        for _ in 0..10 {
+            assert_eq!(limiters.acquire_at("matrix", &unlimited, later), Ok(()));
+        }
+    }
+
+    #[test]
+    fn forgotten_projects_lose_their_limit() {
+        let limiters = RateLimiters::default();
+        let limit = RateLimit {
+            requests_per_second: Some(1),
+            burst: None,
+        };
+        let now = Instant::now();
+
+        limiters.set_limit("matrix", limit);
+        assert_eq!(limiters.limit("matrix"), Some(limit));
+        assert_eq!(limiters.acquire_at("matrix", &limit, now), Ok(()));
+        assert!(limiters.acquire_at("matrix", &limit, now).is_err());
+
+        limiters.forget("matrix");
+        assert_eq!(limiters.limit("matrix"), None);
+        assert_eq!(limiters.acquire_at("matrix", &limit, now), Ok(()));
+    }
+}
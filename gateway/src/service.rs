@@ -1,17 +1,23 @@
+use std::collections::HashMap;
 use std::io::Cursor;
-use std::net::Ipv4Addr;
-use std::ops::Sub;
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::{Deref, DerefMut, Sub};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use axum::body::Body;
 use axum::headers::HeaderMapExt;
 use axum::http::Request;
 use axum::response::Response;
+use bollard::container::LogsOptions;
+use bollard::errors::Error as DockerError;
 use bollard::{Docker, API_DEFAULT_VERSION};
+use chrono::{DateTime, TimeZone, Utc};
 use fqdn::{Fqdn, FQDN};
-use http::header::AUTHORIZATION;
-use http::Uri;
+use futures::{Stream, TryStreamExt};
+use http::header::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use http::{Method, StatusCode, Uri};
 use hyper::client::connect::dns::GaiResolver;
 use hyper::client::HttpConnector;
 use hyper::Client;
@@ -21,33 +27,94 @@ use once_cell::sync::Lazy;
 use opentelemetry::global;
 use opentelemetry_http::HeaderInjector;
 use serde_json::Value;
-use shuttle_common::backends::headers::{XShuttleAccountName, XShuttleAdminSecret};
+use shuttle_common::backends::headers::{
+    XShuttleAccountName, XShuttleAdminSecret, XShuttleDeploymentId,
+};
+use shuttle_common::models::error::ApiError;
+use shuttle_common::models::project::{
+    AuditAction, AuditEntry, EgressPolicy, RateLimit, StatsSample, IDLE_MINUTES,
+};
 use sqlx::error::DatabaseError;
 use sqlx::migrate::Migrator;
-use sqlx::sqlite::SqlitePool;
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::{Sqlite, SqliteConnection, SqlitePool, SqliteRow};
 use sqlx::types::Json as SqlxJson;
 use sqlx::{query, Error as SqlxError, QueryBuilder, Row};
 use tokio::sync::mpsc::Sender;
-use tokio::time::timeout;
+use tokio::sync::{Mutex, MutexGuard, RwLock, Semaphore};
+use tokio::time::{sleep, timeout};
 use tonic::transport::Endpoint;
-use tracing::{debug, instrument, trace, warn, Span};
+use tracing::{debug, info, instrument, trace, warn, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
+use ttl_cache::TtlCache;
+use uuid::Uuid;
+use x509_parser::extensions::GeneralName;
 use x509_parser::nom::AsBytes;
 use x509_parser::parse_x509_certificate;
 use x509_parser::prelude::parse_x509_pem;
 use x509_parser::time::ASN1Time;
 
-use crate::acme::{AccountWrapper, AcmeClient, CustomDomain};
-use crate::args::ContextArgs;
-use crate::project::{Project, ProjectCreating, IS_HEALTHY_TIMEOUT};
-use crate::task::{self, BoxedTask, TaskBuilder};
-use crate::tls::{ChainAndPrivateKey, GatewayCertResolver, RENEWAL_VALIDITY_THRESHOLD_IN_DAYS};
+use crate::acme::{
+    certificate_expiry, custom_domain_identifier, split_custom_domain, wildcard_for,
+    AccountWrapper, AcmeClient, CustomDomain,
+};
+use crate::args::{ContextArgs, PullPolicy, ReadinessCheck};
+use crate::breaker::CircuitBreakers;
+use crate::project::{
+    ContainerInspectResponseExt, Project, ProjectCreating, TransitionHistory, CREATED_TIMEOUT_SECS,
+    IS_HEALTHY_TIMEOUT, VOLUME_RETENTION_DAYS,
+};
+use crate::proxy::set_forwarding_headers;
+use crate::rate_limit::RateLimiters;
+use crate::task::{self, BoxedTask, TaskBuilder, TaskHandle};
+use crate::tls::{ChainAndPrivateKey, GatewayCertResolver};
+use crate::upgrade;
 use crate::worker::TaskRouter;
 use crate::{
-    AccountName, DockerContext, Error, ErrorKind, ProjectDetails, ProjectName, AUTH_CLIENT,
+    AccountName, DockerContext, Error, ErrorKind, ProjectDetails, ProjectName, State, UserProject,
+    AUTH_CLIENT,
 };
 
 pub static MIGRATIONS: Migrator = sqlx::migrate!("./migrations");
+
+/// How long to keep using a JWT whose expiry cannot be read
+const JWT_FALLBACK_TTL: Duration = Duration::from_secs(60);
+/// How long before its expiry a cached JWT is fetched again, so it is never sent out when about
+/// to expire
+const JWT_EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// A JWT of the gateway and until when it can be used
+type CachedJwt = Arc<RwLock<Option<(String, Instant)>>>;
+
+/// Default size of the pool of connections to the state database. The database is in WAL mode,
+/// where readers do not block the single writer, so connections past the number of writers still
+/// serve concurrent reads like the project lookups of proxied requests.
+pub const DB_MAX_CONNECTIONS: u32 = 16;
+/// Default time a connection waits on a write lock of the state database before failing. Writes
+/// are short in WAL mode, so this is only reached when the database is badly contended.
+pub const DB_BUSY_TIMEOUT_MS: u64 = 5_000;
+/// Default time the requests routed to a project can take before they are cut off, so a hung
+/// project cannot hold on to gateway connections
+pub const ROUTE_TIMEOUT_SECS: u64 = 60;
+/// Default time a request is held while its project wakes up, before it is turned away to retry
+/// later
+pub const COLD_START_TIMEOUT_SECS: u64 = 30;
+/// Most requests held at once while a project wakes up
+const COLD_START_QUEUE_LIMIT: usize = 64;
+const COLD_START_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// When clients are told to retry a request to a project which is still waking up
+pub(crate) const COLD_START_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// How long the active deployment of a project is remembered before asking the project again
+const DEPLOYMENT_ID_CACHE_TTL: Duration = Duration::from_secs(30);
+const DEPLOYMENT_ID_CACHE_CAPACITY: usize = 1024;
+const PROJECT_CACHE_CAPACITY: usize = 1024;
+
+/// Upper bound on the number of lines returned from a project container's logs
+pub const MAX_CONTAINER_LOG_LINES: usize = 1000;
+
+/// Most times a project can have its failed builds retried
+pub const MAX_BUILD_RETRIES: u32 = 5;
 static PROXY_CLIENT: Lazy<ReverseProxy<HttpConnector<GaiResolver>>> =
     Lazy::new(|| ReverseProxy::new(Client::new()));
 
@@ -61,10 +128,21 @@ impl From<SqlxError> for Error {
 pub struct ContainerSettingsBuilder {
     prefix: Option<String>,
     image: Option<String>,
+    image_pull_policy: Option<PullPolicy>,
     provisioner: Option<String>,
     auth_uri: Option<String>,
     network_name: Option<String>,
+    restricted_network_name: Option<String>,
+    restricted_network_services: Vec<String>,
+    gateway_container: Option<String>,
     fqdn: Option<String>,
+    health_path: Option<String>,
+    readiness_check: Option<ReadinessCheck>,
+    webhook_url: Option<String>,
+    default_idle_minutes: Option<u64>,
+    idle_grace_checks: Option<u32>,
+    volume_retention_days: Option<u64>,
+    created_timeout_secs: Option<u64>,
 }
 
 impl Default for ContainerSettingsBuilder {
@@ -78,10 +156,21 @@ impl ContainerSettingsBuilder {
         Self {
             prefix: None,
             image: None,
+            image_pull_policy: None,
             provisioner: None,
             auth_uri: None,
             network_name: None,
+            restricted_network_name: None,
+            restricted_network_services: Vec::new(),
+            gateway_container: None,
             fqdn: None,
+            health_path: None,
+            readiness_check: None,
+            webhook_url: None,
+            default_idle_minutes: None,
+            idle_grace_checks: None,
+            volume_retention_days: None,
+            created_timeout_secs: None,
         }
     }
 
@@ -89,20 +178,52 @@ impl ContainerSettingsBuilder {
         let ContextArgs {
             prefix,
             network_name,
+            restricted_network_name,
+            restricted_network_services,
+            gateway_container,
             provisioner_host,
             auth_uri,
             image,
+            image_pull_policy,
             proxy_fqdn,
+            health_path,
+            readiness_check,
+            webhook_url,
+            default_idle_minutes,
+            idle_grace_checks,
+            volume_retention_days,
+            created_timeout_secs,
             ..
         } = args;
-        self.prefix(prefix)
+        let mut builder = self
+            .prefix(prefix)
             .image(image)
+            .image_pull_policy(*image_pull_policy)
             .provisioner_host(provisioner_host)
             .auth_uri(auth_uri)
             .network_name(network_name)
+            .restricted_network_name(restricted_network_name)
             .fqdn(proxy_fqdn)
-            .build()
-            .await
+            .health_path(health_path)
+            .readiness_check(*readiness_check)
+            .default_idle_minutes(*default_idle_minutes)
+            .idle_grace_checks(*idle_grace_checks)
+            .volume_retention_days(*volume_retention_days)
+            .created_timeout_secs(*created_timeout_secs);
+
+        if let Some(webhook_url) = webhook_url {
+            builder = builder.webhook_url(webhook_url);
+        }
+
+        if let Some(gateway_container) = gateway_container {
+            builder = builder.gateway_container(gateway_container);
+        }
+
+        for container in restricted_network_services {
+            builder = builder.restricted_network_service(container);
+        }
+
+        builder.build().await
     }
 
     pub fn prefix<S: ToString>(mut self, prefix: S) -> Self {
@@ -115,6 +236,11 @@ impl ContainerSettingsBuilder {
         self
     }
 
+    pub fn image_pull_policy(mut self, policy: PullPolicy) -> Self {
+        self.image_pull_policy = Some(policy);
+        self
+    }
+
     pub fn provisioner_host<S: ToString>(mut self, host: S) -> Self {
         self.provisioner = Some(host.to_string());
         self
@@ -130,27 +256,134 @@ impl ContainerSettingsBuilder {
         self
     }
 
+    pub fn restricted_network_name<S: ToString>(mut self, name: S) -> Self {
+        self.restricted_network_name = Some(name.to_string());
+        self
+    }
+
+    pub fn restricted_network_service<S: ToString>(mut self, container: S) -> Self {
+        self.restricted_network_services.push(container.to_string());
+        self
+    }
+
+    pub fn gateway_container<S: ToString>(mut self, container: S) -> Self {
+        self.gateway_container = Some(container.to_string());
+        self
+    }
+
     pub fn fqdn<S: ToString>(mut self, fqdn: S) -> Self {
         self.fqdn = Some(fqdn.to_string().trim_end_matches('.').to_string());
         self
     }
 
+    pub fn health_path<S: ToString>(mut self, path: S) -> Self {
+        self.health_path = Some(path.to_string());
+        self
+    }
+
+    pub fn webhook_url<S: ToString>(mut self, url: S) -> Self {
+        self.webhook_url = Some(url.to_string());
+        self
+    }
+
+    pub fn default_idle_minutes(mut self, idle_minutes: u64) -> Self {
+        self.default_idle_minutes = Some(idle_minutes);
+        self
+    }
+
+    pub fn idle_grace_checks(mut self, checks: u32) -> Self {
+        self.idle_grace_checks = Some(checks);
+        self
+    }
+
+    pub fn volume_retention_days(mut self, days: u64) -> Self {
+        self.volume_retention_days = Some(days);
+        self
+    }
+
+    pub fn created_timeout_secs(mut self, secs: u64) -> Self {
+        self.created_timeout_secs = Some(secs);
+        self
+    }
+
+    pub fn readiness_check(mut self, readiness_check: ReadinessCheck) -> Self {
+        self.readiness_check = Some(readiness_check);
+        self
+    }
+
     pub async fn build(mut self) -> ContainerSettings {
         let prefix = self.prefix.take().unwrap();
         let image = self.image.take().unwrap();
+        let image_pull_policy = self.image_pull_policy.take().unwrap_or(PullPolicy::Never);
         let provisioner_host = self.provisioner.take().unwrap();
         let auth_uri = self.auth_uri.take().unwrap();
 
         let network_name = self.network_name.take().unwrap();
+        let restricted_network_name = self.restricted_network_name.take().unwrap();
+        let restricted_network_services = std::mem::take(&mut self.restricted_network_services);
+        let gateway_container = self.gateway_container.take();
         let fqdn = self.fqdn.take().unwrap();
+        let health_path = self
+            .health_path
+            .take()
+            .unwrap_or_else(|| "/projects/{name}/status".to_string());
+        let readiness_check = self.readiness_check.take().unwrap_or_default();
+        let webhook_url = self.webhook_url.take();
+        let default_idle_minutes = self.default_idle_minutes.take().unwrap_or(IDLE_MINUTES);
+        let idle_grace_checks = self.idle_grace_checks.take().unwrap_or_default();
+        let volume_retention_days = self
+            .volume_retention_days
+            .take()
+            .unwrap_or(VOLUME_RETENTION_DAYS);
+        let created_timeout_secs = self
+            .created_timeout_secs
+            .take()
+            .unwrap_or(CREATED_TIMEOUT_SECS);
 
         ContainerSettings {
             prefix,
             image,
+            image_pull_policy,
             provisioner_host,
             auth_uri,
             network_name,
+            restricted_network_name,
+            restricted_network_services,
+            gateway_container,
             fqdn,
+            health_path,
+            readiness_check,
+            webhook_url,
+            default_idle_minutes,
+            idle_grace_checks,
+            volume_retention_days,
+            created_timeout_secs,
+        }
+    }
+}
+
+/// The settings a project is created or recreated with
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProjectOptions {
+    /// Minutes of inactivity before the project is stopped, the gateway's default when `None`
+    pub idle_minutes: Option<u64>,
+    /// Whether the project gets a `SIGTERM` and time to exit when it is stopped
+    pub graceful_stop: bool,
+    /// Whether the project joins a network of its own instead of the shared one
+    pub isolated_network: bool,
+    /// Outbound network access of the project. A recreated project keeps its previous policy
+    /// when `None`, and a new one gets open access.
+    pub egress_policy: Option<EgressPolicy>,
+}
+
+impl From<project::Config> for ProjectOptions {
+    fn from(config: project::Config) -> Self {
+        Self {
+            idle_minutes: config.idle_minutes,
+            graceful_stop: config.graceful_stop,
+            isolated_network: config.isolated_network,
+            // Only admins set the egress policy, through its own endpoint
+            egress_policy: None,
         }
     }
 }
@@ -159,16 +392,40 @@ impl ContainerSettingsBuilder {
 pub struct ContainerSettings {
     pub prefix: String,
     pub image: String,
+    pub image_pull_policy: PullPolicy,
     pub provisioner_host: String,
     pub auth_uri: String,
     pub network_name: String,
+    pub restricted_network_name: String,
+    /// Containers of the internal services, which join the restricted network
+    pub restricted_network_services: Vec<String>,
+    /// Container of the gateway, which joins the networks of isolated and restricted projects
+    pub gateway_container: Option<String>,
     pub fqdn: String,
+    /// Readiness path of the runtimes, where `{name}` stands for the project name
+    pub health_path: String,
+    /// How the readiness of starting projects is checked
+    pub readiness_check: ReadinessCheck,
+    pub webhook_url: Option<String>,
+    /// Idle minutes of projects created without an explicit value
+    pub default_idle_minutes: u64,
+    /// Health checks an idle project is given to see traffic again before it is stopped
+    pub idle_grace_checks: u32,
+    /// Days the volume of a destroyed project is kept for
+    pub volume_retention_days: u64,
+    /// Seconds a container may stay `CREATED` while starting before it is recreated
+    pub created_timeout_secs: u64,
 }
 
 impl ContainerSettings {
     pub fn builder() -> ContainerSettingsBuilder {
         ContainerSettingsBuilder::new()
     }
+
+    /// Name of the volume holding the deployer state of a project
+    pub fn volume_name(&self, project_name: &ProjectName) -> String {
+        format!("{}{project_name}_vol", self.prefix)
+    }
 }
 
 pub struct GatewayContextProvider {
@@ -176,6 +433,7 @@ pub struct GatewayContextProvider {
     settings: ContainerSettings,
     api_key: String,
     auth_key_uri: Uri,
+    jwt: CachedJwt,
 }
 
 impl GatewayContextProvider {
@@ -190,6 +448,7 @@ impl GatewayContextProvider {
             settings,
             api_key,
             auth_key_uri,
+            jwt: Default::default(),
         }
     }
 
@@ -199,6 +458,7 @@ impl GatewayContextProvider {
             settings: self.settings.clone(),
             api_key: self.api_key.clone(),
             auth_key_uri: self.auth_key_uri.clone(),
+            jwt: self.jwt.clone(),
         }
     }
 }
@@ -212,6 +472,135 @@ pub struct GatewayService {
     // We store these because we'll need them for the health checks
     provisioner_host: Endpoint,
     auth_host: Uri,
+
+    /// Whether to add the active deployment id to routed responses
+    expose_deployment_id: bool,
+    /// Maximum number of live projects per non-admin account
+    project_quota: Option<u32>,
+    /// Days of validity left under which certificates are renewed
+    renewal_threshold_days: i64,
+    deployment_ids: Mutex<TtlCache<String, Option<Uuid>>>,
+    /// States of the projects requests are proxied to, when caching them is enabled
+    project_cache: Option<ProjectCache>,
+    /// How long requests routed to a project can take, unless the project sets its own timeout
+    route_timeout: Duration,
+    circuit_breakers: CircuitBreakers,
+    rate_limiters: RateLimiters,
+    /// How long requests are held while their project wakes up
+    cold_start_timeout: Duration,
+    /// Bounds on the requests held for each waking project
+    cold_start_queues: Mutex<HashMap<String, Arc<Semaphore>>>,
+    /// Days the sampled resource usage of projects is stored for, when it is stored at all
+    stats_retention_days: Option<u64>,
+    /// Held while certificates are requested or renewed, see
+    /// [GatewayService::lock_certificate_renewal]
+    certificate_renewal: Mutex<()>,
+}
+
+/// Address of a ready project to denormalize into the `target_ip` column, so it can be proxied to
+/// without parsing its whole state
+fn ready_target_ip(project: &Project) -> Option<String> {
+    project
+        .target_ip()
+        .ok()
+        .flatten()
+        .map(|target_ip| target_ip.to_string())
+}
+
+/// Domain the runtime of a ready project serves, to denormalize into the `runtime_fqdn` column
+fn ready_runtime_fqdn(project: &Project) -> Option<String> {
+    project
+        .is_ready()
+        .then(|| project.container())
+        .flatten()
+        .and_then(|container| container.fqdn().ok())
+        .map(|fqdn| fqdn.to_string())
+}
+
+/// Whether a routed request uploads a new deployment to the deployer of a project
+fn is_deployment_upload(req: &Request<Body>) -> bool {
+    let segments: Vec<_> = req.uri().path().trim_matches('/').split('/').collect();
+
+    req.method() == Method::POST && matches!(segments[..], ["projects", _, "services", _])
+}
+
+/// The response to a request refused for now, like when the circuit breaker of its project is open
+pub(crate) fn retry_later_response(kind: ErrorKind, retry_after: Duration) -> Response<Body> {
+    let error: ApiError = kind.into();
+
+    Response::builder()
+        .status(error.status())
+        .header(RETRY_AFTER, retry_after.as_secs().max(1))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::to_vec(&error).expect("error to serialize"),
+        ))
+        .expect("response to be valid")
+}
+
+/// A transaction started with `BEGIN IMMEDIATE`, which takes the write lock of the state database
+/// up front instead of on its first write. sqlx only starts deferred transactions, so this one is
+/// ended by hand. Dropped before it ends, like when its request is cancelled, the connection is
+/// closed, which rolls the transaction back, rather than handed back to the pool mid-transaction.
+struct ImmediateTransaction {
+    conn: Option<PoolConnection<Sqlite>>,
+}
+
+impl ImmediateTransaction {
+    async fn begin(pool: &SqlitePool) -> Result<Self, Error> {
+        let mut conn = pool.acquire().await?;
+        query("BEGIN IMMEDIATE").execute(&mut *conn).await?;
+
+        Ok(Self { conn: Some(conn) })
+    }
+
+    async fn commit(mut self) -> Result<(), Error> {
+        self.end("COMMIT").await
+    }
+
+    async fn rollback(mut self) -> Result<(), Error> {
+        self.end("ROLLBACK").await
+    }
+
+    async fn end(&mut self, statement: &str) -> Result<(), Error> {
+        query(statement).execute(&mut **self).await?;
+
+        // Out of the transaction, the connection can go back to the pool
+        self.conn.take();
+
+        Ok(())
+    }
+}
+
+impl Deref for ImmediateTransaction {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.conn.as_deref().expect("transaction to not be ended")
+    }
+}
+
+impl DerefMut for ImmediateTransaction {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.conn
+            .as_deref_mut()
+            .expect("transaction to not be ended")
+    }
+}
+
+impl Drop for ImmediateTransaction {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            drop(conn.detach());
+        }
+    }
+}
+
+/// Short-lived copies of the state of projects, sparing a database round-trip and a parse of
+/// the state on every proxied request
+struct ProjectCache {
+    ttl: Duration,
+    projects: Mutex<TtlCache<String, Project>>,
 }
 
 impl GatewayService {
@@ -231,7 +620,7 @@ impl GatewayService {
             format!("{}auth/key", args.auth_uri).parse().unwrap(),
         );
 
-        let task_router = TaskRouter::new();
+        let task_router = TaskRouter::with_limit(args.task_router_limit);
         Self {
             provider,
             db,
@@ -240,6 +629,21 @@ impl GatewayService {
             provisioner_host: Endpoint::new(format!("http://{}:8000", args.provisioner_host))
                 .expect("to have a valid provisioner endpoint"),
             auth_host: args.auth_uri,
+            expose_deployment_id: args.expose_deployment_id,
+            project_quota: args.project_quota,
+            renewal_threshold_days: args.renewal_threshold_days,
+            deployment_ids: Mutex::new(TtlCache::new(DEPLOYMENT_ID_CACHE_CAPACITY)),
+            project_cache: args.project_cache_ttl_secs.map(|ttl_secs| ProjectCache {
+                ttl: Duration::from_secs(ttl_secs),
+                projects: Mutex::new(TtlCache::new(PROJECT_CACHE_CAPACITY)),
+            }),
+            route_timeout: Duration::from_secs(args.route_timeout_secs),
+            circuit_breakers: CircuitBreakers::default(),
+            rate_limiters: RateLimiters::default(),
+            cold_start_timeout: Duration::from_secs(args.cold_start_timeout_secs),
+            cold_start_queues: Mutex::new(HashMap::new()),
+            stats_retention_days: args.stats_retention_days,
+            certificate_renewal: Mutex::new(()),
         }
     }
 
@@ -248,8 +652,17 @@ impl GatewayService {
         project: &Project,
         project_name: &ProjectName,
         account_name: &AccountName,
+        client_ip: Option<IpAddr>,
         mut req: Request<Body>,
     ) -> Result<Response<Body>, Error> {
+        if project.is_waking() {
+            debug!(%project_name, "project is still waking up");
+            return Ok(retry_later_response(
+                ErrorKind::ProjectNotReady,
+                COLD_START_RETRY_AFTER,
+            ));
+        }
+
         let target_ip = project
             .target_ip()?
             .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotReady))?;
@@ -258,61 +671,287 @@ impl GatewayService {
 
         debug!(target_url, "routing control");
 
-        let control_key = self.control_key_from_project_name(project_name).await?;
+        let control_key = match project.admin_secret() {
+            Some(control_key) => control_key,
+            None => self.control_key_from_project_name(project_name).await?,
+        };
 
         let headers = req.headers_mut();
+        // The control API is served over plain http
+        set_forwarding_headers(headers, "http");
         headers.typed_insert(XShuttleAccountName(account_name.to_string()));
-        headers.typed_insert(XShuttleAdminSecret(control_key));
+        headers.typed_insert(XShuttleAdminSecret(control_key.clone()));
 
         let cx = Span::current().context();
         global::get_text_map_propagator(|propagator| {
             propagator.inject_context(&cx, &mut HeaderInjector(headers))
         });
 
-        let resp = PROXY_CLIENT
-            .call(Ipv4Addr::LOCALHOST.into(), &target_url, req)
-            .await
-            .map_err(|_| Error::from_kind(ErrorKind::ProjectUnavailable))?;
+        let client_ip = client_ip.unwrap_or(Ipv4Addr::LOCALHOST.into());
+        let is_upload = is_deployment_upload(&req);
+        let call = async {
+            if upgrade::is_upgrade(&req) {
+                upgrade::forward(client_ip, &target_url, req).await
+            } else {
+                // The proxy client appends the client address to `X-Forwarded-For` itself
+                PROXY_CLIENT
+                    .call(client_ip, &target_url, req)
+                    .await
+                    .map_err(|_| Error::from_kind(ErrorKind::ProjectUnavailable))
+            }
+        };
+
+        // Uploading a deployment over a slow connection can take longer than any other request
+        let mut resp = if is_upload {
+            call.await?
+        } else {
+            timeout(self.route_timeout, call).await.map_err(|_| {
+                warn!(
+                    target_url,
+                    "project took too long to respond to routed request"
+                );
+                Error::from_kind(ErrorKind::ProjectTimedOut)
+            })??
+        };
+
+        if self.expose_deployment_id {
+            if let Some(deployment_id) = self
+                .active_deployment_id(project, project_name, &control_key)
+                .await
+            {
+                resp.headers_mut()
+                    .typed_insert(XShuttleDeploymentId(deployment_id.to_string()));
+            }
+        }
 
         Ok(resp)
     }
 
-    pub async fn iter_projects(
+    /// The active deployment of a project to tell clients about, when the gateway exposes it. Only
+    /// the project name is needed, for requests which were routed without reading the whole
+    /// project.
+    pub async fn exposed_deployment_id(&self, project_name: &ProjectName) -> Option<Uuid> {
+        if !self.expose_deployment_id {
+            return None;
+        }
+
+        if let Some(deployment_id) = self.deployment_ids.lock().await.get(project_name.as_str()) {
+            return *deployment_id;
+        }
+
+        let project = self.find_project_cached(project_name).await.ok()?;
+        let control_key = match project.admin_secret() {
+            Some(control_key) => control_key,
+            None => self
+                .control_key_from_project_name(project_name)
+                .await
+                .ok()?,
+        };
+
+        self.active_deployment_id(&project, project_name, &control_key)
+            .await
+    }
+
+    /// Get the active deployment of a project, remembering it for [DEPLOYMENT_ID_CACHE_TTL]
+    async fn active_deployment_id(
         &self,
-    ) -> Result<impl ExactSizeIterator<Item = (ProjectName, AccountName)>, Error> {
-        let iter = query("SELECT project_name, account_name FROM projects")
-            .fetch_all(&self.db)
-            .await?
-            .into_iter()
-            .map(|row| (row.get("project_name"), row.get("account_name")));
-        Ok(iter)
+        project: &Project,
+        project_name: &ProjectName,
+        control_key: &str,
+    ) -> Option<Uuid> {
+        let Project::Ready(ready) = project else {
+            return None;
+        };
+
+        if let Some(deployment_id) = self.deployment_ids.lock().await.get(project_name.as_str()) {
+            return *deployment_id;
+        }
+
+        let jwt = self.provider.context().get_jwt().await;
+        let deployment_id = match ready.active_deployment_id(&jwt, control_key).await {
+            Ok(deployment_id) => deployment_id,
+            Err(error) => {
+                warn!(error, %project_name, "failed to get the active deployment");
+                return None;
+            }
+        };
+
+        self.deployment_ids.lock().await.insert(
+            project_name.to_string(),
+            deployment_id,
+            DEPLOYMENT_ID_CACHE_TTL,
+        );
+
+        deployment_id
+    }
+
+    /// Stream the name and account of every project, which are read from the database as the
+    /// stream is consumed
+    pub fn iter_projects(
+        &self,
+    ) -> impl Stream<Item = Result<(ProjectName, AccountName), Error>> + Send + '_ {
+        query("SELECT project_name, account_name FROM projects")
+            .fetch(&self.db)
+            .map_ok(|row| (row.get("project_name"), row.get("account_name")))
+            .map_err(Error::from)
+    }
+
+    /// All of [GatewayService::iter_projects] at once
+    pub async fn collect_projects(&self) -> Result<Vec<(ProjectName, AccountName)>, Error> {
+        self.iter_projects().try_collect().await
     }
 
     pub async fn find_project(&self, project_name: &ProjectName) -> Result<Project, Error> {
-        query("SELECT project_state FROM projects WHERE project_name=?1")
+        self.find_project_with_version(project_name)
+            .await
+            .map(|(project, _)| project)
+    }
+
+    /// Same as [GatewayService::find_project], along with the version of the state to pass to
+    /// [GatewayService::update_project_if_unchanged]
+    pub async fn find_project_with_version(
+        &self,
+        project_name: &ProjectName,
+    ) -> Result<(Project, i64), Error> {
+        query("SELECT project_state, version FROM projects WHERE project_name=?1")
             .bind(project_name)
             .fetch_optional(&self.db)
             .await?
             .map(|r| {
-                r.try_get::<SqlxJson<Project>, _>("project_state")
-                    .unwrap()
-                    .0
+                (
+                    r.try_get::<SqlxJson<Project>, _>("project_state")
+                        .unwrap()
+                        .0,
+                    r.get("version"),
+                )
+            })
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))
+    }
+
+    /// Address of the container of a project when it is ready, read without parsing its whole
+    /// state. The project cache is used when it is enabled. `None` when the project is in any
+    /// other state, or its address was not recorded yet.
+    pub async fn find_ready_target(
+        &self,
+        project_name: &ProjectName,
+    ) -> Result<Option<IpAddr>, Error> {
+        if let Some(cache) = &self.project_cache {
+            if let Some(project) = cache.projects.lock().await.get(project_name.as_str()) {
+                return project.target_ip();
+            }
+        }
+
+        let row = query("SELECT state_kind, target_ip FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))?;
+
+        if row.get::<Option<String>, _>("state_kind").as_deref() != Some("ready") {
+            return Ok(None);
+        }
+
+        Ok(row
+            .get::<Option<String>, _>("target_ip")
+            .and_then(|target_ip| target_ip.parse().ok()))
+    }
+
+    /// Domain the runtime of a ready project serves, read without parsing its whole state. The
+    /// project cache is used when it is enabled. `None` when the project is in any other state.
+    pub async fn find_runtime_fqdn(
+        &self,
+        project_name: &ProjectName,
+    ) -> Result<Option<FQDN>, Error> {
+        if let Some(cache) = &self.project_cache {
+            if let Some(project) = cache.projects.lock().await.get(project_name.as_str()) {
+                return Ok(ready_runtime_fqdn(project).and_then(|fqdn| fqdn.parse().ok()));
+            }
+        }
+
+        query("SELECT runtime_fqdn FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .map(|row| {
+                row.get::<Option<String>, _>("runtime_fqdn")
+                    .and_then(|fqdn| fqdn.parse().ok())
             })
             .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))
     }
 
+    /// Same as [GatewayService::find_project], but the state may come from the project cache
+    /// when it is enabled. The state can then be up to the cache's TTL old, unless it was changed
+    /// through [GatewayService::update_project].
+    pub async fn find_project_cached(&self, project_name: &ProjectName) -> Result<Project, Error> {
+        let Some(cache) = &self.project_cache else {
+            return self.find_project(project_name).await;
+        };
+
+        if let Some(project) = cache.projects.lock().await.get(project_name.as_str()) {
+            return Ok(project.clone());
+        }
+
+        let project = self.find_project(project_name).await?;
+        cache
+            .projects
+            .lock()
+            .await
+            .insert(project_name.to_string(), project.clone(), cache.ttl);
+
+        Ok(project)
+    }
+
+    /// Drop the cached state of a project, if any
+    async fn invalidate_cached_project(&self, project_name: &ProjectName) {
+        if let Some(cache) = &self.project_cache {
+            cache.projects.lock().await.remove(project_name.as_str());
+        }
+    }
+
+    /// How long requests routed to projects can take, unless the project sets its own timeout
+    pub fn route_timeout(&self) -> Duration {
+        self.route_timeout
+    }
+
+    /// The circuit breakers of the projects user requests are proxied to
+    pub fn circuit_breakers(&self) -> &CircuitBreakers {
+        &self.circuit_breakers
+    }
+
+    /// The rate limiters of the projects user requests are proxied to
+    pub fn rate_limiters(&self) -> &RateLimiters {
+        &self.rate_limiters
+    }
+
+    /// Drop what is kept in memory about the requests proxied to a project which is gone, be it
+    /// destroyed or renamed
+    fn forget_project(&self, project_name: &ProjectName) {
+        self.circuit_breakers.forget(project_name.as_str());
+        self.rate_limiters.forget(project_name.as_str());
+    }
+
+    /// Iterate over the projects of an account, only keeping those which have all of the `tags`
     pub async fn iter_user_projects_detailed(
         &self,
         account_name: &AccountName,
         offset: u32,
         limit: u32,
-    ) -> Result<impl Iterator<Item = (ProjectName, Project)>, Error> {
+        tags: &[String],
+    ) -> Result<impl Iterator<Item = UserProject>, Error> {
         let mut query = QueryBuilder::new(
-            "SELECT project_name, project_state FROM projects WHERE account_name = ",
+            "SELECT project_name, project_state, created_at, updated_at FROM projects WHERE account_name = ",
         );
 
+        query.push_bind(account_name);
+
+        for tag in tags {
+            query
+                .push(" AND EXISTS (SELECT 1 FROM json_each(tags) WHERE value = ")
+                .push_bind(tag)
+                .push(")");
+        }
+
         query
-            .push_bind(account_name)
             .push(" ORDER BY project_id DESC, project_name LIMIT ")
             .push_bind(limit);
 
@@ -325,15 +964,24 @@ impl GatewayService {
             .fetch_all(&self.db)
             .await?
             .into_iter()
-            .map(|row| {
-                (
-                    row.get("project_name"),
-                    row.get::<SqlxJson<Project>, _>("project_state").0,
-                )
-            });
+            .map(user_project_from_row);
         Ok(iter)
     }
 
+    /// Same as [GatewayService::find_project], along with when the project was created and last
+    /// updated
+    pub async fn find_user_project(
+        &self,
+        project_name: &ProjectName,
+    ) -> Result<UserProject, Error> {
+        query("SELECT project_name, project_state, created_at, updated_at FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .map(user_project_from_row)
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))
+    }
+
     pub async fn iter_user_projects_detailed_filtered(
         &self,
         account_name: AccountName,
@@ -355,788 +1003,4190 @@ impl GatewayService {
         Ok(iter)
     }
 
-    pub async fn update_project(
+    /// Replace the tags used to group a project. Projects are filtered by comma separated tags, so
+    /// a tag cannot be empty nor contain a comma.
+    pub async fn set_project_tags(
         &self,
         project_name: &ProjectName,
-        project: &Project,
+        tags: &[String],
     ) -> Result<(), Error> {
-        let query = match project {
-            Project::Creating(state) => query(
-                "UPDATE projects SET initial_key = ?1, project_state = ?2 WHERE project_name = ?3",
-            )
-            .bind(state.initial_key())
-            .bind(SqlxJson(project))
-            .bind(project_name),
-            _ => query("UPDATE projects SET project_state = ?1 WHERE project_name = ?2")
-                .bind(SqlxJson(project))
-                .bind(project_name),
-        };
-        query.execute(&self.db).await?;
-        Ok(())
+        if tags.iter().any(|tag| tag.is_empty() || tag.contains(',')) {
+            return Err(Error::custom(
+                ErrorKind::InvalidOperation,
+                "a tag cannot be empty nor contain a comma",
+            ));
+        }
+
+        let rows_affected =
+            query("UPDATE projects SET tags = ?1, updated_at = ?2 WHERE project_name = ?3")
+                .bind(SqlxJson(tags))
+                .bind(Utc::now().timestamp_millis())
+                .bind(project_name)
+                .execute(&self.db)
+                .await?
+                .rows_affected();
+
+        if rows_affected > 0 {
+            Ok(())
+        } else {
+            Err(Error::from_kind(ErrorKind::ProjectNotFound))
+        }
     }
 
-    pub async fn account_name_from_project(
+    /// The most recent state transitions of a project, oldest first
+    pub async fn project_transition_history(
         &self,
         project_name: &ProjectName,
-    ) -> Result<AccountName, Error> {
-        query("SELECT account_name FROM projects WHERE project_name = ?1")
+    ) -> Result<TransitionHistory, Error> {
+        query("SELECT transition_history FROM projects WHERE project_name = ?1")
             .bind(project_name)
             .fetch_optional(&self.db)
             .await?
-            .map(|row| row.get("account_name"))
-            .ok_or_else(|| Error::from(ErrorKind::ProjectNotFound))
+            .map(|row| {
+                row.get::<SqlxJson<TransitionHistory>, _>("transition_history")
+                    .0
+            })
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))
     }
 
-    pub async fn control_key_from_project_name(
-        &self,
-        project_name: &ProjectName,
-    ) -> Result<String, Error> {
-        let control_key = query("SELECT initial_key FROM projects WHERE project_name = ?1")
+    /// The tags used to group a project
+    pub async fn project_tags(&self, project_name: &ProjectName) -> Result<Vec<String>, Error> {
+        query("SELECT tags FROM projects WHERE project_name = ?1")
             .bind(project_name)
             .fetch_optional(&self.db)
             .await?
-            .map(|row| row.try_get("initial_key").unwrap())
-            .ok_or_else(|| Error::from(ErrorKind::ProjectNotFound))?;
-        Ok(control_key)
+            .map(|row| row.get::<SqlxJson<Vec<String>>, _>("tags").0)
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))
     }
 
-    pub async fn iter_user_projects(
+    /// Set how long the requests proxied to a project can take, or only keep the proxy's defaults
+    /// when `None`
+    pub async fn set_project_request_timeout(
         &self,
-        AccountName(account_name): &AccountName,
-    ) -> Result<impl Iterator<Item = ProjectName>, Error> {
-        let iter = query("SELECT project_name FROM projects WHERE account_name = ?1")
-            .bind(account_name)
-            .fetch_all(&self.db)
-            .await?
-            .into_iter()
-            .map(|row| row.try_get::<ProjectName, _>("project_name").unwrap());
-        Ok(iter)
-    }
+        project_name: &ProjectName,
+        timeout_secs: Option<u64>,
+    ) -> Result<(), Error> {
+        if timeout_secs == Some(0) {
+            return Err(Error::custom(
+                ErrorKind::InvalidOperation,
+                "the request timeout must be at least one second",
+            ));
+        }
 
-    pub async fn create_project(
-        &self,
-        project_name: ProjectName,
-        account_name: AccountName,
-        is_admin: bool,
-        idle_minutes: u64,
-    ) -> Result<Project, Error> {
-        if let Some(row) = query(
-            r#"
-        SELECT project_name, project_id, account_name, initial_key, project_state
-        FROM projects
-        WHERE (project_name = ?1)
-        AND (account_name = ?2 OR ?3)
-        "#,
+        let rows_affected = query(
+            "UPDATE projects SET request_timeout_secs = ?1, updated_at = ?2 WHERE project_name = ?3",
         )
-        .bind(&project_name)
-        .bind(&account_name)
-        .bind(is_admin)
-        .fetch_optional(&self.db)
+        .bind(timeout_secs.map(|secs| secs as i64))
+        .bind(Utc::now().timestamp_millis())
+        .bind(project_name)
+        .execute(&self.db)
         .await?
-        {
-            // If the project already exists and belongs to this account
-            let project = row.get::<SqlxJson<Project>, _>("project_state").0;
-            let project_id = row.get::<String, _>("project_id");
-            if project.is_destroyed() {
-                // But is in `::Destroyed` state, recreate it
-                let mut creating = ProjectCreating::new_with_random_initial_key(
-                    project_name.clone(),
-                    idle_minutes,
-                );
-                // Restore previous custom domain, if any
-                match self.find_custom_domain_for_project(&project_id).await {
-                    Ok(custom_domain) => {
-                        creating = creating.with_fqdn(custom_domain.fqdn.to_string());
-                    }
-                    Err(error) if error.kind() == ErrorKind::CustomDomainNotFound => {
-                        // no previous custom domain
-                    }
-                    Err(error) => return Err(error),
-                }
-                let project = Project::Creating(creating);
-                self.update_project(&project_name, &project).await?;
-                Ok(project)
-            } else {
-                // Otherwise it already exists
-                Err(Error::from_kind(ErrorKind::ProjectAlreadyExists))
-            }
+        .rows_affected();
+
+        if rows_affected > 0 {
+            Ok(())
         } else {
-            // Check if project name is valid according to new rules if it
-            // doesn't exist.
-            // TODO: remove this check when we update the project name rules
-            // in shuttle-common
-            if project_name.is_valid() {
-                // Otherwise attempt to create a new one. This will fail
-                // outright if the project already exists (this happens if
-                // it belongs to another account).
-                self.insert_project(project_name, account_name, idle_minutes)
-                    .await
-            } else {
-                Err(Error::from_kind(ErrorKind::InvalidProjectName))
-            }
+            Err(Error::from_kind(ErrorKind::ProjectNotFound))
         }
     }
 
-    pub async fn insert_project(
+    pub async fn project_request_timeout(
         &self,
-        project_name: ProjectName,
-        account_name: AccountName,
-        idle_minutes: u64,
-    ) -> Result<Project, Error> {
-        let project = SqlxJson(Project::Creating(
-            ProjectCreating::new_with_random_initial_key(project_name.clone(), idle_minutes),
-        ));
+        project_name: &ProjectName,
+    ) -> Result<Option<Duration>, Error> {
+        query("SELECT request_timeout_secs FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .map(|row| {
+                row.get::<Option<i64>, _>("request_timeout_secs")
+                    .map(|secs| Duration::from_secs(secs as u64))
+            })
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))
+    }
 
-        query("INSERT INTO projects (project_id, project_name, account_name, initial_key, project_state) VALUES (ulid(), ?1, ?2, ?3, ?4)")
-            .bind(&project_name)
-            .bind(&account_name)
-            .bind(project.initial_key().unwrap())
-            .bind(&project)
-            .execute(&self.db)
-            .await
-            .map_err(|err| {
-                // If the error is a broken PK constraint, this is a
-                // project name clash
-                if let Some(db_err_code) = err.as_database_error().and_then(DatabaseError::code) {
-                    if db_err_code == "2067" {  // SQLITE_CONSTRAINT_UNIQUE
-                        return Error::from_kind(ErrorKind::ProjectAlreadyExists)
-                    }
-                }
-                // Otherwise this is internal
-                err.into()
-            })?;
+    /// Set how many times the failed builds of a project are retried, or leave it to the
+    /// deployer's default when `None`
+    pub async fn set_project_max_build_retries(
+        &self,
+        project_name: &ProjectName,
+        max_build_retries: Option<u32>,
+    ) -> Result<(), Error> {
+        if matches!(max_build_retries, Some(retries) if retries > MAX_BUILD_RETRIES) {
+            return Err(Error::custom(
+                ErrorKind::InvalidOperation,
+                format!("builds can be retried at most {MAX_BUILD_RETRIES} times"),
+            ));
+        }
 
-        let project = project.0;
+        let rows_affected = query(
+            "UPDATE projects SET max_build_retries = ?1, updated_at = ?2 WHERE project_name = ?3",
+        )
+        .bind(max_build_retries)
+        .bind(Utc::now().timestamp_millis())
+        .bind(project_name)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
 
-        Ok(project)
+        if rows_affected > 0 {
+            Ok(())
+        } else {
+            Err(Error::from_kind(ErrorKind::ProjectNotFound))
+        }
     }
 
-    pub async fn create_custom_domain(
+    pub async fn project_max_build_retries(
         &self,
         project_name: &ProjectName,
-        fqdn: &Fqdn,
-        certs: &str,
-        private_key: &str,
-    ) -> Result<(), Error> {
-        let project_id = query("SELECT project_id FROM projects WHERE project_name = ?1")
+    ) -> Result<Option<u32>, Error> {
+        query("SELECT max_build_retries FROM projects WHERE project_name = ?1")
             .bind(project_name)
-            .fetch_one(&self.db)
+            .fetch_optional(&self.db)
             .await?
-            .get::<String, _>("project_id");
+            .map(|row| row.get::<Option<u32>, _>("max_build_retries"))
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))
+    }
 
-        query("INSERT OR REPLACE INTO custom_domains (fqdn, project_id, certificate, private_key) VALUES (?1, ?2, ?3, ?4)")
-            .bind(fqdn.to_string())
-            .bind(project_id)
-            .bind(certs)
-            .bind(private_key)
-            .execute(&self.db)
-            .await?;
+    /// Limit the rate of requests routed to a project
+    pub async fn set_project_rate_limit(
+        &self,
+        project_name: &ProjectName,
+        rate_limit: &RateLimit,
+    ) -> Result<(), Error> {
+        let message = match rate_limit {
+            RateLimit {
+                requests_per_second: Some(0),
+                ..
+            } => Some("the rate limit must be at least one request a second"),
+            RateLimit { burst: Some(0), .. } => Some("the burst must be at least one request"),
+            RateLimit {
+                requests_per_second: None,
+                burst: Some(_),
+            } => Some("a burst can only be set along with a rate"),
+            _ => None,
+        };
+        if let Some(message) = message {
+            return Err(Error::custom(ErrorKind::InvalidOperation, message));
+        }
 
-        Ok(())
-    }
+        let rows_affected = query(
+            "UPDATE projects SET rate_limit_per_second = ?1, rate_limit_burst = ?2, updated_at = ?3 WHERE project_name = ?4",
+        )
+        .bind(rate_limit.requests_per_second)
+        .bind(rate_limit.burst)
+        .bind(Utc::now().timestamp_millis())
+        .bind(project_name)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
 
-    pub async fn iter_custom_domains(&self) -> Result<impl Iterator<Item = CustomDomain>, Error> {
-        query("SELECT fqdn, project_name, certificate, private_key FROM custom_domains AS cd JOIN projects AS p ON cd.project_id = p.project_id")
-            .fetch_all(&self.db)
-            .await
-            .map(|res| {
-                res.into_iter().map(|row| CustomDomain {
-                    fqdn: row.get::<&str, _>("fqdn").parse().unwrap(),
-                    project_name: row.try_get("project_name").unwrap(),
-                    certificate: row.get("certificate"),
-                    private_key: row.get("private_key"),
-                })
-            })
-            .map_err(|_| Error::from_kind(ErrorKind::Internal))
+        if rows_affected > 0 {
+            self.rate_limiters
+                .set_limit(project_name.as_str(), *rate_limit);
+            Ok(())
+        } else {
+            Err(Error::from_kind(ErrorKind::ProjectNotFound))
+        }
     }
 
-    async fn find_custom_domain_for_project(
-        &self,
-        project_id: &str,
-    ) -> Result<CustomDomain, Error> {
-        let custom_domain = query(
-            "SELECT fqdn, project_name, certificate, private_key FROM custom_domains AS cd JOIN projects AS p ON cd.project_id = p.project_id WHERE p.project_id = ?1",
+    /// The rate limit of a project. It is only read from the database the first time, as it can
+    /// only change through [GatewayService::set_project_rate_limit].
+    pub async fn project_rate_limit(&self, project_name: &ProjectName) -> Result<RateLimit, Error> {
+        if let Some(rate_limit) = self.rate_limiters.limit(project_name.as_str()) {
+            return Ok(rate_limit);
+        }
+
+        let rate_limit = query(
+            "SELECT rate_limit_per_second, rate_limit_burst FROM projects WHERE project_name = ?1",
         )
-        .bind(project_id)
+        .bind(project_name)
         .fetch_optional(&self.db)
         .await?
-        .map(|row| CustomDomain {
-            fqdn: row.get::<&str, _>("fqdn").parse().unwrap(),
-            project_name: row.try_get("project_name").unwrap(),
-            certificate: row.get("certificate"),
-            private_key: row.get("private_key"),
+        .map(|row| RateLimit {
+            requests_per_second: row.get("rate_limit_per_second"),
+            burst: row.get("rate_limit_burst"),
         })
-        .ok_or_else(|| Error::from(ErrorKind::CustomDomainNotFound))?;
-        Ok(custom_domain)
+        .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))?;
+
+        self.rate_limiters
+            .set_limit(project_name.as_str(), rate_limit);
+
+        Ok(rate_limit)
     }
 
-    pub async fn project_details_for_custom_domain(
+    pub async fn update_project(
         &self,
-        fqdn: &Fqdn,
-    ) -> Result<CustomDomain, Error> {
-        let custom_domain = query(
-            "SELECT fqdn, project_name, certificate, private_key FROM custom_domains AS cd JOIN projects AS p ON cd.project_id = p.project_id WHERE fqdn = ?1",
-        )
-        .bind(fqdn.to_string())
-        .fetch_optional(&self.db)
-        .await?
-        .map(|row| CustomDomain {
-            fqdn: row.get::<&str, _>("fqdn").parse().unwrap(),
-            project_name: row.try_get("project_name").unwrap(),
-            certificate: row.get("certificate"),
-            private_key: row.get("private_key"),
-        })
-        .ok_or_else(|| Error::from(ErrorKind::CustomDomainNotFound))?;
-        Ok(custom_domain)
+        project_name: &ProjectName,
+        project: &Project,
+    ) -> Result<(), Error> {
+        self.write_project(project_name, project, None).await
     }
 
-    pub async fn iter_projects_detailed(
+    /// Same as [GatewayService::update_project], but only when the stored state is still at
+    /// `version`. Fails with [ErrorKind::ProjectConflict] when it was written since, so a change
+    /// based on a stale state never clobbers a newer one and can be retried instead.
+    pub async fn update_project_if_unchanged(
         &self,
-    ) -> Result<impl Iterator<Item = ProjectDetails>, Error> {
-        let iter = query("SELECT project_name, account_name FROM projects")
-            .fetch_all(&self.db)
-            .await?
-            .into_iter()
-            .map(|row| ProjectDetails {
-                project_name: row.try_get("project_name").unwrap(),
-                account_name: row.try_get("account_name").unwrap(),
-            });
-        Ok(iter)
+        project_name: &ProjectName,
+        project: &Project,
+        version: i64,
+    ) -> Result<(), Error> {
+        self.write_project(project_name, project, Some(version))
+            .await
     }
 
-    /// Returns the current certificate as a pair of the chain and private key.
-    /// If the pair doesn't exist for a specific project, create both the certificate
-    /// and the custom domain it will represent.
-    pub async fn create_custom_domain_certificate(
+    async fn write_project(
         &self,
-        fqdn: &Fqdn,
-        acme_client: &AcmeClient,
         project_name: &ProjectName,
-        creds: AccountCredentials<'_>,
-    ) -> Result<(String, String), Error> {
-        match self.project_details_for_custom_domain(fqdn).await {
-            Ok(CustomDomain {
-                certificate,
-                private_key,
-                ..
-            }) => Ok((certificate, private_key)),
-            Err(err) if err.kind() == ErrorKind::CustomDomainNotFound => {
-                let (certs, private_key) = acme_client
-                    .create_certificate(&fqdn.to_string(), ChallengeType::Http01, creds)
-                    .await?;
-                self.create_custom_domain(project_name, fqdn, &certs, &private_key)
-                    .await?;
-                Ok((certs, private_key))
-            }
-            Err(err) => Err(err),
+        project: &Project,
+        expected_version: Option<i64>,
+    ) -> Result<(), Error> {
+        let mut tx = self.db.begin().await?;
+        self.store_project(&mut tx, project_name, project, expected_version)
+            .await?;
+        tx.commit().await?;
+
+        self.invalidate_cached_project(project_name).await;
+
+        if project.is_destroyed() {
+            self.forget_project(project_name);
+
+            // The retention window, as a unix timestamp, starts the first time the project is seen
+            // destroyed
+            let retained_until = Utc::now()
+                + chrono::Duration::days(
+                    self.context().container_settings().volume_retention_days as i64,
+                );
+            query(
+                "UPDATE projects SET volume_retained_until = COALESCE(volume_retained_until, ?1) WHERE project_name = ?2",
+            )
+            .bind(retained_until.timestamp())
+            .bind(project_name)
+            .execute(&self.db)
+            .await?;
+
+            // The usage history goes with the project, a recreated one starts afresh
+            query(
+                "DELETE FROM project_stats WHERE project_id = (SELECT project_id FROM projects WHERE project_name = ?1)",
+            )
+            .bind(project_name)
+            .execute(&self.db)
+            .await?;
         }
+
+        Ok(())
     }
 
-    async fn create_certificate<'a>(
+    /// Write the state of a project, along with its latest resource usage sample when those are
+    /// stored, on a connection the caller has started a transaction on
+    async fn store_project(
         &self,
-        acme: &AcmeClient,
-        creds: AccountCredentials<'a>,
-    ) -> ChainAndPrivateKey {
-        let public: FQDN = self.context().settings.fqdn.parse().unwrap();
-        let identifier = format!("*.{public}");
+        conn: &mut SqliteConnection,
+        project_name: &ProjectName,
+        project: &Project,
+        expected_version: Option<i64>,
+    ) -> Result<(), Error> {
+        // Record the transition from the stored state, if any
+        let mut history = TransitionHistory::default();
+        let mut previous_sample = None;
+        if let Some(row) =
+            query("SELECT project_state, transition_history FROM projects WHERE project_name = ?1")
+                .bind(project_name)
+                .fetch_optional(&mut *conn)
+                .await?
+        {
+            let previous = row.get::<SqlxJson<Project>, _>("project_state").0;
+            history = row
+                .get::<SqlxJson<TransitionHistory>, _>("transition_history")
+                .0;
+            history.record(previous.state(), project.state());
+            previous_sample = previous.stats().pop();
+        }
 
-        // Use ::Dns01 challenge because that's the only supported
-        // challenge type for wildcard domains.
-        let (chain, private_key) = acme
-            .create_certificate(&identifier, ChallengeType::Dns01, creds)
-            .await
-            .unwrap();
+        // Keep the stored key in sync with the one the project is currently using
+        let updated_at = Utc::now().timestamp_millis();
+        let update = match project.admin_secret() {
+            Some(admin_secret) => query(
+                "UPDATE projects SET initial_key = ?1, project_state = ?2, transition_history = ?3, updated_at = ?4, state_kind = ?5, target_ip = ?6, runtime_fqdn = ?7, version = version + 1 WHERE project_name = ?8 AND (?9 IS NULL OR version = ?9)",
+            )
+            .bind(admin_secret)
+            .bind(SqlxJson(project))
+            .bind(SqlxJson(&history))
+            .bind(updated_at)
+            .bind(project.kind())
+            .bind(ready_target_ip(project))
+            .bind(ready_runtime_fqdn(project))
+            .bind(project_name)
+            .bind(expected_version),
+            None => query(
+                "UPDATE projects SET project_state = ?1, transition_history = ?2, updated_at = ?3, state_kind = ?4, target_ip = ?5, runtime_fqdn = ?6, version = version + 1 WHERE project_name = ?7 AND (?8 IS NULL OR version = ?8)",
+            )
+            .bind(SqlxJson(project))
+            .bind(SqlxJson(&history))
+            .bind(updated_at)
+            .bind(project.kind())
+            .bind(ready_target_ip(project))
+            .bind(ready_runtime_fqdn(project))
+            .bind(project_name)
+            .bind(expected_version),
+        };
+        let updated = update.execute(&mut *conn).await?.rows_affected();
 
-        let mut buf = Vec::new();
-        buf.extend(chain.as_bytes());
-        buf.extend(private_key.as_bytes());
+        if updated == 0 && expected_version.is_some() {
+            debug!(%project_name, "refusing to write a stale project state");
+            return Err(Error::from_kind(ErrorKind::ProjectConflict));
+        }
+
+        // A new sample is taken at most once per health check of the project
+        let sample = project
+            .stats()
+            .pop()
+            .filter(|sample| Some(sample) != previous_sample.as_ref());
+        if let (Some(sample), Some(_)) = (sample, self.stats_retention_days) {
+            query(
+                "INSERT INTO project_stats (project_id, cpu_usage, memory_usage, memory_limit, sampled_at)
+                SELECT project_id, ?2, ?3, ?4, ?5 FROM projects WHERE project_name = ?1",
+            )
+            .bind(project_name)
+            .bind(sample.cpu_usage as i64)
+            .bind(sample.memory_usage.map(|usage| usage as i64))
+            .bind(sample.memory_limit.map(|limit| limit as i64))
+            .bind(sample.timestamp.timestamp_millis())
+            .execute(&mut *conn)
+            .await?;
+        }
 
-        ChainAndPrivateKey::parse_pem(Cursor::new(buf)).expect("Malformed PEM buffer.")
+        Ok(())
     }
 
-    /// Fetch the gateway certificate from the state location.
-    /// If not existent, create the gateway certificate and save it to the
-    /// gateway state.
-    pub async fn fetch_certificate(
-        &self,
-        acme: &AcmeClient,
-        creds: AccountCredentials<'_>,
-    ) -> ChainAndPrivateKey {
-        let tls_path = self.state_location.join("ssl.pem");
-        match ChainAndPrivateKey::load_pem(&tls_path) {
-            Ok(valid) => valid,
-            Err(_) => {
-                warn!(
-                    "no valid certificate found at {}, creating one...",
-                    tls_path.display()
-                );
+    /// Remove the volumes of the projects which have been destroyed for longer than the retention
+    /// window. Returns the number of volumes removed.
+    pub async fn reap_expired_volumes(&self) -> Result<usize, Error> {
+        let expired: Vec<ProjectName> =
+            query("SELECT project_name FROM projects WHERE volume_retained_until < ?1")
+                .bind(Utc::now().timestamp())
+                .fetch_all(&self.db)
+                .await?
+                .into_iter()
+                .map(|row| row.get("project_name"))
+                .collect();
 
-                let certs = self.create_certificate(acme, creds).await;
-                certs.clone().save_pem(&tls_path).unwrap();
-                certs
-            }
+        for project_name in &expired {
+            let project = self.find_project(project_name).await?;
+            self.remove_project_volume(&self.db, project_name, &project)
+                .await?;
+        }
+
+        Ok(expired.len())
+    }
+
+    /// Remove the stored resource usage samples which are older than the retention window. Returns
+    /// the number of samples removed.
+    pub async fn reap_expired_stats(&self) -> Result<u64, Error> {
+        let Some(retention_days) = self.stats_retention_days else {
+            return Ok(0);
+        };
+
+        let expired_before = Utc::now() - chrono::Duration::days(retention_days as i64);
+        let removed = query("DELETE FROM project_stats WHERE sampled_at < ?1")
+            .bind(expired_before.timestamp_millis())
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+
+        Ok(removed)
+    }
+
+    /// Get the stored resource usage samples of a project taken since `since`, oldest first
+    pub async fn project_stats_history(
+        &self,
+        project_name: &ProjectName,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<StatsSample>, Error> {
+        if self.stats_retention_days.is_none() {
+            return Err(Error::custom(
+                ErrorKind::InvalidOperation,
+                "the resource usage of projects is not stored on this gateway",
+            ));
         }
+
+        let project_id: String = query("SELECT project_id FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .map(|row| row.get("project_id"))
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))?;
+
+        let samples = query(
+            "SELECT cpu_usage, memory_usage, memory_limit, sampled_at FROM project_stats WHERE project_id = ?1 AND sampled_at >= ?2 ORDER BY sampled_at",
+        )
+        .bind(project_id)
+        .bind(since.timestamp_millis())
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|row| StatsSample {
+            timestamp: Utc
+                .timestamp_millis_opt(row.get("sampled_at"))
+                .single()
+                .unwrap_or_default(),
+            cpu_usage: row.get::<i64, _>("cpu_usage") as u64,
+            memory_usage: row
+                .get::<Option<i64>, _>("memory_usage")
+                .map(|usage| usage as u64),
+            memory_limit: row
+                .get::<Option<i64>, _>("memory_limit")
+                .map(|limit| limit as u64),
+        })
+        .collect();
+
+        Ok(samples)
     }
 
-    /// Renew the gateway certificate if there less than 30 days until the current
-    /// certificate expiration.
-    pub(crate) async fn renew_certificate(
-        &self,
-        acme: &AcmeClient,
-        resolver: Arc<GatewayCertResolver>,
-        creds: AccountCredentials<'_>,
-    ) {
-        let account = AccountWrapper::from(creds).0;
-        let certs = self.fetch_certificate(acme, account.credentials()).await;
-        // Safe to unwrap because a 'ChainAndPrivateKey' is built from a PEM.
-        let chain_and_pk = certs.into_pem().unwrap();
+    /// Remove the volume of a destroyed project, which also ends its retention window
+    async fn remove_project_volume<'c, E>(
+        &self,
+        executor: E,
+        project_name: &ProjectName,
+        project: &Project,
+    ) -> Result<(), Error>
+    where
+        E: sqlx::Executor<'c, Database = Sqlite>,
+    {
+        // A renamed project keeps the volume of its previous name
+        let volume_name = match project {
+            Project::Destroyed(destroyed) => destroyed
+                .container()
+                .and_then(|container| container.volume_name()),
+            _ => None,
+        }
+        .unwrap_or_else(|| {
+            self.context()
+                .container_settings()
+                .volume_name(project_name)
+        });
+
+        match self
+            .context()
+            .docker()
+            .remove_volume(&volume_name, None)
+            .await
+        {
+            Ok(())
+            | Err(DockerError::DockerResponseServerError {
+                status_code: 404, ..
+            }) => {
+                debug!(%project_name, %volume_name, "removed the volume of a destroyed project");
+            }
+            Err(err) => return Err(Error::source(ErrorKind::Internal, err)),
+        }
+
+        query("UPDATE projects SET volume_retained_until = NULL WHERE project_name = ?1")
+            .bind(project_name)
+            .execute(executor)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Count one more restart of the project, this count is never reset
+    pub async fn increment_total_restarts(&self, project_name: &ProjectName) -> Result<(), Error> {
+        query("UPDATE projects SET total_restarts = total_restarts + 1 WHERE project_name = ?1")
+            .bind(project_name)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn account_name_from_project(
+        &self,
+        project_name: &ProjectName,
+    ) -> Result<AccountName, Error> {
+        query("SELECT account_name FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .map(|row| row.get("account_name"))
+            .ok_or_else(|| Error::from(ErrorKind::ProjectNotFound))
+    }
+
+    /// Move a project to another account. The account must be known to the auth service.
+    pub async fn transfer_project(
+        &self,
+        project_name: &ProjectName,
+        new_account: &AccountName,
+    ) -> Result<(), Error> {
+        self.verify_account_exists(new_account).await?;
+
+        let mut tx = self.db.begin().await?;
+
+        let previous_account: AccountName =
+            query("SELECT account_name FROM projects WHERE project_name = ?1")
+                .bind(project_name)
+                .fetch_optional(&mut *tx)
+                .await?
+                .map(|row| row.get("account_name"))
+                .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))?;
+
+        query("UPDATE projects SET account_name = ?1, updated_at = ?2 WHERE project_name = ?3")
+            .bind(new_account)
+            .bind(Utc::now().timestamp_millis())
+            .bind(project_name)
+            .execute(&mut *tx)
+            .await?;
+
+        Self::insert_audit_entry(
+            &mut *tx,
+            project_name,
+            new_account,
+            AuditAction::Transfer,
+            Some(format!("from {previous_account}")),
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        debug!(%project_name, %previous_account, %new_account, "transferred project");
+
+        Ok(())
+    }
+
+    /// Rename a project, which is the name its container is labelled with and the default fqdn it
+    /// is reached at. The container is removed and the project is created again under the new
+    /// name, still mounting the volume of the old name. The deployer renames its service after
+    /// the project when it starts, which keeps its deployments, secrets and resources. Databases
+    /// of the provisioner are named after the project however, so they are not carried over.
+    pub async fn rename_project(
+        &self,
+        project_name: &ProjectName,
+        new_name: &ProjectName,
+    ) -> Result<Project, Error> {
+        if !new_name.is_valid() {
+            return Err(Error::from_kind(ErrorKind::InvalidProjectName));
+        }
+
+        let row = query("SELECT project_id, project_state FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))?;
+        let project_id = row.get::<String, _>("project_id");
+        let project = row.get::<SqlxJson<Project>, _>("project_state").0;
+
+        let volume_name = self
+            .context()
+            .container_settings()
+            .volume_name(project_name);
+        let mut creating = match &project {
+            Project::Creating(creating) => creating.clone(),
+            other => {
+                let container = other.container().ok_or_else(|| {
+                    Error::custom(
+                        ErrorKind::InvalidOperation,
+                        "only a project with a container can be renamed",
+                    )
+                })?;
+                let default_idle_minutes = self.context().container_settings().default_idle_minutes;
+                ProjectCreating::from_container(container, 0, default_idle_minutes)?
+            }
+        }
+        .renamed(new_name.clone(), volume_name);
+        // Keep the project reached at its custom domain, if it has one
+        if let Some(custom_domain) = self.find_primary_custom_domain(project_name).await? {
+            creating = creating.with_fqdn(custom_domain.fqdn.to_string());
+        }
+        let renamed = Project::Creating(creating);
+
+        query("UPDATE projects SET project_name = ?1, initial_key = ?2, project_state = ?3, updated_at = ?4, state_kind = ?5, target_ip = NULL, runtime_fqdn = NULL, version = version + 1 WHERE project_id = ?6")
+            .bind(new_name)
+            .bind(renamed.initial_key().unwrap())
+            .bind(SqlxJson(&renamed))
+            .bind(Utc::now().timestamp_millis())
+            .bind(renamed.kind())
+            .bind(&project_id)
+            .execute(&self.db)
+            .await
+            .map_err(|err| {
+                // The new name is already taken by another project
+                if let Some(db_err_code) = err.as_database_error().and_then(DatabaseError::code) {
+                    if db_err_code == "2067" {  // SQLITE_CONSTRAINT_UNIQUE
+                        return Error::from_kind(ErrorKind::ProjectAlreadyExists)
+                    }
+                }
+                err.into()
+            })?;
+
+        self.deployment_ids
+            .lock()
+            .await
+            .remove(project_name.as_str());
+        self.invalidate_cached_project(project_name).await;
+        self.forget_project(project_name);
+
+        let account_name = self.account_name_from_project(new_name).await?;
+        self.record_audit(
+            new_name,
+            &account_name,
+            AuditAction::Rename,
+            Some(format!("from {project_name}")),
+        )
+        .await?;
+
+        // The labels and arguments of a container cannot be changed, so the old one goes away
+        if let Ok(Project::Errored(error)) = project.destroy()?.next(&self.context()).await {
+            warn!(%project_name, ?error, "failed to remove the container of the renamed project");
+        }
+
+        debug!(%project_name, %new_name, "renamed project");
+
+        Ok(renamed)
+    }
+
+    /// Ask the auth service whether an account exists
+    async fn verify_account_exists(&self, account_name: &AccountName) -> Result<(), Error> {
+        let req = Request::builder()
+            .uri(format!("{}users/{}", self.auth_host, account_name))
+            .header(
+                AUTHORIZATION,
+                format!("Bearer {}", self.context().get_jwt().await),
+            )
+            .body(Body::empty())
+            .unwrap();
+
+        let resp = timeout(IS_HEALTHY_TIMEOUT, AUTH_CLIENT.request(req))
+            .await
+            .map_err(|_| Error::from_kind(ErrorKind::Internal))?
+            .map_err(|err| Error::source(ErrorKind::Internal, err))?;
+
+        match resp.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::NOT_FOUND => Err(Error::from_kind(ErrorKind::UserNotFound)),
+            status => Err(Error::custom(
+                ErrorKind::Internal,
+                format!("unexpected response from the auth service: {status}"),
+            )),
+        }
+    }
+
+    pub async fn control_key_from_project_name(
+        &self,
+        project_name: &ProjectName,
+    ) -> Result<String, Error> {
+        let control_key = query("SELECT initial_key FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .map(|row| row.try_get("initial_key").unwrap())
+            .ok_or_else(|| Error::from(ErrorKind::ProjectNotFound))?;
+        Ok(control_key)
+    }
+
+    pub async fn iter_user_projects(
+        &self,
+        AccountName(account_name): &AccountName,
+    ) -> Result<impl Iterator<Item = ProjectName>, Error> {
+        let iter = query("SELECT project_name FROM projects WHERE account_name = ?1")
+            .bind(account_name)
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(|row| row.try_get::<ProjectName, _>("project_name").unwrap());
+        Ok(iter)
+    }
+
+    /// Count the projects of an account, without loading them. Destroyed projects are only counted
+    /// when `include_destroyed` is set.
+    pub async fn count_projects_for_account(
+        &self,
+        account_name: &AccountName,
+        include_destroyed: bool,
+    ) -> Result<u32, Error> {
+        Self::count_account_projects(&self.db, account_name, include_destroyed).await
+    }
+
+    async fn count_account_projects<'c, E>(
+        executor: E,
+        account_name: &AccountName,
+        include_destroyed: bool,
+    ) -> Result<u32, Error>
+    where
+        E: sqlx::Executor<'c, Database = Sqlite>,
+    {
+        let count = query(
+            "SELECT COUNT(*) AS count FROM projects WHERE account_name = ?1 AND (?2 OR json_type(project_state, '$.destroyed') IS NULL)",
+        )
+        .bind(account_name)
+        .bind(include_destroyed)
+        .fetch_one(executor)
+        .await?
+        .get("count");
+
+        Ok(count)
+    }
+
+    /// Check that the account can have one more project. Admins bypass the quota.
+    async fn check_project_quota(
+        &self,
+        conn: &mut SqliteConnection,
+        account_name: &AccountName,
+        is_admin: bool,
+    ) -> Result<(), Error> {
+        let Some(quota) = self.project_quota else {
+            return Ok(());
+        };
+
+        if is_admin {
+            return Ok(());
+        }
+
+        let count = Self::count_account_projects(conn, account_name, false).await?;
+        if count >= quota {
+            return Err(Error::custom(
+                ErrorKind::QuotaExceeded,
+                format!("account has {count} projects, which reaches its quota of {quota}"),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Create a project, or recreate it if it was destroyed.
+    ///
+    /// A project recreated within the volume retention window mounts the volume of its previous
+    /// container again, so the deployer picks its secrets, resources and deployments back up.
+    /// Past the window, the project starts over with a fresh volume. Databases are provisioned by project
+    /// name and are linked again as is. The custom domain and the network settings of the previous
+    /// container are restored here, but a new admin secret is generated.
+    ///
+    /// Projects created without `idle_minutes` get the default idle minutes of the gateway.
+    ///
+    /// Creating or recreating a project fails with [ErrorKind::QuotaExceeded] when the account
+    /// already has as many projects as the project quota allows.
+    pub async fn create_project(
+        &self,
+        project_name: ProjectName,
+        account_name: AccountName,
+        is_admin: bool,
+        options: ProjectOptions,
+    ) -> Result<Project, Error> {
+        // The gateway has to join the network of an isolated project to route to it
+        if options.isolated_network
+            && self
+                .context()
+                .container_settings()
+                .gateway_container
+                .is_none()
+        {
+            return Err(Error::custom(
+                ErrorKind::InvalidOperation,
+                "isolated networks are not enabled on this gateway",
+            ));
+        }
+
+        // Take the write lock of the state database before checking whether the project exists,
+        // so two creates of the same project cannot both see it missing or destroyed
+        let mut tx = ImmediateTransaction::begin(&self.db).await?;
+        let created = self
+            .create_or_recreate_project(&mut tx, &project_name, &account_name, is_admin, &options)
+            .await;
+
+        match created {
+            Ok(project) => {
+                tx.commit().await?;
+                self.invalidate_cached_project(&project_name).await;
+                Ok(project)
+            }
+            Err(error) => {
+                tx.rollback().await?;
+                Err(error)
+            }
+        }
+    }
+
+    /// The body of [GatewayService::create_project], on a connection holding the write lock
+    async fn create_or_recreate_project(
+        &self,
+        conn: &mut SqliteConnection,
+        project_name: &ProjectName,
+        account_name: &AccountName,
+        is_admin: bool,
+        options: &ProjectOptions,
+    ) -> Result<Project, Error> {
+        if let Some(row) = query(
+            r#"
+        SELECT project_name, project_id, account_name, initial_key, project_state, volume_retained_until
+        FROM projects
+        WHERE (project_name = ?1)
+        AND (account_name = ?2 OR ?3)
+        "#,
+        )
+        .bind(project_name)
+        .bind(account_name)
+        .bind(is_admin)
+        .fetch_optional(&mut *conn)
+        .await?
+        {
+            // If the project already exists and belongs to this account
+            let project = row.get::<SqlxJson<Project>, _>("project_state").0;
+            if project.is_destroyed() {
+                // But is in `::Destroyed` state, recreate it
+                self.check_project_quota(conn, account_name, is_admin).await?;
+                let retained_until = row.get::<Option<i64>, _>("volume_retained_until");
+                if matches!(retained_until, Some(retained_until) if retained_until < Utc::now().timestamp()) {
+                    // The reaper did not get to it yet, so drop the expired volume here
+                    self.remove_project_volume(&mut *conn, project_name, &project).await?;
+                }
+                let mut creating = self.new_project_creating(project_name, options);
+                // Restore the previous egress policy, if any and not overridden
+                if let Project::Destroyed(destroyed) = &project {
+                    if let Some(container) = destroyed.container() {
+                        if options.egress_policy.is_none() {
+                            creating = creating.with_egress_policy(container.egress_policy());
+                        }
+                        // A renamed project keeps the volume of its previous name
+                        if let Some(volume_name) = container.volume_name() {
+                            creating = creating.with_volume_name(volume_name);
+                        }
+                    }
+                }
+                // Restore the previous custom domains, if any. The primary one is the fqdn the
+                // project is reached at, the others keep being routed to it through `custom_domains`
+                if let Some(custom_domain) = Self::primary_custom_domain(&mut *conn, project_name).await? {
+                    creating = creating.with_fqdn(custom_domain.fqdn.to_string());
+                }
+                let project = Project::Creating(creating);
+                self.store_project(conn, project_name, &project, None).await?;
+                // The volume is attached again, so it is no longer up for removal
+                query("UPDATE projects SET volume_retained_until = NULL WHERE project_name = ?1")
+                    .bind(project_name)
+                    .execute(&mut *conn)
+                    .await?;
+                Self::insert_audit_entry(
+                    &mut *conn,
+                    project_name,
+                    account_name,
+                    AuditAction::Create,
+                    Some("recreated".to_string()),
+                )
+                .await?;
+                Ok(project)
+            } else {
+                // Otherwise it already exists
+                Err(Error::from_kind(ErrorKind::ProjectAlreadyExists))
+            }
+        } else {
+            // Check if project name is valid according to new rules if it
+            // doesn't exist.
+            // TODO: remove this check when we update the project name rules
+            // in shuttle-common
+            if project_name.is_valid() {
+                // Otherwise attempt to create a new one. This will fail
+                // outright if the project already exists (this happens if
+                // it belongs to another account).
+                self.check_project_quota(conn, account_name, is_admin).await?;
+                let creating = self.new_project_creating(project_name, options);
+                Self::insert_project(conn, project_name, account_name, creating).await
+            } else {
+                Err(Error::from_kind(ErrorKind::InvalidProjectName))
+            }
+        }
+    }
+
+    /// A project to be created with the given options, with a new admin secret
+    fn new_project_creating(
+        &self,
+        project_name: &ProjectName,
+        options: &ProjectOptions,
+    ) -> ProjectCreating {
+        let idle_minutes = options
+            .idle_minutes
+            .unwrap_or(self.context().container_settings().default_idle_minutes);
+        let creating =
+            ProjectCreating::new_with_random_initial_key(project_name.clone(), idle_minutes)
+                .with_graceful_stop(options.graceful_stop)
+                .with_isolated_network(options.isolated_network);
+
+        match options.egress_policy {
+            Some(egress_policy) => creating.with_egress_policy(egress_policy),
+            None => creating,
+        }
+    }
+
+    async fn insert_project(
+        conn: &mut SqliteConnection,
+        project_name: &ProjectName,
+        account_name: &AccountName,
+        creating: ProjectCreating,
+    ) -> Result<Project, Error> {
+        let project = SqlxJson(Project::Creating(creating));
+
+        let now = Utc::now().timestamp_millis();
+        query("INSERT INTO projects (project_id, project_name, account_name, initial_key, project_state, created_at, updated_at, state_kind) VALUES (ulid(), ?1, ?2, ?3, ?4, ?5, ?6, ?7)")
+            .bind(project_name)
+            .bind(account_name)
+            .bind(project.initial_key().unwrap())
+            .bind(&project)
+            .bind(now)
+            .bind(now)
+            .bind(project.kind())
+            .execute(&mut *conn)
+            .await
+            .map_err(|err| {
+                // If the error is a broken PK constraint, this is a
+                // project name clash
+                if let Some(db_err_code) = err.as_database_error().and_then(DatabaseError::code) {
+                    if db_err_code == "2067" {  // SQLITE_CONSTRAINT_UNIQUE
+                        return Error::from_kind(ErrorKind::ProjectAlreadyExists)
+                    }
+                }
+                // Otherwise this is internal
+                err.into()
+            })?;
+
+        Self::insert_audit_entry(conn, project_name, account_name, AuditAction::Create, None)
+            .await?;
+
+        let project = project.0;
+
+        Ok(project)
+    }
+
+    /// Add an entry to the audit log of a project. The entry is kept with the id of the project,
+    /// so it stays in the log of the project when it is renamed.
+    pub async fn record_audit(
+        &self,
+        project_name: &ProjectName,
+        account_name: &AccountName,
+        action: AuditAction,
+        details: Option<String>,
+    ) -> Result<(), Error> {
+        Self::insert_audit_entry(&self.db, project_name, account_name, action, details).await
+    }
+
+    async fn insert_audit_entry<'c, E>(
+        executor: E,
+        project_name: &ProjectName,
+        account_name: &AccountName,
+        action: AuditAction,
+        details: Option<String>,
+    ) -> Result<(), Error>
+    where
+        E: sqlx::Executor<'c, Database = Sqlite>,
+    {
+        query(
+            "INSERT INTO audit_log (project_id, project_name, account_name, action, details, created_at)
+            SELECT project_id, ?1, ?2, ?3, ?4, ?5 FROM projects WHERE project_name = ?1",
+        )
+        .bind(project_name)
+        .bind(account_name)
+        .bind(action.to_string())
+        .bind(details)
+        .bind(Utc::now().timestamp_millis())
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the audit log of a project, oldest entry first
+    pub async fn project_audit_log(
+        &self,
+        project_name: &ProjectName,
+    ) -> Result<Vec<AuditEntry>, Error> {
+        let project_id: String = query("SELECT project_id FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_optional(&self.db)
+            .await?
+            .map(|row| row.get("project_id"))
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotFound))?;
+
+        query(
+            "SELECT project_name, account_name, action, details, created_at FROM audit_log WHERE project_id = ?1 ORDER BY id",
+        )
+        .bind(project_id)
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let action = row.get::<String, _>("action");
+            Ok(AuditEntry {
+                project_name: row.get("project_name"),
+                account_name: row.get("account_name"),
+                action: action.parse().map_err(|_| {
+                    Error::custom(
+                        ErrorKind::Internal,
+                        format!("unknown audit log action: {action}"),
+                    )
+                })?,
+                details: row.get("details"),
+                at: Utc
+                    .timestamp_millis_opt(row.get("created_at"))
+                    .single()
+                    .unwrap_or_default(),
+            })
+        })
+        .collect()
+    }
+
+    /// Add the custom domain `fqdn` to a project, which can have several of them. Creating a domain
+    /// the project already has replaces its certificate, while a domain of another project is
+    /// refused. The expiry of the certificate is stored alongside it to find the domains due for
+    /// a renewal. A `wildcard` domain routes every direct subdomain of `fqdn` to the project.
+    pub async fn create_custom_domain(
+        &self,
+        project_name: &ProjectName,
+        fqdn: &Fqdn,
+        wildcard: bool,
+        certs: &str,
+        private_key: &str,
+    ) -> Result<(), Error> {
+        self.insert_custom_domain(project_name, fqdn, wildcard, certs, private_key, true)
+            .await
+    }
+
+    /// Add the custom domain `fqdn` to a project with a certificate issued outside of the gateway,
+    /// which is served as is and never renewed through ACME. The chain and private key have to
+    /// parse, and the certificate has to cover the domain. Returns the parsed certificate so it
+    /// can be served.
+    pub async fn upload_custom_domain_certificate(
+        &self,
+        project_name: &ProjectName,
+        fqdn: &Fqdn,
+        wildcard: bool,
+        certs: &str,
+        private_key: &str,
+    ) -> Result<ChainAndPrivateKey, Error> {
+        let identifier = custom_domain_identifier(fqdn, wildcard);
+
+        let names = certificate_names(certs).ok_or_else(|| {
+            Error::custom(
+                ErrorKind::InvalidCertificate,
+                "the certificate chain could not be parsed",
+            )
+        })?;
+        if !names.iter().any(|name| {
+            name.eq_ignore_ascii_case(&identifier)
+                || matches!(wildcard_for(&identifier), Some(wildcard) if name.eq_ignore_ascii_case(&wildcard))
+        }) {
+            return Err(Error::custom(
+                ErrorKind::InvalidCertificate,
+                format!("the certificate does not cover {identifier}"),
+            ));
+        }
+
+        let mut buf = Vec::new();
+        buf.extend(certs.as_bytes());
+        buf.extend(private_key.as_bytes());
+        let chain = ChainAndPrivateKey::parse_pem(Cursor::new(buf)).map_err(|_| {
+            Error::custom(ErrorKind::InvalidCertificate, "the PEM could not be parsed")
+        })?;
+        // Make sure the key can actually be used to serve the certificate
+        chain.clone().into_certified_key().map_err(|_| {
+            Error::custom(
+                ErrorKind::InvalidCertificate,
+                "the private key is not supported",
+            )
+        })?;
+        if !chain.key_matches_certificate() {
+            return Err(Error::custom(
+                ErrorKind::InvalidCertificate,
+                "the private key does not match the certificate",
+            ));
+        }
+
+        self.insert_custom_domain(project_name, fqdn, wildcard, certs, private_key, false)
+            .await?;
+
+        Ok(chain)
+    }
+
+    async fn insert_custom_domain(
+        &self,
+        project_name: &ProjectName,
+        fqdn: &Fqdn,
+        wildcard: bool,
+        certs: &str,
+        private_key: &str,
+        acme_managed: bool,
+    ) -> Result<(), Error> {
+        let project_id = query("SELECT project_id FROM projects WHERE project_name = ?1")
+            .bind(project_name)
+            .fetch_one(&self.db)
+            .await?
+            .get::<String, _>("project_id");
+
+        let rows_affected = query(
+            r#"
+        INSERT INTO custom_domains (fqdn, project_id, certificate, private_key, not_after, acme_managed) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT (fqdn) DO UPDATE SET certificate = excluded.certificate, private_key = excluded.private_key, not_after = excluded.not_after, acme_managed = excluded.acme_managed
+        WHERE custom_domains.project_id = excluded.project_id
+        "#,
+        )
+        .bind(custom_domain_identifier(fqdn, wildcard))
+        .bind(project_id)
+        .bind(certs)
+        .bind(private_key)
+        .bind(certificate_expiry(certs).map(|not_after| not_after.timestamp()))
+        .bind(acme_managed)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+
+        if rows_affected == 0 {
+            Err(Error::from_kind(ErrorKind::CustomDomainAlreadyExists))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Remove the custom domain `fqdn` of a project
+    pub async fn delete_custom_domain(
+        &self,
+        project_name: &ProjectName,
+        fqdn: &Fqdn,
+        wildcard: bool,
+    ) -> Result<(), Error> {
+        let rows_affected = query(
+            "DELETE FROM custom_domains WHERE fqdn = ?1 AND project_id = (SELECT project_id FROM projects WHERE project_name = ?2)",
+        )
+        .bind(custom_domain_identifier(fqdn, wildcard))
+        .bind(project_name)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+
+        if rows_affected == 0 {
+            Err(Error::from_kind(ErrorKind::CustomDomainNotFound))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Iterate over the custom domains pointing at a project
+    pub async fn iter_custom_domains_for_project(
+        &self,
+        project_name: &ProjectName,
+    ) -> Result<impl Iterator<Item = CustomDomain>, Error> {
+        let iter = query("SELECT fqdn, project_name, certificate, private_key FROM custom_domains AS cd JOIN projects AS p ON cd.project_id = p.project_id WHERE p.project_name = ?1")
+            .bind(project_name)
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(custom_domain_from_row);
+        Ok(iter)
+    }
+
+    pub async fn iter_custom_domains(&self) -> Result<impl Iterator<Item = CustomDomain>, Error> {
+        query("SELECT fqdn, project_name, certificate, private_key FROM custom_domains AS cd JOIN projects AS p ON cd.project_id = p.project_id")
+            .fetch_all(&self.db)
+            .await
+            .map(|res| {
+                res.into_iter().map(custom_domain_from_row)
+            })
+            .map_err(|_| Error::from_kind(ErrorKind::Internal))
+    }
+
+    /// The custom domains of a project, in the order they were added
+    /// The custom domain the container of a project is created with, which is the only host its
+    /// runtime serves: the oldest of its domains which is not a wildcard. Requests to the other
+    /// domains of the project are forwarded as this one.
+    pub async fn find_primary_custom_domain(
+        &self,
+        project_name: &ProjectName,
+    ) -> Result<Option<CustomDomain>, Error> {
+        Self::primary_custom_domain(&self.db, project_name).await
+    }
+
+    async fn primary_custom_domain<'c, E>(
+        executor: E,
+        project_name: &ProjectName,
+    ) -> Result<Option<CustomDomain>, Error>
+    where
+        E: sqlx::Executor<'c, Database = Sqlite>,
+    {
+        let custom_domain = query(
+            "SELECT fqdn, project_name, certificate, private_key FROM custom_domains AS cd JOIN projects AS p ON cd.project_id = p.project_id WHERE p.project_name = ?1 AND fqdn NOT LIKE '*.%' ORDER BY cd.rowid LIMIT 1",
+        )
+        .bind(project_name)
+        .fetch_optional(executor)
+        .await?
+        .map(custom_domain_from_row);
+        Ok(custom_domain)
+    }
+
+    /// The custom domains whose stored certificate expires within `days`. Domains whose
+    /// certificate could not be parsed for an expiry, or was not issued through ACME, are never
+    /// due.
+    pub async fn find_custom_domains_expiring_within(
+        &self,
+        days: i64,
+    ) -> Result<Vec<CustomDomain>, Error> {
+        let deadline = Utc::now() + chrono::Duration::days(days);
+
+        let custom_domains = query(
+            "SELECT fqdn, project_name, certificate, private_key FROM custom_domains AS cd JOIN projects AS p ON cd.project_id = p.project_id WHERE cd.not_after <= ?1 AND cd.acme_managed ORDER BY cd.not_after",
+        )
+        .bind(deadline.timestamp())
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(custom_domain_from_row)
+        .collect();
+        Ok(custom_domains)
+    }
+
+    /// Store the expiry of the certificates added before it was tracked. Returns how many were
+    /// filled in.
+    pub async fn backfill_custom_domain_expiries(&self) -> Result<usize, Error> {
+        let rows = query("SELECT fqdn, certificate FROM custom_domains WHERE not_after IS NULL")
+            .fetch_all(&self.db)
+            .await?;
+
+        let mut filled = 0;
+        for row in rows {
+            let Some(not_after) = certificate_expiry(row.get("certificate")) else {
+                continue;
+            };
+
+            query("UPDATE custom_domains SET not_after = ?1 WHERE fqdn = ?2")
+                .bind(not_after.timestamp())
+                .bind(row.get::<String, _>("fqdn"))
+                .execute(&self.db)
+                .await?;
+            filled += 1;
+        }
+
+        Ok(filled)
+    }
+
+    /// Hold off the other certificate requests and renewals, so the periodic renewal and the admin
+    /// API never ask the ACME server for certificates at the same time
+    pub async fn lock_certificate_renewal(&self) -> MutexGuard<'_, ()> {
+        self.certificate_renewal.lock().await
+    }
+
+    /// Renew the certificates of the custom domains expiring within the renewal threshold, then
+    /// store and serve the new ones. A domain failing to renew does not stop the others and is
+    /// attempted again on the next sweep. Returns how many were renewed.
+    pub async fn renew_expiring_custom_domains(
+        &self,
+        acme: &AcmeClient,
+        resolver: &GatewayCertResolver,
+    ) -> Result<usize, Error> {
+        let _renewal = self.lock_certificate_renewal().await;
+        self.backfill_custom_domain_expiries().await?;
+
+        let mut renewed = 0;
+        for custom_domain in self
+            .find_custom_domains_expiring_within(self.renewal_threshold_days)
+            .await?
+        {
+            let identifier = custom_domain.identifier();
+            let CustomDomain {
+                fqdn,
+                wildcard,
+                project_name,
+                ..
+            } = custom_domain;
+
+            // Wildcard certificates can only be issued through dns-01 challenges, which cannot be
+            // completed by hand in the background
+            let challenge_type = if !wildcard {
+                ChallengeType::Http01
+            } else if acme.has_dns_provider() {
+                ChallengeType::Dns01
+            } else {
+                warn!(%identifier, %project_name, "cannot renew a wildcard certificate without a DNS provider");
+                continue;
+            };
+
+            let (certs, private_key) = match acme
+                .create_certificate(&identifier, challenge_type, self.credentials())
+                .await
+            {
+                Ok(certificate) => certificate,
+                Err(error) => {
+                    warn!(%error, %identifier, %project_name, "failed to renew custom domain certificate");
+                    continue;
+                }
+            };
+
+            if let Err(error) = self
+                .create_custom_domain(&project_name, &fqdn, wildcard, &certs, &private_key)
+                .await
+            {
+                warn!(%error, %identifier, %project_name, "failed to store renewed custom domain certificate");
+                continue;
+            }
+
+            let mut buf = Vec::new();
+            buf.extend(certs.as_bytes());
+            buf.extend(private_key.as_bytes());
+            if let Err(error) = resolver.serve_pem(&identifier, Cursor::new(buf)).await {
+                warn!(%error, %identifier, %project_name, "failed to serve renewed custom domain certificate");
+                continue;
+            }
+
+            info!(%identifier, %project_name, "renewed custom domain certificate");
+            renewed += 1;
+        }
+
+        Ok(renewed)
+    }
+
+    /// The custom domain `fqdn` is reached at. This is the domain of that exact name, or else the
+    /// wildcard domain covering it.
+    pub async fn project_details_for_custom_domain(
+        &self,
+        fqdn: &Fqdn,
+    ) -> Result<CustomDomain, Error> {
+        let fqdn = fqdn.to_string();
+        let wildcard = wildcard_for(&fqdn);
+
+        self.find_custom_domain_matching(&fqdn, wildcard.as_deref())
+            .await
+    }
+
+    /// The custom domain named `fqdn`, or `*.<fqdn>` when it is a `wildcard`
+    pub async fn find_custom_domain(
+        &self,
+        fqdn: &Fqdn,
+        wildcard: bool,
+    ) -> Result<CustomDomain, Error> {
+        self.find_custom_domain_matching(&custom_domain_identifier(fqdn, wildcard), None)
+            .await
+    }
+
+    async fn find_custom_domain_matching(
+        &self,
+        identifier: &str,
+        fallback: Option<&str>,
+    ) -> Result<CustomDomain, Error> {
+        let custom_domain = query(
+            "SELECT fqdn, project_name, certificate, private_key FROM custom_domains AS cd JOIN projects AS p ON cd.project_id = p.project_id WHERE fqdn = ?1 OR fqdn = ?2 ORDER BY fqdn = ?1 DESC LIMIT 1",
+        )
+        .bind(identifier)
+        .bind(fallback)
+        .fetch_optional(&self.db)
+        .await?
+        .map(custom_domain_from_row)
+        .ok_or_else(|| Error::from(ErrorKind::CustomDomainNotFound))?;
+        Ok(custom_domain)
+    }
+
+    /// Stream the details of every project, which are read from the database as the stream is
+    /// consumed
+    pub fn iter_projects_detailed(
+        &self,
+    ) -> impl Stream<Item = Result<ProjectDetails, Error>> + Send + '_ {
+        query("SELECT project_name, account_name, total_restarts, updated_at FROM projects")
+            .fetch(&self.db)
+            .map_ok(project_details_from_row)
+            .map_err(Error::from)
+    }
+
+    /// All of [GatewayService::iter_projects_detailed] at once
+    pub async fn collect_projects_detailed(&self) -> Result<Vec<ProjectDetails>, Error> {
+        self.iter_projects_detailed().try_collect().await
+    }
+
+    /// Find the projects whose name or account name contains `search`, most recent first
+    pub async fn search_projects_detailed(
+        &self,
+        search: &str,
+        offset: u32,
+        limit: u32,
+    ) -> Result<impl Iterator<Item = ProjectDetails>, Error> {
+        // Match the search literally, even if it holds `LIKE` wildcards
+        let pattern = format!(
+            "%{}%",
+            search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+
+        let mut query = QueryBuilder::new(
+            "SELECT project_name, account_name, total_restarts, updated_at FROM projects WHERE project_name LIKE ",
+        );
+
+        query
+            .push_bind(pattern.clone())
+            .push(" ESCAPE '\\' OR account_name LIKE ")
+            .push_bind(pattern)
+            .push(" ESCAPE '\\' ORDER BY project_id DESC, project_name LIMIT ")
+            .push_bind(limit);
+
+        if offset > 0 {
+            query.push(" OFFSET ").push_bind(offset);
+        }
+
+        let iter = query
+            .build()
+            .fetch_all(&self.db)
+            .await?
+            .into_iter()
+            .map(project_details_from_row);
+        Ok(iter)
+    }
+
+    /// Returns the current certificate as a pair of the chain and private key.
+    /// If the pair doesn't exist for a specific project, create both the certificate
+    /// and the custom domain it will represent.
+    pub async fn create_custom_domain_certificate(
+        &self,
+        fqdn: &Fqdn,
+        wildcard: bool,
+        acme_client: &AcmeClient,
+        project_name: &ProjectName,
+        challenge_type: ChallengeType,
+        creds: AccountCredentials<'_>,
+    ) -> Result<(String, String), Error> {
+        match self.find_custom_domain(fqdn, wildcard).await {
+            Ok(CustomDomain {
+                project_name: owner,
+                ..
+            }) if &owner != project_name => {
+                Err(Error::from_kind(ErrorKind::CustomDomainAlreadyExists))
+            }
+            Ok(CustomDomain {
+                certificate,
+                private_key,
+                ..
+            }) => Ok((certificate, private_key)),
+            Err(err) if err.kind() == ErrorKind::CustomDomainNotFound => {
+                let (certs, private_key) = acme_client
+                    .create_certificate(
+                        &custom_domain_identifier(fqdn, wildcard),
+                        challenge_type,
+                        creds,
+                    )
+                    .await?;
+                self.create_custom_domain(project_name, fqdn, wildcard, &certs, &private_key)
+                    .await?;
+                Ok((certs, private_key))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn create_certificate<'a>(
+        &self,
+        acme: &AcmeClient,
+        creds: AccountCredentials<'a>,
+    ) -> Result<ChainAndPrivateKey, Error> {
+        let identifier = format!("*.{}", self.public_fqdn());
+
+        // Use ::Dns01 challenge because that's the only supported
+        // challenge type for wildcard domains.
+        let (chain, private_key) = acme
+            .create_certificate(&identifier, ChallengeType::Dns01, creds)
+            .await?;
+
+        let mut buf = Vec::new();
+        buf.extend(chain.as_bytes());
+        buf.extend(private_key.as_bytes());
+
+        ChainAndPrivateKey::parse_pem(Cursor::new(buf))
+    }
+
+    /// Fetch the gateway certificate from the state location.
+    /// If not existent, create the gateway certificate and save it to the
+    /// gateway state.
+    pub async fn fetch_certificate(
+        &self,
+        acme: &AcmeClient,
+        creds: AccountCredentials<'_>,
+    ) -> Result<ChainAndPrivateKey, Error> {
+        let tls_path = self.state_location.join("ssl.pem");
+        match ChainAndPrivateKey::load_pem(&tls_path) {
+            Ok(valid) => Ok(valid),
+            Err(_) => {
+                warn!(
+                    "no valid certificate found at {}, creating one...",
+                    tls_path.display()
+                );
+
+                let certs = self.create_certificate(acme, creds).await?;
+                certs.clone().save_pem(&tls_path)?;
+                Ok(certs)
+            }
+        }
+    }
+
+    /// Renew the gateway certificate if there are less than the renewal threshold days
+    /// until the current certificate expiration. Returns whether it was renewed.
+    pub(crate) async fn renew_certificate(
+        &self,
+        acme: &AcmeClient,
+        resolver: Arc<GatewayCertResolver>,
+        creds: AccountCredentials<'_>,
+    ) -> Result<bool, Error> {
+        let _renewal = self.lock_certificate_renewal().await;
+        let account = AccountWrapper::from(creds).0;
+        let certs = self.fetch_certificate(acme, account.credentials()).await?;
+        // Safe to unwrap because a 'ChainAndPrivateKey' is built from a PEM.
+        let chain_and_pk = certs.into_pem().unwrap();
+
+        let (_, pem) = parse_x509_pem(chain_and_pk.as_bytes())
+            .unwrap_or_else(|_| panic!("Malformed existing PEM certificate for the gateway."));
+        let (_, x509_cert) = parse_x509_certificate(pem.contents.as_bytes())
+            .unwrap_or_else(|_| panic!("Malformed existing X509 certificate for the gateway."));
+
+        // We compute the difference between the certificate expiry date and current timestamp because we want to trigger the
+        // gateway certificate renewal only during its last days of validity or if the certificate is expired.
+        let diff = x509_cert.validity().not_after.sub(ASN1Time::now());
+
+        // Renew only when the difference is `None` (meaning certificate expired) or we're within the renewal threshold.
+        if diff.is_none()
+            || diff
+                .expect("to be Some given we checked for None previously")
+                .whole_days()
+                <= self.renewal_threshold_days
+        {
+            let tls_path = self.state_location.join("ssl.pem");
+            let certs = self.create_certificate(acme, account.credentials()).await?;
+            resolver.serve_default_der(certs.clone()).await?;
+            certs.save_pem(&tls_path)?;
+
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Renew the certificates of the gateway and of the custom domains which expire within the
+    /// renewal threshold. They are renewed one after the other, to stay clear of the rate limits of
+    /// the ACME server. Returns the number of certificates renewed.
+    pub async fn renew_expiring_certificates(
+        &self,
+        acme: &AcmeClient,
+        resolver: &Arc<GatewayCertResolver>,
+    ) -> Result<usize, Error> {
+        let mut renewed = 0;
+
+        match self
+            .renew_certificate(acme, Arc::clone(resolver), self.credentials())
+            .await
+        {
+            Ok(true) => {
+                info!("renewed the gateway certificate");
+                renewed += 1;
+            }
+            Ok(false) => {}
+            Err(error) => error!(%error, "failed to renew the gateway certificate"),
+        }
+
+        renewed += self.renew_expiring_custom_domains(acme, resolver).await?;
+
+        Ok(renewed)
+    }
+
+    pub fn context(&self) -> GatewayContext {
+        self.provider.context()
+    }
+
+    /// The domain under which the gateway serves its projects
+    pub fn public_fqdn(&self) -> FQDN {
+        self.context().settings.fqdn.parse().unwrap()
+    }
+
+    /// Get the last `tail` lines the project's container wrote to stdout and stderr, capped to
+    /// [MAX_CONTAINER_LOG_LINES]. These are available even when the deployer never came up.
+    pub async fn project_container_logs(
+        &self,
+        project_name: &ProjectName,
+        tail: usize,
+    ) -> Result<Vec<String>, Error> {
+        let container_id = self
+            .find_project(project_name)
+            .await?
+            .container_id()
+            .ok_or_else(|| Error::from_kind(ErrorKind::ProjectNotReady))?;
+
+        let tail = tail.min(MAX_CONTAINER_LOG_LINES).to_string();
+
+        self.context()
+            .docker()
+            .logs(
+                &container_id,
+                Some(LogsOptions {
+                    stdout: true,
+                    stderr: true,
+                    tail: tail.as_str(),
+                    ..Default::default()
+                }),
+            )
+            .map_ok(|output| output.to_string().trim_end().to_string())
+            .try_collect()
+            .await
+            .map_err(|err| Error::source(ErrorKind::Internal, err))
+    }
+
+    /// Create a builder for a new [ProjectTask]
+    pub fn new_task(self: &Arc<Self>) -> TaskBuilder {
+        TaskBuilder::new(self.clone())
+    }
+
+    /// Find a project by name. And start the project if it is idle, waiting for it to start up
+    pub async fn find_or_start_project(
+        self: &Arc<Self>,
+        project_name: &ProjectName,
+        task_sender: Sender<BoxedTask>,
+    ) -> Result<Project, Error> {
+        self.find_or_start_project_with(project_name, task_sender, false)
+            .await
+    }
+
+    /// Like [GatewayService::find_or_start_project], but in safe mode: the project is started
+    /// without starting its last deployment or checking its health. This breaks the crash loop of
+    /// a project whose last deployment crashes on startup, so its owner can fix it before starting
+    /// a deployment manually.
+    pub async fn find_or_start_project_in_safe_mode(
+        self: &Arc<Self>,
+        project_name: &ProjectName,
+        task_sender: Sender<BoxedTask>,
+    ) -> Result<Project, Error> {
+        self.find_or_start_project_with(project_name, task_sender, true)
+            .await
+    }
+
+    async fn find_or_start_project_with(
+        self: &Arc<Self>,
+        project_name: &ProjectName,
+        task_sender: Sender<BoxedTask>,
+        safe_mode: bool,
+    ) -> Result<Project, Error> {
+        let mut project = self.find_project_cached(project_name).await?;
+
+        // Start the project if it is idle
+        if project.is_stopped() {
+            trace!(%project_name, safe_mode, "starting up idle project");
+
+            let mut builder = self
+                .new_task()
+                .project(project_name.clone())
+                .and_then(task::start())
+                .and_then(task::run_until_done());
+
+            if !safe_mode {
+                builder = builder
+                    .and_then(task::start_idle_deploys())
+                    .and_then(task::check_health());
+            }
+
+            let handle = builder.send(&task_sender).await?;
+
+            project = self.wait_until_awake(project_name, Some(handle)).await?;
+        } else if project.is_waking() {
+            project = self.wait_until_awake(project_name, None).await?;
+        }
+
+        Ok(project)
+    }
+
+    /// Hold a request while its project wakes up, for at most the cold start timeout. Only so many
+    /// requests are held for a project at once, the others get the project as it is straight away.
+    /// The project can then still be waking up, for callers to turn the request away.
+    async fn wait_until_awake(
+        &self,
+        project_name: &ProjectName,
+        handle: Option<TaskHandle>,
+    ) -> Result<Project, Error> {
+        let queue = self
+            .cold_start_queues
+            .lock()
+            .await
+            .entry(project_name.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(COLD_START_QUEUE_LIMIT)))
+            .clone();
+
+        let project = if let Ok(_permit) = queue.clone().try_acquire_owned() {
+            let wait = async {
+                if let Some(handle) = handle {
+                    handle.await;
+                }
+
+                loop {
+                    let project = self.find_project(project_name).await?;
+                    if !project.is_waking() {
+                        return Ok(project);
+                    }
+
+                    sleep(COLD_START_POLL_INTERVAL).await;
+                }
+            };
+
+            match timeout(self.cold_start_timeout, wait).await {
+                Ok(project) => project,
+                Err(_) => {
+                    debug!(%project_name, "project did not wake up in time");
+                    self.find_project(project_name).await
+                }
+            }
+        } else {
+            debug!(%project_name, "too many requests are waiting on project to wake up");
+            self.find_project(project_name).await
+        };
+
+        // The queue is only needed while requests wait on the project. Clones of it are only made
+        // under the lock, so when the map holds the only other one nobody else is waiting.
+        let mut queues = self.cold_start_queues.lock().await;
+        if Arc::strong_count(&queue) == 2 {
+            queues.remove(project_name.as_str());
+        }
+
+        project
+    }
+
+    pub fn task_router(&self) -> TaskRouter<BoxedTask> {
+        self.task_router.clone()
+    }
+
+    /// Create a new ACME account and persist its credentials in place of the current ones. The
+    /// gateway fetches and renews certificates with the new account from then on.
+    pub async fn rotate_acme_account(
+        &self,
+        acme: &AcmeClient,
+        email: &str,
+        acme_server: Option<String>,
+    ) -> Result<serde_json::Value, Error> {
+        let credentials = acme.create_account(email, acme_server).await?;
+
+        self.save_credentials(&credentials)?;
+
+        info!("rotated the acme account of the gateway");
+
+        Ok(credentials)
+    }
+
+    /// Persist the ACME account credentials used by [GatewayService::credentials]. They are
+    /// written to a temporary file which is then moved in place, so that a failed write never
+    /// leaves the gateway with corrupted credentials.
+    pub fn save_credentials(&self, credentials: &serde_json::Value) -> Result<(), Error> {
+        let creds_path = self.state_location.join("acme.json");
+        let tmp_path = self.state_location.join("acme.json.tmp");
+
+        let file = std::fs::File::create(&tmp_path)?;
+        serde_json::to_writer_pretty(file, credentials)
+            .map_err(|err| Error::source(ErrorKind::Internal, err))?;
+        std::fs::rename(tmp_path, creds_path)?;
+
+        Ok(())
+    }
+
+    /// Whether ACME account credentials were persisted for [GatewayService::credentials]
+    pub fn has_credentials(&self) -> bool {
+        self.state_location.join("acme.json").exists()
+    }
+
+    pub fn credentials(&self) -> AccountCredentials<'_> {
+        let creds_path = self.state_location.join("acme.json");
+        if !creds_path.exists() {
+            panic!(
+                "no ACME credentials found at {}, cannot continue with certificate creation",
+                creds_path.display()
+            );
+        }
+
+        serde_json::from_reader(std::fs::File::open(creds_path).expect("Invalid credentials path"))
+            .expect("Can not parse admin credentials from path")
+    }
+
+    /// Days of validity left under which certificates are renewed
+    pub fn renewal_threshold_days(&self) -> i64 {
+        self.renewal_threshold_days
+    }
+
+    pub fn provisioner_host(&self) -> &Endpoint {
+        &self.provisioner_host
+    }
+    pub fn auth_uri(&self) -> &Uri {
+        &self.auth_host
+    }
+}
+
+#[derive(Clone)]
+pub struct GatewayContext {
+    docker: Docker,
+    settings: ContainerSettings,
+    api_key: String,
+    auth_key_uri: Uri,
+    jwt: CachedJwt,
+}
+
+impl DockerContext for GatewayContext {
+    fn docker(&self) -> &Docker {
+        &self.docker
+    }
+
+    fn container_settings(&self) -> &ContainerSettings {
+        &self.settings
+    }
+}
+
+impl GatewayContext {
+    /// Get a JWT of the gateway. The token is shared by all the contexts and only fetched again
+    /// from the auth service when it is close to expiring.
+    pub async fn get_jwt(&self) -> String {
+        if let Some((jwt, valid_until)) = &*self.jwt.read().await {
+            if Instant::now() < *valid_until {
+                return jwt.clone();
+            }
+        }
+
+        let mut cached = self.jwt.write().await;
+
+        // Another caller may have fetched a new token while this one was waiting on the lock
+        if let Some((jwt, valid_until)) = &*cached {
+            if Instant::now() < *valid_until {
+                return jwt.clone();
+            }
+        }
+
+        let jwt = self.fetch_jwt().await;
+
+        // An empty token means the auth service could not be reached, so try again next time
+        if !jwt.is_empty() {
+            *cached = Some((jwt.clone(), Instant::now() + jwt_ttl(&jwt)));
+        }
+
+        jwt
+    }
+
+    #[instrument(skip(self), fields(auth_key_uri = %self.auth_key_uri, api_key = self.api_key))]
+    async fn fetch_jwt(&self) -> String {
+        let req = Request::builder()
+            .uri(self.auth_key_uri.clone())
+            .header(AUTHORIZATION, format!("Bearer {}", self.api_key))
+            .body(Body::empty())
+            .unwrap();
+
+        trace!("getting jwt");
+
+        let resp = timeout(IS_HEALTHY_TIMEOUT, AUTH_CLIENT.request(req)).await;
+
+        if let Ok(Ok(resp)) = resp {
+            let body = hyper::body::to_bytes(resp.into_body())
+                .await
+                .unwrap_or_default();
+            let convert: Value = serde_json::from_slice(&body).unwrap_or_default();
+
+            trace!(?convert, "got jwt response");
+
+            convert["token"].as_str().unwrap_or_default().to_string()
+        } else {
+            Default::default()
+        }
+    }
+}
+
+/// How long a JWT can be cached. This is never past its `exp` claim, less a margin, and is zero for
+/// a token which already expired. Tokens without a readable `exp` are kept for a short while only.
+fn jwt_ttl(jwt: &str) -> Duration {
+    let Some(exp) = jwt_exp(jwt) else {
+        return JWT_FALLBACK_TTL;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    exp.saturating_sub(now).saturating_sub(JWT_EXPIRY_MARGIN)
+}
+
+/// The `exp` claim of a JWT, as a time since the unix epoch, read without verifying its signature
+fn jwt_exp(jwt: &str) -> Option<Duration> {
+    let claims = jwt.split('.').nth(1)?;
+    let claims = base64::decode_config(claims, base64::URL_SAFE_NO_PAD).ok()?;
+    let exp = serde_json::from_slice::<Value>(&claims).ok()?["exp"].as_u64()?;
+
+    Some(Duration::from_secs(exp))
+}
+
+/// The domain names a PEM encoded certificate is valid for, from its subject alternative names
+/// or else its common name
+fn certificate_names(certificate: &str) -> Option<Vec<String>> {
+    let (_, pem) = parse_x509_pem(certificate.as_bytes()).ok()?;
+    let (_, certificate) = parse_x509_certificate(pem.contents.as_bytes()).ok()?;
+
+    if let Ok(Some(san)) = certificate.subject_alternative_name() {
+        let names: Vec<_> = san
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(name) => Some(name.to_string()),
+                _ => None,
+            })
+            .collect();
+
+        if !names.is_empty() {
+            return Some(names);
+        }
+    }
+
+    Some(
+        certificate
+            .subject()
+            .iter_common_name()
+            .filter_map(|name| name.as_str().ok())
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+fn custom_domain_from_row(row: SqliteRow) -> CustomDomain {
+    let (fqdn, wildcard) = split_custom_domain(row.get("fqdn")).unwrap();
+
+    CustomDomain {
+        fqdn,
+        wildcard,
+        project_name: row.try_get("project_name").unwrap(),
+        certificate: row.get("certificate"),
+        private_key: row.get("private_key"),
+    }
+}
+
+fn user_project_from_row(row: SqliteRow) -> UserProject {
+    UserProject {
+        project_name: row.get("project_name"),
+        project: row.get::<SqlxJson<Project>, _>("project_state").0,
+        created_at: row
+            .get::<Option<i64>, _>("created_at")
+            .and_then(|created_at| Utc.timestamp_millis_opt(created_at).single()),
+        updated_at: row
+            .get::<Option<i64>, _>("updated_at")
+            .and_then(|updated_at| Utc.timestamp_millis_opt(updated_at).single()),
+    }
+}
+
+fn project_details_from_row(row: SqliteRow) -> ProjectDetails {
+    ProjectDetails {
+        project_name: row.try_get("project_name").unwrap(),
+        account_name: row.try_get("account_name").unwrap(),
+        total_restarts: row.try_get("total_restarts").unwrap(),
+        updated_at: row
+            .get::<Option<i64>, _>("updated_at")
+            .and_then(|updated_at| Utc.timestamp_millis_opt(updated_at).single()),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::net::SocketAddr;
+
+    use fqdn::FQDN;
+    use rand::Rng;
+    use shuttle_common::deployment::State;
+    use shuttle_common::models::{deployment, service};
+
+    use super::*;
+
+    use crate::project::tests::{container_stats, ready_project};
+    use crate::project::MAX_TRANSITION_HISTORY;
+    use crate::task::{self, TaskResult};
+    use crate::tests::{assert_err_kind, World};
+    use crate::worker::Worker;
+    use crate::{Error, ErrorKind};
+
+    /// Options of a project that is never stopped for being idle
+    pub fn never_idle() -> ProjectOptions {
+        ProjectOptions {
+            idle_minutes: Some(0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn jwt_expiry() {
+        let jwt = |claims: Value| {
+            format!(
+                "e30.{}.c2ln",
+                base64::encode_config(claims.to_string(), base64::URL_SAFE_NO_PAD)
+            )
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let ttl = jwt_ttl(&jwt(serde_json::json!({ "exp": now + 900 })));
+        assert!(ttl > Duration::from_secs(860) && ttl <= Duration::from_secs(870));
+
+        // Never cached past its expiry, even when it is closer than the fallback
+        let ttl = jwt_ttl(&jwt(serde_json::json!({ "exp": now + 40 })));
+        assert!(ttl <= Duration::from_secs(10));
+
+        assert_eq!(
+            jwt_ttl(&jwt(serde_json::json!({ "exp": now - 10 }))),
+            Duration::ZERO
+        );
+        assert_eq!(
+            jwt_ttl(&jwt(serde_json::json!({ "sub": "gateway" }))),
+            JWT_FALLBACK_TTL
+        );
+        assert_eq!(jwt_ttl("not-a-jwt"), JWT_FALLBACK_TTL);
+    }
+
+    #[tokio::test]
+    async fn service_create_find_delete_project() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let trinity: AccountName = "trinity".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+
+        let creating_same_project_name = |project: &Project, project_name: &ProjectName| {
+            matches!(
+                project,
+                Project::Creating(creating) if creating.project_name() == project_name
+            )
+        };
+
+        let project = svc
+            .create_project(matrix.clone(), neo.clone(), false, never_idle())
+            .await
+            .unwrap();
+
+        assert!(creating_same_project_name(&project, &matrix));
+
+        assert_eq!(svc.find_project(&matrix).await.unwrap(), project);
+        let user_project = svc.find_user_project(&matrix).await.unwrap();
+        assert_eq!(user_project.project, project);
+        assert!(user_project.created_at.is_some());
+        assert_eq!(user_project.created_at, user_project.updated_at);
+        let details = svc
+            .collect_projects_detailed()
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .expect("to get one project with its user");
+        assert!(details.updated_at.is_some());
+        assert_eq!(
+            details,
+            ProjectDetails {
+                project_name: matrix.clone(),
+                account_name: neo.clone(),
+                total_restarts: 0,
+                updated_at: details.updated_at,
+            }
+        );
+
+        svc.increment_total_restarts(&matrix).await.unwrap();
+        assert_eq!(
+            svc.collect_projects_detailed()
+                .await
+                .unwrap()
+                .into_iter()
+                .next()
+                .expect("to get one project with its user")
+                .total_restarts,
+            1
+        );
+        assert_eq!(
+            svc.iter_user_projects_detailed(&neo, 0, u32::MAX, &[])
+                .await
+                .unwrap()
+                .map(|project| project.project_name)
+                .collect::<Vec<_>>(),
+            vec![matrix.clone()]
+        );
+
+        // Test project pagination, first create 20 test projects (including the one from above).
+        for p in (1..20).map(|p| format!("matrix-{p}")) {
+            svc.create_project(ProjectName(p.clone()), neo.clone(), false, never_idle())
+                .await
+                .unwrap();
+        }
+
+        // We need to fetch all of them from the DB since they are ordered by created_at (in the id) and project_name,
+        // and created_at will be the same for some of them.
+        let all_projects = svc
+            .iter_user_projects_detailed(&neo, 0, u32::MAX, &[])
+            .await
+            .unwrap()
+            .map(|project| project.project_name)
+            .collect::<Vec<_>>();
+
+        assert_eq!(all_projects.len(), 20);
+
+        // Every new project records when it was created
+        assert!(svc
+            .iter_user_projects_detailed(&neo, 0, u32::MAX, &[])
+            .await
+            .unwrap()
+            .all(
+                |project| project.created_at.is_some() && project.created_at <= project.updated_at
+            ));
+
+        // Get first 5 projects.
+        let paginated = svc
+            .iter_user_projects_detailed(&neo, 0, 5, &[])
+            .await
+            .unwrap()
+            .map(|project| project.project_name)
+            .collect::<Vec<_>>();
+
+        assert_eq!(all_projects[..5], paginated);
+
+        // Get 10 projects starting at an offset of 10.
+        let paginated = svc
+            .iter_user_projects_detailed(&neo, 10, 10, &[])
+            .await
+            .unwrap()
+            .map(|project| project.project_name)
+            .collect::<Vec<_>>();
+        assert_eq!(all_projects[10..20], paginated);
+
+        // Get 20 projects starting at an offset of 200.
+        let paginated = svc
+            .iter_user_projects_detailed(&neo, 200, 20, &[])
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        assert!(paginated.is_empty());
+
+        let mut work = svc
+            .new_task()
+            .project(matrix.clone())
+            .and_then(task::destroy())
+            .build();
+
+        while let TaskResult::Pending(_) = work.poll(()).await {}
+        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+
+        // After project has been destroyed...
+        assert!(matches!(
+            svc.find_project(&matrix).await,
+            Ok(Project::Destroyed(_))
+        ));
+
+        // If recreated by a different user
+        assert!(matches!(
+            svc.create_project(matrix.clone(), trinity.clone(), false, never_idle(),)
+                .await,
+            Err(Error {
+                kind: ErrorKind::ProjectAlreadyExists,
+                ..
+            })
+        ));
+
+        // If recreated by the same user
+        assert!(matches!(
+            svc.create_project(matrix.clone(), neo, false, never_idle())
+                .await,
+            Ok(Project::Creating(_))
+        ));
+
+        let mut work = svc
+            .new_task()
+            .project(matrix.clone())
+            .and_then(task::destroy())
+            .build();
+
+        while let TaskResult::Pending(_) = work.poll(()).await {}
+        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+
+        // After project has been destroyed again...
+        assert!(matches!(
+            svc.find_project(&matrix).await,
+            Ok(Project::Destroyed(_))
+        ));
+
+        // If recreated by an admin
+        assert!(matches!(
+            svc.create_project(matrix, trinity, true, never_idle())
+                .await,
+            Ok(Project::Creating(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_create_ready_kill_restart_docker() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+
+        svc.create_project(matrix.clone(), neo.clone(), false, never_idle())
+            .await
+            .unwrap();
+
+        let mut task = svc.new_task().project(matrix.clone()).build();
+
+        while let TaskResult::Pending(_) = task.poll(()).await {
+            // keep polling
+        }
+
+        let project = svc.find_project(&matrix).await.unwrap();
+        println!("{:?}", project);
+        assert!(project.is_ready());
+
+        let container = project.container().unwrap();
+        svc.context()
+            .docker()
+            .kill_container::<String>(container.name.unwrap().strip_prefix('/').unwrap(), None)
+            .await
+            .unwrap();
+
+        println!("killed container");
+
+        let mut ambulance_task = svc
+            .new_task()
+            .project(matrix.clone())
+            .and_then(task::check_health())
+            .build();
+
+        // the first poll will trigger a refresh
+        let _ = ambulance_task.poll(()).await;
+
+        let project = svc.find_project(&matrix).await.unwrap();
+        println!("{:?}", project);
+        assert!(!project.is_ready());
+
+        // the subsequent will trigger a restart task
+        while let TaskResult::Pending(_) = ambulance_task.poll(()).await {
+            // keep polling
+        }
+
+        let project = svc.find_project(&matrix).await.unwrap();
+        println!("{:?}", project);
+        assert!(project.is_ready());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_start_project_in_safe_mode_docker() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+        let worker = Worker::new();
+        let sender = worker.sender();
+        tokio::spawn(worker.start());
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+
+        svc.create_project(matrix.clone(), neo.clone(), false, never_idle())
+            .await
+            .unwrap();
+
+        let mut task = svc.new_task().project(matrix.clone()).build();
+        while let TaskResult::Pending(_) = task.poll(()).await {}
+        assert!(svc.find_project(&matrix).await?.is_ready());
+
+        let mut stop = svc
+            .new_task()
+            .project(matrix.clone())
+            .and_then(task::run(|ctx| async move {
+                match ctx.state.stop() {
+                    Ok(stopping) => TaskResult::Done(stopping),
+                    Err(err) => TaskResult::Err(err),
+                }
+            }))
+            .and_then(task::run_until_done())
+            .build();
+        while let TaskResult::Pending(_) = stop.poll(()).await {}
+        assert!(svc.find_project(&matrix).await?.is_stopped());
+
+        let project = svc
+            .find_or_start_project_in_safe_mode(&matrix, sender)
+            .await?;
+        assert!(project.is_ready());
+
+        // The last deployment is not started
+        let control_key = svc.control_key_from_project_name(&matrix).await?;
+        assert_eq!(
+            svc.active_deployment_id(&project, &matrix, &control_key)
+                .await,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_create_find_custom_domain() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let account: AccountName = "neo".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+        let domain: FQDN = "neo.the.matrix".parse().unwrap();
+        let certificate = "dummy certificate";
+        let private_key = "dummy private key";
+
+        assert_err_kind!(
+            svc.project_details_for_custom_domain(&domain).await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        let _ = svc
+            .create_project(project_name.clone(), account.clone(), false, never_idle())
+            .await
+            .unwrap();
+
+        svc.create_custom_domain(&project_name, &domain, false, certificate, private_key)
+            .await
+            .unwrap();
+
+        let custom_domain = svc
+            .project_details_for_custom_domain(&domain)
+            .await
+            .unwrap();
+
+        assert_eq!(custom_domain.project_name, project_name);
+        assert_eq!(custom_domain.certificate, certificate);
+        assert_eq!(custom_domain.private_key, private_key);
+
+        // Should auto replace the domain details
+        let certificate = "dummy certificate update";
+        let private_key = "dummy private key update";
+
+        svc.create_custom_domain(&project_name, &domain, false, certificate, private_key)
+            .await
+            .unwrap();
+
+        let custom_domain = svc
+            .project_details_for_custom_domain(&domain)
+            .await
+            .unwrap();
+
+        assert_eq!(custom_domain.project_name, project_name);
+        assert_eq!(custom_domain.certificate, certificate);
+        assert_eq!(custom_domain.private_key, private_key);
+
+        // Only the project owning the domain can have it removed
+        assert_err_kind!(
+            svc.delete_custom_domain(&"zion".parse().unwrap(), &domain, false)
+                .await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        svc.delete_custom_domain(&project_name, &domain, false)
+            .await
+            .unwrap();
+
+        assert_err_kind!(
+            svc.project_details_for_custom_domain(&domain).await,
+            ErrorKind::CustomDomainNotFound
+        );
+        assert_err_kind!(
+            svc.delete_custom_domain(&project_name, &domain, false)
+                .await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_create_custom_domain_destroy_recreate_project() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let account: AccountName = "neo".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+        let domain: FQDN = "neo.the.matrix".parse().unwrap();
+        let certificate = "dummy certificate";
+        let private_key = "dummy private key";
+
+        assert_err_kind!(
+            svc.project_details_for_custom_domain(&domain).await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        let _ = svc
+            .create_project(project_name.clone(), account.clone(), false, never_idle())
+            .await
+            .unwrap();
+
+        svc.create_custom_domain(&project_name, &domain, false, certificate, private_key)
+            .await
+            .unwrap();
+
+        let mut work = svc
+            .new_task()
+            .project(project_name.clone())
+            .and_then(task::destroy())
+            .build();
+
+        while let TaskResult::Pending(_) = work.poll(()).await {}
+        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+
+        let recreated_project = svc
+            .create_project(project_name.clone(), account.clone(), false, never_idle())
+            .await
+            .unwrap();
+
+        let Project::Creating(creating) = recreated_project else {
+            panic!("Project should be Creating");
+        };
+        assert_eq!(creating.fqdn(), &Some(domain.to_string()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_wildcard_custom_domain() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
+
+        let account: AccountName = "neo".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+        let other_project: ProjectName = "zion".parse().unwrap();
+        let base: FQDN = "the.matrix".parse().unwrap();
+        let exact: FQDN = "trinity.the.matrix".parse().unwrap();
+
+        for name in [&project_name, &other_project] {
+            svc.create_project(name.clone(), account.clone(), false, never_idle())
+                .await
+                .unwrap();
+        }
+
+        svc.create_custom_domain(&project_name, &base, true, "certificate", "private key")
+            .await
+            .unwrap();
+        svc.create_custom_domain(&other_project, &exact, false, "certificate", "private key")
+            .await
+            .unwrap();
+
+        // Subdomains fall back to the wildcard, unless they are a custom domain of their own
+        let custom_domain = svc
+            .project_details_for_custom_domain(&"neo.the.matrix".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(custom_domain.project_name, project_name);
+        assert_eq!(custom_domain.identifier(), "*.the.matrix");
+        assert_eq!(
+            svc.project_details_for_custom_domain(&exact)
+                .await
+                .unwrap()
+                .project_name,
+            other_project
+        );
+
+        // The wildcard covers neither its base domain nor deeper subdomains
+        assert_err_kind!(
+            svc.project_details_for_custom_domain(&base).await,
+            ErrorKind::CustomDomainNotFound
+        );
+        assert_err_kind!(
+            svc.project_details_for_custom_domain(&"a.neo.the.matrix".parse().unwrap())
+                .await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        assert!(svc.find_custom_domain(&base, true).await.unwrap().wildcard);
+        assert_err_kind!(
+            svc.find_custom_domain(&base, false).await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        svc.delete_custom_domain(&project_name, &base, true)
+            .await
+            .unwrap();
+        assert_err_kind!(
+            svc.project_details_for_custom_domain(&"neo.the.matrix".parse().unwrap())
+                .await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_project_with_multiple_custom_domains() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let account: AccountName = "neo".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+        let other_project: ProjectName = "zion".parse().unwrap();
+        let domain: FQDN = "neo.the.matrix".parse().unwrap();
+        let other_domain: FQDN = "trinity.the.matrix".parse().unwrap();
+
+        for name in [&project_name, &other_project] {
+            svc.create_project(name.clone(), account.clone(), false, never_idle())
+                .await
+                .unwrap();
+        }
+
+        svc.create_custom_domain(&project_name, &domain, false, "certificate", "private key")
+            .await
+            .unwrap();
+        svc.create_custom_domain(
+            &project_name,
+            &other_domain,
+            false,
+            "other certificate",
+            "other private key",
+        )
+        .await
+        .unwrap();
+
+        // Both domains are routed to the project
+        for fqdn in [&domain, &other_domain] {
+            let custom_domain = svc.project_details_for_custom_domain(fqdn).await.unwrap();
+            assert_eq!(custom_domain.project_name, project_name);
+        }
+
+        let mut fqdns: Vec<_> = svc
+            .iter_custom_domains_for_project(&project_name)
+            .await
+            .unwrap()
+            .map(|custom_domain| custom_domain.fqdn.to_string())
+            .collect();
+        fqdns.sort();
+        assert_eq!(fqdns, vec![domain.to_string(), other_domain.to_string()]);
+
+        // Another project cannot take over one of the domains
+        assert_err_kind!(
+            svc.create_custom_domain(
+                &other_project,
+                &other_domain,
+                false,
+                "certificate",
+                "private key"
+            )
+            .await,
+            ErrorKind::CustomDomainAlreadyExists
+        );
+        assert_eq!(
+            svc.project_details_for_custom_domain(&other_domain)
+                .await
+                .unwrap()
+                .certificate,
+            "other certificate"
+        );
+
+        // The oldest domain is the one the project is created with, skipping wildcards
+        assert_eq!(
+            svc.find_primary_custom_domain(&project_name)
+                .await
+                .unwrap()
+                .map(|custom_domain| custom_domain.fqdn),
+            Some(domain.clone())
+        );
+        let zion_domain: FQDN = "zion.matrix".parse().unwrap();
+        svc.create_custom_domain(
+            &other_project,
+            &zion_domain,
+            true,
+            "certificate",
+            "private key",
+        )
+        .await
+        .unwrap();
+        assert!(svc
+            .find_primary_custom_domain(&other_project)
+            .await
+            .unwrap()
+            .is_none());
+        svc.create_custom_domain(
+            &other_project,
+            &zion_domain,
+            false,
+            "certificate",
+            "private key",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            svc.find_primary_custom_domain(&other_project)
+                .await
+                .unwrap()
+                .map(|custom_domain| custom_domain.fqdn),
+            Some(zion_domain)
+        );
+
+        let mut work = svc
+            .new_task()
+            .project(project_name.clone())
+            .and_then(task::destroy())
+            .build();
+
+        while let TaskResult::Pending(_) = work.poll(()).await {}
+        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+
+        let recreated_project = svc
+            .create_project(project_name.clone(), account.clone(), false, never_idle())
+            .await
+            .unwrap();
+
+        // The first domain is restored as the fqdn of the project, and both are still routed to it
+        let Project::Creating(creating) = recreated_project else {
+            panic!("Project should be Creating");
+        };
+        assert_eq!(creating.fqdn(), &Some(domain.to_string()));
+
+        for fqdn in [&domain, &other_domain] {
+            let custom_domain = svc.project_details_for_custom_domain(fqdn).await.unwrap();
+            assert_eq!(custom_domain.project_name, project_name);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_destroyed_project_volume_retention() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let account: AccountName = "neo".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+
+        let retained_until = || async {
+            query("SELECT volume_retained_until FROM projects WHERE project_name = ?1")
+                .bind(&project_name)
+                .fetch_one(&svc.db)
+                .await
+                .unwrap()
+                .get::<Option<i64>, _>("volume_retained_until")
+        };
+        let destroy = || async {
+            let mut work = svc
+                .new_task()
+                .project(project_name.clone())
+                .and_then(task::destroy())
+                .build();
+
+            while let TaskResult::Pending(_) = work.poll(()).await {}
+            assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+        };
+
+        svc.create_project(project_name.clone(), account.clone(), false, never_idle())
+            .await
+            .unwrap();
+        assert_eq!(retained_until().await, None);
+
+        destroy().await;
+        let until = retained_until().await.expect("volume to be retained");
+        let window =
+            (svc.context().container_settings().volume_retention_days * 24 * 60 * 60) as i64;
+        assert!(until > Utc::now().timestamp() + window - 60);
+
+        // Nothing is expired yet
+        assert_eq!(svc.reap_expired_volumes().await.unwrap(), 0);
+        assert_eq!(retained_until().await, Some(until));
+
+        // Recreating within the window reattaches the volume
+        svc.create_project(project_name.clone(), account.clone(), false, never_idle())
+            .await
+            .unwrap();
+        assert_eq!(retained_until().await, None);
+
+        destroy().await;
+        query("UPDATE projects SET volume_retained_until = ?1 WHERE project_name = ?2")
+            .bind(Utc::now().timestamp() - 1)
+            .bind(&project_name)
+            .execute(&svc.db)
+            .await
+            .unwrap();
+
+        assert_eq!(svc.reap_expired_volumes().await.unwrap(), 1);
+        assert_eq!(retained_until().await, None);
+        assert_eq!(svc.reap_expired_volumes().await.unwrap(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_records_project_transition_history() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let account: AccountName = "neo".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+
+        let destroy = || async {
+            let mut work = svc
+                .new_task()
+                .project(project_name.clone())
+                .and_then(task::destroy())
+                .build();
+
+            while let TaskResult::Pending(_) = work.poll(()).await {}
+            assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+        };
+
+        svc.create_project(project_name.clone(), account.clone(), false, never_idle())
+            .await
+            .unwrap();
+        assert_eq!(
+            svc.project_transition_history(&project_name).await.unwrap(),
+            TransitionHistory::default()
+        );
+
+        destroy().await;
+        svc.create_project(project_name.clone(), account.clone(), false, never_idle())
+            .await
+            .unwrap();
+        destroy().await;
+
+        let history = svc.project_transition_history(&project_name).await.unwrap();
+        let transitions: Vec<_> = history
+            .iter()
+            .map(|(from, to, _)| (from.as_str(), to.as_str()))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                ("creating", "destroyed"),
+                ("destroyed", "creating"),
+                ("creating", "destroyed"),
+            ]
+        );
+        assert!(history
+            .iter()
+            .zip(history.iter().skip(1))
+            .all(|(a, b)| a.2 <= b.2));
+
+        // Only the most recent transitions are kept
+        for _ in 0..MAX_TRANSITION_HISTORY {
+            let creating = Project::Creating(ProjectCreating::new_with_random_initial_key(
+                project_name.clone(),
+                0,
+            ));
+            svc.update_project(&project_name, &creating).await.unwrap();
+            destroy().await;
+        }
+        let history = svc.project_transition_history(&project_name).await.unwrap();
+        assert_eq!(history.iter().count(), MAX_TRANSITION_HISTORY);
+        assert_eq!(
+            history
+                .iter()
+                .last()
+                .map(|(from, to, _)| (from.as_str(), to.as_str())),
+            Some(("creating", "destroyed"))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_count_projects_for_account() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let trinity: AccountName = "trinity".parse().unwrap();
+
+        for project in ["matrix", "reloaded", "revolutions"] {
+            svc.create_project(project.parse().unwrap(), neo.clone(), false, never_idle())
+                .await
+                .unwrap();
+        }
+        svc.create_project(
+            "zion".parse().unwrap(),
+            trinity.clone(),
+            false,
+            never_idle(),
+        )
+        .await
+        .unwrap();
+
+        let mut work = svc
+            .new_task()
+            .project("revolutions".parse().unwrap())
+            .and_then(task::destroy())
+            .build();
+
+        while let TaskResult::Pending(_) = work.poll(()).await {}
+        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+
+        assert_eq!(svc.count_projects_for_account(&neo, true).await?, 3);
+        assert_eq!(svc.count_projects_for_account(&neo, false).await?, 2);
+        assert_eq!(svc.count_projects_for_account(&trinity, false).await?, 1);
+        assert_eq!(
+            svc.count_projects_for_account(&"smith".parse().unwrap(), true)
+                .await?,
+            0
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_project_quota() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.project_quota = Some(2);
+        let svc = Arc::new(GatewayService::init(args, world.pool(), "".into()).await);
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let admin: AccountName = "admin".parse().unwrap();
+
+        for project in ["matrix", "reloaded"] {
+            svc.create_project(project.parse().unwrap(), neo.clone(), false, never_idle())
+                .await
+                .unwrap();
+        }
+
+        assert_err_kind!(
+            svc.create_project(
+                "revolutions".parse().unwrap(),
+                neo.clone(),
+                false,
+                never_idle(),
+            )
+            .await,
+            ErrorKind::QuotaExceeded
+        );
+
+        // Admins are not limited by the quota
+        for project in ["zion", "nebuchadnezzar", "logos"] {
+            svc.create_project(project.parse().unwrap(), admin.clone(), true, never_idle())
+                .await
+                .unwrap();
+        }
+
+        // Destroyed projects do not count towards the quota, but recreating them does
+        let mut work = svc
+            .new_task()
+            .project("reloaded".parse().unwrap())
+            .and_then(task::destroy())
+            .build();
+
+        while let TaskResult::Pending(_) = work.poll(()).await {}
+        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+
+        svc.create_project(
+            "revolutions".parse().unwrap(),
+            neo.clone(),
+            false,
+            never_idle(),
+        )
+        .await
+        .unwrap();
+
+        assert_err_kind!(
+            svc.create_project(
+                "reloaded".parse().unwrap(),
+                neo.clone(),
+                false,
+                never_idle(),
+            )
+            .await,
+            ErrorKind::QuotaExceeded
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_save_credentials_replaces_acme_account() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let state = tempfile::tempdir()?;
+        let svc =
+            GatewayService::init(world.args(), world.pool(), state.path().to_path_buf()).await;
+
+        let old = serde_json::json!({ "id": "https://acme.test/acct/1" });
+        let new = serde_json::json!({ "id": "https://acme.test/acct/2" });
+
+        svc.save_credentials(&old)?;
+        svc.save_credentials(&new)?;
+
+        let saved: serde_json::Value =
+            serde_json::from_reader(std::fs::File::open(state.path().join("acme.json"))?)?;
+        assert_eq!(saved, new);
+        assert!(!state.path().join("acme.json.tmp").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_project_updated_at_advances() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
+        let trinity: AccountName = world.create_user("trinity").parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+
+        let updated_at = || async {
+            svc.collect_projects_detailed()
+                .await
+                .unwrap()
+                .into_iter()
+                .next()
+                .expect("to get the project")
+                .updated_at
+                .expect("the project to have an update time")
+        };
+
+        svc.create_project(matrix.clone(), neo.clone(), false, never_idle())
+            .await
+            .unwrap();
+        let mut previous = updated_at().await;
+
+        for mutation in ["update", "tags", "transfer"] {
+            // Make sure the clock moves on between mutations
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+            match mutation {
+                "update" => {
+                    let project = svc.find_project(&matrix).await.unwrap();
+                    svc.update_project(&matrix, &project).await.unwrap();
+                }
+                "tags" => svc
+                    .set_project_tags(&matrix, &["prod".to_string()])
+                    .await
+                    .unwrap(),
+                _ => svc.transfer_project(&matrix, &trinity).await.unwrap(),
+            }
+
+            let current = updated_at().await;
+            assert!(current > previous, "{mutation} should advance updated_at");
+            previous = current;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_transfer_project() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
+        let trinity: AccountName = world.create_user("trinity").parse().unwrap();
+        let smith: AccountName = "smith".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+
+        svc.create_project(project_name.clone(), neo.clone(), false, never_idle())
+            .await
+            .unwrap();
+
+        assert_err_kind!(
+            svc.transfer_project(&project_name, &smith).await,
+            ErrorKind::UserNotFound
+        );
+        assert_err_kind!(
+            svc.transfer_project(&"reloaded".parse().unwrap(), &trinity)
+                .await,
+            ErrorKind::ProjectNotFound
+        );
+        assert_eq!(
+            svc.account_name_from_project(&project_name).await.unwrap(),
+            neo
+        );
+
+        svc.transfer_project(&project_name, &trinity).await.unwrap();
+
+        assert_eq!(
+            svc.account_name_from_project(&project_name).await.unwrap(),
+            trinity
+        );
+        assert!(svc.iter_user_projects(&neo).await.unwrap().next().is_none());
+        assert_eq!(
+            svc.iter_user_projects(&trinity)
+                .await
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![project_name]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_project_audit_log() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
+        let trinity: AccountName = world.create_user("trinity").parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let reloaded: ProjectName = "matrix-reloaded".parse().unwrap();
+
+        svc.create_project(matrix.clone(), neo.clone(), false, never_idle())
+            .await
+            .unwrap();
+        svc.transfer_project(&matrix, &trinity).await.unwrap();
+        svc.rename_project(&matrix, &reloaded).await.unwrap();
+
+        let mut work = svc
+            .new_task()
+            .project(reloaded.clone())
+            .and_then(task::destroy())
+            .build();
+
+        while let TaskResult::Pending(_) = work.poll(()).await {}
+        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+
+        let audit_log = svc.project_audit_log(&reloaded).await.unwrap();
+        assert_eq!(
+            audit_log
+                .iter()
+                .map(|entry| (
+                    entry.project_name.as_str(),
+                    entry.account_name.as_str(),
+                    entry.action,
+                    entry.details.as_deref()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("matrix", "neo", AuditAction::Create, None),
+                ("matrix", "trinity", AuditAction::Transfer, Some("from neo")),
+                (
+                    "matrix-reloaded",
+                    "trinity",
+                    AuditAction::Rename,
+                    Some("from matrix")
+                ),
+                ("matrix-reloaded", "trinity", AuditAction::Destroy, None),
+            ]
+        );
+        assert!(audit_log.windows(2).all(|pair| pair[0].at <= pair[1].at));
+
+        assert_err_kind!(
+            svc.project_audit_log(&matrix).await,
+            ErrorKind::ProjectNotFound
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_search_projects_detailed() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let trinity: AccountName = "trinity".parse().unwrap();
+
+        for (project_name, account) in [
+            ("matrix", &neo),
+            ("reloaded", &neo),
+            ("revolutions", &trinity),
+            ("resurrections", &trinity),
+        ] {
+            svc.create_project(
+                project_name.parse().unwrap(),
+                account.clone(),
+                false,
+                never_idle(),
+            )
+            .await
+            .unwrap();
+        }
+
+        // Projects created in the same millisecond have no set order, so sort the results
+        let search = |search: &'static str, offset, limit| {
+            let svc = svc.clone();
+            async move {
+                let mut names = svc
+                    .search_projects_detailed(search, offset, limit)
+                    .await
+                    .unwrap()
+                    .map(|details| details.project_name.to_string())
+                    .collect::<Vec<_>>();
+                names.sort();
+                names
+            }
+        };
+
+        // Matches on the project name
+        assert_eq!(
+            search("re", 0, u32::MAX).await,
+            vec!["reloaded", "resurrections", "revolutions"]
+        );
+        // And on the account name
+        assert_eq!(
+            search("trin", 0, u32::MAX).await,
+            vec!["resurrections", "revolutions"]
+        );
+        // Paginated like the user projects
+        assert_eq!(search("re", 0, 2).await.len(), 2);
+        assert_eq!(search("re", 2, 2).await.len(), 1);
+        assert!(search("re", 3, 2).await.is_empty());
+        // Wildcards are matched literally
+        assert!(search("%", 0, u32::MAX).await.is_empty());
+        assert!(search("m_trix", 0, u32::MAX).await.is_empty());
+        assert_eq!(search("matrix", 0, u32::MAX).await, vec!["matrix"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn deployment_uploads() {
+        let request = |method: Method, path: &str| {
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert!(is_deployment_upload(&request(
+            Method::POST,
+            "/projects/matrix/services/matrix"
+        )));
+        assert!(!is_deployment_upload(&request(
+            Method::GET,
+            "/projects/matrix/services/matrix"
+        )));
+        assert!(!is_deployment_upload(&request(
+            Method::POST,
+            "/projects/matrix/services/matrix/resources"
+        )));
+        assert!(!is_deployment_upload(&request(
+            Method::POST,
+            "/projects/matrix/clean"
+        )));
+    }
+
+    #[tokio::test]
+    async fn service_route_exposes_active_deployment_id() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.expose_deployment_id = true;
+        let svc = GatewayService::init(args, world.pool(), "".into()).await;
+
+        let account: AccountName = "neo".parse().unwrap();
+        let project_name: ProjectName = "matrix".parse().unwrap();
+        let deployment_id = Uuid::new_v4();
+
+        // Stand in for the deployer of the project on its own loopback address
+        let target = Ipv4Addr::new(127, 0, 0, rand::thread_rng().gen_range(2..255));
+        let deployer = axum::Router::new()
+            .route(
+                "/projects/matrix/services/matrix",
+                axum::routing::get(move || async move {
+                    axum::Json(service::Summary {
+                        name: "matrix".to_string(),
+                        deployment: Some(deployment::Response {
+                            id: deployment_id,
+                            service_id: Uuid::new_v4(),
+                            state: State::Running,
+                            last_update: chrono::Utc::now(),
+                            git_commit_id: None,
+                            git_commit_msg: None,
+                            git_branch: None,
+                            git_dirty: None,
+                            toolchain_info: None,
+                            dry_run: false,
+                            tests_passed: None,
+                        }),
+                        uri: "https://matrix.test.shuttleapp.rs".to_string(),
+                    })
+                }),
+            )
+            .route(
+                "/projects/matrix/status",
+                axum::routing::get(|| async { "ok" }),
+            );
+        tokio::spawn(
+            axum::Server::bind(&SocketAddr::from((target, 8001)))
+                .serve(deployer.into_make_service()),
+        );
+
+        let project: Project = serde_json::from_value(serde_json::json!({
+            "ready": {
+                "container": {
+                    "Id": "matrix-container",
+                    "Args": ["--admin-secret", "neo-secret", "--project", "matrix"],
+                    "Config": {
+                        "Labels": {
+                            "shuttle.project": "matrix",
+                        },
+                    },
+                },
+                "service": {
+                    "name": "matrix",
+                    "target": target.to_string(),
+                    "last_check": null,
+                },
+            },
+        }))?;
+
+        let resp = svc
+            .route(
+                &project,
+                &project_name,
+                &account,
+                None,
+                Request::get("/projects/matrix/status")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await?;
+
+        assert_eq!(
+            resp.headers()
+                .typed_get::<XShuttleDeploymentId>()
+                .unwrap()
+                .0,
+            deployment_id.to_string()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_find_project_cached() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.project_cache_ttl_secs = Some(60);
+        let svc = GatewayService::init(args, world.pool(), "".into()).await;
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+
+        let creating = svc
+            .create_project(matrix.clone(), neo, false, never_idle())
+            .await
+            .unwrap();
+        assert_eq!(svc.find_project_cached(&matrix).await.unwrap(), creating);
+
+        // Changes made behind the service's back are not seen until the entry expires
+        let errored = Project::Errored(crate::project::ProjectError::internal("out of band"));
+        query("UPDATE projects SET project_state = ?1 WHERE project_name = ?2")
+            .bind(SqlxJson(&errored))
+            .bind(&matrix)
+            .execute(&svc.db)
+            .await
+            .unwrap();
+        assert_eq!(svc.find_project_cached(&matrix).await.unwrap(), creating);
+        assert_eq!(svc.find_project(&matrix).await.unwrap(), errored);
+
+        // While updating the project invalidates its entry
+        let destroyed = creating.destroy().unwrap();
+        svc.update_project(&matrix, &destroyed).await.unwrap();
+        assert_eq!(svc.find_project_cached(&matrix).await.unwrap(), destroyed);
+
+        assert_err_kind!(
+            svc.find_project_cached(&"oracle".parse().unwrap()).await,
+            ErrorKind::ProjectNotFound
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_rename_project() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let zion: ProjectName = "zion".parse().unwrap();
+        let reloaded: ProjectName = "matrix-reloaded".parse().unwrap();
+
+        let project = svc
+            .create_project(matrix.clone(), neo.clone(), false, never_idle())
+            .await
+            .unwrap();
+        svc.create_project(zion.clone(), neo.clone(), false, never_idle())
+            .await
+            .unwrap();
+
+        let renamed = svc.rename_project(&matrix, &reloaded).await.unwrap();
+        let Project::Creating(creating) = &renamed else {
+            panic!("expected a creating project, got {renamed:?}");
+        };
+        assert_eq!(creating.project_name(), &reloaded);
+        assert_eq!(renamed.initial_key(), project.initial_key());
+
+        assert_eq!(svc.find_project(&reloaded).await.unwrap(), renamed);
+        assert_err_kind!(svc.find_project(&matrix).await, ErrorKind::ProjectNotFound);
+        assert_eq!(
+            svc.iter_user_projects_detailed(&neo, 0, u32::MAX, &[])
+                .await
+                .unwrap()
+                .count(),
+            2
+        );
+
+        assert_err_kind!(
+            svc.rename_project(&reloaded, &zion).await,
+            ErrorKind::ProjectAlreadyExists
+        );
+        assert_err_kind!(
+            svc.rename_project(&reloaded, &"Matrix_Reloaded".parse().unwrap())
+                .await,
+            ErrorKind::InvalidProjectName
+        );
+        assert_err_kind!(
+            svc.rename_project(&matrix, &"oracle".parse().unwrap())
+                .await,
+            ErrorKind::ProjectNotFound
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_project_request_timeout() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+
+        svc.create_project(matrix.clone(), neo, false, never_idle())
+            .await
+            .unwrap();
+
+        assert_eq!(svc.project_request_timeout(&matrix).await.unwrap(), None);
+
+        svc.set_project_request_timeout(&matrix, Some(300))
+            .await
+            .unwrap();
+        assert_eq!(
+            svc.project_request_timeout(&matrix).await.unwrap(),
+            Some(Duration::from_secs(300))
+        );
+
+        assert_err_kind!(
+            svc.set_project_request_timeout(&matrix, Some(0)).await,
+            ErrorKind::InvalidOperation
+        );
+        assert_err_kind!(
+            svc.set_project_request_timeout(&"oracle".parse().unwrap(), Some(300))
+                .await,
+            ErrorKind::ProjectNotFound
+        );
+
+        svc.set_project_request_timeout(&matrix, None)
+            .await
+            .unwrap();
+        assert_eq!(svc.project_request_timeout(&matrix).await.unwrap(), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_project_max_build_retries() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+
+        svc.create_project(matrix.clone(), neo, false, never_idle())
+            .await
+            .unwrap();
+
+        assert_eq!(svc.project_max_build_retries(&matrix).await.unwrap(), None);
+
+        svc.set_project_max_build_retries(&matrix, Some(0))
+            .await
+            .unwrap();
+        assert_eq!(
+            svc.project_max_build_retries(&matrix).await.unwrap(),
+            Some(0)
+        );
+
+        assert_err_kind!(
+            svc.set_project_max_build_retries(&matrix, Some(MAX_BUILD_RETRIES + 1))
+                .await,
+            ErrorKind::InvalidOperation
+        );
+        assert_err_kind!(
+            svc.set_project_max_build_retries(&"oracle".parse().unwrap(), Some(1))
+                .await,
+            ErrorKind::ProjectNotFound
+        );
+
+        svc.set_project_max_build_retries(&matrix, None)
+            .await
+            .unwrap();
+        assert_eq!(svc.project_max_build_retries(&matrix).await.unwrap(), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_holds_requests_to_waking_project() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.cold_start_timeout_secs = 1;
+        let svc = Arc::new(GatewayService::init(args, world.pool(), "".into()).await);
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+
+        // Nothing picks up the tasks, so the project never gets past being created
+        let (sender, _receiver) = tokio::sync::mpsc::channel(256);
+
+        svc.create_project(matrix.clone(), neo, false, never_idle())
+            .await
+            .unwrap();
+
+        let start = Instant::now();
+        let project = svc.find_or_start_project(&matrix, sender).await.unwrap();
 
-        let (_, pem) = parse_x509_pem(chain_and_pk.as_bytes())
-            .unwrap_or_else(|_| panic!("Malformed existing PEM certificate for the gateway."));
-        let (_, x509_cert) = parse_x509_certificate(pem.contents.as_bytes())
-            .unwrap_or_else(|_| panic!("Malformed existing X509 certificate for the gateway."));
+        assert!(project.is_waking());
+        assert!(start.elapsed() >= Duration::from_secs(1));
 
-        // We compute the difference between the certificate expiry date and current timestamp because we want to trigger the
-        // gateway certificate renewal only during it's last 30 days of validity or if the certificate is expired.
-        let diff = x509_cert.validity().not_after.sub(ASN1Time::now());
+        // Nothing waits on the project anymore, so its queue is gone
+        assert!(svc.cold_start_queues.lock().await.is_empty());
 
-        // Renew only when the difference is `None` (meaning certificate expired) or we're within the last 30 days of validity.
-        if diff.is_none()
-            || diff
-                .expect("to be Some given we checked for None previously")
-                .whole_days()
-                <= RENEWAL_VALIDITY_THRESHOLD_IN_DAYS
-        {
-            let tls_path = self.state_location.join("ssl.pem");
-            let certs = self.create_certificate(acme, account.credentials()).await;
-            resolver
-                .serve_default_der(certs.clone())
-                .await
-                .expect("Failed to serve the default certs");
-            certs
-                .save_pem(&tls_path)
-                .expect("to save the certificate locally");
-        }
+        Ok(())
     }
 
-    pub fn context(&self) -> GatewayContext {
-        self.provider.context()
-    }
+    #[tokio::test]
+    async fn service_project_rate_limit() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
 
-    /// Create a builder for a new [ProjectTask]
-    pub fn new_task(self: &Arc<Self>) -> TaskBuilder {
-        TaskBuilder::new(self.clone())
-    }
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
 
-    /// Find a project by name. And start the project if it is idle, waiting for it to start up
-    pub async fn find_or_start_project(
-        self: &Arc<Self>,
-        project_name: &ProjectName,
-        task_sender: Sender<BoxedTask>,
-    ) -> Result<Project, Error> {
-        let mut project = self.find_project(project_name).await?;
+        svc.create_project(matrix.clone(), neo, false, never_idle())
+            .await
+            .unwrap();
 
-        // Start the project if it is idle
-        if project.is_stopped() {
-            trace!(%project_name, "starting up idle project");
+        assert_eq!(
+            svc.project_rate_limit(&matrix).await.unwrap(),
+            RateLimit::default()
+        );
 
-            let handle = self
-                .new_task()
-                .project(project_name.clone())
-                .and_then(task::start())
-                .and_then(task::run_until_done())
-                .and_then(task::start_idle_deploys())
-                .and_then(task::check_health())
-                .send(&task_sender)
-                .await?;
+        let rate_limit = RateLimit {
+            requests_per_second: Some(50),
+            burst: Some(100),
+        };
+        svc.set_project_rate_limit(&matrix, &rate_limit)
+            .await
+            .unwrap();
+        assert_eq!(svc.project_rate_limit(&matrix).await.unwrap(), rate_limit);
 
-            // Wait for project to come up and set new state
-            handle.await;
-            project = self.find_project(project_name).await?;
+        // The limit is kept once read rather than queried on every request
+        query("UPDATE projects SET rate_limit_per_second = NULL WHERE project_name = ?1")
+            .bind(&matrix)
+            .execute(&world.pool())
+            .await
+            .unwrap();
+        assert_eq!(svc.project_rate_limit(&matrix).await.unwrap(), rate_limit);
+
+        for invalid in [
+            RateLimit {
+                requests_per_second: Some(0),
+                burst: None,
+            },
+            RateLimit {
+                requests_per_second: Some(50),
+                burst: Some(0),
+            },
+            RateLimit {
+                requests_per_second: None,
+                burst: Some(100),
+            },
+        ] {
+            assert_err_kind!(
+                svc.set_project_rate_limit(&matrix, &invalid).await,
+                ErrorKind::InvalidOperation
+            );
         }
+        assert_err_kind!(
+            svc.set_project_rate_limit(&"oracle".parse().unwrap(), &rate_limit)
+                .await,
+            ErrorKind::ProjectNotFound
+        );
 
-        Ok(project)
+        Ok(())
     }
 
-    pub fn task_router(&self) -> TaskRouter<BoxedTask> {
-        self.task_router.clone()
-    }
+    #[tokio::test]
+    async fn service_tag_projects() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
 
-    pub fn credentials(&self) -> AccountCredentials<'_> {
-        let creds_path = self.state_location.join("acme.json");
-        if !creds_path.exists() {
-            panic!(
-                "no ACME credentials found at {}, cannot continue with certificate creation",
-                creds_path.display()
-            );
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let zion: ProjectName = "zion".parse().unwrap();
+
+        for project_name in [&matrix, &zion] {
+            svc.create_project(project_name.clone(), neo.clone(), false, never_idle())
+                .await
+                .unwrap();
         }
 
-        serde_json::from_reader(std::fs::File::open(creds_path).expect("Invalid credentials path"))
-            .expect("Can not parse admin credentials from path")
-    }
+        assert!(svc.project_tags(&matrix).await.unwrap().is_empty());
 
-    pub fn provisioner_host(&self) -> &Endpoint {
-        &self.provisioner_host
-    }
-    pub fn auth_uri(&self) -> &Uri {
-        &self.auth_host
-    }
-}
+        let tags = vec!["team-a".to_string(), "production".to_string()];
+        svc.set_project_tags(&matrix, &tags).await.unwrap();
+        svc.set_project_tags(&zion, &["team-a".to_string()])
+            .await
+            .unwrap();
 
-#[derive(Clone)]
-pub struct GatewayContext {
-    docker: Docker,
-    settings: ContainerSettings,
-    api_key: String,
-    auth_key_uri: Uri,
-}
+        assert_eq!(svc.project_tags(&matrix).await.unwrap(), tags);
+        assert_err_kind!(
+            svc.set_project_tags(&"oracle".parse().unwrap(), &tags)
+                .await,
+            ErrorKind::ProjectNotFound
+        );
+        assert_err_kind!(
+            svc.set_project_tags(&matrix, &["team-a,production".to_string()])
+                .await,
+            ErrorKind::InvalidOperation
+        );
 
-impl DockerContext for GatewayContext {
-    fn docker(&self) -> &Docker {
-        &self.docker
-    }
+        let tagged = |tags: Vec<&str>| {
+            let svc = svc.clone();
+            let tags = tags
+                .into_iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            let neo = neo.clone();
+            async move {
+                let mut projects = svc
+                    .iter_user_projects_detailed(&neo, 0, u32::MAX, &tags)
+                    .await
+                    .unwrap()
+                    .map(|project| project.project_name)
+                    .collect::<Vec<_>>();
+                projects.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+                projects
+            }
+        };
 
-    fn container_settings(&self) -> &ContainerSettings {
-        &self.settings
+        assert_eq!(
+            tagged(vec!["team-a"]).await,
+            vec![matrix.clone(), zion.clone()]
+        );
+        assert_eq!(
+            tagged(vec!["team-a", "production"]).await,
+            vec![matrix.clone()]
+        );
+        assert!(tagged(vec!["team-b"]).await.is_empty());
+
+        Ok(())
     }
-}
 
-impl GatewayContext {
-    #[instrument(skip(self), fields(auth_key_uri = %self.auth_key_uri, api_key = self.api_key))]
-    pub async fn get_jwt(&self) -> String {
-        let req = Request::builder()
-            .uri(self.auth_key_uri.clone())
-            .header(AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .body(Body::empty())
-            .unwrap();
+    #[tokio::test]
+    async fn service_project_container_logs_need_a_container() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
 
-        trace!("getting jwt");
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
 
-        let resp = timeout(IS_HEALTHY_TIMEOUT, AUTH_CLIENT.request(req)).await;
+        assert_err_kind!(
+            svc.project_container_logs(&matrix, 10).await,
+            ErrorKind::ProjectNotFound
+        );
 
-        if let Ok(Ok(resp)) = resp {
-            let body = hyper::body::to_bytes(resp.into_body())
-                .await
-                .unwrap_or_default();
-            let convert: Value = serde_json::from_slice(&body).unwrap_or_default();
+        svc.create_project(matrix.clone(), neo, false, never_idle())
+            .await
+            .unwrap();
 
-            trace!(?convert, "got jwt response");
+        // A project which is still being created has no container to read logs from
+        assert_err_kind!(
+            svc.project_container_logs(&matrix, 10).await,
+            ErrorKind::ProjectNotReady
+        );
 
-            convert["token"].as_str().unwrap_or_default().to_string()
-        } else {
-            Default::default()
-        }
+        Ok(())
     }
-}
 
-#[cfg(test)]
-pub mod tests {
-    use fqdn::FQDN;
+    #[tokio::test]
+    async fn service_create_project_with_default_idle_minutes() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.default_idle_minutes = 0;
+        let svc = GatewayService::init(args, world.pool(), "".into()).await;
 
-    use super::*;
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let zion: ProjectName = "zion".parse().unwrap();
 
-    use crate::task::{self, TaskResult};
-    use crate::tests::{assert_err_kind, World};
-    use crate::{Error, ErrorKind};
+        let idle_minutes = |project: Project| match project {
+            Project::Creating(creating) => creating.idle_minutes(),
+            other => panic!("expected a creating project, got {other:?}"),
+        };
+
+        let project = svc
+            .create_project(matrix, neo.clone(), false, ProjectOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(idle_minutes(project), 0);
+
+        // An explicit value still wins over the default
+        let project = svc
+            .create_project(
+                zion,
+                neo,
+                false,
+                ProjectOptions {
+                    idle_minutes: Some(5),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(idle_minutes(project), 5);
+
+        Ok(())
+    }
 
     #[tokio::test]
-    async fn service_create_find_delete_project() -> anyhow::Result<()> {
+    async fn service_iter_custom_domains_for_project() -> anyhow::Result<()> {
         let world = World::new().await;
-        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
 
         let neo: AccountName = "neo".parse().unwrap();
-        let trinity: AccountName = "trinity".parse().unwrap();
         let matrix: ProjectName = "matrix".parse().unwrap();
+        let zion: ProjectName = "zion".parse().unwrap();
 
-        let creating_same_project_name = |project: &Project, project_name: &ProjectName| {
-            matches!(
-                project,
-                Project::Creating(creating) if creating.project_name() == project_name
-            )
-        };
+        for project_name in [&matrix, &zion] {
+            svc.create_project(project_name.clone(), neo.clone(), false, never_idle())
+                .await
+                .unwrap();
+        }
 
-        let project = svc
-            .create_project(matrix.clone(), neo.clone(), false, 0)
+        for (project_name, domain) in [
+            (&matrix, "neo.the.matrix"),
+            (&matrix, "trinity.the.matrix"),
+            (&zion, "morpheus.zion"),
+        ] {
+            svc.create_custom_domain(
+                project_name,
+                &domain.parse().unwrap(),
+                false,
+                "dummy certificate",
+                "dummy private key",
+            )
             .await
             .unwrap();
+        }
 
-        assert!(creating_same_project_name(&project, &matrix));
+        let mut domains = svc
+            .iter_custom_domains_for_project(&matrix)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        domains.sort_by_key(|domain| domain.fqdn.to_string());
 
-        assert_eq!(svc.find_project(&matrix).await.unwrap(), project);
         assert_eq!(
-            svc.iter_projects_detailed()
-                .await
-                .unwrap()
-                .next()
-                .expect("to get one project with its user"),
-            ProjectDetails {
-                project_name: matrix.clone(),
-                account_name: neo.clone(),
-            }
+            domains
+                .iter()
+                .map(|domain| domain.fqdn.to_string())
+                .collect::<Vec<_>>(),
+            vec!["neo.the.matrix", "trinity.the.matrix"]
         );
+        assert!(domains.iter().all(|domain| domain.project_name == matrix));
+        // The dummy certificate cannot be parsed for an expiry
+        assert_eq!(domains[0].certificate_expiry(), None);
+
         assert_eq!(
-            svc.iter_user_projects_detailed(&neo, 0, u32::MAX)
+            svc.iter_custom_domains_for_project(&"oracle".parse().unwrap())
                 .await
                 .unwrap()
-                .map(|item| item.0)
-                .collect::<Vec<_>>(),
-            vec![matrix.clone()]
+                .count(),
+            0
         );
 
-        // Test project pagination, first create 20 test projects (including the one from above).
-        for p in (1..20).map(|p| format!("matrix-{p}")) {
-            svc.create_project(ProjectName(p.clone()), neo.clone(), false, 0)
-                .await
-                .unwrap();
-        }
+        Ok(())
+    }
 
-        // We need to fetch all of them from the DB since they are ordered by created_at (in the id) and project_name,
-        // and created_at will be the same for some of them.
-        let all_projects = svc
-            .iter_user_projects_detailed(&neo, 0, u32::MAX)
+    #[tokio::test]
+    async fn service_upload_custom_domain_certificate() -> anyhow::Result<()> {
+        use chrono::Datelike;
+
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
+
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        let domain: FQDN = "neo.the.matrix".parse().unwrap();
+        let base: FQDN = "the.matrix".parse().unwrap();
+
+        svc.create_project(matrix.clone(), neo, false, never_idle())
             .await
-            .unwrap()
-            .map(|item| item.0)
-            .collect::<Vec<_>>();
+            .unwrap();
 
-        assert_eq!(all_projects.len(), 20);
+        // Valid for a few more days only, so it would be due for a renewal
+        let self_signed = |names: &[&str]| {
+            let not_after = Utc::now() + chrono::Duration::days(10);
+            let mut params = rcgen::CertificateParams::new(
+                names.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            );
+            params.not_after = rcgen::date_time_ymd(
+                not_after.year(),
+                not_after.month() as u8,
+                not_after.day() as u8,
+            );
+            let certificate = rcgen::Certificate::from_params(params).unwrap();
 
-        // Get first 5 projects.
-        let paginated = svc
-            .iter_user_projects_detailed(&neo, 0, 5)
+            (
+                certificate.serialize_pem().unwrap(),
+                certificate.serialize_private_key_pem(),
+            )
+        };
+
+        // The certificate has to cover the domain
+        let (certs, private_key) = self_signed(&["trinity.the.matrix"]);
+        assert_err_kind!(
+            svc.upload_custom_domain_certificate(&matrix, &domain, false, &certs, &private_key)
+                .await,
+            ErrorKind::InvalidCertificate
+        );
+        assert_err_kind!(
+            svc.upload_custom_domain_certificate(&matrix, &domain, false, "certificate", "key")
+                .await,
+            ErrorKind::InvalidCertificate
+        );
+        // And come with its private key
+        assert_err_kind!(
+            svc.upload_custom_domain_certificate(&matrix, &domain, false, &certs, "")
+                .await,
+            ErrorKind::InvalidCertificate
+        );
+        let (_, other_private_key) = self_signed(&["neo.the.matrix"]);
+        let (certs, _) = self_signed(&["neo.the.matrix"]);
+        assert_err_kind!(
+            svc.upload_custom_domain_certificate(
+                &matrix,
+                &domain,
+                false,
+                &certs,
+                &other_private_key
+            )
+            .await,
+            ErrorKind::InvalidCertificate
+        );
+        assert_err_kind!(
+            svc.find_custom_domain(&domain, false).await,
+            ErrorKind::CustomDomainNotFound
+        );
+
+        let (certs, private_key) = self_signed(&["*.the.matrix"]);
+        svc.upload_custom_domain_certificate(&matrix, &domain, false, &certs, &private_key)
             .await
-            .unwrap()
-            .map(|item| item.0)
-            .collect::<Vec<_>>();
+            .unwrap();
+        svc.upload_custom_domain_certificate(&matrix, &base, true, &certs, &private_key)
+            .await
+            .unwrap();
 
-        assert_eq!(all_projects[..5], paginated);
+        let custom_domain = svc.find_custom_domain(&domain, false).await.unwrap();
+        assert_eq!(custom_domain.project_name, matrix);
+        assert_eq!(custom_domain.certificate, certs);
 
-        // Get 10 projects starting at an offset of 10.
-        let paginated = svc
-            .iter_user_projects_detailed(&neo, 10, 10)
+        // Uploaded certificates are never renewed through ACME
+        assert!(svc
+            .find_custom_domains_expiring_within(30)
             .await
             .unwrap()
-            .map(|item| item.0)
-            .collect::<Vec<_>>();
-        assert_eq!(all_projects[10..20], paginated);
+            .is_empty());
 
-        // Get 20 projects starting at an offset of 200.
-        let paginated = svc
-            .iter_user_projects_detailed(&neo, 200, 20)
-            .await
-            .unwrap()
-            .collect::<Vec<_>>();
+        Ok(())
+    }
 
-        assert!(paginated.is_empty());
+    #[tokio::test]
+    async fn service_find_custom_domains_expiring_within() -> anyhow::Result<()> {
+        use chrono::Datelike;
 
-        let mut work = svc
-            .new_task()
-            .project(matrix.clone())
-            .and_then(task::destroy())
-            .build();
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
 
-        while let TaskResult::Pending(_) = work.poll(()).await {}
-        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+        let neo: AccountName = "neo".parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
 
-        // After project has been destroyed...
-        assert!(matches!(
-            svc.find_project(&matrix).await,
-            Ok(Project::Destroyed(_))
-        ));
+        svc.create_project(matrix.clone(), neo, false, never_idle())
+            .await
+            .unwrap();
 
-        // If recreated by a different user
-        assert!(matches!(
-            svc.create_project(matrix.clone(), trinity.clone(), false, 0)
-                .await,
-            Err(Error {
-                kind: ErrorKind::ProjectAlreadyExists,
-                ..
-            })
-        ));
+        let self_signed = |domain: &str, days: i64| {
+            let not_after = Utc::now() + chrono::Duration::days(days);
+            let mut params = rcgen::CertificateParams::new(vec![domain.to_string()]);
+            params.not_after = rcgen::date_time_ymd(
+                not_after.year(),
+                not_after.month() as u8,
+                not_after.day() as u8,
+            );
+            let certificate = rcgen::Certificate::from_params(params).unwrap();
 
-        // If recreated by the same user
-        assert!(matches!(
-            svc.create_project(matrix.clone(), neo, false, 0).await,
-            Ok(Project::Creating(_))
-        ));
+            (
+                certificate.serialize_pem().unwrap(),
+                certificate.serialize_private_key_pem(),
+            )
+        };
 
-        let mut work = svc
-            .new_task()
-            .project(matrix.clone())
-            .and_then(task::destroy())
-            .build();
+        for (domain, days) in [("neo.the.matrix", 10), ("trinity.the.matrix", 300)] {
+            let (certs, private_key) = self_signed(domain, days);
+            svc.create_custom_domain(
+                &matrix,
+                &domain.parse().unwrap(),
+                false,
+                &certs,
+                &private_key,
+            )
+            .await
+            .unwrap();
+        }
+        svc.create_custom_domain(
+            &matrix,
+            &"morpheus.the.matrix".parse().unwrap(),
+            false,
+            "dummy certificate",
+            "dummy private key",
+        )
+        .await
+        .unwrap();
 
-        while let TaskResult::Pending(_) = work.poll(()).await {}
-        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+        let expiring = |days| {
+            let svc = &svc;
+            async move {
+                svc.find_custom_domains_expiring_within(days)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|domain| domain.fqdn.to_string())
+                    .collect::<Vec<_>>()
+            }
+        };
 
-        // After project has been destroyed again...
-        assert!(matches!(
-            svc.find_project(&matrix).await,
-            Ok(Project::Destroyed(_))
-        ));
+        // The dummy certificate has no expiry, so it is never due
+        assert_eq!(expiring(30).await, vec!["neo.the.matrix"]);
+        assert_eq!(
+            expiring(365).await,
+            vec!["neo.the.matrix", "trinity.the.matrix"]
+        );
 
-        // If recreated by an admin
-        assert!(matches!(
-            svc.create_project(matrix, trinity, true, 0).await,
-            Ok(Project::Creating(_))
-        ));
+        // Renewing a certificate stores its new expiry
+        let (certs, private_key) = self_signed("neo.the.matrix", 90);
+        svc.create_custom_domain(
+            &matrix,
+            &"neo.the.matrix".parse().unwrap(),
+            false,
+            &certs,
+            &private_key,
+        )
+        .await
+        .unwrap();
+        assert!(expiring(30).await.is_empty());
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn service_create_ready_kill_restart_docker() -> anyhow::Result<()> {
+    async fn service_reaps_expired_project_stats() -> anyhow::Result<()> {
         let world = World::new().await;
-        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
+        let mut args = world.args();
+        args.stats_retention_days = Some(7);
+        let svc = GatewayService::init(args, world.pool(), "".into()).await;
 
-        let neo: AccountName = "neo".parse().unwrap();
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
         let matrix: ProjectName = "matrix".parse().unwrap();
 
-        svc.create_project(matrix.clone(), neo.clone(), false, 0)
+        svc.create_project(matrix.clone(), neo.clone(), false, never_idle())
             .await
             .unwrap();
 
-        let mut task = svc.new_task().project(matrix.clone()).build();
-
-        while let TaskResult::Pending(_) = task.poll(()).await {
-            // keep polling
+        let now = Utc::now();
+        let old = now - chrono::Duration::days(8);
+        for (sampled_at, cpu_usage) in [(old, 100), (now, 200)] {
+            query(
+                "INSERT INTO project_stats (project_id, cpu_usage, memory_usage, memory_limit, sampled_at)
+                SELECT project_id, ?2, 1024, NULL, ?3 FROM projects WHERE project_name = ?1",
+            )
+            .bind(&matrix)
+            .bind(cpu_usage)
+            .bind(sampled_at.timestamp_millis())
+            .execute(&svc.db)
+            .await?;
         }
 
-        let project = svc.find_project(&matrix).await.unwrap();
-        println!("{:?}", project);
-        assert!(project.is_ready());
+        let history = svc.project_stats_history(&matrix, old).await.unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|sample| sample.cpu_usage)
+                .collect::<Vec<_>>(),
+            vec![100, 200]
+        );
 
-        let container = project.container().unwrap();
-        svc.context()
-            .docker()
-            .kill_container::<String>(container.name.unwrap().strip_prefix('/').unwrap(), None)
-            .await
-            .unwrap();
+        assert_eq!(svc.reap_expired_stats().await.unwrap(), 1);
 
-        println!("killed container");
+        let history = svc.project_stats_history(&matrix, old).await.unwrap();
+        assert_eq!(
+            history,
+            vec![StatsSample {
+                timestamp: Utc.timestamp_millis_opt(now.timestamp_millis()).unwrap(),
+                cpu_usage: 200,
+                memory_usage: Some(1024),
+                memory_limit: None,
+            }]
+        );
 
-        let mut ambulance_task = svc
-            .new_task()
-            .project(matrix.clone())
-            .and_then(task::check_health())
-            .build();
+        assert_err_kind!(
+            svc.project_stats_history(&"reloaded".parse().unwrap(), old)
+                .await,
+            ErrorKind::ProjectNotFound
+        );
 
-        // the first poll will trigger a refresh
-        let _ = ambulance_task.poll(()).await;
+        Ok(())
+    }
 
-        let project = svc.find_project(&matrix).await.unwrap();
-        println!("{:?}", project);
-        assert!(!project.is_ready());
+    #[tokio::test]
+    async fn service_find_ready_target() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
 
-        // the subsequent will trigger a restart task
-        while let TaskResult::Pending(_) = ambulance_task.poll(()).await {
-            // keep polling
-        }
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
 
-        let project = svc.find_project(&matrix).await.unwrap();
-        println!("{:?}", project);
-        assert!(project.is_ready());
+        svc.create_project(matrix.clone(), neo, false, never_idle())
+            .await
+            .unwrap();
+        assert_eq!(svc.find_ready_target(&matrix).await.unwrap(), None);
+
+        // The state and address of a ready project are denormalized when it is updated
+        query("UPDATE projects SET state_kind = 'ready', target_ip = ?1 WHERE project_name = ?2")
+            .bind("10.0.0.2")
+            .bind(&matrix)
+            .execute(&svc.db)
+            .await?;
+        assert_eq!(
+            svc.find_ready_target(&matrix).await.unwrap(),
+            Some("10.0.0.2".parse().unwrap())
+        );
+
+        let ready: Project = serde_json::from_value(serde_json::json!({
+            "ready": {
+                "container": {
+                    "Id": "matrix-container",
+                    "Args": ["--proxy-fqdn", "matrix.test.shuttleapp.rs", "--project", "matrix"],
+                    "Config": {
+                        "Labels": {
+                            "shuttle.project": "matrix",
+                        },
+                    },
+                },
+                "service": {
+                    "name": "matrix",
+                    "target": "10.0.0.3",
+                    "last_check": null,
+                },
+            },
+        }))?;
+        svc.update_project(&matrix, &ready).await.unwrap();
+        assert_eq!(
+            svc.find_ready_target(&matrix).await.unwrap(),
+            Some("10.0.0.3".parse().unwrap())
+        );
+        assert_eq!(
+            svc.find_runtime_fqdn(&matrix).await.unwrap(),
+            Some("matrix.test.shuttleapp.rs".parse().unwrap())
+        );
+
+        let errored = Project::Errored(crate::project::ProjectError::internal("out of band"));
+        svc.update_project(&matrix, &errored).await.unwrap();
+        assert_eq!(svc.find_ready_target(&matrix).await.unwrap(), None);
+        assert_eq!(svc.find_runtime_fqdn(&matrix).await.unwrap(), None);
+
+        assert_err_kind!(
+            svc.find_ready_target(&"oracle".parse().unwrap()).await,
+            ErrorKind::ProjectNotFound
+        );
+        assert_err_kind!(
+            svc.find_runtime_fqdn(&"oracle".parse().unwrap()).await,
+            ErrorKind::ProjectNotFound
+        );
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn service_create_find_custom_domain() -> anyhow::Result<()> {
+    async fn service_concurrent_creates() -> anyhow::Result<()> {
         let world = World::new().await;
         let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
 
-        let account: AccountName = "neo".parse().unwrap();
-        let project_name: ProjectName = "matrix".parse().unwrap();
-        let domain: FQDN = "neo.the.matrix".parse().unwrap();
-        let certificate = "dummy certificate";
-        let private_key = "dummy private key";
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
 
+        let create = || svc.create_project(matrix.clone(), neo.clone(), false, never_idle());
+
+        // Creating a new project
+        let (first, second) = tokio::join!(create(), create());
+        assert_eq!(
+            first.is_ok() as u8 + second.is_ok() as u8,
+            1,
+            "exactly one create should succeed"
+        );
         assert_err_kind!(
-            svc.project_details_for_custom_domain(&domain).await,
-            ErrorKind::CustomDomainNotFound
+            first.and(second).map(|_| ()),
+            ErrorKind::ProjectAlreadyExists
         );
 
-        let _ = svc
-            .create_project(project_name.clone(), account.clone(), false, 0)
-            .await
-            .unwrap();
+        let mut work = svc
+            .new_task()
+            .project(matrix.clone())
+            .and_then(task::destroy())
+            .build();
 
-        svc.create_custom_domain(&project_name, &domain, certificate, private_key)
-            .await
-            .unwrap();
+        while let TaskResult::Pending(_) = work.poll(()).await {}
+        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
 
-        let custom_domain = svc
-            .project_details_for_custom_domain(&domain)
-            .await
-            .unwrap();
+        // Recreating a destroyed project
+        let (first, second) = tokio::join!(create(), create());
+        assert_eq!(
+            first.is_ok() as u8 + second.is_ok() as u8,
+            1,
+            "exactly one recreate should succeed"
+        );
+        assert_err_kind!(
+            first.and(second).map(|_| ()),
+            ErrorKind::ProjectAlreadyExists
+        );
 
-        assert_eq!(custom_domain.project_name, project_name);
-        assert_eq!(custom_domain.certificate, certificate);
-        assert_eq!(custom_domain.private_key, private_key);
+        Ok(())
+    }
 
-        // Should auto replace the domain details
-        let certificate = "dummy certificate update";
-        let private_key = "dummy private key update";
+    #[tokio::test]
+    async fn service_refuses_stale_project_updates() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
+
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
 
-        svc.create_custom_domain(&project_name, &domain, certificate, private_key)
+        svc.create_project(matrix.clone(), neo, false, never_idle())
             .await
             .unwrap();
 
-        let custom_domain = svc
-            .project_details_for_custom_domain(&domain)
+        // Two tasks read the same state
+        let (_, version) = svc.find_project_with_version(&matrix).await.unwrap();
+
+        let errored = Project::Errored(crate::project::ProjectError::internal("first"));
+        svc.update_project_if_unchanged(&matrix, &errored, version)
             .await
             .unwrap();
 
-        assert_eq!(custom_domain.project_name, project_name);
-        assert_eq!(custom_domain.certificate, certificate);
-        assert_eq!(custom_domain.private_key, private_key);
+        // The second write is based on the stale state
+        let stale = Project::Errored(crate::project::ProjectError::internal("second"));
+        assert_err_kind!(
+            svc.update_project_if_unchanged(&matrix, &stale, version)
+                .await,
+            ErrorKind::ProjectConflict
+        );
+        assert_eq!(svc.find_project(&matrix).await.unwrap(), errored);
+
+        // Retrying from the fresh state goes through
+        let (_, version) = svc.find_project_with_version(&matrix).await.unwrap();
+        svc.update_project_if_unchanged(&matrix, &stale, version)
+            .await
+            .unwrap();
+        assert_eq!(svc.find_project(&matrix).await.unwrap(), stale);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn service_create_custom_domain_destroy_recreate_project() -> anyhow::Result<()> {
+    async fn service_refuses_isolated_projects_without_gateway_container() -> anyhow::Result<()> {
+        let isolated = || ProjectOptions {
+            isolated_network: true,
+            ..never_idle()
+        };
+
         let world = World::new().await;
-        let svc = Arc::new(GatewayService::init(world.args(), world.pool(), "".into()).await);
 
-        let account: AccountName = "neo".parse().unwrap();
-        let project_name: ProjectName = "matrix".parse().unwrap();
-        let domain: FQDN = "neo.the.matrix".parse().unwrap();
-        let certificate = "dummy certificate";
-        let private_key = "dummy private key";
+        let mut args = world.args();
+        args.gateway_container = None;
+        let svc = GatewayService::init(args, world.pool(), "".into()).await;
+
+        let neo: AccountName = world.create_user("neo").parse().unwrap();
+        let matrix: ProjectName = "matrix".parse().unwrap();
 
         assert_err_kind!(
-            svc.project_details_for_custom_domain(&domain).await,
-            ErrorKind::CustomDomainNotFound
+            svc.create_project(matrix.clone(), neo.clone(), false, isolated())
+                .await
+                .map(|_| ()),
+            ErrorKind::InvalidOperation
         );
 
-        let _ = svc
-            .create_project(project_name.clone(), account.clone(), false, 0)
-            .await
-            .unwrap();
+        let mut args = world.args();
+        args.gateway_container = Some("gateway".to_string());
+        let svc = GatewayService::init(args, world.pool(), "".into()).await;
+
+        assert!(matches!(
+            svc.create_project(matrix, neo, false, isolated()).await,
+            Ok(Project::Creating(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn service_stores_project_stats() -> anyhow::Result<()> {
+        let world = World::new().await;
+        let mut args = world.args();
+        args.stats_retention_days = Some(7);
+        let svc = GatewayService::init(args, world.pool(), "".into()).await;
 
-        svc.create_custom_domain(&project_name, &domain, certificate, private_key)
+        world.create_user("neo");
+        let matrix: ProjectName = "matrix".parse().unwrap();
+        svc.create_project(matrix.clone(), "neo".parse().unwrap(), false, never_idle())
             .await
             .unwrap();
 
-        let mut work = svc
-            .new_task()
-            .project(project_name.clone())
-            .and_then(task::destroy())
-            .build();
+        let first = container_stats("2023-06-01T12:00:00Z", 100, 1024);
+        let second = container_stats("2023-06-01T12:01:00Z", 200, 2048);
+        let since = "2023-06-01T00:00:00Z".parse().unwrap();
+        let cpu_usage_history = |history: Vec<StatsSample>| {
+            history
+                .into_iter()
+                .map(|sample| sample.cpu_usage)
+                .collect::<Vec<_>>()
+        };
 
-        while let TaskResult::Pending(_) = work.poll(()).await {}
-        assert!(matches!(work.poll(()).await, TaskResult::Done(())));
+        svc.update_project(&matrix, &ready_project(vec![first.clone()]))
+            .await
+            .unwrap();
+        assert_eq!(
+            cpu_usage_history(svc.project_stats_history(&matrix, since).await.unwrap()),
+            vec![100]
+        );
 
-        let recreated_project = svc
-            .create_project(project_name.clone(), account.clone(), false, 0)
+        // Updates between health checks see the same last sample, which is only stored once
+        svc.update_project(&matrix, &ready_project(vec![first.clone()]))
+            .await
+            .unwrap();
+        svc.update_project(&matrix, &ready_project(vec![first, second]))
             .await
             .unwrap();
+        let history = svc.project_stats_history(&matrix, since).await.unwrap();
+        assert_eq!(
+            history.last(),
+            Some(&StatsSample {
+                timestamp: "2023-06-01T12:01:00Z".parse().unwrap(),
+                cpu_usage: 200,
+                memory_usage: Some(2048),
+                memory_limit: Some(1 << 30),
+            })
+        );
+        assert_eq!(cpu_usage_history(history), vec![100, 200]);
+
+        // The history is dropped with the project
+        let destroyed = Project::Creating(ProjectCreating::new_with_random_initial_key(
+            matrix.clone(),
+            0,
+        ))
+        .destroy()
+        .unwrap();
+        svc.update_project(&matrix, &destroyed).await.unwrap();
+        assert!(svc
+            .project_stats_history(&matrix, since)
+            .await
+            .unwrap()
+            .is_empty());
 
-        let Project::Creating(creating) = recreated_project else {
-            panic!("Project should be Creating");
-        };
-        assert_eq!(creating.fqdn(), &Some(domain.to_string()));
+        // None is served when the gateway does not store it
+        let svc = GatewayService::init(world.args(), world.pool(), "".into()).await;
+        assert_err_kind!(
+            svc.project_stats_history(&matrix, since).await,
+            ErrorKind::InvalidOperation
+        );
 
         Ok(())
     }
@@ -1,4 +1,6 @@
 use futures::Future;
+use shuttle_common::models::deployment::{RestartAttempt, RestartKind};
+use shuttle_common::models::project::{self, AuditAction};
 use std::collections::VecDeque;
 use std::marker::PhantomData;
 use std::pin::Pin;
@@ -12,8 +14,9 @@ use uuid::Uuid;
 
 use crate::project::*;
 use crate::service::{GatewayContext, GatewayService};
+use crate::webhook;
 use crate::worker::TaskRouter;
-use crate::{AccountName, EndState, Error, ErrorKind, ProjectName, Refresh, State};
+use crate::{AccountName, DockerContext, EndState, Error, ErrorKind, ProjectName, Refresh, State};
 
 // Default maximum _total_ time a task is allowed to run
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
@@ -153,7 +156,10 @@ pub fn check_health() -> impl Task<ProjectContext, Output = Project, Error = Err
     run(|ctx| async move {
         match ctx.state.refresh(&ctx.gateway).await {
             Ok(Project::Ready(mut ready)) => {
-                if ready.is_healthy().await {
+                if ready
+                    .is_healthy(&ctx.gateway.container_settings().health_path)
+                    .await
+                {
                     TaskResult::Done(Project::Ready(ready))
                 } else {
                     TaskResult::Done(Project::Ready(ready).reboot().unwrap())
@@ -218,6 +224,7 @@ impl TaskBuilder {
                 project_name: self.project_name.expect("project_name is required"),
                 service: self.service,
                 tasks: self.tasks,
+                restart_attempts: Vec::new(),
             },
         ))
     }
@@ -415,6 +422,8 @@ pub struct ProjectTask<T> {
     project_name: ProjectName,
     service: Arc<GatewayService>,
     tasks: VecDeque<T>,
+    /// Attempts at bringing the project back up since it was last ready, to report to its deployment
+    restart_attempts: Vec<RestartAttempt>,
 }
 
 impl<T> ProjectTask<T> {
@@ -458,8 +467,13 @@ where
         }
 
         let ctx = self.service.context();
+        let webhook_url = ctx.container_settings().webhook_url.clone();
 
-        let project = match self.service.find_project(&self.project_name).await {
+        let (project, version) = match self
+            .service
+            .find_project_with_version(&self.project_name)
+            .await
+        {
             Ok(project) => project,
             Err(err) => return TaskResult::Err(err),
         };
@@ -472,21 +486,24 @@ where
             Ok(account_name) => account_name,
             Err(err) => return TaskResult::Err(err),
         };
-        let admin_secret = match self
-            .service
-            .control_key_from_project_name(&self.project_name)
-            .await
-        {
-            Ok(account_name) => account_name,
-            Err(err) => return TaskResult::Err(err),
+        let admin_secret = match project.admin_secret() {
+            Some(admin_secret) => admin_secret,
+            None => match self
+                .service
+                .control_key_from_project_name(&self.project_name)
+                .await
+            {
+                Ok(admin_secret) => admin_secret,
+                Err(err) => return TaskResult::Err(err),
+            },
         };
 
         let project_ctx = ProjectContext {
             project_name: self.project_name.clone(),
             account_name: account_name.clone(),
-            gateway: ctx,
+            gateway: ctx.clone(),
             state: project,
-            admin_secret,
+            admin_secret: admin_secret.clone(),
         };
 
         let span = info_span!(
@@ -497,6 +514,11 @@ where
         );
         let _ = span.enter();
 
+        let previous_state = project_ctx.state.state();
+        let was_destroyed = matches!(
+            project_ctx.state,
+            Project::Destroying(_) | Project::Destroyed(_)
+        );
         let task = self.tasks.front_mut().unwrap();
 
         let timeout = sleep(PROJECT_TASK_MAX_IDLE_TIMEOUT);
@@ -519,11 +541,75 @@ where
             trace!(new_state = ?update.state(), "new state");
             match self
                 .service
-                .update_project(&self.project_name, update)
+                .update_project_if_unchanged(&self.project_name, update, version)
                 .await
             {
                 Ok(_) => {
                     trace!(new_state = ?update.state(), "successfully updated project state");
+
+                    if update.state() != previous_state
+                        && matches!(update, Project::Restarting(_) | Project::Rebooting(_))
+                    {
+                        if let Err(err) = self
+                            .service
+                            .increment_total_restarts(&self.project_name)
+                            .await
+                        {
+                            error!(err = %err, "could not count project restart");
+                        }
+                    }
+
+                    if !was_destroyed
+                        && matches!(update, Project::Destroying(_) | Project::Destroyed(_))
+                    {
+                        if let Err(err) = self
+                            .service
+                            .record_audit(
+                                &self.project_name,
+                                &account_name,
+                                AuditAction::Destroy,
+                                None,
+                            )
+                            .await
+                        {
+                            error!(err = %err, "could not record project destruction");
+                        }
+                    }
+
+                    if update.state() != previous_state {
+                        if let Some(attempt) = restart_attempt(update, &self.restart_attempts) {
+                            self.restart_attempts.push(attempt);
+                        }
+                    }
+
+                    if let Project::Ready(ready) = update {
+                        if !self.restart_attempts.is_empty() {
+                            let ready = ready.clone();
+                            let attempts = std::mem::take(&mut self.restart_attempts);
+                            let jwt = ctx.get_jwt().await;
+                            let admin_secret = admin_secret.clone();
+
+                            tokio::spawn(async move {
+                                ready
+                                    .report_restart_attempts(jwt, admin_secret, attempts)
+                                    .await
+                            });
+                        }
+                    }
+
+                    if let Some(webhook_url) = webhook_url {
+                        if update.state() != previous_state && webhook::should_notify(update) {
+                            webhook::notify(webhook_url, &self.project_name, update);
+                        }
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::ProjectConflict => {
+                    // Another task changed the project in the meantime, so start over from its state
+                    warn!(
+                        project_name = ?self.project_name,
+                        "project state changed while polling, trying again"
+                    );
+                    return TaskResult::TryAgain;
                 }
                 Err(err) => {
                     error!(err = %err, "could not update project state");
@@ -554,6 +640,26 @@ where
     }
 }
 
+/// The attempt at bringing a project back up which `project` is in, if any. Reboots do not keep a count, so
+/// they are counted from the `previous` attempts.
+fn restart_attempt(project: &Project, previous: &[RestartAttempt]) -> Option<RestartAttempt> {
+    let (kind, attempt) = match project::State::from(project.clone()) {
+        project::State::Restarting { restart_count } => (RestartKind::Restart, restart_count),
+        project::State::Recreating { recreate_count } => (RestartKind::Recreate, recreate_count),
+        project::State::Rebooting => {
+            let reboots = previous
+                .iter()
+                .filter(|attempt| attempt.kind == RestartKind::Reboot)
+                .count();
+
+            (RestartKind::Reboot, reboots + 1)
+        }
+        _ => return None,
+    };
+
+    Some(RestartAttempt { kind, attempt })
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
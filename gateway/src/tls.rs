@@ -8,6 +8,8 @@ use axum_server::accept::DefaultAcceptor;
 use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
 use futures::executor::block_on;
 use pem::Pem;
+use rsa::pkcs1::{DecodeRsaPrivateKey, EncodeRsaPublicKey};
+use rsa::RsaPrivateKey;
 use rustls::server::{ClientHello, ResolvesServerCert};
 use rustls::sign::{self, CertifiedKey};
 use rustls::{Certificate, PrivateKey, ServerConfig};
@@ -15,7 +17,9 @@ use rustls_pemfile::Item;
 use shuttle_common::models::error::ErrorKind;
 use tokio::runtime::Handle;
 use tokio::sync::RwLock;
+use x509_parser::parse_x509_certificate;
 
+use crate::acme::wildcard_for;
 use crate::Error;
 
 /// LetsEncrypt recommends to renew a certificate when its close to 30 days validity window.
@@ -46,7 +50,7 @@ impl ChainAndPrivateKey {
 
         Ok(Self {
             chain,
-            private_key: private_key.unwrap(),
+            private_key: private_key.ok_or_else(|| Error::from_kind(ErrorKind::Internal))?,
         })
     }
 
@@ -78,6 +82,28 @@ impl ChainAndPrivateKey {
         Ok(CertifiedKey::new(self.chain, signing_key))
     }
 
+    /// Whether the private key is the one of the first certificate of the chain, by comparing
+    /// the public key derived from it with the one in the certificate
+    pub fn key_matches_certificate(&self) -> bool {
+        let Some(certificate) = self.chain.first() else {
+            return false;
+        };
+        let Ok((_, certificate)) = parse_x509_certificate(&certificate.0) else {
+            return false;
+        };
+        let certificate_key: &[u8] = &certificate.public_key().subject_public_key.data;
+
+        // PKCS#8 keys of any type, then PKCS#1 RSA keys
+        if let Ok(key_pair) = rcgen::KeyPair::from_der(&self.private_key.0) {
+            return key_pair.public_key_raw() == certificate_key;
+        }
+
+        RsaPrivateKey::from_pkcs1_der(&self.private_key.0)
+            .ok()
+            .and_then(|key| key.to_public_key().to_pkcs1_der().ok())
+            .map_or(false, |public_key| public_key.as_bytes() == certificate_key)
+    }
+
     pub fn save_pem<P: AsRef<Path>>(self, path: P) -> Result<(), Error> {
         let as_pem = self.into_pem()?;
         let mut f = File::create(path)?;
@@ -106,9 +132,13 @@ impl GatewayCertResolver {
     }
 
     /// Get the loaded [CertifiedKey] associated with the given
-    /// domain.
+    /// domain, or else with the wildcard domain covering it.
     pub async fn get(&self, sni: &str) -> Option<Arc<CertifiedKey>> {
-        self.keys.read().await.get(sni).map(Arc::clone)
+        let keys = self.keys.read().await;
+
+        keys.get(sni)
+            .or_else(|| keys.get(&wildcard_for(sni)?))
+            .map(Arc::clone)
     }
 
     pub async fn serve_default_der(&self, certs: ChainAndPrivateKey) -> Result<(), Error> {
@@ -136,6 +166,12 @@ impl GatewayCertResolver {
         let certs = ChainAndPrivateKey::parse_pem(rd)?;
         self.serve_der(sni, certs).await
     }
+
+    /// Stop serving the certificate of the given domain. Returns
+    /// whether a certificate was being served for it.
+    pub async fn remove(&self, sni: &str) -> bool {
+        self.keys.write().await.remove(sni).is_some()
+    }
 }
 
 impl ResolvesServerCert for GatewayCertResolver {
@@ -0,0 +1,186 @@
+//! Passing through the connection upgrades, like WebSockets, of requests routed to projects.
+//!
+//! The handshake is forwarded to the project as is. Once the project switches protocols, the
+//! upgraded connections of the client and of the project are bridged until either side closes.
+
+use std::net::IpAddr;
+
+use axum::body::Body;
+use axum::http::Request;
+use axum::response::Response;
+use http::header::{CONNECTION, UPGRADE};
+use http::{HeaderValue, StatusCode, Uri};
+use hyper::client::HttpConnector;
+use hyper::Client;
+use once_cell::sync::Lazy;
+use tokio::io::copy_bidirectional;
+use tracing::{debug, warn};
+
+use crate::{Error, ErrorKind};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+static UPGRADE_CLIENT: Lazy<Client<HttpConnector>> = Lazy::new(Client::new);
+
+/// Whether the client asks to upgrade the connection of this request
+pub fn is_upgrade(req: &Request<Body>) -> bool {
+    let asks_upgrade = req
+        .headers()
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+
+    asks_upgrade && req.headers().contains_key(UPGRADE)
+}
+
+/// Forward an upgrade request to `target_url` and, once the project accepts it, bridge the
+/// upgraded connections in the background
+pub async fn forward(
+    client_ip: IpAddr,
+    target_url: &str,
+    mut req: Request<Body>,
+) -> Result<Response<Body>, Error> {
+    let client_upgrade = hyper::upgrade::on(&mut req);
+
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+    let uri: Uri = format!("{target_url}{path_and_query}")
+        .parse()
+        .map_err(|error| Error::source(ErrorKind::Internal, error))?;
+
+    let (mut parts, body) = req.into_parts();
+    parts.uri = uri;
+
+    // Append the client like the proxy client does for other requests
+    let forwarded_for = match parts.headers.get(X_FORWARDED_FOR) {
+        Some(existing) => format!("{}, {client_ip}", existing.to_str().unwrap_or_default()),
+        None => client_ip.to_string(),
+    };
+    if let Ok(forwarded_for) = HeaderValue::from_str(&forwarded_for) {
+        parts.headers.insert(X_FORWARDED_FOR, forwarded_for);
+    }
+
+    let mut resp = UPGRADE_CLIENT
+        .request(Request::from_parts(parts, body))
+        .await
+        .map_err(|error| Error::source(ErrorKind::ProjectUnavailable, error))?;
+
+    if resp.status() == StatusCode::SWITCHING_PROTOCOLS {
+        let project_upgrade = hyper::upgrade::on(&mut resp);
+
+        tokio::spawn(async move {
+            match tokio::try_join!(client_upgrade, project_upgrade) {
+                Ok((mut client, mut project)) => {
+                    match copy_bidirectional(&mut client, &mut project).await {
+                        Ok((to_project, to_client)) => {
+                            debug!(to_project, to_client, "upgraded connection closed")
+                        }
+                        Err(error) => debug!(%error, "upgraded connection closed with an error"),
+                    }
+                }
+                Err(error) => warn!(%error, "failed to upgrade routed connection"),
+            }
+        });
+    }
+
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+    use axum::routing::get;
+    use axum::Router;
+    use futures::{SinkExt, StreamExt};
+    use hyper::service::{make_service_fn, service_fn};
+    use tokio_tungstenite::tungstenite;
+
+    use super::*;
+
+    async fn echo(mut socket: WebSocket) {
+        while let Some(Ok(msg)) = socket.recv().await {
+            if socket.send(msg).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn websocket_echo_through_upgrade() {
+        // Stand in for a project serving a websocket echo endpoint
+        let project_addr =
+            SocketAddr::from((Ipv4Addr::LOCALHOST, portpicker::pick_unused_port().unwrap()));
+        let project = Router::new().route(
+            "/ws",
+            get(|ws: WebSocketUpgrade| async move { ws.on_upgrade(echo) }),
+        );
+        tokio::spawn(axum::Server::bind(&project_addr).serve(project.into_make_service()));
+
+        // Stand in for the gateway routing to it
+        let gateway_addr =
+            SocketAddr::from((Ipv4Addr::LOCALHOST, portpicker::pick_unused_port().unwrap()));
+        let target_url = format!("http://{project_addr}");
+        let make_service = make_service_fn(move |_| {
+            let target_url = target_url.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let target_url = target_url.clone();
+                    async move {
+                        assert!(is_upgrade(&req));
+                        Ok::<_, Infallible>(
+                            forward(Ipv4Addr::LOCALHOST.into(), &target_url, req)
+                                .await
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+        tokio::spawn(hyper::Server::bind(&gateway_addr).serve(make_service));
+
+        let (mut socket, resp) =
+            tokio_tungstenite::connect_async(format!("ws://{gateway_addr}/ws"))
+                .await
+                .unwrap();
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+        socket
+            .send(tungstenite::Message::Text(
+                "follow the white rabbit".to_string(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            socket.next().await.unwrap().unwrap(),
+            tungstenite::Message::Text("follow the white rabbit".to_string())
+        );
+
+        socket
+            .send(tungstenite::Message::Close(None))
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn detects_upgrades() {
+        let upgrade = Request::get("/ws")
+            .header(CONNECTION, "keep-alive, Upgrade")
+            .header(UPGRADE, "websocket")
+            .body(Body::empty())
+            .unwrap();
+        assert!(is_upgrade(&upgrade));
+
+        let plain = Request::get("/ws")
+            .header(CONNECTION, "keep-alive")
+            .body(Body::empty())
+            .unwrap();
+        assert!(!is_upgrade(&plain));
+    }
+}
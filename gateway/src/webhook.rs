@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use shuttle_common::models::project::State;
+use tokio::time::sleep;
+use tracing::{debug, error, warn};
+
+use crate::project::Project;
+use crate::ProjectName;
+
+const MAX_ATTEMPTS: usize = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+/// The payloads POSTed to the webhook, told apart by their `event` field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    StateChange(ProjectStateChange),
+    IdleWarning(IdleWarning),
+}
+
+impl Event {
+    pub fn project_name(&self) -> &str {
+        match self {
+            Self::StateChange(change) => &change.project_name,
+            Self::IdleWarning(warning) => &warning.project_name,
+        }
+    }
+}
+
+/// Sent when a project changes state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectStateChange {
+    pub project_name: String,
+    pub state: State,
+}
+
+/// Sent when an idle project is about to be stopped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdleWarning {
+    pub project_name: String,
+    /// Health checks, about a minute apart, the project has left to see traffic before it is stopped
+    pub grace_checks: u32,
+}
+
+/// Only transitions into these states are of interest to external systems
+pub fn should_notify(project: &Project) -> bool {
+    matches!(
+        project,
+        Project::Ready(_) | Project::Errored(_) | Project::Stopped(_) | Project::Destroyed(_)
+    )
+}
+
+/// Notify the webhook at `url` of a project entering a new state.
+///
+/// Delivery happens in the background so the state machine is never
+/// blocked on the receiving end.
+pub fn notify(url: String, project_name: &ProjectName, project: &Project) {
+    let change = Event::StateChange(ProjectStateChange {
+        project_name: project_name.to_string(),
+        state: project.clone().into(),
+    });
+
+    tokio::spawn(deliver(url, change));
+}
+
+/// Warn the webhook at `url` that a project is idle and will be stopped
+/// unless it sees traffic within `grace_checks` health checks
+pub fn warn_idle(url: String, project_name: &ProjectName, grace_checks: u32) {
+    let warning = Event::IdleWarning(IdleWarning {
+        project_name: project_name.to_string(),
+        grace_checks,
+    });
+
+    tokio::spawn(deliver(url, warning));
+}
+
+/// Deliver an event, retrying with an exponential backoff until
+/// [MAX_ATTEMPTS] is reached
+pub async fn deliver(url: String, event: Event) -> bool {
+    let project_name = event.project_name();
+    let body = match serde_json::to_vec(&event) {
+        Ok(body) => body,
+        Err(error) => {
+            error!(%error, "failed to serialize webhook payload");
+            return false;
+        }
+    };
+
+    let mut delay = INITIAL_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let res = CLIENT
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status());
+
+        match res {
+            Ok(_) => {
+                debug!(project_name, "delivered webhook payload");
+                return true;
+            }
+            Err(error) => {
+                warn!(
+                    %error,
+                    project_name,
+                    attempt,
+                    "failed to deliver webhook payload"
+                );
+            }
+        }
+
+        if attempt < MAX_ATTEMPTS {
+            sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
+    error!(
+        project_name,
+        "giving up on delivering webhook payload after {MAX_ATTEMPTS} attempts"
+    );
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use axum::extract::State as AxumState;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn deliver_retries_until_success() {
+        let port = portpicker::pick_unused_port().unwrap();
+        let addr: SocketAddr = format!("127.0.0.1:{port}").parse().unwrap();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let (tx, mut rx) = mpsc::channel(1);
+
+        let router = Router::new()
+            .route(
+                "/hook",
+                post(
+                    |AxumState((attempts, tx)): AxumState<(
+                        Arc<AtomicUsize>,
+                        mpsc::Sender<Event>,
+                    )>,
+                     Json(change): Json<Event>| async move {
+                        // Fail the first delivery to exercise the retry
+                        if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                            StatusCode::INTERNAL_SERVER_ERROR
+                        } else {
+                            tx.send(change).await.unwrap();
+                            StatusCode::OK
+                        }
+                    },
+                ),
+            )
+            .with_state((attempts.clone(), tx));
+
+        tokio::spawn(axum::Server::bind(&addr).serve(router.into_make_service()));
+
+        let change = Event::StateChange(ProjectStateChange {
+            project_name: "matrix".to_string(),
+            state: State::Ready,
+        });
+
+        assert!(deliver(format!("http://{addr}/hook"), change.clone()).await);
+        assert_eq!(rx.recv().await.unwrap(), change);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn events_are_told_apart() {
+        let warning = Event::IdleWarning(IdleWarning {
+            project_name: "matrix".to_string(),
+            grace_checks: 2,
+        });
+        let json = serde_json::to_value(&warning).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "event": "idle_warning",
+                "project_name": "matrix",
+                "grace_checks": 2,
+            })
+        );
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), warning);
+
+        let change = Event::StateChange(ProjectStateChange {
+            project_name: "matrix".to_string(),
+            state: State::Stopped,
+        });
+
+        assert_eq!(
+            serde_json::to_value(&change).unwrap()["event"],
+            "state_change"
+        );
+    }
+}
@@ -1,16 +1,22 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use async_trait::async_trait;
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
-use crate::task::{BoxedTask, TaskResult};
+use crate::task::{BoxedTask, Task, TaskResult};
 use crate::{Error, ProjectName};
 
 pub const WORKER_QUEUE_SIZE: usize = 2048;
 
+/// Default number of projects the [TaskRouter] keeps a worker for before
+/// evicting the idle ones
+pub const TASK_ROUTER_LIMIT: usize = 1024;
+
 pub struct Worker<W = BoxedTask> {
     send: Option<Sender<W>>,
     recv: Receiver<W>,
@@ -77,14 +83,35 @@ impl Worker<BoxedTask> {
     }
 }
 
+/// The worker of a project, along with the number of its tasks which are
+/// queued or running
+struct RouterEntry<W> {
+    sender: Sender<W>,
+    active: Arc<AtomicUsize>,
+}
+
+impl<W> RouterEntry<W> {
+    fn is_idle(&self) -> bool {
+        self.active.load(Ordering::SeqCst) == 0
+    }
+}
+
+/// Routes the tasks of each project to a worker of its own, so they are run
+/// one after the other.
+///
+/// Workers are kept around for the projects they served. Once there are
+/// `limit` of them, the workers without queued or running tasks are evicted
+/// to make room for new ones.
 pub struct TaskRouter<W> {
-    table: Arc<RwLock<HashMap<ProjectName, Sender<W>>>>,
+    table: Arc<RwLock<HashMap<ProjectName, RouterEntry<W>>>>,
+    limit: usize,
 }
 
 impl<W> Clone for TaskRouter<W> {
     fn clone(&self) -> Self {
         Self {
             table: self.table.clone(),
+            limit: self.limit,
         }
     }
 }
@@ -97,10 +124,56 @@ impl<W> Default for TaskRouter<W> {
 
 impl<W> TaskRouter<W> {
     pub fn new() -> Self {
+        Self::with_limit(TASK_ROUTER_LIMIT)
+    }
+
+    pub fn with_limit(limit: usize) -> Self {
         Self {
             table: Arc::new(RwLock::new(HashMap::new())),
+            limit,
         }
     }
+
+    /// Number of projects a worker is kept for before idle ones are evicted
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Number of projects a worker is currently kept for
+    pub async fn tracked_count(&self) -> usize {
+        self.table.read().await.len()
+    }
+
+    /// Number of projects with queued or running tasks
+    pub async fn active_count(&self) -> usize {
+        self.table
+            .read()
+            .await
+            .values()
+            .filter(|entry| !entry.is_idle())
+            .count()
+    }
+
+    /// Drop the workers of projects without queued or running tasks.
+    /// Returns how many were evicted.
+    pub async fn evict_idle(&self) -> usize {
+        evict_idle(&mut *self.table.write().await)
+    }
+}
+
+fn evict_idle<W>(table: &mut HashMap<ProjectName, RouterEntry<W>>) -> usize {
+    let before = table.len();
+
+    table.retain(|_, entry| !entry.is_idle());
+
+    let evicted = before - table.len();
+    debug!(
+        evicted,
+        tracked = table.len(),
+        "evicted idle task router entries"
+    );
+
+    evicted
 }
 
 impl TaskRouter<BoxedTask> {
@@ -110,19 +183,147 @@ impl TaskRouter<BoxedTask> {
         task: BoxedTask,
     ) -> Result<(), SendError<BoxedTask>> {
         let mut table = self.table.write().await;
-        if let Some(sender) = table.get(name) {
-            sender.send(task).await
-        } else {
+
+        if !table.contains_key(name) && table.len() >= self.limit {
+            evict_idle(&mut table);
+
+            if table.len() >= self.limit {
+                warn!(
+                    tracked = table.len(),
+                    limit = self.limit,
+                    "task router is over its limit with only active projects"
+                );
+            }
+        }
+
+        let entry = table.entry(name.clone()).or_insert_with(|| {
             let worker = Worker::new();
             let sender = worker.sender();
 
             tokio::spawn(worker.start());
 
-            let res = sender.send(task).await;
+            RouterEntry {
+                sender,
+                active: Default::default(),
+            }
+        });
+
+        let task = Box::new(Tracked::new(task, entry.active.clone()));
+
+        entry.sender.send(task).await
+    }
+}
+
+/// A routed task, counted as active on its [RouterEntry] until it is
+/// dropped by the worker
+struct Tracked {
+    inner: BoxedTask,
+    active: Arc<AtomicUsize>,
+}
+
+impl Tracked {
+    fn new(inner: BoxedTask, active: Arc<AtomicUsize>) -> Self {
+        active.fetch_add(1, Ordering::SeqCst);
 
-            table.insert(name.clone(), sender);
+        Self { inner, active }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Task<()> for Tracked {
+    type Output = ();
+
+    type Error = Error;
+
+    async fn poll(&mut self, ctx: ()) -> TaskResult<Self::Output, Self::Error> {
+        self.inner.poll(ctx).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+    use tokio::time::{sleep, timeout};
+
+    use super::*;
+
+    /// A task which is done right away
+    struct Noop;
+
+    #[async_trait]
+    impl Task<()> for Noop {
+        type Output = ();
+
+        type Error = Error;
+
+        async fn poll(&mut self, _ctx: ()) -> TaskResult<Self::Output, Self::Error> {
+            TaskResult::Done(())
+        }
+    }
+
+    /// A task which is done once it is released
+    struct WaitFor(oneshot::Receiver<()>);
+
+    #[async_trait]
+    impl Task<()> for WaitFor {
+        type Output = ();
+
+        type Error = Error;
 
-            res
+        async fn poll(&mut self, _ctx: ()) -> TaskResult<Self::Output, Self::Error> {
+            let _ = (&mut self.0).await;
+            TaskResult::Done(())
         }
     }
+
+    async fn wait_until_active(router: &TaskRouter<BoxedTask>, count: usize) {
+        timeout(Duration::from_secs(5), async {
+            while router.active_count().await != count {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("tasks should complete");
+    }
+
+    #[tokio::test]
+    async fn task_router_evicts_idle_projects() {
+        let router = TaskRouter::with_limit(10);
+
+        let busy: ProjectName = "busy".parse().unwrap();
+        let (release, blocked) = oneshot::channel();
+        router
+            .route(&busy, Box::new(WaitFor(blocked)))
+            .await
+            .unwrap();
+
+        for i in 0..100 {
+            let name: ProjectName = format!("project-{i}").parse().unwrap();
+            router.route(&name, Box::new(Noop)).await.unwrap();
+
+            wait_until_active(&router, 1).await;
+            assert!(
+                router.tracked_count().await <= router.limit(),
+                "idle projects should be evicted past the limit"
+            );
+        }
+
+        // The project with a running task is never evicted
+        assert!(router.table.read().await.contains_key(&busy));
+
+        release.send(()).unwrap();
+        wait_until_active(&router, 0).await;
+
+        let tracked = router.tracked_count().await;
+        assert_eq!(router.evict_idle().await, tracked);
+        assert_eq!(router.tracked_count().await, 0);
+    }
 }
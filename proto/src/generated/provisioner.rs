@@ -20,7 +20,7 @@ pub mod database_request {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Shared {
-    #[prost(oneof = "shared::Engine", tags = "1, 50")]
+    #[prost(oneof = "shared::Engine", tags = "1, 50, 51, 52")]
     pub engine: ::core::option::Option<shared::Engine>,
 }
 /// Nested message and enum types in `Shared`.
@@ -32,12 +32,16 @@ pub mod shared {
         Postgres(::prost::alloc::string::String),
         #[prost(string, tag = "50")]
         Mongodb(::prost::alloc::string::String),
+        #[prost(string, tag = "51")]
+        Sqlite(::prost::alloc::string::String),
+        #[prost(string, tag = "52")]
+        Mysql(::prost::alloc::string::String),
     }
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct AwsRds {
-    #[prost(oneof = "aws_rds::Engine", tags = "1, 2, 3")]
+    #[prost(oneof = "aws_rds::Engine", tags = "1, 2, 3, 4")]
     pub engine: ::core::option::Option<aws_rds::Engine>,
 }
 /// Nested message and enum types in `AwsRds`.
@@ -51,6 +55,8 @@ pub mod aws_rds {
         Mysql(super::RdsConfig),
         #[prost(message, tag = "3")]
         Mariadb(super::RdsConfig),
+        #[prost(message, tag = "4")]
+        Sqlserver(super::RdsConfig),
     }
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
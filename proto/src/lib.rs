@@ -33,6 +33,8 @@ pub mod provisioner {
                     let engine = match engine {
                         SharedEngine::Postgres => shared::Engine::Postgres(String::new()),
                         SharedEngine::MongoDb => shared::Engine::Mongodb(String::new()),
+                        SharedEngine::Sqlite => shared::Engine::Sqlite(String::new()),
+                        SharedEngine::MySql => shared::Engine::Mysql(String::new()),
                     };
                     database_request::DbType::Shared(Shared {
                         engine: Some(engine),
@@ -44,6 +46,7 @@ pub mod provisioner {
                         AwsRdsEngine::Postgres => aws_rds::Engine::Postgres(config),
                         AwsRdsEngine::MariaDB => aws_rds::Engine::Mariadb(config),
                         AwsRdsEngine::MySql => aws_rds::Engine::Mysql(config),
+                        AwsRdsEngine::SqlServer => aws_rds::Engine::Sqlserver(config),
                     };
                     database_request::DbType::AwsRds(AwsRds {
                         engine: Some(engine),
@@ -65,6 +68,8 @@ pub mod provisioner {
                     shared::Engine::Mongodb(_) => {
                         Some(database::Type::Shared(SharedEngine::MongoDb))
                     }
+                    shared::Engine::Sqlite(_) => Some(database::Type::Shared(SharedEngine::Sqlite)),
+                    shared::Engine::Mysql(_) => Some(database::Type::Shared(SharedEngine::MySql)),
                 },
                 database_request::DbType::AwsRds(AwsRds {
                     engine: Some(engine),
@@ -76,6 +81,9 @@ pub mod provisioner {
                     aws_rds::Engine::Mariadb(_) => {
                         Some(database::Type::AwsRds(AwsRdsEngine::MariaDB))
                     }
+                    aws_rds::Engine::Sqlserver(_) => {
+                        Some(database::Type::AwsRds(AwsRdsEngine::SqlServer))
+                    }
                 },
                 database_request::DbType::Shared(Shared { engine: None })
                 | database_request::DbType::AwsRds(AwsRds { engine: None }) => None,
@@ -89,6 +97,7 @@ pub mod provisioner {
                 Self::Mariadb(_) => write!(f, "mariadb"),
                 Self::Mysql(_) => write!(f, "mysql"),
                 Self::Postgres(_) => write!(f, "postgres"),
+                Self::Sqlserver(_) => write!(f, "sqlserver"),
             }
         }
     }
@@ -147,6 +156,7 @@ pub mod runtime {
                 line: log.line,
                 target: log.target,
                 fields: log.fields,
+                trace_id: None,
             })
         }
     }
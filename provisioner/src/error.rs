@@ -37,6 +37,9 @@ pub enum Error {
     #[error("failed to get description of RDS instance: {0}")]
     DescribeRDSInstance(#[from] SdkError<DescribeDBInstancesError>),
 
+    #[error("unsupported database engine: {0}")]
+    UnsupportedEngine(String),
+
     #[error["plain error: {0}"]]
     Plain(String),
 }
@@ -45,6 +48,11 @@ unsafe impl Send for Error {}
 
 impl From<Error> for Status {
     fn from(err: Error) -> Self {
+        // A request for an engine which is never provisioned is the caller's mistake
+        if let Error::UnsupportedEngine(_) = err {
+            return Status::invalid_argument(err.to_string());
+        }
+
         error!(error = &err as &dyn std::error::Error, "provision failed");
         Status::internal("failed to provision a database")
     }
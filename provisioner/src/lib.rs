@@ -18,7 +18,9 @@ use shuttle_proto::provisioner::{
 };
 use shuttle_proto::provisioner::{provisioner_server::Provisioner, DatabaseDeletionResponse};
 use shuttle_proto::provisioner::{Ping, Pong};
-use sqlx::{postgres::PgPoolOptions, ConnectOptions, Executor, PgPool};
+use sqlx::{
+    mysql::MySqlPoolOptions, postgres::PgPoolOptions, ConnectOptions, Executor, MySqlPool, PgPool,
+};
 use tokio::time::sleep;
 use tonic::{Request, Response, Status};
 use tracing::{debug, info};
@@ -27,6 +29,10 @@ mod args;
 mod error;
 
 const AWS_RDS_CLASS: &str = "db.t4g.micro";
+/// SQL Server is not offered on the Graviton instance classes
+const AWS_RDS_SQLSERVER_CLASS: &str = "db.t3.small";
+/// Database to connect to on SQL Server instances, which are created without one
+const SQLSERVER_DEFAULT_DATABASE: &str = "master";
 const MASTER_USERNAME: &str = "master";
 const RDS_SUBNET_GROUP: &str = "shuttle_rds";
 
@@ -34,18 +40,22 @@ pub struct MyProvisioner {
     pool: PgPool,
     rds_client: aws_sdk_rds::Client,
     mongodb_client: mongodb::Client,
+    mysql_pool: Option<MySqlPool>,
     fqdn: String,
     internal_pg_address: String,
     internal_mongodb_address: String,
+    internal_mysql_address: String,
 }
 
 impl MyProvisioner {
     pub async fn new(
         shared_pg_uri: &str,
         shared_mongodb_uri: &str,
+        shared_mysql_uri: Option<&str>,
         fqdn: String,
         internal_pg_address: String,
         internal_mongodb_address: String,
+        internal_mysql_address: String,
     ) -> Result<Self, Error> {
         let pool = PgPoolOptions::new()
             .min_connections(4)
@@ -56,6 +66,15 @@ impl MyProvisioner {
         let mongodb_options = ClientOptions::parse(shared_mongodb_uri).await?;
         let mongodb_client = mongodb::Client::with_options(mongodb_options)?;
 
+        let mysql_pool = shared_mysql_uri
+            .map(|uri| {
+                MySqlPoolOptions::new()
+                    .max_connections(4)
+                    .acquire_timeout(Duration::from_secs(60))
+                    .connect_lazy(uri)
+            })
+            .transpose()?;
+
         // Default timeout is too long so lowering it
         let timeout_config = timeout::TimeoutConfig::builder()
             .operation_timeout(Duration::from_secs(120))
@@ -73,9 +92,11 @@ impl MyProvisioner {
             pool,
             rds_client,
             mongodb_client,
+            mysql_pool,
             fqdn,
             internal_pg_address,
             internal_mongodb_address,
+            internal_mysql_address,
         })
     }
 
@@ -114,6 +135,23 @@ impl MyProvisioner {
                     port: "27017".to_string(),
                 })
             }
+            shared::Engine::Mysql(_) => {
+                let (username, password, database_name) = self.shared_mysql(project_name).await?;
+
+                Ok(DatabaseResponse {
+                    engine: "mysql".to_string(),
+                    username,
+                    password,
+                    database_name,
+                    address_private: self.internal_mysql_address.clone(),
+                    address_public: self.fqdn.clone(),
+                    port: "3306".to_string(),
+                })
+            }
+            shared::Engine::Sqlite(_) => Err(Error::UnsupportedEngine(
+                "sqlite databases are embedded in the service and cannot be provisioned"
+                    .to_string(),
+            )),
         }
     }
 
@@ -245,6 +283,50 @@ impl MyProvisioner {
         }
     }
 
+    fn mysql_pool(&self) -> Result<&MySqlPool, Error> {
+        self.mysql_pool.as_ref().ok_or_else(|| {
+            Error::Plain("shared MySQL databases are not offered by this provisioner".to_string())
+        })
+    }
+
+    async fn shared_mysql(&self, project_name: &str) -> Result<(String, String, String), Error> {
+        let pool = self.mysql_pool()?;
+        let username = format!("user-{project_name}");
+        let password = generate_password();
+        let database_name = format!("db-{project_name}");
+
+        // Binding does not work for identifiers
+        let create_db_query = format!("CREATE DATABASE IF NOT EXISTS `{database_name}`");
+        sqlx::query(&create_db_query)
+            .execute(pool)
+            .await
+            .map_err(|e| Error::CreateDB(e.to_string()))?;
+
+        let create_user_query =
+            format!("CREATE USER IF NOT EXISTS '{username}'@'%' IDENTIFIED BY '{password}'");
+        sqlx::query(&create_user_query)
+            .execute(pool)
+            .await
+            .map_err(|e| Error::CreateRole(e.to_string()))?;
+
+        // Cycles the password when the user already existed
+        let update_user_query = format!("ALTER USER '{username}'@'%' IDENTIFIED BY '{password}'");
+        sqlx::query(&update_user_query)
+            .execute(pool)
+            .await
+            .map_err(|e| Error::UpdateRole(e.to_string()))?;
+
+        // Only let the user see its own database
+        let grant_query =
+            format!("GRANT ALL PRIVILEGES ON `{database_name}`.* TO '{username}'@'%'");
+        sqlx::query(&grant_query)
+            .execute(pool)
+            .await
+            .map_err(|e| Error::UpdateRole(e.to_string()))?;
+
+        Ok((username, password, database_name))
+    }
+
     async fn request_aws_rds(
         &self,
         project_name: &str,
@@ -273,10 +355,11 @@ impl MyProvisioner {
 
                     // The engine display impl is used for both the engine and the database name,
                     // but for mysql the engine name is an invalid database name.
-                    let db_name = if let aws_rds::Engine::Mysql(_) = engine {
-                        "msql".to_string()
-                    } else {
-                        engine.to_string()
+                    // SQL Server instances cannot be created with a database at all.
+                    let db_name = match engine {
+                        aws_rds::Engine::Mysql(_) => Some("msql".to_string()),
+                        aws_rds::Engine::Sqlserver(_) => None,
+                        _ => Some(engine.to_string()),
                     };
 
                     client
@@ -284,12 +367,12 @@ impl MyProvisioner {
                         .db_instance_identifier(&instance_name)
                         .master_username(MASTER_USERNAME)
                         .master_user_password(&password)
-                        .engine(engine.to_string())
-                        .db_instance_class(AWS_RDS_CLASS)
+                        .engine(engine_to_rds_engine(&engine))
+                        .db_instance_class(engine_to_rds_class(&engine))
                         .allocated_storage(20)
                         .backup_retention_period(0) // Disable backups
                         .publicly_accessible(true)
-                        .db_name(db_name)
+                        .set_db_name(db_name)
                         .set_db_subnet_group_name(Some(RDS_SUBNET_GROUP.to_string()))
                         .send()
                         .await?
@@ -328,9 +411,10 @@ impl MyProvisioner {
                 .master_username
                 .expect("instance to have a username"),
             password,
+            // SQL Server instances only have their system databases
             database_name: instance
                 .db_name
-                .expect("instance to have a default database"),
+                .unwrap_or_else(|| SQLSERVER_DEFAULT_DATABASE.to_string()),
             address_private: address.clone(),
             address_public: address,
             port: engine_to_port(engine),
@@ -345,6 +429,9 @@ impl MyProvisioner {
         match engine {
             shared::Engine::Postgres(_) => self.delete_pg(project_name).await?,
             shared::Engine::Mongodb(_) => self.delete_mongodb(project_name).await?,
+            shared::Engine::Mysql(_) => self.delete_mysql(project_name).await?,
+            // Nothing was provisioned for an embedded database
+            shared::Engine::Sqlite(_) => {}
         }
         Ok(DatabaseDeletionResponse {})
     }
@@ -353,7 +440,7 @@ impl MyProvisioner {
         let database_name = format!("db-{project_name}");
         let role_name = format!("user-{project_name}");
 
-        // Idenfitiers cannot be used as query parameters
+        // Identifiers cannot be used as query parameters
         let drop_db_query = format!("DROP DATABASE \"{database_name}\";");
 
         // Drop the database. Note that this can fail if there are still active connections to it
@@ -396,6 +483,27 @@ impl MyProvisioner {
         Ok(())
     }
 
+    async fn delete_mysql(&self, project_name: &str) -> Result<(), Error> {
+        let pool = self.mysql_pool()?;
+        let database_name = format!("db-{project_name}");
+        let username = format!("user-{project_name}");
+
+        // Identifiers cannot be used as query parameters
+        let drop_db_query = format!("DROP DATABASE IF EXISTS `{database_name}`");
+        sqlx::query(&drop_db_query)
+            .execute(pool)
+            .await
+            .map_err(|e| Error::DeleteDB(e.to_string()))?;
+
+        let drop_user_query = format!("DROP USER IF EXISTS '{username}'@'%'");
+        sqlx::query(&drop_user_query)
+            .execute(pool)
+            .await
+            .map_err(|e| Error::DeleteRole(e.to_string()))?;
+
+        Ok(())
+    }
+
     async fn delete_aws_rds(
         &self,
         project_name: &str,
@@ -526,5 +634,22 @@ fn engine_to_port(engine: aws_rds::Engine) -> String {
         aws_rds::Engine::Postgres(_) => "5432".to_string(),
         aws_rds::Engine::Mariadb(_) => "3306".to_string(),
         aws_rds::Engine::Mysql(_) => "3306".to_string(),
+        aws_rds::Engine::Sqlserver(_) => "1433".to_string(),
+    }
+}
+
+fn engine_to_rds_class(engine: &aws_rds::Engine) -> &'static str {
+    match engine {
+        aws_rds::Engine::Sqlserver(_) => AWS_RDS_SQLSERVER_CLASS,
+        _ => AWS_RDS_CLASS,
+    }
+}
+
+/// Name RDS knows the engine by
+fn engine_to_rds_engine(engine: &aws_rds::Engine) -> String {
+    match engine {
+        // The free edition, in line with the instance class used for the other engines
+        aws_rds::Engine::Sqlserver(_) => "sqlserver-ex".to_string(),
+        _ => engine.to_string(),
     }
 }
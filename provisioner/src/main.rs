@@ -17,9 +17,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         port,
         shared_pg_uri,
         shared_mongodb_uri,
+        shared_mysql_uri,
         fqdn,
         internal_pg_address,
         internal_mongodb_address,
+        internal_mysql_address,
         auth_uri,
     } = Args::parse();
     let addr = SocketAddr::new(ip, port);
@@ -27,9 +29,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let provisioner = MyProvisioner::new(
         &shared_pg_uri,
         &shared_mongodb_uri,
+        shared_mysql_uri.as_deref(),
         fqdn.to_string(),
         internal_pg_address,
         internal_mongodb_address,
+        internal_mysql_address,
     )
     .await
     .unwrap();
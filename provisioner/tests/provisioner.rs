@@ -4,7 +4,8 @@ use helpers::{exec_mongosh, exec_psql, DbType, DockerInstance};
 use once_cell::sync::Lazy;
 use serde_json::Value;
 use shuttle_proto::provisioner::shared;
-use shuttle_provisioner::MyProvisioner;
+use shuttle_provisioner::{Error, MyProvisioner};
+use tonic::{Code, Status};
 
 static PG: Lazy<DockerInstance> = Lazy::new(|| DockerInstance::new(DbType::Postgres));
 static MONGODB: Lazy<DockerInstance> = Lazy::new(|| DockerInstance::new(DbType::MongoDb));
@@ -20,9 +21,11 @@ async fn shared_db_role_does_not_exist() {
     let provisioner = MyProvisioner::new(
         &PG.uri,
         &MONGODB.uri,
+        None,
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        "mysql".to_string(),
     )
     .await
     .unwrap();
@@ -48,9 +51,11 @@ async fn shared_db_role_does_exist() {
     let provisioner = MyProvisioner::new(
         &PG.uri,
         &MONGODB.uri,
+        None,
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        "mysql".to_string(),
     )
     .await
     .unwrap();
@@ -81,9 +86,11 @@ async fn injection_safe() {
     let provisioner = MyProvisioner::new(
         &PG.uri,
         &MONGODB.uri,
+        None,
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        "mysql".to_string(),
     )
     .await
     .unwrap();
@@ -102,9 +109,11 @@ async fn shared_db_missing() {
     let provisioner = MyProvisioner::new(
         &PG.uri,
         &MONGODB.uri,
+        None,
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        "mysql".to_string(),
     )
     .await
     .unwrap();
@@ -130,9 +139,11 @@ async fn shared_db_filled() {
     let provisioner = MyProvisioner::new(
         &PG.uri,
         &MONGODB.uri,
+        None,
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        "mysql".to_string(),
     )
     .await
     .unwrap();
@@ -160,9 +171,11 @@ async fn shared_mongodb_role_does_not_exist() {
     let provisioner = MyProvisioner::new(
         &PG.uri,
         &MONGODB.uri,
+        None,
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        "mysql".to_string(),
     )
     .await
     .unwrap();
@@ -184,9 +197,11 @@ async fn shared_mongodb_role_does_exist() {
     let provisioner = MyProvisioner::new(
         &PG.uri,
         &MONGODB.uri,
+        None,
         "fqdn".to_string(),
         "pg".to_string(),
         "mongodb".to_string(),
+        "mysql".to_string(),
     )
     .await
     .unwrap();
@@ -234,3 +249,25 @@ async fn shared_mongodb_role_does_exist() {
     let user_cycled_key = &user["credentials"]["SCRAM-SHA-256"]["storedKey"];
     assert_ne!(user_stored_key, user_cycled_key);
 }
+
+#[tokio::test]
+async fn shared_sqlite_is_rejected() {
+    let provisioner = MyProvisioner::new(
+        &PG.uri,
+        &MONGODB.uri,
+        None,
+        "fqdn".to_string(),
+        "pg".to_string(),
+        "mongodb".to_string(),
+        "mysql".to_string(),
+    )
+    .await
+    .unwrap();
+
+    let error = provisioner
+        .request_shared_db("sqlite", shared::Engine::Sqlite(String::new()))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::UnsupportedEngine(_)));
+    assert_eq!(Status::from(error).code(), Code::InvalidArgument);
+}
@@ -14,6 +14,7 @@ pub enum AwsRdsType {
     Postgres,
     MySql,
     MariaDB,
+    SqlServer,
 }
 
 #[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
@@ -21,6 +22,8 @@ pub enum AwsRdsType {
 pub enum SharedType {
     Postgres,
     MongoDb,
+    MySql,
+    Sqlite,
 }
 
 impl Display for Type {
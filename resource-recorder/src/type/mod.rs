@@ -3,6 +3,7 @@ use std::{borrow::Cow, fmt::Display, str::FromStr};
 use sqlx::Database;
 
 pub mod database;
+pub mod queue;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Type {
@@ -11,6 +12,8 @@ pub enum Type {
     StaticFolder,
     Persist,
     Turso,
+    Redis,
+    Queue(queue::Type),
     Custom,
 }
 
@@ -22,6 +25,8 @@ impl Display for Type {
             Type::StaticFolder => write!(f, "static_folder"),
             Type::Persist => write!(f, "persist"),
             Type::Turso => write!(f, "turso"),
+            Type::Redis => write!(f, "redis"),
+            Type::Queue(queue_type) => write!(f, "queue::{queue_type}"),
             Type::Custom => write!(f, "custom"),
         }
     }
@@ -34,6 +39,9 @@ impl FromStr for Type {
         if let Some((prefix, rest)) = s.split_once("::") {
             match prefix {
                 "database" => Ok(Self::Database(database::Type::from_str(rest)?)),
+                "queue" => Ok(Self::Queue(
+                    queue::Type::from_str(rest).map_err(|e| e.to_string())?,
+                )),
                 _ => Err(format!("'{prefix}' is an unknown resource type")),
             }
         } else {
@@ -42,6 +50,7 @@ impl FromStr for Type {
                 "static_folder" => Ok(Self::StaticFolder),
                 "persist" => Ok(Self::Persist),
                 "turso" => Ok(Self::Turso),
+                "redis" => Ok(Self::Redis),
                 "custom" => Ok(Self::Custom),
                 _ => Err(format!("'{s}' is an unknown resource type")),
             }
@@ -83,7 +92,7 @@ impl<'r> sqlx::Decode<'r, sqlx::Sqlite> for Type {
 mod tests {
     use std::str::FromStr;
 
-    use super::{database, Type};
+    use super::{database, queue, Type};
 
     #[test]
     fn to_string_and_back() {
@@ -91,12 +100,18 @@ mod tests {
             Type::Database(database::Type::AwsRds(database::AwsRdsType::Postgres)),
             Type::Database(database::Type::AwsRds(database::AwsRdsType::MySql)),
             Type::Database(database::Type::AwsRds(database::AwsRdsType::MariaDB)),
+            Type::Database(database::Type::AwsRds(database::AwsRdsType::SqlServer)),
             Type::Database(database::Type::Shared(database::SharedType::Postgres)),
             Type::Database(database::Type::Shared(database::SharedType::MongoDb)),
+            Type::Database(database::Type::Shared(database::SharedType::MySql)),
+            Type::Database(database::Type::Shared(database::SharedType::Sqlite)),
             Type::Secrets,
             Type::StaticFolder,
             Type::Persist,
             Type::Turso,
+            Type::Redis,
+            Type::Queue(queue::Type::RabbitMq),
+            Type::Queue(queue::Type::Sqs),
         ];
 
         for input in inputs {
@@ -0,0 +1,8 @@
+use strum::{Display, EnumString};
+
+#[derive(Clone, Copy, Debug, Display, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum Type {
+    RabbitMq,
+    Sqs,
+}
@@ -3,7 +3,7 @@ use std::sync::{Arc, Mutex};
 use anyhow::Context;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
-use shuttle_common::resource::{self, Type};
+use shuttle_common::resource::{self, ProvisioningStatus, Type};
 use shuttle_service::ResourceBuilder;
 
 use crate::ProvisionerFactory;
@@ -48,12 +48,47 @@ impl ResourceTracker {
 
 /// Helper function to get a resource from a builder.
 ///
-/// This function is called by the codegen to create each type of needed resource.
+/// This function is called by the codegen to create each type of needed resource. The resource
+/// fields of its logs let the deployer track when each resource was requested and became ready.
 pub async fn get_resource<B, T, O>(
     builder: B,
     factory: &mut ProvisionerFactory,
     resource_tracker: &mut ResourceTracker,
 ) -> Result<T, shuttle_service::Error>
+where
+    B: ResourceBuilder<T, Output = O>,
+    O: Serialize + DeserializeOwned,
+{
+    tracing::info!(
+        resource_type = %B::TYPE,
+        resource_status = %ProvisioningStatus::Provisioning,
+        "resource requested"
+    );
+
+    let resource = build_resource(builder, factory, resource_tracker).await;
+
+    match &resource {
+        Ok(_) => tracing::info!(
+            resource_type = %B::TYPE,
+            resource_status = %ProvisioningStatus::Ready,
+            "resource ready"
+        ),
+        Err(error) => tracing::error!(
+            resource_type = %B::TYPE,
+            resource_status = %ProvisioningStatus::Failed,
+            error = error as &dyn std::error::Error,
+            "resource failed"
+        ),
+    }
+
+    resource
+}
+
+async fn build_resource<B, T, O>(
+    builder: B,
+    factory: &mut ProvisionerFactory,
+    resource_tracker: &mut ResourceTracker,
+) -> Result<T, shuttle_service::Error>
 where
     B: ResourceBuilder<T, Output = O>,
     O: Serialize + DeserializeOwned,
@@ -1,16 +1,30 @@
 use std::fs::read_to_string;
 use std::io::BufRead;
 use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
 
 use anyhow::{anyhow, bail, Context};
 use cargo_metadata::Message;
 use cargo_metadata::{Package, Target};
 use crossbeam_channel::Sender;
 use shuttle_common::project::ProjectName;
+use thiserror::Error;
 use tracing::{debug, error, trace};
 
 use crate::{NEXT_NAME, RUNTIME_NAME};
 
+/// Ways a build can fail after cargo was started. Downcast the error returned by
+/// [build_workspace] to this to tell the failures of the network apart from those of the code
+#[derive(Debug, Error)]
+pub enum BuildError {
+    /// The dependencies could not be downloaded, so the code never got compiled
+    #[error("failed to fetch the dependencies of the project")]
+    Fetch,
+    /// The code of the project did not compile
+    #[error("Build failed. Is the Shuttle runtime missing?")]
+    Compile,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 /// This represents a compiled alpha or shuttle-next service.
 pub struct BuiltService {
@@ -85,6 +99,13 @@ pub async fn build_workspace(
     if !manifest_path.exists() {
         return Err(anyhow!("failed to read the Shuttle project manifest"));
     }
+
+    // Download the dependencies up front so a network failure is not mistaken for broken code
+    if deployment {
+        fetch(&manifest_path, tx.clone()).await?;
+        trace!("dependencies fetched");
+    }
+
     let metadata = cargo_metadata::MetadataCommand::new()
         .manifest_path(&manifest_path)
         .exec()?;
@@ -208,6 +229,45 @@ fn is_cdylib(target: &Target) -> bool {
     target.kind.iter().any(|kind| kind == "cdylib")
 }
 
+async fn fetch(manifest_path: &Path, tx: Sender<Message>) -> anyhow::Result<()> {
+    let mut cargo = tokio::process::Command::new("cargo");
+    cargo.arg("fetch").arg("--manifest-path").arg(manifest_path);
+
+    if !run_cargo(cargo, tx).await?.success() {
+        return Err(BuildError::Fetch.into());
+    }
+
+    Ok(())
+}
+
+/// Run a cargo command, sending the lines of its output on `tx`
+async fn run_cargo(
+    mut cargo: tokio::process::Command,
+    tx: Sender<Message>,
+) -> anyhow::Result<ExitStatus> {
+    let (reader, writer) = os_pipe::pipe()?;
+    let writer_clone = writer.try_clone()?;
+    cargo.stdout(writer);
+    cargo.stderr(writer_clone);
+
+    let mut handle = cargo.spawn()?;
+
+    tokio::task::spawn_blocking(move || {
+        let reader = std::io::BufReader::new(reader);
+        for line in reader.lines() {
+            if let Ok(line) = line {
+                if let Err(error) = tx.send(Message::TextLine(line)) {
+                    error!("failed to send cargo message on channel: {error}");
+                };
+            } else {
+                error!("Failed to read Cargo log messages");
+            };
+        }
+    });
+
+    Ok(handle.wait().await?)
+}
+
 async fn compile(
     packages: Vec<&Package>,
     release_mode: bool,
@@ -225,11 +285,6 @@ async fn compile(
 
     let mut cargo = tokio::process::Command::new("cargo");
 
-    let (reader, writer) = os_pipe::pipe()?;
-    let writer_clone = writer.try_clone()?;
-    cargo.stdout(writer);
-    cargo.stderr(writer_clone);
-
     cargo.arg("build").arg("--manifest-path").arg(manifest_path);
 
     if deployment {
@@ -252,25 +307,8 @@ async fn compile(
         cargo.arg("--target").arg("wasm32-wasi");
     }
 
-    let mut handle = cargo.spawn()?;
-
-    tokio::task::spawn_blocking(move || {
-        let reader = std::io::BufReader::new(reader);
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                if let Err(error) = tx.send(Message::TextLine(line)) {
-                    error!("failed to send cargo message on channel: {error}");
-                };
-            } else {
-                error!("Failed to read Cargo log messages");
-            };
-        }
-    });
-
-    let command = handle.wait().await?;
-
-    if !command.success() {
-        bail!("Build failed. Is the Shuttle runtime missing?");
+    if !run_cargo(cargo, tx).await?.success() {
+        return Err(BuildError::Compile.into());
     }
 
     let mut outputs = Vec::new();